@@ -0,0 +1,65 @@
+// submits 10 000 jobs one at a time to an otherwise idle pool and measures
+// how long each one takes to start running, with workers that go to sleep
+// right away and workers that spin for a while first, run with:-
+//
+// cargo bench --bench latency
+//
+// spinning only pays off with a core to spare for it, on a machine with a
+// single core the spinning worker keeps the submitting thread off the cpu
+
+use hello::{IdleStrategy, ThreadPool};
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+const JOBS: usize = 10_000;
+
+fn run(idle_strategy: IdleStrategy) -> Vec<Duration> {
+    let pool = ThreadPool::builder()
+        .num_threads(1)
+        .idle_strategy(idle_strategy)
+        .build()
+        .unwrap();
+    let (sender, receiver) = mpsc::channel();
+
+    (0..JOBS)
+        .map(|_| {
+            let sender = sender.clone();
+            let submitted_at = Instant::now();
+
+            pool.execute(move || sender.send(submitted_at.elapsed()).unwrap());
+            let latency = receiver.recv().unwrap();
+
+            // gives the worker a moment to go idle again, as it would
+            // between requests that don't come in back to back
+            thread::sleep(Duration::from_micros(50));
+            latency
+        })
+        .collect()
+}
+
+fn main() {
+    let strategies = [
+        ("park", IdleStrategy::Park),
+        (
+            "spin then park",
+            IdleStrategy::SpinThenPark { spins: 100_000 },
+        ),
+    ];
+
+    for (name, idle_strategy) in strategies {
+        let mut latencies = run(idle_strategy);
+        latencies.sort();
+
+        let percentile = |p: usize| latencies[(JOBS - 1) * p / 100];
+
+        println!(
+            "{name:>14}: p50 {:>9.1?}  p99 {:>9.1?}  max {:>9.1?}",
+            percentile(50),
+            percentile(99),
+            latencies[JOBS - 1],
+        );
+    }
+}
@@ -0,0 +1,36 @@
+// runs 1 million empty jobs on pools taking 1 and 16 jobs at a time off the
+// queue, the difference is the time the workers spend taking turns at the
+// lock on the queue, run with:-
+//
+// cargo bench --bench throughput
+
+use hello::ThreadPool;
+use std::time::{Duration, Instant};
+
+const JOBS: usize = 1_000_000;
+
+fn run(batch_size: usize) -> Duration {
+    let pool = ThreadPool::builder()
+        .num_threads(4)
+        .batch_size(batch_size)
+        .build()
+        .unwrap();
+
+    let start = Instant::now();
+
+    for _ in 0..JOBS {
+        pool.execute(|| {});
+    }
+
+    pool.join();
+    start.elapsed()
+}
+
+fn main() {
+    for batch_size in [1, 16] {
+        let elapsed = run(batch_size);
+        let per_second = JOBS as f64 / elapsed.as_secs_f64();
+
+        println!("batch size {batch_size:>2}: {elapsed:>10.1?} ({per_second:.0} jobs/s)");
+    }
+}
@@ -0,0 +1,99 @@
+// pinning worker threads to cpu cores, only implemented on linux, on every
+// other platform pinning does nothing and workers stay unpinned
+
+// pins the calling thread to a single cpu, the `n`th(wrapping around) of the
+// cpus the process is allowed to run on, which isn't necessarily every cpu
+// of the machine inside containers or under `taskset`, returns the cpu the
+// thread was pinned to
+#[cfg(target_os = "linux")]
+pub(crate) fn pin_current_thread(n: usize) -> std::io::Result<Option<usize>> {
+    use std::{io, mem};
+
+    // SAFETY: cpu_set_t is a plain bitmask for which all zeroes is a valid
+    // (empty) value, both calls only read or write within the size passed,
+    // and pid 0 refers to the calling thread
+    unsafe {
+        let mut allowed: libc::cpu_set_t = mem::zeroed();
+
+        if libc::sched_getaffinity(0, mem::size_of::<libc::cpu_set_t>(), &mut allowed) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let cpus = (0..libc::CPU_SETSIZE as usize)
+            .filter(|cpu| libc::CPU_ISSET(*cpu, &allowed))
+            .collect::<Vec<_>>();
+
+        if cpus.is_empty() {
+            return Err(io::Error::other("process isn't allowed to run on any cpu"));
+        }
+
+        let cpu = cpus[n % cpus.len()];
+        let mut pinned: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(cpu, &mut pinned);
+
+        if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &pinned) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Some(cpu))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn pin_current_thread(_n: usize) -> std::io::Result<Option<usize>> {
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreadPool;
+    use std::{
+        sync::{Arc, Barrier},
+        thread,
+    };
+
+    #[test]
+    fn pin_current_thread_fails_soft() {
+        // on a thread of its own, the test thread would stay pinned otherwise
+        let pinned = thread::spawn(|| pin_current_thread(1)).join().unwrap();
+
+        if cfg!(target_os = "linux") {
+            assert!(pinned.unwrap().is_some());
+        } else {
+            assert_eq!(pinned.unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn workers_are_only_pinned_when_asked_to() {
+        let pool = ThreadPool::new(2);
+        assert!(pool
+            .workers()
+            .iter()
+            .all(|worker| worker.pinned_core.is_none()));
+
+        let pool = ThreadPool::builder()
+            .num_threads(2)
+            .pin_workers(true)
+            .build()
+            .unwrap();
+        // a worker pins itself as it starts, both have started once they
+        // run a job each
+        let started = Arc::new(Barrier::new(3));
+        for _ in 0..2 {
+            let started = Arc::clone(&started);
+            pool.execute(move || {
+                started.wait();
+            });
+        }
+        started.wait();
+
+        let pinned = pool
+            .workers()
+            .iter()
+            .map(|worker| worker.pinned_core.is_some())
+            .collect::<Vec<_>>();
+        assert_eq!(pinned, [cfg!(target_os = "linux"); 2]);
+    }
+}
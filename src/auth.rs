@@ -0,0 +1,125 @@
+use crate::http::{constant_time_eq, Request, Response};
+use std::io;
+
+// user names and passwords accepted by protected(), along with the realm
+// browsers show when asking for them, e.g.:-
+//
+// let credentials = Credentials::new("admin area").user("jane", "hunter2");
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credentials {
+    realm: String,
+    users: Vec<(String, String)>,
+}
+
+impl Credentials {
+    pub fn new(realm: &str) -> Credentials {
+        Credentials {
+            realm: realm.to_owned(),
+            users: Vec::new(),
+        }
+    }
+
+    pub fn user(mut self, name: &str, password: &str) -> Credentials {
+        self.users.push((name.to_owned(), password.to_owned()));
+        self
+    }
+
+    // whether the request carries the name and password of one of the
+    // users, every user is compared against even after a match so that
+    // the time taken doesn't tell which user matched
+    pub fn check(&self, request: &Request) -> bool {
+        let Some((name, password)) = request.basic_auth() else {
+            return false;
+        };
+
+        self.users
+            .iter()
+            .fold(false, |matched, (user, user_password)| {
+                let name_matches = constant_time_eq(name.as_bytes(), user.as_bytes());
+                let password_matches =
+                    constant_time_eq(password.as_bytes(), user_password.as_bytes());
+
+                matched | (name_matches & password_matches)
+            })
+    }
+
+    // 401 response asking the client for credentials, browsers show a login
+    // prompt for it
+    pub fn challenge(&self) -> Response {
+        // the realm is sent as a quoted string, quotes and backslashes in it
+        // have to be escaped
+        let realm = self.realm.replace('\\', "\\\\").replace('"', "\\\"");
+
+        Response::new(401).with_header("WWW-Authenticate", &format!("Basic realm=\"{realm}\""))
+    }
+}
+
+// wraps `handler` so that it only runs for requests carrying the name and
+// password of one of the users in `credentials`, every other request gets
+// a 401 asking for them, e.g.:-
+//
+// let admin = protected(credentials, |request| Ok(Response::new(200)));
+// let router = Router::new().get("/admin", admin);
+pub fn protected<H>(
+    credentials: Credentials,
+    handler: H,
+) -> impl Fn(&Request) -> io::Result<Response>
+where
+    H: Fn(&Request) -> io::Result<Response>,
+{
+    move |request| {
+        if credentials.check(request) {
+            handler(request)
+        } else {
+            Ok(credentials.challenge())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(authorization: Option<&str>) -> Request {
+        let header = authorization
+            .map(|value| format!("Authorization: {value}\r\n"))
+            .unwrap_or_default();
+        let request = format!("GET /admin HTTP/1.1\r\n{header}\r\n");
+        Request::parse(&mut request.as_bytes()).unwrap()
+    }
+
+    fn admin() -> impl Fn(&Request) -> Response {
+        let credentials = Credentials::new("admin \"area\"").user("jane", "hunter2");
+        let handler = protected(credentials, |_| Ok(Response::new(200)));
+        move |request| handler(request).unwrap()
+    }
+
+    #[test]
+    fn no_credentials() {
+        let response = admin()(&request(None));
+        assert_eq!(response.status, 401);
+        assert_eq!(
+            response.headers.get("WWW-Authenticate"),
+            Some("Basic realm=\"admin \\\"area\\\"\"")
+        );
+    }
+
+    #[test]
+    fn wrong_credentials() {
+        for authorization in [
+            "Basic amFuZTp3cm9uZw==",
+            "Basic not base64",
+            "Bearer amFuZTpodW50ZXIy",
+        ] {
+            assert_eq!(admin()(&request(Some(authorization))).status, 401);
+        }
+    }
+
+    #[test]
+    fn right_credentials() {
+        assert_eq!(
+            admin()(&request(Some("Basic amFuZTpodW50ZXIy"))).status,
+            200
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use crate::ThreadPool;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+
+// the output of a job run through ThreadPool::spawn_blocking(), resolves
+// once a worker has run the job, the worker wakes up the task awaiting it,
+// so it works with any async runtime, e.g.:-
+//
+// let hash = pool.spawn_blocking(move || hash_password(&password)).await;
+//
+// awaiting it panics if the job panicked or was dropped without being run,
+// e.g. by a pool shutting down with jobs still queued
+pub struct BlockingFuture<T> {
+    result: Arc<Mutex<BlockingResult<T>>>,
+}
+
+struct BlockingResult<T> {
+    output: Option<T>,
+    // set once the job is gone, having returned or not
+    finished: bool,
+    // of the task that last polled the future before the job finished
+    waker: Option<Waker>,
+}
+
+// the job's end of a BlockingFuture, dropped without completing when the
+// job panics or is dropped unrun, which finishes the future all the same
+struct Completion<T> {
+    result: Arc<Mutex<BlockingResult<T>>>,
+}
+
+impl<T> Completion<T> {
+    fn complete(self, output: T) {
+        lock(&self.result).output = Some(output);
+    }
+}
+
+impl<T> Drop for Completion<T> {
+    fn drop(&mut self) {
+        // the waker is woken outside of the lock, it might poll the future
+        // right away on the waking thread
+        let waker = {
+            let mut result = lock(&self.result);
+            result.finished = true;
+            result.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> Future for BlockingFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut result = lock(&self.result);
+
+        if let Some(output) = result.output.take() {
+            return Poll::Ready(output);
+        }
+
+        if result.finished {
+            panic!("blocking job panicked or was dropped before running");
+        }
+
+        result.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl<T> fmt::Debug for BlockingFuture<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BlockingFuture")
+            .field("finished", &lock(&self.result).finished)
+            .finish_non_exhaustive()
+    }
+}
+
+// doesn't unwrap the lock, the job's end is dropped while unwinding from a
+// panicking job and a second panic would abort the process
+fn lock<T>(result: &Mutex<BlockingResult<T>>) -> MutexGuard<'_, BlockingResult<T>> {
+    result
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl ThreadPool {
+    // runs `f` on the pool like execute(), returning a future of what it
+    // returns, which lets async code offload blocking work, e.g. file io or
+    // hashing, to the pool and await it without blocking the runtime's own
+    // threads, see BlockingFuture
+    //
+    // panics once the pool is closed, like execute()
+    pub fn spawn_blocking<F, T>(&self, f: F) -> BlockingFuture<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let result = Arc::new(Mutex::new(BlockingResult {
+            output: None,
+            finished: false,
+            waker: None,
+        }));
+        let completion = Completion {
+            result: Arc::clone(&result),
+        };
+
+        self.execute(move || completion.complete(f()));
+
+        BlockingFuture { result }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        task::Wake,
+        thread::{self, Thread},
+        time::Duration,
+    };
+
+    // wakes up the thread blocked in block_on()
+    struct Unpark(Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    // the simplest executor there is, polls the future on the calling
+    // thread, parking the thread in between till the future's woken up
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = Box::pin(future);
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn spawn_blocking_awaited() {
+        let pool = ThreadPool::new(2);
+
+        let output = block_on(async {
+            let first = pool.spawn_blocking(|| {
+                thread::sleep(Duration::from_millis(50));
+                (1..=10).sum::<u32>()
+            });
+            let second = pool.spawn_blocking(|| thread::current().name().map(str::to_owned));
+
+            (first.await, second.await)
+        });
+
+        assert_eq!(output.0, 55);
+        // ran on a worker rather than the awaiting thread
+        assert_ne!(output.1.as_deref(), thread::current().name());
+    }
+
+    #[test]
+    fn spawn_blocking_polled_after_the_job_finished() {
+        let pool = ThreadPool::new(1);
+        let future = pool.spawn_blocking(|| "done");
+
+        pool.join();
+        assert_eq!(block_on(future), "done");
+    }
+
+    #[test]
+    #[should_panic(expected = "blocking job panicked")]
+    fn spawn_blocking_panics_with_the_job() {
+        let pool = ThreadPool::new(1);
+        block_on(pool.spawn_blocking(|| panic!("oops")))
+    }
+}
@@ -0,0 +1,612 @@
+use crate::{
+    queue::{JobQueue, QueueFactory},
+    watchdog::WatchdogConfig,
+    watermark::Watermarks,
+    ThreadPool,
+};
+use log::warn;
+use std::{error::Error, fmt, io, num::NonZeroUsize, sync::Arc, thread, time::Duration};
+
+// configuration for a ThreadPool, every setting has a default so only the
+// ones that matter need to be set, e.g.:-
+//
+// let pool = ThreadPool::builder().num_threads(8).build()?;
+#[derive(Debug, Clone)]
+pub struct ThreadPoolBuilder {
+    pub(crate) num_threads: usize,
+    pub(crate) pin_workers: bool,
+    pub(crate) max_queue_age: Option<Duration>,
+    pub(crate) watermarks: Watermarks,
+    pub(crate) wait_for_workers: bool,
+    pub(crate) queue_capacity: Option<usize>,
+    pub(crate) watchdog: Option<WatchdogConfig>,
+    pub(crate) batch_size: usize,
+    pub(crate) idle_strategy: IdleStrategy,
+    pub(crate) job_queue: Option<QueueFactory>,
+    pub(crate) name: Option<String>,
+    pub(crate) first_worker_id: usize,
+    pub(crate) on_thread_start: Option<StartHook>,
+    pub(crate) before_each: Option<StartHook>,
+    pub(crate) after_each: Option<AfterHook>,
+    pub(crate) class_weights: Vec<(String, u32)>,
+    pub(crate) max_threads: Option<usize>,
+    pub(crate) keep_alive: Option<Duration>,
+    pub(crate) max_jobs_per_worker: Option<usize>,
+    pub(crate) idle_sample_interval: Duration,
+    pub(crate) caller_runs: bool,
+    pub(crate) dispatch_mode: DispatchMode,
+    pub(crate) on_reentrant_full: ReentrantFull,
+    pub(crate) warn_on_drop: bool,
+    pub(crate) inline: bool,
+}
+
+impl ThreadPoolBuilder {
+    // defaults to one thread per cpu core available to the process
+    pub fn new() -> ThreadPoolBuilder {
+        ThreadPoolBuilder {
+            num_threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            pin_workers: false,
+            max_queue_age: None,
+            watermarks: Watermarks::default(),
+            wait_for_workers: true,
+            queue_capacity: None,
+            watchdog: None,
+            batch_size: 16,
+            idle_strategy: IdleStrategy::Park,
+            job_queue: None,
+            name: None,
+            first_worker_id: 1,
+            on_thread_start: None,
+            before_each: None,
+            after_each: None,
+            class_weights: Vec::new(),
+            max_threads: None,
+            keep_alive: None,
+            max_jobs_per_worker: None,
+            idle_sample_interval: Duration::from_secs(1),
+            caller_runs: false,
+            dispatch_mode: DispatchMode::Shared,
+            on_reentrant_full: ReentrantFull::Overflow,
+            warn_on_drop: true,
+            inline: false,
+        }
+    }
+
+    // preset for jobs that keep a core busy the whole time they run, e.g.
+    // hashing or compression, one thread per cpu core since more threads
+    // than that only take turns on the same cores, a queue of 4 jobs per
+    // thread, and once that's full the thread submitting the job runs it
+    // itself(see caller_runs()), which slows down whatever keeps submitting
+    // them rather than letting the queue grow, a job running for more than
+    // 10 seconds is logged as a warning
+    pub fn for_cpu() -> ThreadPoolBuilder {
+        let builder = ThreadPoolBuilder::new();
+        let capacity = builder.num_threads * 4;
+
+        builder.queue_capacity(capacity).caller_runs(true).watchdog(
+            Duration::from_secs(1),
+            Duration::from_secs(10),
+            |pool, id, name, running| {
+                warn!(
+                    "Thread {pool}-{id} has been running job {} for {running:?}.",
+                    name.unwrap_or("<unnamed>")
+                );
+            },
+        )
+    }
+
+    // preset for jobs that spend most of their time waiting, e.g. on
+    // sockets or a database, which is what it takes more threads than
+    // cores for, it starts with one thread per cpu core(or `max_threads` if
+    // that's fewer) and starts more, up to `max_threads`, once every thread
+    // is busy, threads started that way exit again after a minute without a
+    // job(see max_threads() and keep_alive()), the queue takes 16 jobs per
+    // thread and a job running for more than a minute is logged as a
+    // warning
+    pub fn for_io(max_threads: usize) -> ThreadPoolBuilder {
+        let builder = ThreadPoolBuilder::new();
+        let num_threads = builder.num_threads.min(max_threads);
+
+        builder
+            .num_threads(num_threads)
+            .max_threads(max_threads)
+            .keep_alive(Duration::from_secs(60))
+            .queue_capacity(max_threads.saturating_mul(16))
+            .watchdog(
+                Duration::from_secs(5),
+                Duration::from_secs(60),
+                |pool, id, name, running| {
+                    warn!(
+                        "Thread {pool}-{id} has been running job {} for {running:?}.",
+                        name.unwrap_or("<unnamed>")
+                    );
+                },
+            )
+    }
+
+    pub fn num_threads(mut self, num_threads: usize) -> ThreadPoolBuilder {
+        self.num_threads = num_threads;
+        self
+    }
+
+    // tells the pool apart from the other pools of the process, its
+    // threads are named after it(e.g. "maintenance-2" for worker 2), and so
+    // are they in its log lines, it's passed to the watchdog() callback,
+    // and it's in PoolStats and the labels of its metrics with the metrics
+    // feature enabled, pools built without one are named "pool-1", "pool-2"
+    // etc. in the order they're built
+    pub fn name(mut self, name: &str) -> ThreadPoolBuilder {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    // id of the pool's first worker, the others count up from it, defaults
+    // to 1, i.e. workers 1 to num_threads(), ids are what the pool's threads
+    // are named and logged by(e.g. "maintenance-0" with 0), what the hooks
+    // get(on_thread_start() etc.) and what ThreadPool::execute_on(),
+    // ThreadPool::join_worker() and ThreadPool::workers() go by, 0 has a
+    // worker's id match its index, e.g. the worker execute_keyed() picks for
+    // a key being the one with id `key % num_threads`
+    pub fn first_worker_id(mut self, id: usize) -> ThreadPoolBuilder {
+        self.first_worker_id = id;
+        self
+    }
+
+    // pins worker `n` to the `n`th cpu core(wrapping around when there are
+    // more workers than cores), which keeps the caches of a core warm for
+    // compute-heavy jobs, only supported on linux and ignored elsewhere, a
+    // worker that fails to pin itself logs a warning and keeps running
+    // unpinned, ThreadPool::workers() tells which core each worker got
+    pub fn pin_workers(mut self, pin_workers: bool) -> ThreadPoolBuilder {
+        self.pin_workers = pin_workers;
+        self
+    }
+
+    // jobs submitted with ThreadPool::execute() that waited in the queue for
+    // longer than `max_queue_age` are dropped without running once a worker
+    // gets to them, e.g. a request the client has most likely given up on
+    // by then, under overload running it late only delays the requests
+    // behind it further, dropped jobs are counted in PoolStats::jobs_expired
+    pub fn max_queue_age(mut self, max_queue_age: Duration) -> ThreadPoolBuilder {
+        self.max_queue_age = Some(max_queue_age);
+        self
+    }
+
+    // calls `callback` once the number of queued jobs grows past
+    // `threshold`, e.g. to start turning requests away before the queue
+    // gets any longer, it's called once per crossing rather than for every
+    // job submitted while the queue is over the threshold, it isn't called
+    // again until the queue has gone back under the on_queue_low()
+    // threshold(or under this one if that isn't set)
+    //
+    // callbacks are called on whichever thread submitted or picked up the
+    // job that made the queue cross the threshold, or on a thread that was
+    // already calling one, with no lock of the pool held, so they can use
+    // the pool, e.g. submit jobs, crossings caused by a callback are
+    // called after it returns rather than from inside of it, they should
+    // still return quickly and never wait for jobs of the pool since they
+    // can end up running on one of its workers
+    pub fn on_queue_high<F>(mut self, threshold: usize, callback: F) -> ThreadPoolBuilder
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.watermarks.high = Some((threshold, Arc::new(callback)));
+        self
+    }
+
+    // calls `callback` once the number of queued jobs shrinks below
+    // `threshold` after having gone past the on_queue_high() threshold, e.g.
+    // to start accepting requests again, see on_queue_high() for how and
+    // where callbacks are called, without an on_queue_high() threshold it's
+    // called once the queue shrinks below `threshold` after having reached it
+    pub fn on_queue_low<F>(mut self, threshold: usize, callback: F) -> ThreadPoolBuilder
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.watermarks.low = Some((threshold, Arc::new(callback)));
+        self
+    }
+
+    // by default building the pool only returns once every worker thread
+    // is up and waiting for jobs, so the first jobs don't have to wait for
+    // threads to start and ThreadPool::stats() counts every worker right
+    // away, with `false` it returns as soon as the threads are spawned
+    pub fn wait_for_workers(mut self, wait_for_workers: bool) -> ThreadPoolBuilder {
+        self.wait_for_workers = wait_for_workers;
+        self
+    }
+
+    // bounds the queue to `capacity` jobs waiting for a worker, once it's
+    // full ThreadPool::execute() waits for a worker to make room instead of
+    // letting the queue grow without limit, a capacity of 0 makes every
+    // execute() wait for a worker to take the job
+    //
+    // jobs submitted from inside jobs of the pool never wait, since that
+    // could leave every worker waiting on one another, they go past the
+    // capacity instead, see Handle
+    pub fn queue_capacity(mut self, capacity: usize) -> ThreadPoolBuilder {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    // starts a thread alongside the workers which looks at the jobs they're
+    // running every `interval` and calls `callback` with the pool's
+    // name(see name()), the worker's id, the job's name(see
+    // ThreadPool::execute_named()) and how long it's been running for every
+    // job running for longer than `threshold`, e.g. to find requests stuck
+    // waiting on a server that's down, a job keeps being reported every
+    // `interval` until it finishes, the thread is stopped once the pool is
+    // dropped and its workers have finished
+    pub fn watchdog<F>(
+        mut self,
+        interval: Duration,
+        threshold: Duration,
+        callback: F,
+    ) -> ThreadPoolBuilder
+    where
+        F: Fn(&str, usize, Option<&str>, Duration) + Send + Sync + 'static,
+    {
+        self.watchdog = Some(WatchdogConfig {
+            interval,
+            threshold,
+            callback: Arc::new(callback),
+        });
+        self
+    }
+
+    // most jobs a worker takes off the queue at once, 16 by default, taking
+    // several at once means taking the lock on the queue less often, which
+    // the workers would otherwise spend most of their time waiting for with
+    // jobs that only take microseconds, a worker never takes more than its
+    // share of the queued jobs though, 1 turns batching off
+    pub fn batch_size(mut self, batch_size: usize) -> ThreadPoolBuilder {
+        self.batch_size = batch_size;
+        self
+    }
+
+    // what a worker does while the queue is empty, see IdleStrategy
+    pub fn idle_strategy(mut self, idle_strategy: IdleStrategy) -> ThreadPoolBuilder {
+        self.idle_strategy = idle_strategy;
+        self
+    }
+
+    // queue the jobs wait in, see JobQueue, `new_queue` is called once for
+    // every pool built, a MpscQueue by default, queue_capacity() only applies
+    // to that default queue, the capacity of any other is up to the queue
+    pub fn job_queue<F, Q>(mut self, new_queue: F) -> ThreadPoolBuilder
+    where
+        F: Fn() -> Q + Send + Sync + 'static,
+        Q: JobQueue + 'static,
+    {
+        self.job_queue = Some(QueueFactory(Arc::new(move || Box::new(new_queue()))));
+        self
+    }
+
+    // calls `callback` with the worker's id on every worker thread once it
+    // has started, before it takes any job, e.g. to set up state every
+    // worker keeps for itself in a thread_local, which jobs for a particular
+    // worker(ThreadPool::execute_on()) can then get to, building the pool
+    // waits for it to return on every worker unless wait_for_workers() is
+    // turned off, a worker whose callback panics exits without taking a job
+    pub fn on_thread_start<F>(mut self, callback: F) -> ThreadPoolBuilder
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.on_thread_start = Some(StartHook(Arc::new(callback)));
+        self
+    }
+
+    // calls `callback` with the worker's id on the worker right before it
+    // runs each job, e.g. to enter a tracing span or count the jobs started,
+    // jobs dropped past their deadline(ThreadPool::execute_with_deadline())
+    // are never run so there's no call for them, a callback that panics
+    // takes the worker down like a job did before it was caught, see
+    // ThreadPool::execute()
+    pub fn before_each<F>(mut self, callback: F) -> ThreadPoolBuilder
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.before_each = Some(StartHook(Arc::new(callback)));
+        self
+    }
+
+    // calls `callback` with the worker's id and how long the job ran for
+    // on the worker right after each job returns, also when it panicked,
+    // e.g. to leave the span entered in before_each()
+    pub fn after_each<F>(mut self, callback: F) -> ThreadPoolBuilder
+    where
+        F: Fn(usize, Duration) + Send + Sync + 'static,
+    {
+        self.after_each = Some(AfterHook(Arc::new(callback)));
+        self
+    }
+
+    // share of the workers' time `class` gets relative to the other
+    // classes, see ThreadPool::execute_classed(), classes without a weight
+    // set here and jobs without a class have a weight of 1
+    pub fn class_weight(mut self, class: &str, weight: u32) -> ThreadPoolBuilder {
+        self.class_weights.retain(|(name, _)| name != class);
+        self.class_weights.push((class.to_owned(), weight));
+        self
+    }
+
+    // lets the pool start up to `max_threads` threads in total once every
+    // one of its num_threads() threads is busy and jobs are waiting, one at
+    // a time as long as there are more jobs waiting than threads free to
+    // take them, the threads past num_threads() are only ever started that
+    // way, so they're not counted by ThreadPool::stats() before then,
+    // defaults to num_threads(), i.e. a fixed number of threads
+    pub fn max_threads(mut self, max_threads: usize) -> ThreadPoolBuilder {
+        self.max_threads = Some(max_threads);
+        self
+    }
+
+    // how long a thread started past num_threads()(see max_threads()) waits
+    // for a job before exiting, without it those threads keep running till
+    // the pool shuts down once they're started
+    pub fn keep_alive(mut self, keep_alive: Duration) -> ThreadPoolBuilder {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    // replaces a worker with a fresh thread once it has run `max_jobs` jobs,
+    // the way pre-fork servers recycle their processes, e.g. for jobs that
+    // leave state behind in thread_locals or leak memory, the new thread
+    // takes over the worker's id and the jobs left for it(ThreadPool::execute_on()
+    // and execute_keyed()) and goes through on_thread_start() again, jobs
+    // dropped past their deadline don't count, without it a worker keeps
+    // its thread for as long as it runs
+    pub fn max_jobs_per_worker(mut self, max_jobs: usize) -> ThreadPoolBuilder {
+        self.max_jobs_per_worker = Some(max_jobs);
+        self
+    }
+
+    // how often a worker waiting for a job wakes up to add the time it has
+    // been waiting so far to PoolStats::total_idle, it goes straight back
+    // to waiting after that, so it only decides how far behind total_idle
+    // can be while workers sit idle, defaults to a second
+    pub fn idle_sample_interval(mut self, interval: Duration) -> ThreadPoolBuilder {
+        self.idle_sample_interval = interval;
+        self
+    }
+
+    // makes ThreadPool::execute() run the job on the thread calling it once
+    // the queue is full(see queue_capacity()) rather than wait for room in
+    // the queue, which keeps the thread submitting jobs from getting ahead
+    // of the workers by making it do some of the work, it only applies to
+    // threads other than the pool's own workers
+    pub fn caller_runs(mut self, caller_runs: bool) -> ThreadPoolBuilder {
+        self.caller_runs = caller_runs;
+        self
+    }
+
+    // how jobs get from the queue to the workers, see DispatchMode
+    pub fn dispatch_mode(mut self, dispatch_mode: DispatchMode) -> ThreadPoolBuilder {
+        self.dispatch_mode = dispatch_mode;
+        self
+    }
+
+    // what ThreadPool::execute() does with a job submitted from inside a job
+    // of the pool once the queue is full(see queue_capacity()), see
+    // ReentrantFull, until then such jobs are queued like any other
+    pub fn on_reentrant_full(mut self, on_reentrant_full: ReentrantFull) -> ThreadPoolBuilder {
+        self.on_reentrant_full = on_reentrant_full;
+        self
+    }
+
+    // by default dropping the pool while jobs are still queued logs a
+    // warning, dropping it waits for them to run, which is easy to miss
+    // when the pool was meant to be dropped only once it's done, `false`
+    // turns the warning off for pools that are dropped that way deliberately
+    pub fn warn_on_drop(mut self, warn_on_drop: bool) -> ThreadPoolBuilder {
+        self.warn_on_drop = warn_on_drop;
+        self
+    }
+
+    // runs every job right away on the thread submitting it instead of on a
+    // worker thread, no thread is started at all, e.g. to step through a
+    // handler in a debugger or to get a stack trace through the code that
+    // submitted the job, or in tests, where the job has run by the time
+    // ThreadPool::execute() returns:-
+    //
+    // let pool = ThreadPool::builder().inline(cfg!(test)).build()?;
+    //
+    // everything else about the pool stays the same, a job runs as the first
+    // worker unless it's for a particular one(ThreadPool::execute_on() and
+    // execute_keyed()), WorkerContext::current() works inside it, a panic is
+    // caught and counted like on a worker and shutting the pool down has
+    // nothing left to wait for, jobs that are due later(ThreadPool::execute_with_retry()
+    // and execute_recurring_with_delay()) run on the pool's timer thread
+    // once they are, no watchdog(watchdog()) is started, there's no worker
+    // to keep an eye on
+    pub fn inline(mut self, inline: bool) -> ThreadPoolBuilder {
+        self.inline = inline;
+        self
+    }
+
+    pub fn build(self) -> Result<ThreadPool, PoolCreationError> {
+        if self.num_threads == 0 {
+            return Err(PoolCreationError::ZeroThreads);
+        }
+
+        if self
+            .max_threads
+            .is_some_and(|max_threads| max_threads < self.num_threads)
+        {
+            return Err(PoolCreationError::MaxThreadsBelowNumThreads);
+        }
+
+        let workers = self.max_threads.unwrap_or(self.num_threads);
+
+        if self.first_worker_id.checked_add(workers).is_none() {
+            return Err(PoolCreationError::WorkerIdOverflow);
+        }
+
+        if self.batch_size == 0 {
+            return Err(PoolCreationError::ZeroBatchSize);
+        }
+
+        if self.max_jobs_per_worker == Some(0) {
+            return Err(PoolCreationError::ZeroMaxJobsPerWorker);
+        }
+
+        if self.class_weights.iter().any(|(_, weight)| *weight == 0) {
+            return Err(PoolCreationError::ZeroClassWeight);
+        }
+
+        if let (Some((high, _)), Some((low, _))) = (&self.watermarks.high, &self.watermarks.low) {
+            if low > high {
+                return Err(PoolCreationError::InvertedQueueWatermarks);
+            }
+        }
+
+        ThreadPool::spawn(&self)
+    }
+}
+
+impl Default for ThreadPoolBuilder {
+    fn default() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+    }
+}
+
+// see ThreadPoolBuilder::on_thread_start() and before_each()
+#[derive(Clone)]
+pub(crate) struct StartHook(pub(crate) Arc<dyn Fn(usize) + Send + Sync>);
+
+impl fmt::Debug for StartHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StartHook").finish_non_exhaustive()
+    }
+}
+
+// see ThreadPoolBuilder::after_each()
+#[derive(Clone)]
+pub(crate) struct AfterHook(pub(crate) Arc<dyn Fn(usize, Duration) + Send + Sync>);
+
+impl fmt::Debug for AfterHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AfterHook").finish_non_exhaustive()
+    }
+}
+
+// how a worker waits for the next job once the queue is empty
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdleStrategy {
+    // goes to sleep right away until a job is submitted, waking a sleeping
+    // thread goes through the operating system, which adds a few
+    // microseconds to every job submitted to an idle pool
+    #[default]
+    Park,
+    // keeps checking for a new job up to `spins` times before going to
+    // sleep, a job submitted meanwhile is picked up without waking anything,
+    // at the cost of keeping a core busy for that long, only worth it with
+    // spare cores and jobs coming in quick succession
+    SpinThenPark {
+        spins: u32,
+    },
+}
+
+// how jobs get from the pool's queue to its workers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DispatchMode {
+    // every worker takes the next job off the queue itself once it's free,
+    // whichever worker gets to the queue first gets the job, which keeps
+    // the overhead per job as low as it gets, but leaves it up to the
+    // operating system's scheduler which worker that is, a few workers can
+    // end up with most of the jobs while the rest wait
+    #[default]
+    Shared,
+    // a thread of its own takes the jobs off the queue and hands them out
+    // to the workers in turn, an idle worker first, one running a job with
+    // nothing else waiting for it otherwise, every worker has at most one
+    // job waiting for it, the dispatcher waits for a worker to become free
+    // when none is, so jobs of equal length are spread evenly, at the cost
+    // of every job going through one more thread, the workers take the
+    // jobs still queued off the queue themselves once the pool shuts down
+    RoundRobin,
+}
+
+// what ThreadPool::execute() does with a job submitted from inside a job of
+// the pool once the queue is full, waiting for room like it does on any
+// other thread could leave every worker waiting for room that only a worker
+// can make, e.g. a request handler that fans out sub-jobs would wedge the
+// whole server under load
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReentrantFull {
+    // puts the job past the capacity of the queue, the workers take those
+    // jobs before the ones in the queue, see Handle
+    #[default]
+    Overflow,
+    // runs the job right away on the worker submitting it, which keeps the
+    // queue within its capacity, counted in PoolStats::jobs_ran_inline, a
+    // panic of the job is caught the same as one of a queued job, the job
+    // submitting it goes on, past 32 such jobs run nested in one another the
+    // next one goes past the capacity like with Overflow
+    Inline,
+    // drops the job, ThreadPool::try_execute() returns PoolError::QueueFull,
+    // execute() panics with it
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PoolCreationError {
+    // a pool needs at least 1 thread to ever run anything
+    ZeroThreads,
+    // the on_queue_low() threshold is above the on_queue_high() one, the
+    // queue would count as low again right after going high
+    InvertedQueueWatermarks,
+    // not every worker thread started within a few seconds, see
+    // ThreadPoolBuilder::wait_for_workers()
+    WorkersNotReady,
+    // a worker has to take at least 1 job at a time off the queue
+    ZeroBatchSize,
+    // the operating system refused to start another thread, e.g. because
+    // the process is at its limit on the number of threads, the threads
+    // started before that are shut down again
+    SpawnFailed(io::ErrorKind),
+    // a class with a weight of 0 would never get a job run while there are
+    // jobs of other classes waiting
+    ZeroClassWeight,
+    // max_threads() is the most threads the pool has in total, it can't
+    // start with more than that
+    MaxThreadsBelowNumThreads,
+    // the ids of the workers counting up from first_worker_id() would go
+    // past usize::MAX
+    WorkerIdOverflow,
+    // a worker has to run at least 1 job before it's replaced, see
+    // ThreadPoolBuilder::max_jobs_per_worker()
+    ZeroMaxJobsPerWorker,
+}
+
+impl fmt::Display for PoolCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolCreationError::ZeroThreads => f.write_str("thread pool needs at least 1 thread"),
+            PoolCreationError::InvertedQueueWatermarks => {
+                f.write_str("low queue watermark is above the high queue watermark")
+            }
+            PoolCreationError::WorkersNotReady => {
+                f.write_str("thread pool workers didn't start in time")
+            }
+            PoolCreationError::ZeroBatchSize => f.write_str("batch size must be at least 1"),
+            PoolCreationError::SpawnFailed(kind) => {
+                write!(f, "failed to spawn a thread pool thread: {kind}")
+            }
+            PoolCreationError::ZeroClassWeight => {
+                f.write_str("job class weight must be at least 1")
+            }
+            PoolCreationError::MaxThreadsBelowNumThreads => {
+                f.write_str("max threads must be at least the number of threads")
+            }
+            PoolCreationError::WorkerIdOverflow => {
+                f.write_str("worker ids would go past the largest usize")
+            }
+            PoolCreationError::ZeroMaxJobsPerWorker => {
+                f.write_str("max jobs per worker must be at least 1")
+            }
+        }
+    }
+}
+
+impl Error for PoolCreationError {}
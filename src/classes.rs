@@ -0,0 +1,171 @@
+use crate::queue::{Job, JobQueue};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+// weight of jobs submitted without a class and of classes used without a
+// weight set with ThreadPoolBuilder::class_weight()
+const DEFAULT_WEIGHT: u32 = 1;
+
+// jobs of ThreadPool::execute_classed(), every class has a queue of its own
+// and workers pick the class to take the next job from by smooth weighted
+// round-robin(the way nginx balances between weighted servers), so that
+// out of every `total weight` jobs taken while every class has jobs waiting,
+// each class gets its weight's worth, spread out rather than in a row, e.g.
+// with weights 3 and 1 classes are served a a b a, a a b a and so on
+//
+// jobs submitted without a class are in the pool's JobQueue, which takes
+// part in the round-robin as a class of its own with a weight of 1, only
+// while there are classed jobs waiting though, workers don't even take the
+// lock here otherwise
+pub(crate) struct JobClasses {
+    state: Mutex<ClassesState>,
+    // jobs waiting in every class together
+    len: AtomicUsize,
+}
+
+struct ClassesState {
+    classes: Vec<Class>,
+    by_name: HashMap<String, usize>,
+    // round-robin standing of the jobs without a class, see Class::current
+    unclassed_current: i64,
+}
+
+struct Class {
+    weight: u32,
+    // goes up by the class's weight every time a job is picked and down by
+    // the total weight of the classes that were in the running whenever
+    // this class is the one picked, the class that's furthest behind on
+    // its share has the highest
+    current: i64,
+    jobs: VecDeque<Job>,
+}
+
+impl JobClasses {
+    pub(crate) fn new(weights: &[(String, u32)]) -> JobClasses {
+        let mut state = ClassesState {
+            classes: Vec::new(),
+            by_name: HashMap::new(),
+            unclassed_current: 0,
+        };
+
+        for (name, weight) in weights {
+            let index = state.class_index(name);
+            state.classes[index].weight = *weight;
+        }
+
+        JobClasses {
+            state: Mutex::new(state),
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    pub(crate) fn push(&self, class: &str, job: Job) {
+        let mut state = self.lock();
+        let index = state.class_index(class);
+
+        state.classes[index].jobs.push_back(job);
+        self.len.fetch_add(1, Ordering::Release);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len.load(Ordering::Acquire) == 0
+    }
+
+    // the next job by weight, out of the classes and `queue`
+    pub(crate) fn pop(&self, queue: &dyn JobQueue) -> Option<Job> {
+        if self.is_empty() {
+            return queue.try_pop();
+        }
+
+        let mut state = self.lock();
+        let ClassesState {
+            classes,
+            unclassed_current,
+            ..
+        } = &mut *state;
+
+        let unclassed_waiting = !queue.is_empty();
+        let mut total = 0;
+        let mut picked = None;
+        let mut highest = i64::MIN;
+
+        if unclassed_waiting {
+            *unclassed_current += i64::from(DEFAULT_WEIGHT);
+            total += i64::from(DEFAULT_WEIGHT);
+            highest = *unclassed_current;
+        }
+
+        for (index, class) in classes.iter_mut().enumerate() {
+            if class.jobs.is_empty() {
+                continue;
+            }
+
+            class.current += i64::from(class.weight);
+            total += i64::from(class.weight);
+
+            if class.current > highest {
+                highest = class.current;
+                picked = Some(index);
+            }
+        }
+
+        match picked {
+            Some(index) => {
+                classes[index].current -= total;
+                self.len.fetch_sub(1, Ordering::Release);
+                classes[index].jobs.pop_front()
+            }
+            None if unclassed_waiting => {
+                *unclassed_current -= total;
+                drop(state);
+                queue.try_pop()
+            }
+            // every class emptied out since is_empty() was checked
+            None => queue.try_pop(),
+        }
+    }
+
+    // takes every job out, see ShutdownMode::Abandon
+    pub(crate) fn clear(&self) -> usize {
+        let mut state = self.lock();
+        let cleared = state
+            .classes
+            .iter_mut()
+            .map(|class| class.jobs.drain(..).count())
+            .sum();
+
+        self.len.fetch_sub(cleared, Ordering::Release);
+        cleared
+    }
+
+    // doesn't unwrap the lock, jobs are taken while unwinding from a
+    // panicking job when the jobs it left behind are handed back
+    fn lock(&self) -> std::sync::MutexGuard<'_, ClassesState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ClassesState {
+    // index of `name`'s class, added with the default weight the first
+    // time it's used
+    fn class_index(&mut self, name: &str) -> usize {
+        if let Some(index) = self.by_name.get(name) {
+            return *index;
+        }
+
+        self.classes.push(Class {
+            weight: DEFAULT_WEIGHT,
+            current: 0,
+            jobs: VecDeque::new(),
+        });
+        self.by_name.insert(name.to_owned(), self.classes.len() - 1);
+        self.classes.len() - 1
+    }
+}
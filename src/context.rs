@@ -0,0 +1,282 @@
+use crate::{Shared, WhenFull};
+use std::{
+    cell::{Cell, RefCell},
+    error::Error,
+    fmt,
+    marker::PhantomData,
+    ptr,
+    sync::Arc,
+    time::Instant,
+};
+
+thread_local! {
+    // id and pool of the worker running on the current thread, None on
+    // every other thread
+    static CURRENT: RefCell<Option<(usize, Arc<Shared>)>> = const { RefCell::new(None) };
+    // how many jobs run inline are nested on the current thread's stack,
+    // see Shared::run_inline()
+    static INLINE_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+// called by a worker thread before it starts taking jobs
+pub(crate) fn enter(id: usize, shared: &Arc<Shared>) {
+    CURRENT.with(|current| *current.borrow_mut() = Some((id, Arc::clone(shared))));
+}
+
+// makes the calling thread count as the worker with `id` until the returned
+// guard is dropped, which puts back whatever the thread counted as before,
+// for a job run on the thread that submitted it, see
+// ThreadPoolBuilder::inline()
+pub(crate) fn enter_for_job(id: usize, shared: &Arc<Shared>) -> Entered {
+    let previous = CURRENT.with(|current| current.replace(Some((id, Arc::clone(shared)))));
+    Entered { previous }
+}
+
+pub(crate) struct Entered {
+    previous: Option<(usize, Arc<Shared>)>,
+}
+
+impl Drop for Entered {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+// counts one more job run inline on the current thread until the returned
+// guard is dropped
+pub(crate) fn enter_inline() -> Inlined {
+    INLINE_DEPTH.with(|depth| depth.set(depth.get() + 1));
+    Inlined
+}
+
+// number of jobs run inline the current thread is in the middle of
+pub(crate) fn inline_depth() -> usize {
+    INLINE_DEPTH.with(Cell::get)
+}
+
+pub(crate) struct Inlined;
+
+impl Drop for Inlined {
+    fn drop(&mut self) {
+        INLINE_DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+// id of the worker of the pool `shared` belongs to running on the current
+// thread, None on any other thread
+pub(crate) fn worker_id_of(shared: &Shared) -> Option<usize> {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .filter(|(_, current)| ptr::eq(Arc::as_ptr(current), shared))
+            .map(|(id, _)| *id)
+    })
+}
+
+// whether the current thread is one of the workers of the pool `shared`
+// belongs to
+pub(crate) fn is_worker_of(shared: &Shared) -> bool {
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .is_some_and(|(_, current)| ptr::eq(Arc::as_ptr(current), shared))
+    })
+}
+
+// the worker a job is running on, only available from inside a job, e.g.:-
+//
+// pool.execute(|| {
+//     let worker = WorkerContext::current().unwrap();
+//     worker.handle().execute(|| println!("follow-up job"));
+// });
+//
+// it can't be sent to another thread, it describes the thread it was
+// gotten on
+#[derive(Clone)]
+pub struct WorkerContext {
+    id: usize,
+    shared: Arc<Shared>,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl WorkerContext {
+    // None when not called from a worker thread
+    pub fn current() -> Option<WorkerContext> {
+        CURRENT.with(|current| {
+            current.borrow().as_ref().map(|(id, shared)| WorkerContext {
+                id: *id,
+                shared: Arc::clone(shared),
+                _not_send: PhantomData,
+            })
+        })
+    }
+
+    // id of the worker, the same one ThreadPool::workers() reports
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    // handle for submitting jobs to the pool this worker belongs to
+    pub fn handle(&self) -> Handle {
+        Handle {
+            shared: Arc::clone(&self.shared),
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl fmt::Debug for WorkerContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorkerContext")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+// submits jobs to a pool from inside one of its own jobs, without needing a
+// reference to the pool itself and without ever blocking
+//
+// with a bounded queue(ThreadPoolBuilder::queue_capacity()) ThreadPool::execute()
+// waits for room in the queue, which only a worker can make by taking a job
+// out of it, if every worker is running a job that submits follow-up work
+// while the queue is full, they would all be waiting on each other forever,
+// e.g. with a single worker and a capacity of 1 a job submitting 2 jobs
+// would never finish, a Handle either goes past the capacity(execute())
+// or tells the job that the queue is full(try_execute())
+#[derive(Clone)]
+pub struct Handle {
+    shared: Arc<Shared>,
+    // only used on the worker it came from, jobs it puts past the capacity
+    // of the queue are picked up by the workers once they're done with their
+    // current job, which a thread that isn't a worker doesn't have
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Handle {
+    // submits a job, goes past the capacity of the queue when it's full
+    // instead of waiting for room
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let job = Box::new(f);
+        let deadline = self.shared.max_queue_age.map(|age| Instant::now() + age);
+
+        // can't fail, jobs that don't fit are put aside for the workers
+        let _ = self.shared.enqueue(job, deadline, None, WhenFull::Overflow);
+    }
+
+    // submits a job unless the queue is full, in which case it's up to the
+    // job to decide what to do instead, e.g. run the work itself
+    pub fn try_execute<F>(&self, f: F) -> Result<(), QueueFull>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let job = Box::new(f);
+        let deadline = self.shared.max_queue_age.map(|age| Instant::now() + age);
+
+        self.shared
+            .enqueue(job, deadline, None, WhenFull::Fail)
+            .map_err(|_| QueueFull)
+    }
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle").finish_non_exhaustive()
+    }
+}
+
+// returned by Handle::try_execute() when the queue is at its capacity
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueFull;
+
+impl fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("thread pool queue is full")
+    }
+}
+
+impl Error for QueueFull {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreadPool;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc,
+        },
+        time::Duration,
+    };
+
+    fn single_slot_pool() -> ThreadPool {
+        ThreadPool::builder()
+            .num_threads(1)
+            .queue_capacity(1)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn handle_never_blocks_on_a_full_queue() {
+        let pool = single_slot_pool();
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        let children = Arc::clone(&ran);
+        pool.execute(move || {
+            let handle = WorkerContext::current().unwrap().handle();
+
+            for _ in 0..2 {
+                let ran = Arc::clone(&children);
+                handle.execute(move || {
+                    ran.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        assert_eq!(pool.join_timeout(Duration::from_secs(5)), Ok(()));
+        assert_eq!(ran.load(Ordering::SeqCst), 2);
+        assert_eq!(pool.stats().jobs_submitted_by_workers, 2);
+    }
+
+    #[test]
+    fn handle_reports_a_full_queue() {
+        let pool = single_slot_pool();
+        let (sender, receiver) = mpsc::channel();
+
+        pool.execute(move || {
+            let handle = WorkerContext::current().unwrap().handle();
+            let first = handle.try_execute(|| {});
+            let second = handle.try_execute(|| {});
+            sender.send((first, second)).unwrap();
+        });
+
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+            (Ok(()), Err(QueueFull))
+        );
+        pool.join();
+    }
+
+    #[test]
+    fn worker_context_only_on_workers() {
+        assert!(WorkerContext::current().is_none());
+
+        let pool = ThreadPool::new(2);
+        let (sender, receiver) = mpsc::channel();
+        pool.execute(move || {
+            sender
+                .send(WorkerContext::current().map(|worker| worker.id()))
+                .unwrap();
+        });
+
+        let id = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        // worker ids start at 1
+        assert!(matches!(id, Some(1 | 2)));
+    }
+}
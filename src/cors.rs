@@ -0,0 +1,224 @@
+use crate::http::{Method, Request, Response};
+
+// origins that are allowed to make cross-origin requests to the server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AllowedOrigins {
+    // any origin at all, sent to the browser as `*`
+    Any,
+    // only these exact origins, e.g. "https://example.com", the origin of
+    // the request is echoed back when it is one of them
+    List(Vec<String>),
+}
+
+// cross-origin resource sharing(CORS) configuration, browsers refuse to let
+// a page read responses from another origin unless the server opts in by
+// sending Access-Control-Allow-* headers, for anything but simple requests
+// the browser first asks for permission with a `preflight` OPTIONS request
+// carrying the method and headers of the actual request:-
+//
+// OPTIONS /api HTTP/1.1
+// Origin: https://example.com
+// Access-Control-Request-Method: PUT
+// Access-Control-Request-Headers: content-type
+//
+// Cors::preflight() answers those, Cors::apply() adds the allow-origin
+// header to the response of the actual request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cors {
+    origins: AllowedOrigins,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+}
+
+impl Cors {
+    // allows the methods of simple requests(GET, HEAD and POST) and no
+    // request headers beyond the ones browsers always allow
+    pub fn new(origins: AllowedOrigins) -> Cors {
+        Cors {
+            origins,
+            methods: vec![Method::Get, Method::Head, Method::Post],
+            headers: Vec::new(),
+        }
+    }
+
+    // methods allowed in cross-origin requests
+    pub fn allowed_methods(mut self, methods: &[Method]) -> Cors {
+        self.methods = methods.to_vec();
+        self
+    }
+
+    // request headers allowed in cross-origin requests
+    pub fn allowed_headers(mut self, headers: &[&str]) -> Cors {
+        self.headers = headers.iter().map(|header| header.to_string()).collect();
+        self
+    }
+
+    // the response to a preflight request, None for any other request so
+    // that it can be routed as usual, a preflight from an origin that isn't
+    // allowed still gets an answer, just without the headers the browser is
+    // looking for, which makes it block the actual request
+    pub fn preflight(&self, request: &Request) -> Option<Response> {
+        if request.method != Method::Options
+            || !request.headers.contains("Access-Control-Request-Method")
+        {
+            return None;
+        }
+
+        let response = Response::new(204);
+        let Some(origin) = self.allowed_origin(request) else {
+            return Some(self.vary(response));
+        };
+
+        let methods = self
+            .methods
+            .iter()
+            .map(Method::as_str)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let mut response = response
+            .with_header("Access-Control-Allow-Origin", origin)
+            .with_header("Access-Control-Allow-Methods", &methods);
+
+        if !self.headers.is_empty() {
+            response =
+                response.with_header("Access-Control-Allow-Headers", &self.headers.join(", "));
+        }
+
+        Some(self.vary(response))
+    }
+
+    // adds the allow-origin header to the response of a request coming from
+    // an allowed origin, responses to same-origin requests(no Origin header)
+    // and ones from other origins don't get one, see vary() for what they
+    // get all the same
+    pub fn apply(&self, request: &Request, response: Response) -> Response {
+        match self.allowed_origin(request) {
+            Some(origin) => self.vary(response.with_header("Access-Control-Allow-Origin", origin)),
+            None => self.vary(response),
+        }
+    }
+
+    // value for the allow-origin header if the request's origin is allowed
+    fn allowed_origin<'a>(&'a self, request: &'a Request) -> Option<&'a str> {
+        let origin = request.headers.get("Origin")?;
+
+        match &self.origins {
+            AllowedOrigins::Any => Some("*"),
+            AllowedOrigins::List(origins) => origins
+                .iter()
+                .any(|allowed| allowed == origin)
+                .then_some(origin),
+        }
+    }
+
+    // echoing the request's origin back makes the response depend on that
+    // header, which caches between the client and the server need to know,
+    // the responses without the allow-origin header included, a cache would
+    // otherwise hand the one to a disallowed origin to an allowed one too
+    fn vary(&self, response: Response) -> Response {
+        match self.origins {
+            AllowedOrigins::Any => response,
+            AllowedOrigins::List(_) => response.with_header("Vary", "Origin"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(request: &str) -> Request {
+        Request::parse(&mut request.as_bytes()).unwrap()
+    }
+
+    fn cors() -> Cors {
+        Cors::new(AllowedOrigins::List(vec!["https://example.com".to_owned()]))
+            .allowed_methods(&[Method::Get, Method::Put])
+            .allowed_headers(&["content-type", "x-token"])
+    }
+
+    #[test]
+    fn preflight() {
+        let response = cors()
+            .preflight(&parse(
+                "OPTIONS /api HTTP/1.1\r\nOrigin: https://example.com\r\nAccess-Control-Request-Method: PUT\r\n\r\n",
+            ))
+            .unwrap();
+
+        assert_eq!(response.status, 204);
+        assert_eq!(
+            response.headers.get("Access-Control-Allow-Origin"),
+            Some("https://example.com")
+        );
+        assert_eq!(
+            response.headers.get("Access-Control-Allow-Methods"),
+            Some("GET, PUT")
+        );
+        assert_eq!(
+            response.headers.get("Access-Control-Allow-Headers"),
+            Some("content-type, x-token")
+        );
+        assert_eq!(response.headers.get("Vary"), Some("Origin"));
+    }
+
+    #[test]
+    fn preflight_from_another_origin() {
+        let response = cors()
+            .preflight(&parse(
+                "OPTIONS /api HTTP/1.1\r\nOrigin: https://evil.com\r\nAccess-Control-Request-Method: PUT\r\n\r\n",
+            ))
+            .unwrap();
+
+        assert_eq!(response.status, 204);
+        assert_eq!(response.headers.get("Access-Control-Allow-Origin"), None);
+        assert_eq!(response.headers.get("Access-Control-Allow-Methods"), None);
+        assert_eq!(response.headers.get("Vary"), Some("Origin"));
+    }
+
+    #[test]
+    fn not_a_preflight() {
+        assert!(cors()
+            .preflight(&parse(
+                "GET /api HTTP/1.1\r\nOrigin: https://example.com\r\n\r\n"
+            ))
+            .is_none());
+        // an OPTIONS request without Access-Control-Request-Method is an
+        // ordinary one
+        assert!(cors()
+            .preflight(&parse(
+                "OPTIONS /api HTTP/1.1\r\nOrigin: https://example.com\r\n\r\n"
+            ))
+            .is_none());
+    }
+
+    #[test]
+    fn simple_get() {
+        let request = parse("GET /api HTTP/1.1\r\nOrigin: https://example.com\r\n\r\n");
+        let response = cors().apply(&request, Response::new(200));
+        assert_eq!(
+            response.headers.get("Access-Control-Allow-Origin"),
+            Some("https://example.com")
+        );
+        assert_eq!(response.headers.get("Vary"), Some("Origin"));
+
+        let response = Cors::new(AllowedOrigins::Any).apply(&request, Response::new(200));
+        assert_eq!(
+            response.headers.get("Access-Control-Allow-Origin"),
+            Some("*")
+        );
+        assert_eq!(response.headers.get("Vary"), None);
+
+        let request = parse("GET /api HTTP/1.1\r\n\r\n");
+        let response = cors().apply(&request, Response::new(200));
+        assert_eq!(response.headers.get("Access-Control-Allow-Origin"), None);
+        // the response still depends on the Origin header, one sent along
+        // would've changed it
+        assert_eq!(response.headers.get("Vary"), Some("Origin"));
+
+        let request = parse("GET /api HTTP/1.1\r\nOrigin: https://evil.com\r\n\r\n");
+        let response = cors().apply(&request, Response::new(200));
+        assert_eq!(response.headers.get("Access-Control-Allow-Origin"), None);
+        assert_eq!(response.headers.get("Vary"), Some("Origin"));
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{queue::Job, Shared, SLEEP_BACKOFF};
+use std::{
+    io,
+    sync::{
+        atomic::{self, AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+};
+
+// thread that hands the jobs of the pool's queue out to the workers one at a
+// time in turn, see DispatchMode::RoundRobin, the workers only take jobs out
+// of their own mailboxes meanwhile
+//
+// the dispatcher sleeps while there's no job to hand out or no worker to
+// take it, it's woken up the same way workers are(see Shared::wake_one()),
+// by whoever queued a job or emptied a mailbox, once they see it asleep
+pub(crate) struct Dispatcher {
+    asleep: AtomicBool,
+    lock: Mutex<()>,
+    wake: Condvar,
+}
+
+impl Dispatcher {
+    pub(crate) fn new() -> Dispatcher {
+        Dispatcher {
+            asleep: AtomicBool::new(false),
+            lock: Mutex::new(()),
+            wake: Condvar::new(),
+        }
+    }
+
+    // wakes the dispatcher up if it's asleep, called once a job was queued
+    // or a mailbox emptied
+    pub(crate) fn notify(&self) {
+        // pairs with the fence in run(), either the dispatcher going to
+        // sleep sees the change or this sees it asleep
+        atomic::fence(Ordering::SeqCst);
+
+        if !self.asleep.load(Ordering::Relaxed) {
+            return;
+        }
+
+        // taking the lock makes sure it's waiting already rather than about
+        // to, it holds the lock from checking for work till then
+        let _lock = self
+            .lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.wake.notify_one();
+    }
+}
+
+pub(crate) fn spawn(shared: Arc<Shared>) -> io::Result<thread::JoinHandle<()>> {
+    thread::Builder::new()
+        .name(format!("{}-dispatcher", shared.name))
+        .spawn(move || run(&shared))
+}
+
+fn run(shared: &Shared) {
+    let Some(dispatcher) = &shared.dispatcher else {
+        return;
+    };
+
+    // the job taken off the queue that no worker was free to take yet,
+    // still counted as queued
+    let mut held: Option<Job> = None;
+    // the worker to try first for the next job
+    let mut next = 0;
+
+    // the workers take jobs from the queue themselves once the pool shuts
+    // down, see Shared::next_job(), they're the ones that drain it
+    // rounds in a row without handing a job out
+    let mut idle_rounds = 0;
+
+    while !shared.closed.load(Ordering::Acquire) {
+        if held.is_none() {
+            held = shared
+                .pop_overflow()
+                .or_else(|| shared.classes.pop(&*shared.queue));
+        }
+
+        if let Some(job) = held.take() {
+            match hand_out(shared, job, &mut next) {
+                Ok(()) => {
+                    idle_rounds = 0;
+                    continue;
+                }
+                Err(job) => held = Some(job),
+            }
+
+            // every worker is busy, a worker started on demand takes the
+            // job right away, see ThreadPoolBuilder::max_threads()
+            shared.grow();
+
+            if let Some(job) = held.take() {
+                match hand_out(shared, job, &mut next) {
+                    Ok(()) => {
+                        idle_rounds = 0;
+                        continue;
+                    }
+                    Err(job) => held = Some(job),
+                }
+            }
+        }
+
+        // gives the workers and whoever is submitting jobs a chance to
+        // catch up first, same as the workers do, see SLEEP_BACKOFF
+        if idle_rounds < SLEEP_BACKOFF {
+            idle_rounds += 1;
+            thread::yield_now();
+            continue;
+        }
+
+        let lock = dispatcher
+            .lock
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        dispatcher.asleep.store(true, Ordering::SeqCst);
+        atomic::fence(Ordering::SeqCst);
+
+        // checked one last time after being counted as asleep, a job queued
+        // or a mailbox emptied after this finds the dispatcher asleep
+        let work = match held {
+            Some(_) => any_worker_free(shared),
+            None => shared.has_queued_jobs(),
+        };
+
+        if !work && !shared.closed.load(Ordering::Acquire) {
+            let _lock = dispatcher
+                .wake
+                .wait(lock)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+
+        dispatcher.asleep.store(false, Ordering::SeqCst);
+        idle_rounds = 0;
+    }
+
+    // the workers drain the overflow before exiting
+    if let Some(job) = held {
+        shared.push_overflow(job);
+    }
+}
+
+// puts `job` into the mailbox of the next worker in turn that's free, idle
+// workers come first, then the ones running a job with nothing waiting for
+// them, a worker with a job already waiting in its mailbox is skipped, hands
+// the job back if every worker is that busy
+fn hand_out(shared: &Shared, job: Job, next: &mut usize) -> Result<(), Job> {
+    let workers = shared.workers.len();
+
+    for idle_only in [true, false] {
+        for offset in 0..workers {
+            let index = (*next + offset) % workers;
+            let worker = &shared.workers[index];
+
+            if idle_only
+                && worker
+                    .current_job
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .is_some()
+            {
+                continue;
+            }
+
+            let mut mailbox = worker
+                .mailbox
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+            // stopped workers' mailboxes are closed
+            if mailbox.closed || !mailbox.jobs.is_empty() {
+                continue;
+            }
+
+            mailbox.jobs.push_back(job);
+            drop(mailbox);
+
+            shared.wake(index);
+            *next = index + 1;
+            return Ok(());
+        }
+    }
+
+    Err(job)
+}
+
+fn any_worker_free(shared: &Shared) -> bool {
+    shared.workers.iter().any(|worker| {
+        let mailbox = worker
+            .mailbox
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        !mailbox.closed && mailbox.jobs.is_empty()
+    })
+}
@@ -0,0 +1,174 @@
+use crate::{Shared, ThreadPool};
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{mpsc, Arc, Mutex},
+    task::{Context, Wake, Waker},
+};
+
+// a future spawned on the pool together with what it needs to put itself
+// back on the queue, there's no reactor or timer here, a task is only ever
+// polled again once something calls wake() on the waker it was polled with
+struct Task {
+    // None once the future has completed
+    future: Mutex<Option<Pin<Box<dyn Future<Output = ()> + Send>>>>,
+    // only shared state, not the pool itself, so a task that is never woken
+    // up again doesn't keep the pool from shutting down
+    shared: Arc<Shared>,
+}
+
+impl Task {
+    // queues a job which polls the future once on whichever worker picks it
+    // up, silently drops the task if the pool is already shutting down
+    fn schedule(self: Arc<Self>) {
+        let task = Arc::clone(&self);
+        let _ = self.shared.submit(Box::new(move || task.poll()), None);
+    }
+
+    fn poll(self: &Arc<Self>) {
+        // the lock is held for the whole poll, a wake() from another thread
+        // in the meantime queues another poll which waits here for this one
+        // to finish instead of polling the same future twice at once, a
+        // poisoned lock means the future panicked and there's nothing left
+        // worth polling
+        let Ok(mut slot) = self.future.lock() else {
+            return;
+        };
+        // a wake() after completion still queues a poll, which finds nothing
+        let Some(mut future) = slot.take() else {
+            return;
+        };
+
+        let waker = Waker::from(Arc::clone(self));
+        let mut cx = Context::from_waker(&waker);
+
+        if future.as_mut().poll(&mut cx).is_pending() {
+            *slot = Some(future);
+        }
+    }
+}
+
+impl Wake for Task {
+    fn wake(self: Arc<Self>) {
+        self.schedule();
+    }
+}
+
+impl ThreadPool {
+    // runs a future on the pool, polling it on whichever worker picks it up
+    // each time its waker is woken, e.g. futures waiting on each other
+    // through channels and wakers handed between them, blocking inside the
+    // future blocks the worker polling it like any other job would
+    //
+    // join() only waits for futures that are queued to be polled, not for
+    // ones waiting to be woken up
+    pub fn spawn_future<F>(&self, future: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let task = Arc::new(Task {
+            future: Mutex::new(Some(Box::pin(future))),
+            shared: Arc::clone(&self.shared),
+        });
+
+        task.schedule();
+    }
+
+    // runs a future on the pool and blocks the calling thread until it
+    // completes, returning its output, mostly meant for tests, calling it
+    // from inside a job of the same pool can deadlock if every worker ends
+    // up blocked here, panics if the future panics
+    pub fn block_on_pool<F>(&self, future: F) -> F::Output
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+
+        self.spawn_future(async move {
+            let _ = sender.send(future.await);
+        });
+
+        // the sender is dropped without sending when the future panics
+        match receiver.recv() {
+            Ok(output) => output,
+            Err(_) => panic!("future panicked while running on the thread pool"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Poll;
+
+    // a value handed from one task to another, waking the receiving task
+    // through the waker it left behind when it found nothing there yet
+    #[derive(Clone, Default)]
+    struct Handoff {
+        slot: Arc<Mutex<(Option<u32>, Option<Waker>)>>,
+    }
+
+    impl Handoff {
+        fn send(&self, value: u32) {
+            let waker = {
+                let mut slot = self.slot.lock().unwrap();
+                slot.0 = Some(value);
+                slot.1.take()
+            };
+
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+
+    impl Future for Handoff {
+        type Output = u32;
+
+        fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+            let mut slot = self.slot.lock().unwrap();
+
+            match slot.0.take() {
+                Some(value) => Poll::Ready(value),
+                None => {
+                    slot.1 = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tasks_handing_off_through_a_waker() {
+        let pool = ThreadPool::new(2);
+        let ping = Handoff::default();
+        let pong = Handoff::default();
+
+        let (ping_rx, pong_tx) = (ping.clone(), pong.clone());
+        pool.spawn_future(async move {
+            let value = ping_rx.await;
+            pong_tx.send(value + 1);
+        });
+
+        let ping_tx = ping.clone();
+        pool.spawn_future(async move {
+            ping_tx.send(41);
+        });
+
+        assert_eq!(pool.block_on_pool(pong), 42);
+    }
+
+    #[test]
+    fn block_on_pool_returns_the_output() {
+        let pool = ThreadPool::new(1);
+        assert_eq!(pool.block_on_pool(async { 6 * 7 }), 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "future panicked")]
+    fn block_on_pool_panics_with_the_future() {
+        let pool = ThreadPool::new(1);
+        pool.block_on_pool(async { panic!("oops") });
+    }
+}
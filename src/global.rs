@@ -0,0 +1,110 @@
+use crate::{PoolCreationError, ThreadPool, ThreadPoolBuilder};
+use std::{error::Error, fmt, sync::OnceLock};
+
+// process-wide pool shared by everything that calls ThreadPool::global(),
+// so that libraries built on top of this crate don't each spin up threads
+// of their own, statics are never dropped, which means Drop for ThreadPool
+// never runs for this pool, its threads just stay blocked waiting for jobs
+// until the process exits and takes them down with it, jobs still queued
+// or running at that point are lost, call ThreadPool::global().join()
+// before returning from main() to let them finish first
+static GLOBAL: OnceLock<ThreadPool> = OnceLock::new();
+
+impl ThreadPool {
+    // returns the process-wide pool, creating it on first use with one
+    // thread per cpu core unless ThreadPool::init_global() configured it
+    pub fn global() -> &'static ThreadPool {
+        GLOBAL.get_or_init(|| {
+            ThreadPoolBuilder::new()
+                .build()
+                .expect("default configuration is always valid")
+        })
+    }
+
+    // configures the process-wide pool, this can only happen once and only
+    // before the first call to ThreadPool::global(), every later call
+    // returns an error and leaves the existing pool untouched
+    pub fn init_global(builder: ThreadPoolBuilder) -> Result<(), GlobalPoolError> {
+        if GLOBAL.get().is_some() {
+            return Err(GlobalPoolError::AlreadyInitialized);
+        }
+
+        let pool = builder.build().map_err(GlobalPoolError::Build)?;
+
+        // another thread could have initialized the pool while this one
+        // was building, in which case the pool built here is dropped
+        GLOBAL
+            .set(pool)
+            .map_err(|_| GlobalPoolError::AlreadyInitialized)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GlobalPoolError {
+    // the global pool was already configured or already used
+    AlreadyInitialized,
+    Build(PoolCreationError),
+}
+
+impl fmt::Display for GlobalPoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GlobalPoolError::AlreadyInitialized => {
+                f.write_str("global thread pool is already initialized")
+            }
+            GlobalPoolError::Build(err) => write!(f, "failed to build global thread pool: {err}"),
+        }
+    }
+}
+
+impl Error for GlobalPoolError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GlobalPoolError::Build(err) => Some(err),
+            GlobalPoolError::AlreadyInitialized => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    };
+
+    // the global pool is shared by every test in the process, tests using
+    // it take turns
+    static GLOBAL_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn init_global_once() {
+        let _lock = GLOBAL_LOCK
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        // an invalid configuration leaves the pool to be initialized still
+        assert_eq!(
+            ThreadPool::init_global(ThreadPoolBuilder::new().num_threads(0)),
+            Err(GlobalPoolError::Build(PoolCreationError::ZeroThreads))
+        );
+
+        let builder = ThreadPoolBuilder::new().name("global").num_threads(2);
+        assert_eq!(ThreadPool::init_global(builder.clone()), Ok(()));
+        assert_eq!(
+            ThreadPool::init_global(builder),
+            Err(GlobalPoolError::AlreadyInitialized)
+        );
+
+        let pool = ThreadPool::global();
+        assert!(std::ptr::eq(pool, ThreadPool::global()));
+        assert_eq!(pool.name(), "global");
+
+        let ran = Arc::new(AtomicBool::new(false));
+        let job_ran = Arc::clone(&ran);
+        pool.execute(move || job_ran.store(true, Ordering::SeqCst));
+        pool.join();
+        assert!(ran.load(Ordering::SeqCst));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::ThreadPoolHandle;
+use std::{
+    fmt,
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+};
+
+// a set of jobs that can be waited for or cancelled together, e.g. every
+// job of one connection, gotten through ThreadPool::group():-
+//
+// let group = pool.group();
+// group.execute(move || send_headers(&stream));
+// group.execute(move || send_body(&stream));
+//
+// // the client went away, no point in running what hasn't started yet
+// group.cancel_pending();
+// group.wait();
+//
+// it can be sent to other threads and shared between them, dropping it
+// doesn't wait for its jobs nor cancel them, they run as if submitted with
+// ThreadPool::execute()
+pub struct JobGroup {
+    handle: ThreadPoolHandle,
+    state: Arc<GroupState>,
+}
+
+struct GroupState {
+    counts: Mutex<GroupCounts>,
+    // notified once the group has nothing left queued or running
+    finished: Condvar,
+}
+
+#[derive(Default)]
+struct GroupCounts {
+    // every job of the group gets the next number
+    next_seq: u64,
+    // jobs numbered below this were cancelled unless they started already
+    cancelled_before: u64,
+    // submitted, not started and not cancelled
+    queued: usize,
+    running: usize,
+}
+
+impl GroupCounts {
+    fn is_finished(&self) -> bool {
+        self.queued == 0 && self.running == 0
+    }
+}
+
+impl JobGroup {
+    pub(crate) fn new(handle: ThreadPoolHandle) -> JobGroup {
+        JobGroup {
+            handle,
+            state: Arc::new(GroupState {
+                counts: Mutex::new(GroupCounts::default()),
+                finished: Condvar::new(),
+            }),
+        }
+    }
+
+    // same as ThreadPool::execute() for a job of the group, panics once the
+    // pool is closed or dropped
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let seq = {
+            let mut counts = self.state.lock();
+            let seq = counts.next_seq;
+            counts.next_seq += 1;
+            counts.queued += 1;
+            seq
+        };
+
+        // dropping the ticket settles the counts whatever happens to the
+        // job, it might panic, or be dropped without running when the pool
+        // shuts down
+        let mut ticket = Ticket {
+            state: Arc::clone(&self.state),
+            seq,
+            started: false,
+        };
+
+        self.handle.execute(move || {
+            if ticket.start() {
+                f();
+            }
+        });
+    }
+
+    // blocks until every job of the group submitted so far has finished or
+    // was cancelled, calling it from inside a job of the group never
+    // returns since that job itself never finishes
+    pub fn wait(&self) {
+        let counts = self.state.lock();
+        let _counts = self
+            .state
+            .finished
+            .wait_while(counts, |counts| !counts.is_finished())
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+
+    // cancels every job of the group that hasn't started yet, returning
+    // how many, jobs already running keep going, the cancelled ones are
+    // dropped without running once a worker gets to them, but wait() stops
+    // waiting for them right away, jobs submitted after this run as usual
+    pub fn cancel_pending(&self) -> usize {
+        let mut counts = self.state.lock();
+        let cancelled = counts.queued;
+
+        counts.queued = 0;
+        counts.cancelled_before = counts.next_seq;
+
+        if counts.is_finished() {
+            self.state.finished.notify_all();
+        }
+
+        cancelled
+    }
+}
+
+impl fmt::Debug for JobGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counts = self.state.lock();
+
+        f.debug_struct("JobGroup")
+            .field("queued", &counts.queued)
+            .field("running", &counts.running)
+            .finish()
+    }
+}
+
+impl GroupState {
+    // doesn't unwrap the lock, tickets are dropped while unwinding from a
+    // panicking job
+    fn lock(&self) -> MutexGuard<'_, GroupCounts> {
+        self.counts
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// a job's place in its group's counts
+struct Ticket {
+    state: Arc<GroupState>,
+    seq: u64,
+    started: bool,
+}
+
+impl Ticket {
+    // moves the job from queued to running, false if it was cancelled
+    fn start(&mut self) -> bool {
+        let mut counts = self.state.lock();
+
+        if self.seq < counts.cancelled_before {
+            return false;
+        }
+
+        counts.queued -= 1;
+        counts.running += 1;
+        self.started = true;
+        true
+    }
+}
+
+impl Drop for Ticket {
+    fn drop(&mut self) {
+        let mut counts = self.state.lock();
+
+        if self.started {
+            counts.running -= 1;
+        } else if self.seq >= counts.cancelled_before {
+            // dropped without ever running, cancelled ones aren't counted
+            counts.queued -= 1;
+        }
+
+        if counts.is_finished() {
+            self.state.finished.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ThreadPool;
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc, Arc,
+        },
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn cancel_pending_jobs() {
+        let pool = ThreadPool::new(1);
+        let group = pool.group();
+        let ran = Arc::new(AtomicUsize::new(0));
+        let (started, running) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+
+        let counted = Arc::clone(&ran);
+        group.execute(move || {
+            started.send(()).unwrap();
+            released.recv().unwrap();
+            counted.fetch_add(1, Ordering::SeqCst);
+        });
+        for _ in 0..3 {
+            let counted = Arc::clone(&ran);
+            group.execute(move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        running.recv().unwrap();
+
+        assert_eq!(group.cancel_pending(), 3);
+        release.send(()).unwrap();
+        group.wait();
+        pool.join();
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+
+        // nothing is left to wait for
+        let waited = Instant::now();
+        group.wait();
+        assert!(waited.elapsed() < Duration::from_millis(100));
+        assert_eq!(group.cancel_pending(), 0);
+    }
+
+    #[test]
+    fn jobs_of_a_dropped_group_still_run() {
+        let pool = ThreadPool::new(2);
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        let group = pool.group();
+        for _ in 0..5 {
+            let counted = Arc::clone(&ran);
+            group.execute(move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        drop(group);
+
+        pool.join();
+        assert_eq!(ran.load(Ordering::SeqCst), 5);
+    }
+}
@@ -0,0 +1,207 @@
+use crate::{context, PoolError, Rejected, Shared};
+use std::{
+    fmt,
+    sync::{Arc, Weak},
+};
+
+// submits jobs to a pool from anywhere without a reference to the pool, e.g.
+// from modules and threads that only ever submit jobs, gotten through
+// ThreadPool::handle() and cloned as often as needed:-
+//
+// let handle = pool.handle();
+// thread::spawn(move || handle.execute(|| println!("from another thread")));
+//
+// unlike a Handle(see WorkerContext) it can be sent to other threads and
+// waits for room in a full queue the same way ThreadPool::execute() does,
+// a handle doesn't keep the pool alive, the pool still shuts down once the
+// ThreadPool itself is dropped, jobs submitted through its handles after
+// that aren't taken
+#[derive(Clone)]
+pub struct ThreadPoolHandle {
+    shared: Weak<Shared>,
+    // kept here as well so that it's still known once the pool is gone
+    name: Arc<str>,
+}
+
+impl ThreadPoolHandle {
+    pub(crate) fn new(shared: Weak<Shared>, name: Arc<str>) -> ThreadPoolHandle {
+        ThreadPoolHandle { shared, name }
+    }
+
+    // name of the pool, see ThreadPool::name()
+    pub fn pool_name(&self) -> &str {
+        &self.name
+    }
+
+    // whether the pool stopped taking jobs from the calling thread, i.e. it
+    // was closed(ThreadPool::close()), is shutting down or is gone already,
+    // execute() panics from then on and try_execute() returns
+    // PoolError::ShutDown, e.g. for a thread that keeps submitting jobs for
+    // as long as the pool is around:-
+    //
+    // while !handle.is_shut_down() {
+    //     let request = next_request();
+    //     let _ = handle.try_execute(move || answer(request));
+    // }
+    //
+    // the pool can still shut down right after it returned false, hence
+    // try_execute() rather than execute(), a ThreadPool can't tell this
+    // about itself, shutting it down takes the ThreadPool, see
+    // ThreadPool::is_closed() for closing it
+    pub fn is_shut_down(&self) -> bool {
+        match self.shared.upgrade() {
+            Some(shared) => !shared.takes_jobs(context::is_worker_of(&shared)),
+            None => true,
+        }
+    }
+
+    // same as ThreadPool::execute(), panics once the pool is closed(see
+    // ThreadPool::close()) or dropped
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if let Err(err) = self.try_execute(f) {
+            panic!("{err}");
+        }
+    }
+
+    // same as execute() but returns an error instead of panicking once the
+    // pool is closed or dropped, see ThreadPool::try_execute()
+    pub fn try_execute<F>(&self, f: F) -> Result<(), PoolError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let shared = self.shared.upgrade().ok_or(PoolError::ShutDown)?;
+
+        shared.execute(Box::new(f))
+    }
+
+    // same as try_execute() but hands the job back when the pool doesn't
+    // take it, see ThreadPool::try_execute_returning()
+    pub fn try_execute_returning<F>(&self, f: F) -> Result<(), Rejected<F>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match self.shared.upgrade() {
+            Some(shared) => shared.execute_returning(f),
+            None => Err(Rejected {
+                error: PoolError::ShutDown,
+                job: f,
+            }),
+        }
+    }
+}
+
+impl fmt::Debug for ThreadPoolHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThreadPoolHandle")
+            .field("pool", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{PoolError, ThreadPool};
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn cloned_handle_submits_from_another_thread() {
+        let pool = ThreadPool::new(2);
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        let handle = pool.handle().clone();
+        let counted = Arc::clone(&ran);
+        thread::spawn(move || {
+            for _ in 0..10 {
+                let counted = Arc::clone(&counted);
+                handle.execute(move || {
+                    counted.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        })
+        .join()
+        .unwrap();
+
+        pool.join();
+        assert_eq!(ran.load(Ordering::SeqCst), 10);
+    }
+
+    #[test]
+    fn handle_outliving_the_pool() {
+        let pool = ThreadPool::new(2);
+        let handle = pool.handle();
+
+        assert_eq!(handle.try_execute(|| {}), Ok(()));
+        drop(pool);
+        assert_eq!(handle.try_execute(|| {}), Err(PoolError::ShutDown));
+
+        let ran = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&ran);
+        let rejected = handle
+            .try_execute_returning(move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+            })
+            .unwrap_err();
+        assert_eq!(rejected.error, PoolError::ShutDown);
+        (rejected.job)();
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn shut_down_seen_through_a_handle() {
+        let pool = ThreadPool::new(2);
+        let handle = pool.handle();
+
+        assert!(!handle.is_shut_down());
+        pool.shutdown();
+        assert!(handle.is_shut_down());
+
+        // closing it is enough, nothing is taken from outside anymore
+        let pool = ThreadPool::new(1);
+        let handle = pool.handle();
+        pool.close();
+        assert!(handle.is_shut_down());
+        assert_eq!(handle.try_execute(|| {}), Err(PoolError::ShutDown));
+
+        // a thread submitting jobs stops once the pool is shut down instead
+        // of panicking on execute()
+        let pool = ThreadPool::new(2);
+        let handle = pool.handle();
+        let ran = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&ran);
+        let producer = thread::spawn(move || {
+            let mut submitted = 0;
+
+            while !handle.is_shut_down() {
+                let counted = Arc::clone(&counted);
+                if handle
+                    .try_execute(move || {
+                        counted.fetch_add(1, Ordering::SeqCst);
+                    })
+                    .is_ok()
+                {
+                    submitted += 1;
+                }
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            submitted
+        });
+
+        thread::sleep(Duration::from_millis(50));
+        pool.shutdown();
+        let submitted = producer.join().unwrap();
+        assert!(submitted > 0);
+        // every job taken before that still ran
+        assert_eq!(ran.load(Ordering::SeqCst), submitted);
+    }
+}
@@ -0,0 +1,3007 @@
+use crate::mime;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    error::Error,
+    fmt,
+    fs::File,
+    io::{self, prelude::*},
+    net::SocketAddr,
+    path::Path,
+    str::{self, Utf8Error},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+// http methods the server knows about, a request with any other method
+// is rejected while parsing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Options,
+    Patch,
+}
+
+impl Method {
+    fn parse(token: &str) -> Option<Method> {
+        // method names are case-sensitive, `get` is not the same as `GET`
+        match token {
+            "GET" => Some(Method::Get),
+            "HEAD" => Some(Method::Head),
+            "POST" => Some(Method::Post),
+            "PUT" => Some(Method::Put),
+            "DELETE" => Some(Method::Delete),
+            "OPTIONS" => Some(Method::Options),
+            "PATCH" => Some(Method::Patch),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Options => "OPTIONS",
+            Method::Patch => "PATCH",
+        }
+    }
+}
+
+impl fmt::Display for Method {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpVersion {
+    Http10,
+    Http11,
+}
+
+impl HttpVersion {
+    fn parse(token: &str) -> Option<HttpVersion> {
+        match token {
+            "HTTP/1.0" => Some(HttpVersion::Http10),
+            "HTTP/1.1" => Some(HttpVersion::Http11),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HttpVersion::Http10 => "HTTP/1.0",
+            HttpVersion::Http11 => "HTTP/1.1",
+        }
+    }
+}
+
+// list of header name and value pairs, kept in the order they were added,
+// header names are case-insensitive(`Content-Length` and `content-length`
+// are the same header) so every lookup ignores case, the same header can
+// show up multiple times(`Set-Cookie` for example), so nothing is merged
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HeaderMap {
+    entries: Vec<(String, String)>,
+}
+
+impl HeaderMap {
+    pub fn new() -> HeaderMap {
+        HeaderMap::default()
+    }
+
+    // value of the first header with the given name
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // values of every header with the given name, in order
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    // adds a header, keeping any existing headers with the same name
+    pub fn append(&mut self, name: &str, value: &str) {
+        self.entries.push((name.to_owned(), value.to_owned()));
+    }
+
+    // replaces every existing header with the same name
+    pub fn insert(&mut self, name: &str, value: &str) {
+        self.remove(name);
+        self.append(name, value);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.entries
+            .retain(|(key, _)| !key.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    // reading from the connection failed
+    Io(io::Error),
+    // the connection was closed before a request line was sent
+    Empty,
+    // request line is not of the format <method> <target> <version>
+    MalformedRequestLine,
+    // the method is well-formed but not one the server implements
+    UnsupportedMethod(String),
+    UnsupportedVersion(String),
+    // a header line without a `:` or with an empty name
+    MalformedHeader(String),
+    // Content-Length header which is not a non-negative number, several of
+    // them that don't agree on one, or one sent along with a
+    // Transfer-Encoding
+    InvalidContentLength(String),
+    // a POST, PUT or PATCH without a Content-Length or a chunked body, there
+    // is no telling where its body ends
+    LengthRequired(Method),
+    // Transfer-Encoding other than chunked, the only one the server decodes
+    UnsupportedTransferEncoding(String),
+    // a chunk of a chunked body whose size line isn't a hex number or which
+    // isn't followed by a line break
+    MalformedChunk(String),
+    // a chunk size line of a chunked body longer than
+    // ParseLimits::max_header_line_length, extensions included, the rest of
+    // it is left unread
+    ChunkLineTooLong(usize),
+    // a request line longer than ParseLimits::max_request_line_length, which
+    // is down to the target in all but the most contrived requests, the rest
+    // of it is left unread
+    UriTooLong(usize),
+    // a header line longer than ParseLimits::max_header_line_length, the
+    // rest of it is left unread
+    HeaderLineTooLong(usize),
+    // more header lines than ParseLimits::max_headers
+    TooManyHeaders(usize),
+    // a header section larger than ParseLimits::max_header_size
+    HeadersTooLarge(usize),
+    // the body is bigger than the limit it was read with, MAX_BODY_SIZE
+    // unless a route says otherwise, see Request::read_body_limited()
+    BodyTooLarge(u64),
+    // a path that isn't correctly percent-encoded or has control characters
+    // in it once decoded, see normalize_path()
+    InvalidPath(String),
+    // an Expect header asking for anything but `100-continue`, the only
+    // expectation there is
+    UnsupportedExpectation(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(err) => write!(f, "failed to read request: {err}"),
+            ParseError::Empty => f.write_str("connection closed before a request was sent"),
+            ParseError::MalformedRequestLine => f.write_str("malformed request line"),
+            ParseError::UnsupportedMethod(method) => write!(f, "unsupported method {method:?}"),
+            ParseError::UnsupportedVersion(version) => {
+                write!(f, "unsupported http version {version:?}")
+            }
+            ParseError::MalformedHeader(line) => write!(f, "malformed header line {line:?}"),
+            ParseError::InvalidContentLength(value) => {
+                write!(f, "invalid Content-Length {value:?}")
+            }
+            ParseError::LengthRequired(method) => {
+                write!(f, "{method} request without a Content-Length")
+            }
+            ParseError::UnsupportedTransferEncoding(value) => {
+                write!(f, "unsupported Transfer-Encoding {value:?}")
+            }
+            ParseError::MalformedChunk(line) => write!(f, "malformed chunk {line:?}"),
+            ParseError::ChunkLineTooLong(limit) => {
+                write!(f, "chunk size line is longer than {limit} bytes")
+            }
+            ParseError::UriTooLong(limit) => {
+                write!(f, "request line is longer than {limit} bytes")
+            }
+            ParseError::HeaderLineTooLong(limit) => {
+                write!(f, "header line is longer than {limit} bytes")
+            }
+            ParseError::TooManyHeaders(limit) => write!(f, "more than {limit} header lines"),
+            ParseError::HeadersTooLarge(limit) => {
+                write!(f, "header section is larger than {limit} bytes")
+            }
+            ParseError::BodyTooLarge(limit) => {
+                write!(f, "request body is larger than {limit} bytes")
+            }
+            ParseError::InvalidPath(path) => write!(f, "invalid path {path:?}"),
+            ParseError::UnsupportedExpectation(value) => {
+                write!(f, "unsupported Expect {value:?}")
+            }
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> ParseError {
+        ParseError::Io(err)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: Method,
+    // the request target without the query string, percent-decoded and
+    // normalized, see normalize_path()
+    pub path: String,
+    pub version: HttpVersion,
+    pub headers: HeaderMap,
+    // the request target as sent, see target()
+    target: String,
+    // normalized the same way as `path` but still percent-encoded, which is
+    // what routes are matched against, see Router::route()
+    raw_path: String,
+    // everything after the first `?` of the target, see query()
+    query: String,
+    cookies: HashMap<String, String>,
+    body: Vec<u8>,
+    // segments of the path captured by the route the request was matched
+    // to, see Router::route()
+    params: HashMap<String, Param>,
+    // see peer_addr()
+    peer: Option<SocketAddr>,
+}
+
+impl Request {
+    // reads the request line and every header line up to the empty line
+    // that ends the header section, the format of the request is:-
+    //
+    // <http_method> <route_segment> <http_version>
+    // <header_name>: <header_value>
+    // ...
+    //
+    // <request_body>
+    //
+    // and then the body, see parse_head() for reading the body separately,
+    // the request line and the headers are limited the ParseLimits::default()
+    // way
+    pub fn parse(reader: &mut impl BufRead) -> Result<Request, ParseError> {
+        Request::parse_limited(reader, &ParseLimits::default())
+    }
+
+    // same as parse() but reading no more of the request line and the header
+    // section than `limits` allow
+    pub fn parse_limited(
+        reader: &mut impl BufRead,
+        limits: &ParseLimits,
+    ) -> Result<Request, ParseError> {
+        let mut request = Request::parse_head_limited(reader, limits)?;
+        request.read_body_limited(reader, limits, MAX_BODY_SIZE)?;
+        Ok(request)
+    }
+
+    // same as parse() but stops at the end of the headers, leaving the body
+    // to be read with read_body(), e.g. to answer `Expect: 100-continue`
+    // first, see expects_continue(), the headers describing the body are
+    // checked here already, so a body that would be rejected, e.g. one
+    // larger than MAX_BODY_SIZE, is rejected before the client sends it
+    pub fn parse_head(reader: &mut impl BufRead) -> Result<Request, ParseError> {
+        Request::parse_head_limited(reader, &ParseLimits::default())
+    }
+
+    // same as parse_head() but reading no more of the request line and the
+    // header section than `limits` allow, see ParseLimits
+    pub fn parse_head_limited(
+        reader: &mut impl BufRead,
+        limits: &ParseLimits,
+    ) -> Result<Request, ParseError> {
+        let request_line = match read_limited_line(
+            reader,
+            limits.max_request_line_length,
+            ParseError::UriTooLong,
+        )? {
+            Some(line) => line,
+            None => return Err(ParseError::Empty),
+        };
+
+        let mut parts = request_line.split(' ');
+        let (method, path, version) = match (parts.next(), parts.next(), parts.next(), parts.next())
+        {
+            (Some(method), Some(path), Some(version), None)
+                if !method.is_empty() && !path.is_empty() =>
+            {
+                (method, path, version)
+            }
+            _ => return Err(ParseError::MalformedRequestLine),
+        };
+
+        let method = Method::parse(method)
+            .ok_or_else(|| ParseError::UnsupportedMethod(method.to_owned()))?;
+        let version = HttpVersion::parse(version)
+            .ok_or_else(|| ParseError::UnsupportedVersion(version.to_owned()))?;
+        let target = path.to_owned();
+        let (raw_path, query) = path.split_once('?').unwrap_or((path, ""));
+        // the asterisk-form of `OPTIONS * HTTP/1.1` asks about the server as
+        // a whole rather than about any path, so it's left as it is, it means
+        // nothing with another method
+        let normalized = match raw_path {
+            "*" if method == Method::Options => Some((raw_path.to_owned(), raw_path.to_owned())),
+            "*" => None,
+            _ => normalize_path(raw_path),
+        };
+        let (path, raw_path) =
+            normalized.ok_or_else(|| ParseError::InvalidPath(raw_path.to_owned()))?;
+        let query = query.to_owned();
+
+        let mut headers = HeaderMap::new();
+        let mut header_lines = 0;
+        let mut header_size = 0;
+
+        loop {
+            let line = match read_limited_line(
+                reader,
+                limits.max_header_line_length,
+                ParseError::HeaderLineTooLong,
+            )? {
+                Some(line) => line,
+                // a client which closes the connection half way through
+                // the headers is not sending a complete request
+                None => return Err(ParseError::MalformedRequestLine),
+            };
+
+            // empty line marks the end of the headers
+            if line.is_empty() {
+                break;
+            }
+
+            header_lines += 1;
+            if header_lines > limits.max_headers {
+                return Err(ParseError::TooManyHeaders(limits.max_headers));
+            }
+
+            header_size += line.len() + 2;
+            if header_size > limits.max_header_size {
+                return Err(ParseError::HeadersTooLarge(limits.max_header_size));
+            }
+
+            // a line starting with whitespace continues the value of the
+            // header before it, an obsolete way of folding long headers
+            // over several lines which is still accepted, the line break is
+            // replaced with a single space
+            if line.starts_with([' ', '\t']) {
+                match headers.entries.last_mut() {
+                    Some((_, value)) => {
+                        let continuation = line.trim();
+
+                        if !continuation.is_empty() {
+                            if !value.is_empty() {
+                                value.push(' ');
+                            }
+                            value.push_str(continuation);
+                        }
+                        continue;
+                    }
+                    None => return Err(ParseError::MalformedHeader(line)),
+                }
+            }
+
+            match line.split_once(':') {
+                Some((name, value)) if !name.is_empty() && !name.contains([' ', '\t']) => {
+                    headers.append(name, value.trim());
+                }
+                _ => return Err(ParseError::MalformedHeader(line)),
+            }
+        }
+
+        // the body would otherwise be taken as empty and whatever the client
+        // sends of it as the next request on the connection
+        let sends_body = matches!(method, Method::Post | Method::Put | Method::Patch);
+        let length_known =
+            headers.get("Content-Length").is_some() || headers.get("Transfer-Encoding").is_some();
+
+        if sends_body && !length_known {
+            return Err(ParseError::LengthRequired(method));
+        }
+
+        body_length(&headers)?;
+
+        // an HTTP/1.0 client can't know about expectations, the header is
+        // ignored for one
+        if let Some(expect) = headers.get("Expect") {
+            if version == HttpVersion::Http11 && !expect.eq_ignore_ascii_case("100-continue") {
+                return Err(ParseError::UnsupportedExpectation(expect.to_owned()));
+            }
+        }
+
+        let cookies = parse_cookies(&headers);
+
+        Ok(Request {
+            method,
+            path,
+            version,
+            headers,
+            target,
+            raw_path,
+            query,
+            cookies,
+            body: Vec::new(),
+            params: HashMap::new(),
+            peer: None,
+        })
+    }
+
+    // reads the body of a request read with parse_head(), up to
+    // MAX_BODY_SIZE bytes
+    pub fn read_body(&mut self, reader: &mut impl BufRead) -> Result<(), ParseError> {
+        self.read_body_limited(reader, &ParseLimits::default(), MAX_BODY_SIZE)
+    }
+
+    // same as read_body() but with a limit of `max_body_size` bytes, e.g. a
+    // larger one for an upload route(see Router::route_max_body_size()), a
+    // body announced to be larger is rejected without reading any of it, a
+    // chunked one as soon as its chunks add up to more, the rest of it is
+    // left unread then, so the connection can't be used for another request,
+    // the lines framing a chunked body are limited by `limits`, see
+    // ParseLimits
+    pub fn read_body_limited(
+        &mut self,
+        reader: &mut impl BufRead,
+        limits: &ParseLimits,
+        max_body_size: u64,
+    ) -> Result<(), ParseError> {
+        self.body = read_body(reader, &self.headers, limits, max_body_size)?;
+        Ok(())
+    }
+
+    // fails with BodyTooLarge when the Content-Length is more than
+    // `max_body_size`, checked before reading the body and before telling
+    // a client waiting for it to send the body, see expects_continue()
+    pub fn check_body_size(&self, max_body_size: u64) -> Result<(), ParseError> {
+        match body_length(&self.headers)? {
+            Some(length) if length > max_body_size => Err(ParseError::BodyTooLarge(max_body_size)),
+            _ => Ok(()),
+        }
+    }
+
+    // whether the client is waiting for a `100 Continue` interim response
+    // before sending the body, which clients like curl ask for with
+    // `Expect: 100-continue` before a large upload, it's only worth asking
+    // for when there's a body to send, the client sends it anyway after
+    // waiting for a while without an answer
+    pub fn expects_continue(&self) -> bool {
+        self.version == HttpVersion::Http11
+            && self
+                .headers
+                .get("Expect")
+                .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+            && body_length(&self.headers).is_ok_and(|len| len != Some(0))
+    }
+
+    // user name and password sent in an `Authorization` header using the
+    // Basic scheme, which is the two joined by a `:` and base64 encoded:-
+    //
+    // Authorization: Basic amFuZTpodW50ZXIy
+    //
+    // None when there's no such header or it isn't correctly encoded, the
+    // user name can't contain a `:` but the password can
+    pub fn basic_auth(&self) -> Option<(String, String)> {
+        let (scheme, credentials) = self.headers.get("Authorization")?.trim().split_once(' ')?;
+
+        if !scheme.eq_ignore_ascii_case("Basic") {
+            return None;
+        }
+
+        let decoded = String::from_utf8(base64_decode(credentials.trim())?).ok()?;
+        let (user, password) = decoded.split_once(':')?;
+
+        Some((user.to_owned(), password.to_owned()))
+    }
+
+    // the request target as sent, the path along with the query string,
+    // e.g. `/search?q=rust` for the request line `GET /search?q=rust HTTP/1.1`
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    pub(crate) fn raw_path(&self) -> &str {
+        &self.raw_path
+    }
+
+    // the query string, without the `?` and still encoded, empty when the
+    // target has none, e.g. `q=rust&limit=10` for `/search?q=rust&limit=10`
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    // decoded name and value pairs of the query string, in order, a name
+    // sent more than once shows up once for every time it was sent, `+`
+    // stands for a space and a name without a `=` has an empty value:-
+    //
+    // q=hello+world&tag=a&tag=b&flag => [("q", "hello world"), ("tag", "a"),
+    //                                    ("tag", "b"), ("flag", "")]
+    //
+    // a name or value that isn't correctly encoded is left as it was sent
+    pub fn query_pairs(&self) -> impl Iterator<Item = (Cow<'_, str>, Cow<'_, str>)> {
+        self.query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+
+                (decode_query_component(name), decode_query_component(value))
+            })
+    }
+
+    // decoded value of the first `name` in the query string, see
+    // query_pairs(), None when it's not there
+    pub fn query_param(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.query_pairs()
+            .find(|(pair_name, _)| pair_name == name)
+            .map(|(_, value)| value)
+    }
+
+    // cookies sent by the client in the `Cookie` header(s), keyed by name
+    pub fn cookies(&self) -> &HashMap<String, String> {
+        &self.cookies
+    }
+
+    // the path segment captured as `name` by the route the request was
+    // matched to, percent-decoded, e.g. "7" for `/users/7` matched to
+    // `/users/:id`, None when the route has no such segment
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|param| param.decoded.as_str())
+    }
+
+    // same as param() but as sent, without percent-decoding it, for a rest
+    // of the path captured by `*name` that's the only way to tell `a%2Fb`
+    // from `a/b`
+    pub fn raw_param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|param| param.raw.as_str())
+    }
+
+    pub(crate) fn set_params(&mut self, params: HashMap<String, Param>) {
+        self.params = params;
+    }
+
+    // address of the client the request came from, None for a request that
+    // wasn't read from a connection, e.g. one parsed from a string, or when
+    // the address of the connection couldn't be told, which only happens
+    // once the client is gone already, see server::serve_connection(), it's
+    // the address of whatever connected to the server, a proxy in front of
+    // the server rather than the client behind it
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    pub(crate) fn set_peer_addr(&mut self, peer: Option<SocketAddr>) {
+        self.peer = peer;
+    }
+
+    // raw bytes of the request body, empty when none was sent
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    // the request body as text, an error when it isn't valid UTF-8, e.g.
+    // an uploaded image
+    pub fn body_string(&self) -> Result<&str, Utf8Error> {
+        str::from_utf8(&self.body)
+    }
+
+    // fields of a submitted html form, i.e. a body with the content type
+    // `application/x-www-form-urlencoded`, encoded the same way as a query
+    // string:-
+    //
+    // name=Jane+Doe&city=New%20York
+    //
+    // when a field is sent more than once, the last value wins
+    pub fn form(&self) -> Result<HashMap<String, String>, FormError> {
+        // the media type can be followed by parameters like `; charset=UTF-8`
+        let media_type = self
+            .headers
+            .get("Content-Type")
+            .and_then(|content_type| content_type.split(';').next())
+            .map(str::trim);
+
+        match media_type {
+            Some(media_type) if media_type.eq_ignore_ascii_case(FORM_CONTENT_TYPE) => {}
+            _ => return Err(FormError::WrongContentType),
+        }
+
+        let body = std::str::from_utf8(&self.body).map_err(|_| FormError::InvalidEncoding)?;
+
+        parse_urlencoded(body)
+            .map(|pairs| pairs.into_iter().collect())
+            .ok_or(FormError::InvalidEncoding)
+    }
+
+    // the first of the `available` media types the client accepts, going
+    // by the order of the media ranges in its `Accept` header, e.g. for:-
+    //
+    // Accept: application/json, text/*
+    //
+    // application/json is picked over text/html when both are available,
+    // wildcards like `text/*` and `*/*` match any of the available types
+    // they cover, q-values are ignored for now, a request without an
+    // Accept header accepts anything and gets the first available type
+    pub fn preferred_media_type<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        let Some(accept) = self.headers.get("Accept") else {
+            return available.first().copied();
+        };
+
+        accept
+            .split(',')
+            // parameters, q-values included, follow a `;`
+            .filter_map(|range| range.split(';').next())
+            .map(str::trim)
+            .find_map(|range| {
+                available
+                    .iter()
+                    .copied()
+                    .find(|media_type| media_range_matches(range, media_type))
+            })
+    }
+}
+
+// part of the path captured by a route, both as sent and percent-decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Param {
+    raw: String,
+    decoded: String,
+}
+
+impl Param {
+    // None when `raw` isn't correctly encoded
+    pub(crate) fn new(raw: &str) -> Option<Param> {
+        Some(Param {
+            raw: raw.to_owned(),
+            decoded: percent_decode(raw)?,
+        })
+    }
+}
+
+// the path of a request target percent-decoded and normalized, along with
+// the path normalized the same way but still percent-encoded, normalizing
+// means resolving `.` and `..` segments and dropping empty ones, the ones
+// duplicate slashes leave behind:-
+//
+// /docs/./guide//../intro%20page.html => /docs/intro page.html
+//
+// `..` can't climb past the root, `/../etc` becomes `/etc`, and a trailing
+// slash is kept, `/docs/` is a directory rather than a file, the decoded
+// path is normalized again after decoding, an encoded slash(`%2F`) being a
+// separator there, so it never has a `..` in it either, the encoded path
+// keeps such a segment whole, e.g. `/files/a%2Fb` is 2 segments there
+//
+// None when the path isn't correctly percent-encoded, decodes to something
+// other than UTF-8(overlong sequences included) or has control characters
+// in it, NUL included, a target that isn't a path, like `*`, is only checked
+fn normalize_path(raw: &str) -> Option<(String, String)> {
+    let decoded = percent_decode(raw)?;
+
+    if decoded.contains(char::is_control) {
+        return None;
+    }
+
+    if !raw.starts_with('/') {
+        return Some((decoded, raw.to_owned()));
+    }
+
+    // every segment decodes fine, the whole path did
+    let raw_segments = raw
+        .split('/')
+        .map(|segment| (segment, percent_decode(segment).unwrap_or_default()));
+    let decoded_segments = decoded
+        .split('/')
+        .map(|segment| (segment, segment.to_owned()));
+
+    Some((
+        remove_dot_segments(decoded_segments),
+        remove_dot_segments(raw_segments),
+    ))
+}
+
+// joins the segments of a path, given both as they're to be joined and
+// decoded, after resolving `.` and `..` and dropping empty segments, see
+// normalize_path()
+fn remove_dot_segments<'a>(segments: impl Iterator<Item = (&'a str, String)>) -> String {
+    let mut kept: Vec<&str> = Vec::new();
+    // a path ending in a directory, like `/docs/`, `/docs/.` or `/docs/a/..`
+    let mut trailing_slash = false;
+
+    // the first segment is the empty one in front of the leading slash
+    for (segment, decoded) in segments.skip(1) {
+        trailing_slash = true;
+
+        match decoded.as_str() {
+            "" | "." => {}
+            ".." => {
+                kept.pop();
+            }
+            _ => {
+                kept.push(segment);
+                trailing_slash = false;
+            }
+        }
+    }
+
+    let mut path = format!("/{}", kept.join("/"));
+
+    if trailing_slash && !kept.is_empty() {
+        path.push('/');
+    }
+
+    path
+}
+
+// whether a media range from an Accept header, like `text/*`, covers
+// `media_type`
+fn media_range_matches(range: &str, media_type: &str) -> bool {
+    let (Some((range_type, range_subtype)), Some((type_, subtype))) =
+        (range.split_once('/'), media_type.split_once('/'))
+    else {
+        return false;
+    };
+
+    (range_type == "*" || range_type.eq_ignore_ascii_case(type_))
+        && (range_subtype == "*" || range_subtype.eq_ignore_ascii_case(subtype))
+}
+
+const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+
+// media types Response::error() can describe an error in, in the order they
+// are preferred when the client accepts several of them equally
+const ERROR_MEDIA_TYPES: [&str; 3] = ["text/html", "application/json", "text/plain"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormError {
+    // the request body is not a urlencoded form
+    WrongContentType,
+    // a field contains an invalid escape sequence or isn't valid UTF-8
+    InvalidEncoding,
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormError::WrongContentType => {
+                write!(f, "request body is not of type {FORM_CONTENT_TYPE}")
+            }
+            FormError::InvalidEncoding => f.write_str("request body is not correctly urlencoded"),
+        }
+    }
+}
+
+impl Error for FormError {}
+
+// most bytes a request body can have by default, whether its length is
+// sent upfront or only known once the last chunk of a chunked body is read,
+// see Router::max_body_size() for another limit
+pub const MAX_BODY_SIZE: u64 = 8 * 1024 * 1024;
+
+// how much of the request line and the header section of a request is read
+// before giving up on it, both are kept in memory, without a limit a client
+// could keep sending a line without ever ending it, or header lines, for as
+// long as it likes, a request line going past its limit is answered with a
+// 414 and headers going past any of theirs with a 431, see
+// Request::parse_head_limited()
+//
+// the lines of a chunked body other than the chunks themselves are limited
+// the same way, a chunk size line to max_header_line_length(a 400 past it)
+// and the trailer section to the limits of the header section, counted on
+// its own, see Request::read_body_limited()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    // most bytes of the request line, line break aside
+    pub max_request_line_length: usize,
+    // most bytes of a single header line, line break aside, every line of
+    // a header folded over several lines counts on its own
+    pub max_header_line_length: usize,
+    // most header lines, a header sent twice counts twice
+    pub max_headers: usize,
+    // most bytes of the header lines put together, line breaks included,
+    // the request line aside
+    pub max_header_size: usize,
+}
+
+impl Default for ParseLimits {
+    fn default() -> ParseLimits {
+        ParseLimits {
+            max_request_line_length: 8 * 1024,
+            max_header_line_length: 8 * 1024,
+            max_headers: 100,
+            max_header_size: 64 * 1024,
+        }
+    }
+}
+
+// the length of the body the headers announce, 0 without a body, None for
+// a chunked body, whose length isn't known till it's read, fails for a body
+// that can't be read, its size is checked once the limit is known, see
+// Request::check_body_size()
+fn body_length(headers: &HeaderMap) -> Result<Option<u64>, ParseError> {
+    if headers.contains("Transfer-Encoding") {
+        // every Transfer-Encoding header counts, the codings of all of them
+        // put together have to be just chunked
+        let transfer_encoding = headers
+            .get_all("Transfer-Encoding")
+            .map(str::trim)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        if !transfer_encoding.eq_ignore_ascii_case("chunked") {
+            return Err(ParseError::UnsupportedTransferEncoding(transfer_encoding));
+        }
+
+        // a proxy in front of the server going by the Content-Length while
+        // the server decodes the chunks would take what's left of the body
+        // for a request of its own(request smuggling), so a request sending
+        // both is rejected rather than guessed at
+        if let Some(content_length) = headers.get("Content-Length") {
+            return Err(ParseError::InvalidContentLength(content_length.to_owned()));
+        }
+
+        return Ok(None);
+    }
+
+    // a Content-Length sent several times, or as a list, is fine as long as
+    // every value is the same, a proxy in front of the server could go by
+    // another one than the server otherwise, and take whatever is left of
+    // the body for a request of its own(request smuggling)
+    let mut length = None;
+
+    for value in headers
+        .get_all("Content-Length")
+        .flat_map(|header| header.split(','))
+    {
+        let value = value.trim();
+        let invalid = || ParseError::InvalidContentLength(value.to_owned());
+
+        // parse() would accept a leading `+` as well
+        if value.is_empty() || !value.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(invalid());
+        }
+
+        let value = value.parse::<u64>().map_err(|_| invalid())?;
+        if length.is_some_and(|length| length != value) {
+            return Err(ParseError::InvalidContentLength(
+                headers
+                    .get_all("Content-Length")
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ));
+        }
+        length = Some(value);
+    }
+
+    Ok(Some(length.unwrap_or(0)))
+}
+
+// reads exactly as many bytes as the Content-Length header says, no header
+// means no body, the bytes are read through take() instead of into a buffer
+// allocated upfront, so a client lying about a huge length can't make the
+// server allocate it all before sending anything, a body sent with
+// `Transfer-Encoding: chunked` is read chunk by chunk instead, see
+// read_chunked_body(), a Content-Length sent along with it fails the
+// request, see body_length(), a body larger than `max_body_size` fails without reading any of it
+fn read_body(
+    reader: &mut impl BufRead,
+    headers: &HeaderMap,
+    limits: &ParseLimits,
+    max_body_size: u64,
+) -> Result<Vec<u8>, ParseError> {
+    let Some(content_length) = body_length(headers)? else {
+        return read_chunked_body(reader, limits, max_body_size);
+    };
+
+    if content_length > max_body_size {
+        return Err(ParseError::BodyTooLarge(max_body_size));
+    }
+
+    if content_length == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut body = Vec::new();
+    reader.take(content_length).read_to_end(&mut body)?;
+
+    if (body.len() as u64) < content_length {
+        return Err(ParseError::Io(io::ErrorKind::UnexpectedEof.into()));
+    }
+
+    Ok(body)
+}
+
+// decodes a body sent as a series of chunks, each one its size in hex
+// followed by that many bytes, up to a chunk of size 0:-
+//
+// <chunk_size_in_hex>[;<extension>]
+// <chunk>
+// ...
+// 0
+// [<trailer_name>: <trailer_value>]
+//
+// each line ending with \r\n, chunk extensions and trailers are read but
+// ignored, the size of the body is checked against `max_body_size` before
+// every chunk is read, so a client can't get around it by sending many
+// small chunks, nor with endless chunk size lines or trailers, those are
+// limited by `limits`, see ParseLimits
+fn read_chunked_body(
+    reader: &mut impl BufRead,
+    limits: &ParseLimits,
+    max_body_size: u64,
+) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+    let read_chunk_line = |reader: &mut _| {
+        read_limited_line(
+            reader,
+            limits.max_header_line_length,
+            ParseError::ChunkLineTooLong,
+        )
+    };
+
+    loop {
+        let Some(line) = read_chunk_line(reader)? else {
+            return Err(ParseError::Io(io::ErrorKind::UnexpectedEof.into()));
+        };
+        let size = line.split(';').next().unwrap_or_default().trim();
+
+        // from_str_radix() would accept a leading `+` as well
+        if size.is_empty() || !size.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(ParseError::MalformedChunk(line));
+        }
+
+        let Ok(size) = u64::from_str_radix(size, 16) else {
+            // too many digits for a u64, which is way past the limit anyway
+            return Err(ParseError::BodyTooLarge(max_body_size));
+        };
+
+        if size == 0 {
+            break;
+        }
+
+        // the body never gets past the limit, so this can't underflow, the
+        // sum could overflow for a chunk as large as a u64 gets
+        if size > max_body_size - body.len() as u64 {
+            return Err(ParseError::BodyTooLarge(max_body_size));
+        }
+
+        let read = reader.take(size).read_to_end(&mut body)?;
+
+        if (read as u64) < size {
+            return Err(ParseError::Io(io::ErrorKind::UnexpectedEof.into()));
+        }
+
+        // every chunk ends with a line break of its own
+        match read_chunk_line(reader)? {
+            Some(line) if line.is_empty() => {}
+            Some(line) => return Err(ParseError::MalformedChunk(line)),
+            None => return Err(ParseError::Io(io::ErrorKind::UnexpectedEof.into())),
+        }
+    }
+
+    // the trailer section ends with an empty line just like the headers,
+    // and is limited just like them
+    let mut trailer_lines = 0;
+    let mut trailer_size = 0;
+
+    loop {
+        let line = match read_limited_line(
+            reader,
+            limits.max_header_line_length,
+            ParseError::HeaderLineTooLong,
+        )? {
+            Some(line) if line.is_empty() => return Ok(body),
+            Some(line) => line,
+            None => return Err(ParseError::Io(io::ErrorKind::UnexpectedEof.into())),
+        };
+
+        trailer_lines += 1;
+        if trailer_lines > limits.max_headers {
+            return Err(ParseError::TooManyHeaders(limits.max_headers));
+        }
+
+        trailer_size += line.len() + 2;
+        if trailer_size > limits.max_header_size {
+            return Err(ParseError::HeadersTooLarge(limits.max_header_size));
+        }
+    }
+}
+
+// reads a single line terminated by \r\n(or a bare \n, which some clients
+// send), without the line terminator, None if the connection is closed,
+// stops reading once the line turns out to be longer than `max_length`
+// bytes instead of buffering whatever the client sends until it sends a
+// line break, failing with `too_long` of the limit then, see ParseLimits
+fn read_limited_line(
+    reader: &mut impl BufRead,
+    max_length: usize,
+    too_long: fn(usize) -> ParseError,
+) -> Result<Option<String>, ParseError> {
+    // room for the \r\n as well
+    let limit = u64::try_from(max_length.saturating_add(2)).unwrap_or(u64::MAX);
+    let mut line = Vec::new();
+
+    if reader.take(limit).read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+
+    if !line.ends_with(b"\n") && line.len() as u64 == limit {
+        return Err(too_long(max_length));
+    }
+
+    // the same error BufRead::read_line() fails with
+    let mut line = String::from_utf8(line)
+        .map_err(|err| ParseError::Io(io::Error::new(io::ErrorKind::InvalidData, err)))?;
+    trim_line_break(&mut line);
+
+    if line.len() > max_length {
+        return Err(too_long(max_length));
+    }
+
+    Ok(Some(line))
+}
+
+fn trim_line_break(line: &mut String) {
+    if line.ends_with('\n') {
+        line.pop();
+
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+}
+
+// the `Cookie` header is of the format:-
+//
+// Cookie: <name>=<value>; <name>=<value>
+//
+// when the same cookie name is sent twice, the first one wins since clients
+// send cookies with more specific paths first
+fn parse_cookies(headers: &HeaderMap) -> HashMap<String, String> {
+    let mut cookies = HashMap::new();
+
+    for pair in headers
+        .get_all("Cookie")
+        .flat_map(|header| header.split(';'))
+    {
+        if let Some((name, value)) = pair.split_once('=') {
+            let name = name.trim();
+
+            if !name.is_empty() {
+                cookies
+                    .entry(name.to_owned())
+                    .or_insert_with(|| value.trim().to_owned());
+            }
+        }
+    }
+
+    cookies
+}
+
+// optional attributes of a cookie set with Response::set_cookie(), the
+// defaults leave every attribute out:-
+//
+// CookieAttributes {
+//     http_only: true,
+//     max_age: Some(3600),
+//     ..Default::default()
+// }
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CookieAttributes {
+    // hides the cookie from javascript running in the browser
+    pub http_only: bool,
+    // only sends the cookie back over https
+    pub secure: bool,
+    // number of seconds until the cookie expires, 0 deletes it right away
+    pub max_age: Option<u64>,
+    // url path prefix the cookie is sent back for
+    pub path: Option<String>,
+}
+
+// body of a response, either already in memory, a file which is only
+// read while the response is being written, in chunks, so serving a large
+// file doesn't mean loading all of it into memory first, or generated while
+// the response is being written, see Response::streaming()
+pub enum Body {
+    Text(String),
+    // anything that isn't text, e.g. an image generated by a handler, sent
+    // as is
+    Bytes(Vec<u8>),
+    // `len` is taken from the file's metadata when the file is opened, it's
+    // what the Content-Length header is set to
+    File {
+        file: File,
+        len: u64,
+    },
+    // anything else to read the body from, e.g. a file being decompressed on
+    // the fly, sent like a file, in chunks, `len` is what the Content-Length
+    // header is set to, see Response::with_reader()
+    Reader {
+        reader: Mutex<Box<dyn Read + Send>>,
+        len: u64,
+    },
+    // called with a writer sending everything written to it as a chunk of
+    // its own, the length of the body isn't known until it's done
+    Stream(StreamFn),
+}
+
+// writes a streamed body, see Response::streaming()
+pub type StreamFn = Box<dyn Fn(&mut ChunkedWriter<'_>) -> io::Result<()> + Send + Sync>;
+
+impl Body {
+    // the length of the body, None for a streamed body, which is sent with
+    // `Transfer-Encoding: chunked` instead of a Content-Length header
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Body::Text(text) => Some(text.len() as u64),
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::File { len, .. } | Body::Reader { len, .. } => Some(*len),
+            Body::Stream(_) => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        match self {
+            Body::Text(text) => writer.write_all(text.as_bytes()),
+            Body::Bytes(bytes) => writer.write_all(bytes),
+            Body::Stream(write) => {
+                write(&mut ChunkedWriter {
+                    writer: &mut *writer,
+                })?;
+                // the last chunk is an empty one, followed by the empty line
+                // ending the(always empty) trailer section, a body that
+                // failed half way is left without it, so the client can tell
+                // it's incomplete
+                writer.write_all(b"0\r\n\r\n")
+            }
+            Body::File { file, len } => copy_body(file, *len, writer),
+            Body::Reader { reader, len } => {
+                // a reader that panicked half way through a body is done for
+                let mut reader = reader.lock().unwrap_or_else(|err| err.into_inner());
+                copy_body(&mut *reader, *len, writer)
+            }
+        }
+    }
+}
+
+// io::copy() moves the body in chunks through a fixed size buffer, so only
+// that much of it is ever in memory, take() makes sure no more than the
+// promised Content-Length is sent if a file grew in the meantime, the client
+// going away half way fails the copy with the error writing to it returned
+fn copy_body(reader: impl Read, len: u64, writer: &mut impl Write) -> io::Result<()> {
+    let copied = io::copy(&mut reader.take(len), writer)?;
+
+    // and a file that shrank would leave the client waiting for bytes that
+    // never come
+    if copied < len {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "body is shorter than its Content-Length",
+        ));
+    }
+
+    Ok(())
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Text(text) => f.debug_tuple("Text").field(text).finish(),
+            // the length says more than a long list of numbers
+            Body::Bytes(bytes) => f
+                .debug_struct("Bytes")
+                .field("len", &bytes.len())
+                .finish_non_exhaustive(),
+            Body::File { file, len } => f
+                .debug_struct("File")
+                .field("file", file)
+                .field("len", len)
+                .finish(),
+            Body::Reader { len, .. } => f
+                .debug_struct("Reader")
+                .field("len", len)
+                .finish_non_exhaustive(),
+            Body::Stream(_) => f.write_str("Stream"),
+        }
+    }
+}
+
+// what a streamed body is written to, frames every write as a chunk of its
+// own, its length in hex followed by the bytes written:-
+//
+// <chunk_length_in_hex>
+// <chunk>
+//
+// each line ending with \r\n, many small writes mean many small chunks, a
+// BufWriter around it sends fewer and larger ones
+pub struct ChunkedWriter<'a> {
+    writer: &'a mut dyn Write,
+}
+
+impl Write for ChunkedWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // an empty chunk is what ends the body
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        write!(self.writer, "{:X}\r\n", buf.len())?;
+        self.writer.write_all(buf)?;
+        self.writer.write_all(b"\r\n")?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl fmt::Debug for ChunkedWriter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChunkedWriter").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub headers: HeaderMap,
+    pub body: Body,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: HeaderMap::new(),
+            body: Body::Text(String::new()),
+        }
+    }
+
+    // 204 response, for a request that succeeded with nothing to send back,
+    // e.g. a DELETE, it has neither a body nor a Content-Length, see
+    // allows_body()
+    pub fn no_content() -> Response {
+        Response::new(204)
+    }
+
+    // response telling the client to request `location` instead, with an
+    // empty body, `location` is sent as is so it has to be a valid url
+    // already, panics for anything but a redirect status code since that's
+    // a bug in the calling handler rather than something a client did
+    pub fn redirect(status: u16, location: &str) -> Response {
+        assert!(
+            matches!(status, 301 | 302 | 303 | 307 | 308),
+            "{status} is not a redirect status code"
+        );
+
+        Response::new(status).with_header("Location", location)
+    }
+
+    // 200 response with `value` serialized as the json body, serializing
+    // can fail for types like maps with non-string keys, which results in
+    // an empty 500 response instead since that's a bug in the handler
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(value: &T) -> Response {
+        match serde_json::to_string(value) {
+            Ok(body) => Response::new(200)
+                .with_header("Content-Type", "application/json")
+                .with_body(body),
+            Err(_) => Response::new(500),
+        }
+    }
+
+    // response with a short body describing `status`, in whichever of html,
+    // json and plain text the client prefers, see
+    // Request::preferred_media_type(), plain text when it accepts none of
+    // them since an error the client can't parse still beats no response
+    pub fn error(request: &Request, status: u16) -> Response {
+        let reason = reason_phrase(status);
+        let media_type = request
+            .preferred_media_type(&ERROR_MEDIA_TYPES)
+            .unwrap_or("text/plain");
+
+        let body = match media_type {
+            "text/html" => format!(
+                "<!DOCTYPE html>\n<html lang=\"en\">\n  <head>\n    <meta charset=\"utf-8\">\n    \
+                 <title>{reason}</title>\n  </head>\n  <body>\n    <h1>{status} {reason}</h1>\n  \
+                 </body>\n</html>\n"
+            ),
+            "application/json" => format!("{{\"status\":{status},\"error\":\"{reason}\"}}"),
+            _ => format!("{status} {reason}\n"),
+        };
+
+        Response::new(status)
+            .with_header("Content-Type", media_type)
+            .with_body(body)
+    }
+
+    // response whose body is written by `write` while the response is being
+    // sent, for a body generated bit by bit whose length isn't known upfront,
+    // e.g.:-
+    //
+    // Response::streaming(200, |writer| {
+    //     for row in report_rows() {
+    //         writeln!(writer, "{row}")?;
+    //     }
+    //     Ok(())
+    // })
+    //
+    // the body is sent with `Transfer-Encoding: chunked`, an error returned
+    // by `write` fails writing the response the same as the client going away
+    // would
+    pub fn streaming<F>(status: u16, write: F) -> Response
+    where
+        F: Fn(&mut ChunkedWriter<'_>) -> io::Result<()> + Send + Sync + 'static,
+    {
+        Response {
+            status,
+            headers: HeaderMap::new(),
+            body: Body::Stream(Box::new(write)),
+        }
+    }
+
+    // a line break in `name` or `value` is written as a space, it would
+    // otherwise end the header there and start another one, see head()
+    pub fn with_header(mut self, name: &str, value: &str) -> Response {
+        self.headers.append(name, value);
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<String>) -> Response {
+        self.body = Body::Text(body.into());
+        self
+    }
+
+    // same as with_body() for a body that isn't valid UTF-8, e.g. an image,
+    // files are better sent with with_file(), which doesn't read them into
+    // memory
+    pub fn with_bytes(mut self, body: impl Into<Vec<u8>>) -> Response {
+        self.body = Body::Bytes(body.into());
+        self
+    }
+
+    // uses the file at `path` as the body, the file is opened right away,
+    // so a missing file is reported here, but only read once the response
+    // is written, a Content-Type going by the file's extension is added
+    // unless the response has one already, see mime::for_path()
+    pub fn with_file(mut self, path: impl AsRef<Path>) -> io::Result<Response> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        if !self.headers.contains("Content-Type") {
+            self.headers.append("Content-Type", mime::for_path(path));
+        }
+
+        self.body = Body::File { file, len };
+        Ok(self)
+    }
+
+    // uses `len` bytes read from `reader` as the body, read in chunks while
+    // the response is written, the same as a file is, e.g.:-
+    //
+    // Response::new(200).with_reader(GzDecoder::new(file), uncompressed_len)
+    //
+    // writing the response fails if `reader` runs out before `len` bytes, the
+    // body can only be written once, the reader is used up by then
+    pub fn with_reader(mut self, reader: impl Read + Send + 'static, len: u64) -> Response {
+        self.body = Body::Reader {
+            reader: Mutex::new(Box::new(reader)),
+            len,
+        };
+        self
+    }
+
+    // adds a `Set-Cookie` header, each cookie gets a header of its own:-
+    //
+    // Set-Cookie: <name>=<value>; Max-Age=<seconds>; Path=<path>; Secure; HttpOnly
+    //
+    // a `;` or control character in the name, value or path is
+    // percent-encoded, and a `=` in the name, a value like `x; Domain=evil`
+    // would otherwise add attributes of its own to the cookie
+    pub fn set_cookie(self, name: &str, value: &str, attrs: CookieAttributes) -> Response {
+        let name = escape_cookie_part(name, b"=");
+        let value = escape_cookie_part(value, b"");
+        let mut cookie = format!("{name}={value}");
+
+        if let Some(max_age) = attrs.max_age {
+            cookie.push_str(&format!("; Max-Age={max_age}"));
+        }
+        if let Some(path) = &attrs.path {
+            let path = escape_cookie_part(path, b"");
+            cookie.push_str(&format!("; Path={path}"));
+        }
+        if attrs.secure {
+            cookie.push_str("; Secure");
+        }
+        if attrs.http_only {
+            cookie.push_str("; HttpOnly");
+        }
+
+        self.with_header("Set-Cookie", &cookie)
+    }
+
+    // whether the status lets the response have a body at all, a 1xx, 204
+    // No Content or 304 Not Modified response ends right after its headers,
+    // a client reads whatever follows them as the next response, so the
+    // body is left out along with Content-Length, even when one was set
+    pub fn allows_body(&self) -> bool {
+        !matches!(self.status, 100..=199 | 204 | 304)
+    }
+
+    // writes the response in the following format, Content-Length is
+    // always computed from the body instead of trusting a header(a streamed
+    // body gets `Transfer-Encoding: chunked` instead):-
+    //
+    // <http_version> <status_code> <status_code_keyword>
+    // <header_name>: <header_value>
+    // Content-Length: <content_length>
+    //
+    // <response_body>
+    //
+    // both left out for a status that allows no body, see allows_body()
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut writer = RetryWouldBlock(writer);
+
+        writer.write_all(self.head().as_bytes())?;
+        if self.allows_body() {
+            self.body.write_to(&mut writer)?;
+        }
+        writer.flush()
+    }
+
+    // writes the response to a HEAD request, which is the same response a
+    // GET request would get minus the body, the Content-Length header still
+    // has the length of the body that would have been sent, handlers don't
+    // need to know about HEAD requests at all, as long as they are routed
+    // to the GET handler and the response is written with this, a file,
+    // reader or streamed body isn't read from or called at all
+    pub fn write_head_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        let mut writer = RetryWouldBlock(writer);
+
+        writer.write_all(self.head().as_bytes())?;
+        writer.flush()
+    }
+
+    // writes the response the way `method` expects it
+    pub fn write_for(&self, method: Method, writer: &mut impl Write) -> io::Result<()> {
+        match method {
+            Method::Head => self.write_head_to(writer),
+            _ => self.write_to(writer),
+        }
+    }
+
+    // status line and headers, up to and including the empty line, \r\n is
+    // CRLF character(carriage return line feed), it seperates different
+    // lines within a http request and response object, an empty line with
+    // zero characters and just \r\n signifies the start of the body
+    fn head(&self) -> String {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n",
+            self.status,
+            reason_phrase(self.status)
+        );
+
+        for (name, value) in self.headers.iter() {
+            if !name.eq_ignore_ascii_case("Content-Length")
+                && !name.eq_ignore_ascii_case("Transfer-Encoding")
+            {
+                // a line break would end the header early and let whatever
+                // follows it pass for headers of its own, or even the body
+                let (name, value) = (without_line_breaks(name), without_line_breaks(value));
+                head.push_str(&format!("{name}: {value}\r\n"));
+            }
+        }
+
+        match self.body.len() {
+            _ if !self.allows_body() => head.push_str("\r\n"),
+            Some(len) => head.push_str(&format!("Content-Length: {len}\r\n\r\n")),
+            None => head.push_str("Transfer-Encoding: chunked\r\n\r\n"),
+        }
+        head
+    }
+}
+
+// write_all() already keeps going after a short write, where the writer
+// only took part of the buffer, but gives up on the first error, this makes
+// a non-blocking socket whose send buffer is full, which reports WouldBlock,
+// get retried the same way instead of failing the whole response
+struct RetryWouldBlock<W>(W);
+
+// how long to back off before retrying a write that would have blocked, so
+// a client that is slow to read doesn't have the worker spinning
+const WOULD_BLOCK_BACKOFF: Duration = Duration::from_millis(1);
+
+impl<W: Write> Write for RetryWouldBlock<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match self.0.write(buf) {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(WOULD_BLOCK_BACKOFF);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        loop {
+            match self.0.flush() {
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    thread::sleep(WOULD_BLOCK_BACKOFF);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        100 => "Continue",
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        417 => "Expectation Failed",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+// splits `application/x-www-form-urlencoded` data(query strings and form
+// bodies) into decoded name and value pairs, in order:-
+//
+// a=1&b=two+words&c => [("a", "1"), ("b", "two words"), ("c", "")]
+//
+// returns None if any name or value fails to decode
+pub fn parse_urlencoded(input: &str) -> Option<Vec<(String, String)>> {
+    input
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+
+            Some((decode_form_component(name)?, decode_form_component(value)?))
+        })
+        .collect()
+}
+
+// same as percent_decode() but `+` stands for a space as well, which is
+// only the case in urlencoded form data, not in url paths
+fn decode_form_component(input: &str) -> Option<String> {
+    percent_decode(&input.replace('+', " "))
+}
+
+// decode_form_component() for a part of the query string, which is only
+// copied when there's anything to decode, and left as it is when it isn't
+// correctly encoded
+fn decode_query_component(input: &str) -> Cow<'_, str> {
+    if !input.contains(['%', '+']) {
+        return Cow::Borrowed(input);
+    }
+
+    decode_form_component(input).map_or(Cow::Borrowed(input), Cow::Owned)
+}
+
+// decodes %XX escape sequences in a percent-encoded url component, returns
+// None for a truncated or non-hex escape sequence(`%2`, `%ZZ`) or when the
+// decoded bytes are not valid UTF-8, so callers can reject the request
+// instead of guessing what the client meant
+pub fn percent_decode(input: &str) -> Option<String> {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let high = hex_value(*bytes.get(i + 1)?)?;
+            let low = hex_value(*bytes.get(i + 2)?)?;
+            decoded.push(high << 4 | low);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+// percent-encodes the control characters and `;` of a part of a cookie set
+// with Response::set_cookie(), along with the bytes in `also`, anything else
+// is left as it is
+fn escape_cookie_part<'a>(part: &'a str, also: &[u8]) -> Cow<'a, str> {
+    let escaped = |byte: u8| byte.is_ascii_control() || byte == b';' || also.contains(&byte);
+
+    if !part.bytes().any(escaped) {
+        return Cow::Borrowed(part);
+    }
+
+    let mut encoded = String::with_capacity(part.len());
+    for c in part.chars() {
+        match u8::try_from(c) {
+            Ok(byte) if escaped(byte) => encoded.push_str(&format!("%{byte:02X}")),
+            _ => encoded.push(c),
+        }
+    }
+
+    Cow::Owned(encoded)
+}
+
+// `line` with every \r and \n replaced by a space, see Response::head()
+fn without_line_breaks(line: &str) -> Cow<'_, str> {
+    if line.contains(['\r', '\n']) {
+        Cow::Owned(line.replace(['\r', '\n'], " "))
+    } else {
+        Cow::Borrowed(line)
+    }
+}
+
+// the reverse of percent_decode(), escapes every byte except letters,
+// digits and `-._~`, which makes the result safe to use as a single segment
+// of a url path or as a query string value, e.g. "my file.txt" becomes
+// "my%20file.txt"
+pub fn percent_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+
+    for byte in input.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    encoded
+}
+
+// decodes standard base64(the `+/` alphabet) with or without `=` padding,
+// returns None for anything that isn't valid base64
+pub fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    let input = input.as_bytes();
+    let input = match input {
+        [rest @ .., b'=', b'='] if input.len().is_multiple_of(4) => rest,
+        [rest @ .., b'='] if input.len().is_multiple_of(4) => rest,
+        _ => input,
+    };
+
+    // a single character left over only carries 6 bits, not a whole byte
+    if input.len() % 4 == 1 {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(input.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut bit_count = 0;
+
+    for &byte in input {
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        };
+
+        bits = bits << 6 | u32::from(value);
+        bit_count += 6;
+
+        if bit_count >= 8 {
+            bit_count -= 8;
+            decoded.push((bits >> bit_count) as u8);
+            bits &= (1 << bit_count) - 1;
+        }
+    }
+
+    Some(decoded)
+}
+
+// compares two byte strings in time that only depends on their lengths, not
+// on where they first differ, an early return on the first mismatch would
+// let a client guess a secret one byte at a time by timing the responses
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        collections::hash_map::DefaultHasher,
+        env, fs,
+        hash::Hasher,
+        process,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    fn parse(request: &str) -> Result<Request, ParseError> {
+        Request::parse(&mut request.as_bytes())
+    }
+
+    // the response as it's sent
+    fn written(response: &Response) -> String {
+        let mut written = Vec::new();
+        response.write_to(&mut written).unwrap();
+        String::from_utf8(written).unwrap()
+    }
+
+    #[test]
+    fn request() {
+        let request = parse(
+            "GET /index.html HTTP/1.1\r\nHost: localhost:7878\r\nAccept: text/html\r\naccept: */*\r\n\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(request.method, Method::Get);
+        assert_eq!(request.path, "/index.html");
+        assert_eq!(request.version, HttpVersion::Http11);
+        assert_eq!(request.headers.len(), 3);
+        assert_eq!(request.headers.get("HOST"), Some("localhost:7878"));
+        // both headers are kept, whatever the case of their names
+        assert_eq!(
+            request.headers.get_all("Accept").collect::<Vec<_>>(),
+            ["text/html", "*/*"]
+        );
+        assert!(request.body().is_empty());
+    }
+
+    #[test]
+    fn request_without_headers() {
+        let request = parse("DELETE /users/7 HTTP/1.0\r\n\r\n").unwrap();
+
+        assert_eq!(request.method, Method::Delete);
+        assert_eq!(request.path, "/users/7");
+        assert_eq!(request.version, HttpVersion::Http10);
+        assert!(request.headers.is_empty());
+
+        // bare \n line endings are accepted as well
+        let request = parse("GET / HTTP/1.1\nHost: a\n\n").unwrap();
+        assert_eq!(request.headers.get("Host"), Some("a"));
+    }
+
+    #[test]
+    fn query_string() {
+        let request = parse("GET /search?q=rust+lang&limit=10 HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(request.path, "/search");
+        assert_eq!(request.target(), "/search?q=rust+lang&limit=10");
+        assert_eq!(request.query(), "q=rust+lang&limit=10");
+        assert_eq!(request.query_param("q").unwrap(), "rust lang");
+        assert_eq!(request.query_param("limit").unwrap(), "10");
+        assert_eq!(request.query_param("page"), None);
+
+        // no query string at all, and an empty one
+        for target in ["/search", "/search?"] {
+            let request = parse(&format!("GET {target} HTTP/1.1\r\n\r\n")).unwrap();
+            assert_eq!(request.path, "/search");
+            assert_eq!(request.target(), target);
+            assert_eq!(request.query(), "");
+            assert_eq!(request.query_pairs().count(), 0);
+        }
+
+        let request =
+            parse("GET /?tag=a&flag&tag=b&&q=fish%26chips&empty=&a%20b=%zz HTTP/1.1\r\n\r\n")
+                .unwrap();
+        let pairs: Vec<(String, String)> = request
+            .query_pairs()
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                ("tag", "a"),
+                ("flag", ""),
+                ("tag", "b"),
+                // an encoded `&` doesn't split the pair
+                ("q", "fish&chips"),
+                ("empty", ""),
+                // left as sent, it isn't correctly encoded
+                ("a b", "%zz"),
+            ]
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        );
+        // the first one sent
+        assert_eq!(request.query_param("tag").unwrap(), "a");
+        assert_eq!(request.query_param("flag").unwrap(), "");
+        // nothing to decode, nothing copied
+        assert!(matches!(request.query_param("tag"), Some(Cow::Borrowed(_))));
+    }
+
+    #[test]
+    fn path_decoded_and_normalized() {
+        let path = |target: &str| {
+            let request = parse(&format!("GET {target} HTTP/1.1\r\n\r\n")).unwrap();
+            assert_eq!(request.target(), target);
+            (request.path, request.raw_path)
+        };
+
+        assert_eq!(path("/"), ("/".to_owned(), "/".to_owned()));
+        assert_eq!(
+            path("/hello%20world"),
+            ("/hello world".to_owned(), "/hello%20world".to_owned())
+        );
+        // multibyte UTF-8
+        assert_eq!(path("/caf%C3%A9/%E2%9C%93?q=1").0, "/café/✓");
+        assert_eq!(
+            path("/docs/./guide//../intro%20page.html").0,
+            "/docs/intro page.html"
+        );
+        assert_eq!(path("//a///b").0, "/a/b");
+        // a trailing slash is kept, a directory is asked for
+        assert_eq!(path("/docs/").0, "/docs/");
+        assert_eq!(path("/docs/.").0, "/docs/");
+        assert_eq!(path("/docs/a/..").0, "/docs/");
+        assert_eq!(path("/docs/..").0, "/");
+
+        // traversal attempts can't climb past the root, encoded or not
+        assert_eq!(path("/../../etc/passwd").0, "/etc/passwd");
+        assert_eq!(path("/static/%2e%2e/%2E%2E/etc/passwd").0, "/etc/passwd");
+        assert_eq!(
+            path("/static/%2e%2e%2f%2e%2e%2fetc/passwd"),
+            (
+                "/etc/passwd".to_owned(),
+                // a single segment as sent, see Router::route()
+                "/static/%2e%2e%2f%2e%2e%2fetc/passwd".to_owned()
+            )
+        );
+        assert_eq!(path("/a/%2e/b").1, "/a/b");
+        assert_eq!(path("/a/b/%2e%2e").1, "/a/");
+    }
+
+    #[test]
+    fn invalid_paths() {
+        for target in [
+            // not valid percent-encoding
+            "/%ZZ",
+            "/a%2",
+            "/a%",
+            // overlong encodings of `.` and `/`, not valid UTF-8
+            "/%C0%AE%C0%AE/etc/passwd",
+            "/%c0%af",
+            // a lone continuation byte
+            "/%80",
+            // control characters
+            "/a%00b",
+            "/a%0d%0aSet-Cookie:%20x",
+            "/%7F",
+            "/a\u{1}b",
+            // only OPTIONS takes the asterisk-form
+            "*",
+        ] {
+            assert!(
+                matches!(
+                    parse(&format!("GET {target} HTTP/1.1\r\n\r\n")),
+                    Err(ParseError::InvalidPath(path)) if path == target
+                ),
+                "{target:?}"
+            );
+        }
+
+        // only the path has to be a valid one, the query string is decoded
+        // when it's looked at
+        assert!(parse("GET /?q=%zz HTTP/1.1\r\n\r\n").is_ok());
+    }
+
+    #[test]
+    fn asterisk_form() {
+        let request = parse("OPTIONS * HTTP/1.1\r\nHost: a\r\n\r\n").unwrap();
+
+        assert_eq!(request.method, Method::Options);
+        assert_eq!(request.target(), "*");
+        assert_eq!(request.path, "*");
+        assert_eq!(request.raw_path(), "*");
+        assert_eq!(request.query(), "");
+    }
+
+    #[test]
+    fn folded_header() {
+        let request =
+            parse("GET / HTTP/1.1\r\nX-Long: first\r\n  second\r\n\tthird\r\nHost: a\r\n\r\n")
+                .unwrap();
+
+        assert_eq!(request.headers.get("X-Long"), Some("first second third"));
+        assert_eq!(request.headers.get("Host"), Some("a"));
+
+        // there's no header to continue yet
+        assert!(matches!(
+            parse("GET / HTTP/1.1\r\n folded\r\n\r\n"),
+            Err(ParseError::MalformedHeader(_))
+        ));
+    }
+
+    #[test]
+    fn malformed_requests() {
+        assert!(matches!(parse(""), Err(ParseError::Empty)));
+        assert!(matches!(
+            parse("GET /\r\n\r\n"),
+            Err(ParseError::MalformedRequestLine)
+        ));
+        assert!(matches!(
+            parse("GET  / HTTP/1.1\r\n\r\n"),
+            Err(ParseError::MalformedRequestLine)
+        ));
+        assert!(matches!(
+            parse("get / HTTP/1.1\r\n\r\n"),
+            Err(ParseError::UnsupportedMethod(method)) if method == "get"
+        ));
+        assert!(matches!(
+            parse("GET / HTTP/2.0\r\n\r\n"),
+            Err(ParseError::UnsupportedVersion(version)) if version == "HTTP/2.0"
+        ));
+        assert!(matches!(
+            parse("GET / HTTP/1.1\r\nHost localhost\r\n\r\n"),
+            Err(ParseError::MalformedHeader(line)) if line == "Host localhost"
+        ));
+        assert!(matches!(
+            parse("GET / HTTP/1.1\r\nHost : localhost\r\n\r\n"),
+            Err(ParseError::MalformedHeader(_))
+        ));
+        // closed before the empty line ending the headers
+        assert!(matches!(
+            parse("GET / HTTP/1.1\r\nHost: localhost\r\n"),
+            Err(ParseError::MalformedRequestLine)
+        ));
+    }
+
+    #[test]
+    fn body_by_content_length() {
+        let mut input =
+            &b"POST /submit HTTP/1.1\r\nContent-Length: 5\r\n\r\nhelloGET / HTTP/1.1\r\n\r\n"[..];
+        let request = Request::parse(&mut input).unwrap();
+        assert_eq!(request.body(), b"hello");
+        assert_eq!(request.body_string(), Ok("hello"));
+        // exactly the body is read, the next request is left where it was
+        assert_eq!(input, b"GET / HTTP/1.1\r\n\r\n");
+
+        let request = parse("PUT /submit HTTP/1.1\r\nContent-Length: 0\r\n\r\n").unwrap();
+        assert!(request.body().is_empty());
+        assert_eq!(request.body_string(), Ok(""));
+
+        let request =
+            Request::parse(&mut &b"POST / HTTP/1.1\r\nContent-Length: 2\r\n\r\n\xff\xfe"[..])
+                .unwrap();
+        assert_eq!(request.body(), b"\xff\xfe");
+        assert!(request.body_string().is_err());
+
+        // closed before the whole body was sent
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello"),
+            Err(ParseError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: -1\r\n\r\n"),
+            Err(ParseError::InvalidContentLength(value)) if value == "-1"
+        ));
+    }
+
+    #[test]
+    fn content_length_digits_only_and_agreed_on() {
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: +5\r\n\r\nhello"),
+            Err(ParseError::InvalidContentLength(value)) if value == "+5"
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 0x5\r\n\r\nhello"),
+            Err(ParseError::InvalidContentLength(_))
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 5\r\nContent-Length: 3\r\n\r\nhello"),
+            Err(ParseError::InvalidContentLength(value)) if value == "5, 3"
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 5, 3\r\n\r\nhello"),
+            Err(ParseError::InvalidContentLength(_))
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 5,\r\n\r\nhello"),
+            Err(ParseError::InvalidContentLength(_))
+        ));
+
+        // the same length sent twice is still only one
+        let request =
+            parse("POST / HTTP/1.1\r\nContent-Length: 5\r\ncontent-length: 5, 5\r\n\r\nhello")
+                .unwrap();
+        assert_eq!(request.body(), b"hello");
+    }
+
+    #[test]
+    fn length_required() {
+        for method in ["POST", "PUT", "PATCH"] {
+            assert!(
+                matches!(
+                    parse(&format!("{method} / HTTP/1.1\r\n\r\nhello")),
+                    Err(ParseError::LengthRequired(_))
+                ),
+                "{method}"
+            );
+        }
+
+        // a chunked body carries its own length
+        assert!(parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\n").is_ok());
+        // other methods don't send a body unless they say so
+        assert!(parse("DELETE / HTTP/1.1\r\n\r\n").is_ok());
+    }
+
+    #[test]
+    fn chunked_body() {
+        let request = parse(
+            "POST /upload HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+             5\r\nhello\r\n\
+             1;name=value\r\n \r\n\
+             B\r\nchunked wor\r\n\
+             2\r\nld\r\n\
+             0\r\nExpires: never\r\n\r\n\
+             GET /next HTTP/1.1\r\n\r\n",
+        )
+        .unwrap();
+
+        assert_eq!(request.body(), b"hello chunked world");
+
+        let mut input = &b"POST / HTTP/1.1\r\nTransfer-Encoding: Chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\nGET /next HTTP/1.1\r\n\r\n"[..];
+        assert_eq!(Request::parse(&mut input).unwrap().body(), b"abc");
+        // and the next request is left where it starts
+        assert_eq!(Request::parse(&mut input).unwrap().path, "/next");
+
+        // a Content-Length along with it is never guessed at
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nContent-Length: 2\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\n\r\n"),
+            Err(ParseError::InvalidContentLength(value)) if value == "2"
+        ));
+    }
+
+    #[test]
+    fn chunked_decoder() {
+        let decode = |input: &[u8]| {
+            let mut input = input;
+            let body = read_chunked_body(&mut input, &ParseLimits::default(), MAX_BODY_SIZE);
+            (body, input.to_vec())
+        };
+
+        let (body, rest) = decode(b"4\r\nwiki\r\n5;ext\r\npedia\r\n0\r\n\r\nnext");
+        assert_eq!(body.unwrap(), b"wikipedia");
+        assert_eq!(rest, b"next");
+
+        // trailers are read up to the empty line and thrown away
+        let (body, rest) = decode(b"3\r\nabc\r\n0\r\nChecksum: 1234\r\nExpires: never\r\n\r\nnext");
+        assert_eq!(body.unwrap(), b"abc");
+        assert_eq!(rest, b"next");
+
+        // the sum of the chunks counts, not the size of any one of them
+        let half = MAX_BODY_SIZE / 2;
+        let chunk = format!("{half:x}\r\n{}\r\n", "a".repeat(half as usize));
+        let input = format!("{chunk}{chunk}1\r\na\r\n0\r\n\r\n");
+        assert!(matches!(
+            decode(input.as_bytes()).0,
+            Err(ParseError::BodyTooLarge(_))
+        ));
+        let input = format!("{chunk}{chunk}0\r\n\r\n");
+        assert_eq!(
+            decode(input.as_bytes()).0.unwrap().len() as u64,
+            MAX_BODY_SIZE
+        );
+        // a chunk as large as a u64 gets on top of the ones already read
+        let mut input = &b"1\r\na\r\nffffffffffffffff\r\nendless"[..];
+        assert!(matches!(
+            read_chunked_body(&mut input, &ParseLimits::default(), MAX_BODY_SIZE),
+            Err(ParseError::BodyTooLarge(_))
+        ));
+        assert_eq!(input, b"endless");
+
+        // closed before the trailers ended
+        assert!(matches!(
+            decode(b"3\r\nabc\r\n0\r\nChecksum: 1234\r\n").0,
+            Err(ParseError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof
+        ));
+        // the line break after a chunk is missing
+        assert!(matches!(
+            decode(b"3\r\nabc0\r\n\r\n").0,
+            Err(ParseError::MalformedChunk(line)) if line == "0"
+        ));
+    }
+
+    #[test]
+    fn chunked_body_limits() {
+        let limits = ParseLimits {
+            max_header_line_length: 16,
+            max_headers: 2,
+            max_header_size: 24,
+            ..ParseLimits::default()
+        };
+        let decode = |input: &[u8]| {
+            let mut input = input;
+            let body = read_chunked_body(&mut input, &limits, MAX_BODY_SIZE);
+            (body, input.len())
+        };
+
+        // a chunk size line that never ends, the rest of it is left unread
+        let input = format!("3;{}\r\nabc\r\n0\r\n\r\n", "x".repeat(1024));
+        let (body, left) = decode(input.as_bytes());
+        assert!(matches!(body, Err(ParseError::ChunkLineTooLong(16))));
+        assert!(left > 1000);
+        // extensions within the limit are fine
+        assert_eq!(
+            decode(b"3;name=value\r\nabc\r\n0\r\n\r\n").0.unwrap(),
+            b"abc"
+        );
+
+        // trailers are limited like headers
+        let input = format!("3\r\nabc\r\n0\r\nX-Trailer: {}\r\n\r\n", "x".repeat(1024));
+        let (body, left) = decode(input.as_bytes());
+        assert!(matches!(body, Err(ParseError::HeaderLineTooLong(16))));
+        assert!(left > 1000);
+        assert!(matches!(
+            decode(b"3\r\nabc\r\n0\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n").0,
+            Err(ParseError::TooManyHeaders(2))
+        ));
+        assert!(matches!(
+            decode(b"3\r\nabc\r\n0\r\nChecksum: 1234\r\nExpires: x\r\n\r\n").0,
+            Err(ParseError::HeadersTooLarge(24))
+        ));
+        assert_eq!(
+            decode(b"3\r\nabc\r\n0\r\nA: 1\r\nB: 2\r\n\r\n").0.unwrap(),
+            b"abc"
+        );
+    }
+
+    #[test]
+    fn request_line_limit() {
+        let limits = ParseLimits::default();
+
+        // given up on without reading all of it
+        let input = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(1024 * 1024));
+        let mut reader = input.as_bytes();
+        assert!(matches!(
+            Request::parse(&mut reader),
+            Err(ParseError::UriTooLong(length)) if length == limits.max_request_line_length
+        ));
+        assert_eq!(
+            input.len() - reader.len(),
+            limits.max_request_line_length + 2
+        );
+
+        // a client hanging up before ending the line
+        let mut reader = &b"GET /"[..];
+        assert!(matches!(
+            Request::parse(&mut reader),
+            Err(ParseError::MalformedRequestLine)
+        ));
+
+        let limits = ParseLimits {
+            max_request_line_length: 20,
+            ..ParseLimits::default()
+        };
+        let parse_limited =
+            |request: &str| Request::parse_limited(&mut request.as_bytes(), &limits);
+
+        // right at the limit
+        assert!(parse_limited("GET /abcdef HTTP/1.1\r\n\r\n").is_ok());
+        assert!(matches!(
+            parse_limited("GET /abcdefg HTTP/1.1\r\n\r\n"),
+            Err(ParseError::UriTooLong(20))
+        ));
+        // header lines have a limit of their own
+        let header = format!("X-Long: {}", "a".repeat(100));
+        assert!(parse_limited(&format!("GET / HTTP/1.1\r\n{header}\r\n\r\n")).is_ok());
+    }
+
+    #[test]
+    fn header_limits() {
+        let limits = ParseLimits::default();
+
+        // one enormous line, given up on without reading all of it
+        let input = format!(
+            "GET / HTTP/1.1\r\nX-Huge: {}\r\n\r\n",
+            "a".repeat(1024 * 1024)
+        );
+        let mut reader = input.as_bytes();
+        assert!(matches!(
+            Request::parse(&mut reader),
+            Err(ParseError::HeaderLineTooLong(length)) if length == limits.max_header_line_length
+        ));
+        assert!(reader.len() > input.len() - limits.max_header_line_length - 100);
+
+        // many small ones
+        let headers: String = (0..500).map(|n| format!("X-Header-{n}: {n}\r\n")).collect();
+        assert!(matches!(
+            parse(&format!("GET / HTTP/1.1\r\n{headers}\r\n")),
+            Err(ParseError::TooManyHeaders(100))
+        ));
+
+        // lines within the limit adding up to more than the whole section
+        let line = format!(
+            "X-Large: {}\r\n",
+            "a".repeat(limits.max_header_line_length - 9)
+        );
+        assert!(matches!(
+            parse(&format!("GET / HTTP/1.1\r\n{}\r\n", line.repeat(8))),
+            Err(ParseError::HeadersTooLarge(size)) if size == limits.max_header_size
+        ));
+        // right at the limit of a line
+        let request = parse(&format!("GET / HTTP/1.1\r\n{line}\r\n")).unwrap();
+        assert_eq!(request.headers.get("X-Large").unwrap().len(), 8 * 1024 - 9);
+
+        let limits = ParseLimits {
+            max_header_line_length: 16,
+            max_headers: 2,
+            max_header_size: 24,
+            ..ParseLimits::default()
+        };
+        let parse_limited =
+            |request: &str| Request::parse_head_limited(&mut request.as_bytes(), &limits);
+
+        assert!(parse_limited("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").is_ok());
+        // the request line isn't a header line
+        assert!(parse_limited("GET /a/rather/long/path HTTP/1.1\r\n\r\n").is_ok());
+        assert!(matches!(
+            parse_limited("GET / HTTP/1.1\r\nHost: example.com:80\r\n\r\n"),
+            Err(ParseError::HeaderLineTooLong(16))
+        ));
+        // a byte too long, which a bare \n leaves room to read
+        assert!(matches!(
+            parse_limited("GET / HTTP/1.1\nHost: localhost:8\n\n"),
+            Err(ParseError::HeaderLineTooLong(16))
+        ));
+        assert!(matches!(
+            parse_limited("GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n"),
+            Err(ParseError::TooManyHeaders(2))
+        ));
+        // folded lines count on their own
+        assert!(matches!(
+            parse_limited("GET / HTTP/1.1\r\nA: 1\r\n 2\r\n 3\r\n\r\n"),
+            Err(ParseError::TooManyHeaders(2))
+        ));
+        assert!(matches!(
+            parse_limited("GET / HTTP/1.1\r\nHost: localhost\r\nAccept: */*\r\n\r\n"),
+            Err(ParseError::HeadersTooLarge(24))
+        ));
+    }
+
+    #[test]
+    fn body_limited() {
+        let head = |input: &mut &[u8]| Request::parse_head(input).unwrap();
+
+        let mut input = &b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhelloworld"[..];
+        let mut request = head(&mut input);
+        assert!(request.check_body_size(10).is_ok());
+        assert!(matches!(
+            request.check_body_size(9),
+            Err(ParseError::BodyTooLarge(9))
+        ));
+        // turned down without reading any of it
+        assert!(matches!(
+            request.read_body_limited(&mut input, &ParseLimits::default(), 5),
+            Err(ParseError::BodyTooLarge(5))
+        ));
+        assert_eq!(input, b"helloworld");
+        request
+            .read_body_limited(&mut input, &ParseLimits::default(), 10)
+            .unwrap();
+        assert_eq!(request.body(), b"helloworld");
+
+        // a chunked body is only found out about once it gets there
+        let mut input =
+            &b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n5\r\nworld\r\n0\r\n\r\n"[..];
+        let mut request = head(&mut input);
+        assert!(request.check_body_size(0).is_ok());
+        assert!(matches!(
+            request.read_body_limited(&mut input, &ParseLimits::default(), 9),
+            Err(ParseError::BodyTooLarge(9))
+        ));
+
+        // a larger limit than the default
+        let too_large = MAX_BODY_SIZE + 1;
+        let input = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {too_large}\r\n\r\n{}",
+            "a".repeat(too_large as usize)
+        );
+        let mut input = input.as_bytes();
+        let mut request = head(&mut input);
+        request
+            .read_body_limited(&mut input, &ParseLimits::default(), too_large)
+            .unwrap();
+        assert_eq!(request.body().len() as u64, too_large);
+    }
+
+    #[test]
+    fn malformed_chunked_body() {
+        let chunked = |body: &str| {
+            parse(&format!(
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{body}"
+            ))
+        };
+
+        for size in ["xyz", "", "+5", "-1", "0x5"] {
+            assert!(
+                matches!(
+                    chunked(&format!("{size}\r\nhello\r\n0\r\n\r\n")),
+                    Err(ParseError::MalformedChunk(line)) if line == size
+                ),
+                "{size:?}"
+            );
+        }
+        // more bytes than the size says
+        assert!(matches!(
+            chunked("3\r\nhello\r\n0\r\n\r\n"),
+            Err(ParseError::MalformedChunk(line)) if line == "lo"
+        ));
+        // closed before the last chunk
+        assert!(matches!(
+            chunked("5\r\nhel"),
+            Err(ParseError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof
+        ));
+        assert!(matches!(
+            chunked("5\r\nhello\r\n"),
+            Err(ParseError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof
+        ));
+
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n"),
+            Err(ParseError::UnsupportedTransferEncoding(value)) if value == "gzip, chunked"
+        ));
+        // every Transfer-Encoding header counts, not just the first one
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: identity\r\n\r\n0\r\n\r\n"),
+            Err(ParseError::UnsupportedTransferEncoding(value)) if value == "chunked, identity"
+        ));
+    }
+
+    #[test]
+    fn body_too_large() {
+        let too_large = MAX_BODY_SIZE + 1;
+
+        assert!(matches!(
+            parse(&format!(
+                "POST / HTTP/1.1\r\nContent-Length: {too_large}\r\n\r\n"
+            )),
+            Err(ParseError::BodyTooLarge(_))
+        ));
+        // found out about before the chunk is read
+        assert!(matches!(
+            parse(&format!(
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{too_large:x}\r\n"
+            )),
+            Err(ParseError::BodyTooLarge(_))
+        ));
+        assert!(matches!(
+            parse("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffffff\r\n"),
+            Err(ParseError::BodyTooLarge(_))
+        ));
+
+        // many chunks adding up to more than the limit
+        let chunk = format!("{:x}\r\n{}\r\n", 1024 * 1024, "a".repeat(1024 * 1024));
+        let body = chunk.repeat(MAX_BODY_SIZE as usize / (1024 * 1024) + 1);
+        assert!(matches!(
+            parse(&format!(
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{body}0\r\n\r\n"
+            )),
+            Err(ParseError::BodyTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn cookies() {
+        let request =
+            parse("GET / HTTP/1.1\r\nCookie: session=abc123; theme = dark;empty=; broken\r\n\r\n")
+                .unwrap();
+        let cookies = request.cookies();
+
+        assert_eq!(cookies.get("session").map(String::as_str), Some("abc123"));
+        assert_eq!(cookies.get("theme").map(String::as_str), Some("dark"));
+        assert_eq!(cookies.get("empty").map(String::as_str), Some(""));
+        assert!(!cookies.contains_key("broken"));
+    }
+
+    #[test]
+    fn first_cookie_of_a_name_wins() {
+        let request = parse("GET / HTTP/1.1\r\nCookie: a=1; a=2\r\nCookie: a=3\r\n\r\n").unwrap();
+        assert_eq!(request.cookies().get("a").map(String::as_str), Some("1"));
+    }
+
+    #[test]
+    fn set_cookie() {
+        let response = Response::new(200).set_cookie(
+            "session",
+            "abc123",
+            CookieAttributes {
+                http_only: true,
+                secure: true,
+                max_age: Some(3600),
+                path: Some("/app".to_owned()),
+            },
+        );
+        assert_eq!(
+            response.headers.get("Set-Cookie"),
+            Some("session=abc123; Max-Age=3600; Path=/app; Secure; HttpOnly")
+        );
+        assert!(written(&response).contains(
+            "\r\nSet-Cookie: session=abc123; Max-Age=3600; Path=/app; Secure; HttpOnly\r\n"
+        ));
+
+        let response = Response::new(200).set_cookie("a", "1", CookieAttributes::default());
+        assert_eq!(response.headers.get("Set-Cookie"), Some("a=1"));
+    }
+
+    #[test]
+    fn set_cookie_cant_inject_attributes() {
+        let response = Response::new(200).set_cookie(
+            "a=b;",
+            "x; Domain=evil\r\nSet-Cookie: admin=1",
+            CookieAttributes {
+                path: Some("/; Domain=evil".to_owned()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            response.headers.get("Set-Cookie"),
+            Some("a%3Db%3B=x%3B Domain=evil%0D%0ASet-Cookie: admin=1; Path=/%3B Domain=evil")
+        );
+        // anything else is left as it is
+        let response = Response::new(200).set_cookie("a", "é=\"1\"", CookieAttributes::default());
+        assert_eq!(response.headers.get("Set-Cookie"), Some("a=é=\"1\""));
+    }
+
+    #[test]
+    fn header_cant_split_the_response() {
+        let response = Response::new(200)
+            .with_header("Location", "/next\r\nSet-Cookie: admin=1")
+            .with_header("X-A\nX-B", "1\r\n\r\n<html>");
+        let written = written(&response);
+
+        assert_eq!(written.matches("\r\n").count(), 5);
+        assert!(written.contains("\r\nLocation: /next  Set-Cookie: admin=1\r\n"));
+        assert!(written.contains("\r\nX-A X-B: 1    <html>\r\n"));
+    }
+
+    #[test]
+    fn redirect() {
+        assert_eq!(
+            written(&Response::redirect(302, "/login")),
+            "HTTP/1.1 302 Found\r\nLocation: /login\r\nContent-Length: 0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn redirect_with_a_non_redirect_status() {
+        Response::redirect(200, "/login");
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_response() {
+        #[derive(serde::Serialize)]
+        struct User {
+            id: u32,
+            name: &'static str,
+        }
+
+        let response = Response::json(&User { id: 7, name: "ann" });
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.headers.get("Content-Type"),
+            Some("application/json")
+        );
+        assert!(matches!(&response.body, Body::Text(body) if body == r#"{"id":7,"name":"ann"}"#));
+        let written = written(&response);
+        assert!(written.contains("\r\nContent-Length: 21\r\n"));
+        assert!(written.ends_with("\r\n\r\n{\"id\":7,\"name\":\"ann\"}"));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json_response_failing_to_serialize() {
+        let map = HashMap::from([((1, 2), 3)]);
+        assert_eq!(Response::json(&map).status, 500);
+    }
+
+    fn form_request(body: &str) -> Request {
+        parse(&format!(
+            "POST / HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded; charset=UTF-8\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn form() {
+        let form = form_request("name=Jane+Doe&city=New%20York&empty=&flag")
+            .form()
+            .unwrap();
+        assert_eq!(form.len(), 4);
+        assert_eq!(form["name"], "Jane Doe");
+        assert_eq!(form["city"], "New York");
+        assert_eq!(form["empty"], "");
+        assert_eq!(form["flag"], "");
+
+        assert_eq!(form_request("a=1&a=2").form().unwrap()["a"], "2");
+        assert!(form_request("").form().unwrap().is_empty());
+        assert_eq!(form_request("a=%2").form(), Err(FormError::InvalidEncoding));
+
+        let request = parse("POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\na=1").unwrap();
+        assert_eq!(request.form(), Err(FormError::WrongContentType));
+    }
+
+    #[test]
+    fn head_answered_like_get_without_the_body() {
+        let response = Response::new(200)
+            .with_header("Content-Type", "text/plain")
+            .with_body("hello");
+
+        let mut get = Vec::new();
+        response.write_for(Method::Get, &mut get).unwrap();
+        let mut head = Vec::new();
+        response.write_for(Method::Head, &mut head).unwrap();
+
+        let head = String::from_utf8(head).unwrap();
+        assert_eq!(String::from_utf8(get).unwrap(), format!("{head}hello"));
+        assert!(head.contains("\r\nContent-Length: 5\r\n"));
+        assert!(head.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn streamed_body_sent_in_chunks() {
+        let response = Response::streaming(200, |writer| {
+            writer.write_all(b"hello ")?;
+            // nothing is sent for an empty write, it would end the body
+            writer.write_all(b"")?;
+            writer.write_all(b"streamed world")
+        })
+        .with_header("Content-Length", "20");
+
+        let written = written(&response);
+        let (head, body) = written.split_once("\r\n\r\n").unwrap();
+        assert!(head.ends_with("\r\nTransfer-Encoding: chunked"));
+        // the body decides how it's sent, not the handler's headers
+        assert!(!head.contains("Content-Length"));
+        assert_eq!(body, "6\r\nhello \r\nE\r\nstreamed world\r\n0\r\n\r\n");
+        assert_eq!(response.body.len(), None);
+
+        let mut head = Vec::new();
+        response.write_for(Method::Head, &mut head).unwrap();
+        assert!(String::from_utf8(head)
+            .unwrap()
+            .ends_with("\r\nTransfer-Encoding: chunked\r\n\r\n"));
+    }
+
+    #[test]
+    fn failed_stream_left_unterminated() {
+        let response = Response::streaming(200, |writer| {
+            writer.write_all(b"partial")?;
+            Err(io::Error::other("report generation failed"))
+        });
+
+        let mut written = Vec::new();
+        assert!(response.write_to(&mut written).is_err());
+        assert!(written.ends_with(b"\r\n\r\n7\r\npartial\r\n"));
+    }
+
+    // keeps what's written along with the size of the largest single write
+    #[derive(Default)]
+    struct Recorder {
+        written: Vec<u8>,
+        largest_write: usize,
+    }
+
+    impl Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.largest_write = self.largest_write.max(buf.len());
+            self.written.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn large_file_streamed_in_chunks() {
+        let path = env::temp_dir().join(format!("hello-large-{}.bin", process::id()));
+        let content = (0..4 * 1024 * 1024)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        fs::write(&path, &content).unwrap();
+
+        let response = Response::new(200).with_file(&path).unwrap();
+        // only opened so far, nothing read yet
+        assert!(matches!(response.body, Body::File { len, .. } if len == content.len() as u64));
+
+        let mut recorder = Recorder::default();
+        response.write_to(&mut recorder).unwrap();
+        assert!(recorder.largest_write <= 64 * 1024);
+        assert!(recorder.written.ends_with(&content));
+        assert!(
+            String::from_utf8_lossy(&recorder.written).contains("\r\nContent-Length: 4194304\r\n")
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    // bytes of a body that's never in memory as a whole, `i % 251` for the
+    // i-th byte, the pattern doesn't line up with any buffer size
+    struct Generated {
+        at: u64,
+        len: u64,
+    }
+
+    impl Read for Generated {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min((self.len - self.at) as usize);
+
+            for byte in &mut buf[..n] {
+                *byte = (self.at % 251) as u8;
+                self.at += 1;
+            }
+            Ok(n)
+        }
+    }
+
+    // hashes everything written to it instead of keeping it
+    #[derive(Default)]
+    struct Hashed {
+        hasher: DefaultHasher,
+        written: u64,
+    }
+
+    impl Write for Hashed {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.hasher.write(buf);
+            self.written += buf.len() as u64;
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn large_reader_streamed_in_chunks() {
+        let len = 16 * 1024 * 1024;
+        let response = Response::new(200).with_reader(Generated { at: 0, len }, len);
+
+        let mut sent = Hashed::default();
+        response.write_to(&mut sent).unwrap();
+
+        let mut expected = Hashed::default();
+        expected.write_all(response.head().as_bytes()).unwrap();
+        io::copy(&mut Generated { at: 0, len }, &mut expected).unwrap();
+
+        assert_eq!(sent.written, expected.written);
+        assert_eq!(sent.hasher.finish(), expected.hasher.finish());
+        assert!(response.head().contains("\r\nContent-Length: 16777216\r\n"));
+    }
+
+    // takes `left` bytes and fails like a socket whose peer went away after
+    // that
+    struct Disconnecting {
+        left: usize,
+    }
+
+    impl Write for Disconnecting {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.left == 0 {
+                return Err(io::ErrorKind::BrokenPipe.into());
+            }
+
+            let n = buf.len().min(self.left);
+            self.left -= n;
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn client_going_away_mid_body() {
+        let path = env::temp_dir().join(format!("hello-abort-{}.bin", process::id()));
+        fs::write(&path, vec![b'a'; 1024 * 1024]).unwrap();
+
+        let len = 1024 * 1024;
+        let responses = [
+            Response::new(200).with_file(&path).unwrap(),
+            Response::new(200).with_reader(Generated { at: 0, len }, len),
+        ];
+
+        for response in responses {
+            let err = response
+                .write_to(&mut Disconnecting { left: 100 * 1024 })
+                .unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+        }
+        fs::remove_file(path).unwrap();
+
+        // a reader running out early fails the response as well
+        let response = Response::new(200).with_reader(Generated { at: 0, len: 10 }, 20);
+        let err = response.write_to(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn byte_length_of_the_body() {
+        let bytes = [0x89, b'P', b'N', b'G', 0x00, 0xff, 0xfe, b'\r', b'\n'];
+        let mut written = Vec::new();
+        Response::new(200)
+            .with_bytes(bytes)
+            .write_to(&mut written)
+            .unwrap();
+        assert!(written.ends_with(b"\r\nContent-Length: 9\r\n\r\n\x89PNG\x00\xff\xfe\r\n"));
+
+        // 2 bytes for é and 3 for ✓
+        let mut written = Vec::new();
+        Response::new(200)
+            .with_body("<p>café ✓</p>")
+            .write_to(&mut written)
+            .unwrap();
+        assert!(written.ends_with("\r\nContent-Length: 16\r\n\r\n<p>café ✓</p>".as_bytes()));
+    }
+
+    #[test]
+    fn head_leaves_the_body_unread() {
+        let written = Arc::new(AtomicUsize::new(0));
+        let counted = Arc::clone(&written);
+        let response = Response::streaming(200, move |writer| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            writer.write_all(b"streamed")
+        });
+
+        let mut head = Vec::new();
+        response.write_for(Method::Head, &mut head).unwrap();
+        assert_eq!(
+            head,
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n"
+        );
+        assert_eq!(written.load(Ordering::SeqCst), 0);
+
+        // a reader isn't touched either, all of it is still there for a GET
+        let response = Response::new(200).with_reader(&b"from a reader"[..], 13);
+        let mut head = Vec::new();
+        response.write_for(Method::Head, &mut head).unwrap();
+        assert!(head.ends_with(b"\r\nContent-Length: 13\r\n\r\n"));
+
+        let mut get = Vec::new();
+        response.write_for(Method::Get, &mut get).unwrap();
+        assert_eq!(get, [&head[..], b"from a reader"].concat());
+    }
+
+    #[test]
+    fn no_content() {
+        let written = |response: Response| {
+            let mut written = Vec::new();
+            response.write_to(&mut written).unwrap();
+            String::from_utf8(written).unwrap()
+        };
+
+        assert_eq!(
+            written(Response::no_content()),
+            "HTTP/1.1 204 No Content\r\n\r\n"
+        );
+
+        // a body set anyway isn't sent, nor is a Content-Length set by hand
+        assert_eq!(
+            written(
+                Response::new(304)
+                    .with_header("ETag", "\"v1\"")
+                    .with_header("Content-Length", "5")
+                    .with_body("stale")
+            ),
+            "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\n\r\n"
+        );
+        assert_eq!(
+            written(Response::streaming(204, |writer| writer.write_all(b"never"))),
+            "HTTP/1.1 204 No Content\r\n\r\n"
+        );
+
+        // an empty body of any other status still says so
+        assert_eq!(
+            written(Response::new(200)),
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn file_content_type() {
+        let path = env::temp_dir().join(format!("hello-page-{}.html", process::id()));
+        fs::write(&path, "<h1>hello</h1>").unwrap();
+
+        let response = Response::new(200).with_file(&path).unwrap();
+        assert_eq!(
+            response.headers.get("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+
+        // one set already is kept
+        let response = Response::new(200)
+            .with_header("Content-Type", "text/plain")
+            .with_file(&path)
+            .unwrap();
+        assert_eq!(
+            response.headers.get_all("Content-Type").collect::<Vec<_>>(),
+            ["text/plain"]
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    // takes at most a few bytes per write and has every other write fail
+    // with WouldBlock, like a non-blocking socket with a tiny send buffer
+    #[derive(Default)]
+    struct Trickle {
+        written: Vec<u8>,
+        writes: usize,
+    }
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.writes += 1;
+
+            if self.writes.is_multiple_of(2) {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+
+            let len = buf.len().min(3);
+            self.written.extend_from_slice(&buf[..len]);
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn short_writes_retried() {
+        let response = Response::new(200).with_body("hello world");
+        let mut trickle = Trickle::default();
+        response.write_to(&mut trickle).unwrap();
+
+        assert_eq!(
+            String::from_utf8(trickle.written).unwrap(),
+            written(&response)
+        );
+        assert!(trickle.writes > 20);
+    }
+
+    #[test]
+    fn broken_pipe_returned() {
+        struct Closed;
+
+        impl Write for Closed {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::ErrorKind::BrokenPipe.into())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let err = Response::new(200).write_to(&mut Closed).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn error_in_the_accepted_media_type() {
+        let json = parse("GET /missing HTTP/1.1\r\nAccept: application/json\r\n\r\n").unwrap();
+        let json = written(&Response::error(&json, 404));
+        assert!(json.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(json.contains("\r\nContent-Type: application/json\r\n"));
+        assert!(json.ends_with("\r\n\r\n{\"status\":404,\"error\":\"Not Found\"}"));
+
+        let html =
+            parse("GET /missing HTTP/1.1\r\nAccept: text/html, application/json\r\n\r\n").unwrap();
+        let html = written(&Response::error(&html, 404));
+        assert!(html.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(html.contains("\r\nContent-Type: text/html\r\n"));
+        assert!(html.contains("<h1>404 Not Found</h1>"));
+
+        let text = parse("GET /missing HTTP/1.1\r\nAccept: image/png\r\n\r\n").unwrap();
+        let text = written(&Response::error(&text, 404));
+        assert!(text.contains("\r\nContent-Type: text/plain\r\n"));
+        assert!(text.ends_with("\r\n\r\n404 Not Found\n"));
+
+        let wildcard = parse("GET / HTTP/1.1\r\nAccept: text/*;q=0.9\r\n\r\n").unwrap();
+        assert_eq!(
+            wildcard.preferred_media_type(&ERROR_MEDIA_TYPES),
+            Some("text/html")
+        );
+    }
+
+    #[test]
+    fn base64() {
+        assert_eq!(base64_decode("amFuZTpodW50ZXIy").unwrap(), b"jane:hunter2");
+        assert_eq!(base64_decode("YTpiOmM=").unwrap(), b"a:b:c");
+        assert_eq!(base64_decode("YTpiOmM").unwrap(), b"a:b:c");
+        assert_eq!(base64_decode("").unwrap(), b"");
+        assert_eq!(base64_decode("YT*i"), None);
+
+        let request = parse("GET / HTTP/1.1\r\nAuthorization: Basic YTpiOmM=\r\n\r\n").unwrap();
+        // only the first `:` separates the name from the password
+        assert_eq!(
+            request.basic_auth(),
+            Some(("a".to_owned(), "b:c".to_owned()))
+        );
+    }
+
+    #[test]
+    fn constant_time_comparison() {
+        assert!(constant_time_eq(b"hunter2", b"hunter2"));
+        assert!(!constant_time_eq(b"hunter2", b"hunter3"));
+        assert!(!constant_time_eq(b"hunter2", b"hunter"));
+        assert!(constant_time_eq(b"", b""));
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+// sub-buckets every power of two is split into, 2^SUB_BUCKET_BITS of them,
+// so every bucket is at most 1/32nd(about 3%) wider than the durations in it
+const SUB_BUCKET_BITS: u32 = 5;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+// enough for every u64 number of nanoseconds, durations under SUB_BUCKETS
+// nanoseconds get a bucket each, every power of two above gets SUB_BUCKETS
+const BUCKETS: usize = (64 - SUB_BUCKET_BITS as usize + 1) * SUB_BUCKETS;
+
+// percentiles of how long jobs took to run, see ThreadPool::latency_snapshot()
+//
+// each one is the upper bound of the histogram bucket it falls into, so it
+// can be up to about 3% above the actual duration, but never above `max`,
+// all of them are zero until the first job finishes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LatencySnapshot {
+    // jobs recorded, panicked ones included
+    pub count: u64,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+// execution times of jobs in buckets growing with the duration(the way
+// HdrHistogram does it), so that the precision stays the same from
+// microseconds to minutes, recording a duration is a couple of relaxed
+// atomic adds on the worker that ran the job, without a lock, tiny jobs
+// would otherwise spend more time recording than running
+pub(crate) struct LatencyHistogram {
+    buckets: Box<[AtomicU64]>,
+    count: AtomicU64,
+    // in nanoseconds
+    max: AtomicU64,
+}
+
+impl LatencyHistogram {
+    pub(crate) fn new() -> LatencyHistogram {
+        LatencyHistogram {
+            buckets: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn record(&self, elapsed: Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+
+        self.buckets[bucket_index(nanos)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.max.fetch_max(nanos, Ordering::Relaxed);
+    }
+
+    // jobs keep being recorded while the buckets are read, so the
+    // percentiles of a pool that's busy are off by the few jobs that
+    // finished meanwhile
+    pub(crate) fn snapshot(&self) -> LatencySnapshot {
+        let counts: Vec<u64> = self
+            .buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect();
+        let count: u64 = counts.iter().sum();
+        let max = self.max.load(Ordering::Relaxed);
+
+        if count == 0 {
+            return LatencySnapshot::default();
+        }
+
+        let percentile = |quantile: f64| {
+            // number of jobs at or below the percentile, at least 1
+            let rank = ((quantile * count as f64).ceil() as u64).max(1);
+            let mut seen = 0;
+
+            for (index, bucket_count) in counts.iter().enumerate() {
+                seen += bucket_count;
+
+                if seen >= rank {
+                    return Duration::from_nanos(bucket_upper_bound(index).min(max));
+                }
+            }
+
+            Duration::from_nanos(max)
+        };
+
+        LatencySnapshot {
+            count,
+            p50: percentile(0.50),
+            p90: percentile(0.90),
+            p99: percentile(0.99),
+            max: Duration::from_nanos(max),
+        }
+    }
+}
+
+fn bucket_index(nanos: u64) -> usize {
+    if nanos < SUB_BUCKETS as u64 {
+        return nanos as usize;
+    }
+
+    // position of the highest bit set, the bits right below it pick the
+    // sub-bucket
+    let exponent = 63 - nanos.leading_zeros();
+    let sub_bucket = (nanos >> (exponent - SUB_BUCKET_BITS)) as usize & (SUB_BUCKETS - 1);
+
+    (exponent - SUB_BUCKET_BITS + 1) as usize * SUB_BUCKETS + sub_bucket
+}
+
+// largest number of nanoseconds that goes into the bucket at `index`
+fn bucket_upper_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64;
+    }
+
+    let shift = (index / SUB_BUCKETS - 1) as u32;
+    let lower = ((SUB_BUCKETS + index % SUB_BUCKETS) as u64) << shift;
+
+    lower.saturating_add((1 << shift) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreadPool;
+    use std::thread;
+
+    #[test]
+    fn buckets_within_3_percent() {
+        for nanos in (0..100_000).chain([u64::MAX / 3, u64::MAX - 1, u64::MAX]) {
+            let upper_bound = bucket_upper_bound(bucket_index(nanos));
+
+            assert!(upper_bound >= nanos, "{nanos}");
+            assert!(upper_bound - nanos <= nanos / 32, "{nanos}");
+        }
+    }
+
+    #[test]
+    fn percentiles() {
+        let histogram = LatencyHistogram::new();
+        assert_eq!(histogram.snapshot(), LatencySnapshot::default());
+
+        for millis in 1..=100 {
+            histogram.record(Duration::from_millis(millis));
+        }
+
+        let snapshot = histogram.snapshot();
+        let around = |millis: u64, actual: Duration| {
+            let expected = Duration::from_millis(millis);
+            actual >= expected && actual <= expected + expected / 32
+        };
+        assert_eq!(snapshot.count, 100);
+        assert!(around(50, snapshot.p50), "{snapshot:?}");
+        assert!(around(90, snapshot.p90), "{snapshot:?}");
+        assert!(around(99, snapshot.p99), "{snapshot:?}");
+        assert_eq!(snapshot.max, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn percentiles_of_the_pool() {
+        let pool = ThreadPool::new(4);
+
+        // 9 jobs out of 10 sleep for 10ms, the rest for 50ms
+        for job in 0..20 {
+            let millis = if job % 10 == 9 { 50 } else { 10 };
+            pool.execute(move || thread::sleep(Duration::from_millis(millis)));
+        }
+        pool.join();
+
+        let snapshot = pool.latency_snapshot();
+        let slack = Duration::from_millis(20);
+        assert_eq!(snapshot.count, 20);
+        assert!(snapshot.p50 >= Duration::from_millis(10), "{snapshot:?}");
+        assert!(
+            snapshot.p90 < Duration::from_millis(10) + slack,
+            "{snapshot:?}"
+        );
+        assert!(snapshot.p99 >= Duration::from_millis(50), "{snapshot:?}");
+        assert!(
+            snapshot.max < Duration::from_millis(50) + slack,
+            "{snapshot:?}"
+        );
+    }
+}
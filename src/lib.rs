@@ -1,135 +1,5085 @@
+mod affinity;
+pub mod auth;
+#[cfg(feature = "async")]
+mod blocking;
+mod builder;
+mod classes;
+mod context;
+pub mod cors;
+mod dispatcher;
+#[cfg(feature = "futures")]
+mod executor;
+mod global;
+mod group;
+mod handle;
+pub mod http;
+#[cfg(feature = "latency")]
+mod latency;
+pub mod mime;
+mod queue;
+mod retry;
+pub mod router;
+pub mod server;
+mod snapshot;
+pub mod static_files;
+mod stats;
+#[cfg(feature = "metrics")]
+mod telemetry;
+mod timer;
+mod watchdog;
+mod watermark;
+
+#[cfg(feature = "async")]
+pub use blocking::BlockingFuture;
+pub use builder::{
+    DispatchMode, IdleStrategy, PoolCreationError, ReentrantFull, ThreadPoolBuilder,
+};
+pub use context::{Handle, QueueFull, WorkerContext};
+pub use global::GlobalPoolError;
+pub use group::JobGroup;
+pub use handle::ThreadPoolHandle;
+#[cfg(feature = "latency")]
+pub use latency::LatencySnapshot;
+pub use queue::{Job, JobQueue, MpscQueue, PushError, VecDequeQueue};
+pub use retry::{Backoff, RetryError, RetryHandle, RetryPolicy};
+pub use snapshot::QueuedJobInfo;
+pub use stats::{DurationStats, PoolStats, ShutdownReport, BUCKET_BOUNDS};
+
+use builder::{AfterHook, StartHook};
+use classes::JobClasses;
+use log::{debug, error, info, warn};
+use queue::Task;
+use retry::Retry;
+use snapshot::PendingJobs;
 use std::{
-    sync::{mpsc, Arc, Mutex},
+    any::Any,
+    collections::{HashMap, VecDeque},
+    error::Error,
+    fmt, hint, io, mem,
+    ops::ControlFlow,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{self, AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc, Condvar, Mutex, OnceLock, TryLockError, Weak,
+    },
     thread::{self},
+    time::{Duration, Instant},
 };
+use timer::Timer;
+use watchdog::{CurrentJob, Watchdog};
+use watermark::{Crossing, QueueWatermarks};
 
 pub struct ThreadPool {
-    // sends closure jobs for execution to receivers inside spawned threads,
-    // wrapped in Option type to make it easily destroyable by swapping the
-    // Some variant with None variant, we want it to be destroyable because
-    // sender being destroyed signals the receivers that no more messages
-    // are to be received, therefore signalling them to halt their process
-    // of listening for messages sent by the sender
-    // .recv() method on receivers blocks the thread execution and waits for
-    // messages to be sent by the sender, when sender goes out of scope(destroyed)
-    // .recv() returns an Err variant, which gives us the programmer a lean way
-    // for gracefully shutting down whatever task we were doing with the receiver
-    sender: Option<mpsc::Sender<Job>>,
     threads: Vec<Option<(usize, thread::JoinHandle<()>)>>,
+    shared: Arc<Shared>,
+    // see ThreadPoolBuilder::watchdog()
+    watchdog: Option<Watchdog>,
+    // see DispatchMode::RoundRobin
+    dispatcher: Option<thread::JoinHandle<()>>,
 }
 
-impl ThreadPool {
-    pub fn execute<F>(&self, f: F)
+// state shared between the pool, its worker threads and the job closures,
+// none of its locks are held while a job runs but they're never unwrapped
+// either, a lock can still end up poisoned by code of the user running
+// under it, e.g. a JobQueue implementation panicking in push(), and the
+// rest of the pool has to keep working past that rather than panicking on
+// every lock after it
+struct Shared {
+    // see ThreadPoolBuilder::name(), threads of the pool are named after it
+    name: Arc<str>,
+    // jobs waiting for a worker, see JobQueue and
+    // ThreadPoolBuilder::job_queue(), the workers never wait inside it, a
+    // worker with nothing to run waits in `sleepers` for whoever submits the
+    // next job to wake it, it's closed once the pool shuts down, the workers
+    // finish the jobs still in it and exit, a job submitted after that, e.g.
+    // by a future waking itself up(see executor.rs), is handed back instead
+    // of queued
+    queue: Box<dyn JobQueue>,
+    // jobs of execute_classed(), workers take jobs from the classes and the
+    // queue by weight
+    classes: JobClasses,
+    // names and ages of the jobs counted as queued, see
+    // ThreadPool::queue_snapshot()
+    pending: PendingJobs,
+    // jobs submitted from inside jobs while a bounded queue was full, see
+    // Handle, every worker checks it before waiting on the queue
+    overflow: Mutex<VecDeque<Job>>,
+    // number of jobs waiting to be run and jobs currently running, both
+    // behind a single Mutex so that they are always read together
+    // consistently, `idle` is notified whenever both of them reach 0
+    counters: Mutex<Counters>,
+    idle: Condvar,
+    // bookkeeping for jobs submitted through execute_limited(), maps a key
+    // to the number of its jobs currently running and the jobs of that key
+    // which were dequeued while the key was already at its limit, shared
+    // with the job closures themselves since they do the admission checks
+    limits: Mutex<HashMap<String, KeyedJobs>>,
+    // bookkeeping for jobs submitted through execute_serial(), a key is in
+    // the map for as long as one of its jobs is in flight, mapped to the
+    // jobs of that key submitted since, in submission order
+    serial: Mutex<HashMap<u64, VecDeque<Task>>>,
+    // one entry per worker, the worker with id `n` is at index `n - 1`,
+    // there's one for every thread the pool can grow to, see
+    // ThreadPoolBuilder::max_threads()
+    workers: Vec<WorkerState>,
+    // number of workers started along with the pool, these are never
+    // stopped for being idle, the ones after them are only started once
+    // every worker is busy
+    core_workers: usize,
+    // id of the worker at index 0 of `workers`, the others counting up from
+    // it, see ThreadPoolBuilder::first_worker_id()
+    first_worker_id: usize,
+    // see ThreadPoolBuilder::keep_alive()
+    keep_alive: Option<Duration>,
+    // see ThreadPoolBuilder::idle_sample_interval()
+    idle_sample_interval: Duration,
+    // time every worker spent waiting for a job, added up, see
+    // PoolStats::total_idle
+    total_idle_nanos: AtomicU64,
+    // how workers started after the pool was built are set up
+    worker_config: WorkerConfig,
+    // threads of the workers after the core ones, indexed by their index
+    // minus `core_workers`, joined before a worker is started in the same
+    // slot again and when the pool is dropped
+    extra_threads: Mutex<Vec<Option<thread::JoinHandle<()>>>>,
+    // threads started in place of workers that ran max_jobs_per_worker()
+    // jobs, paired with the id of the worker, see Shared::replace_worker()
+    replacement_threads: Mutex<Vec<(usize, thread::JoinHandle<()>)>>,
+    // lets methods taking &self start workers, which need a reference of
+    // their own to the pool
+    this: Weak<Shared>,
+    // see ThreadPoolBuilder::caller_runs()
+    caller_runs: bool,
+    // see ThreadPoolBuilder::on_reentrant_full()
+    on_reentrant_full: ReentrantFull,
+    // see ThreadPoolBuilder::warn_on_drop()
+    warn_on_drop: bool,
+    // see ThreadPoolBuilder::inline()
+    inline: bool,
+    // ids of the workers that were taken down by a panic, see
+    // ShutdownReport::panicked_workers
+    panicked_workers: Mutex<Vec<usize>>,
+    // which workers are waiting for a job, rather than waiting inside the
+    // queue they wait on a Condvar of their own, so that a job meant for a
+    // particular worker(execute_keyed()) can wake up that very worker
+    sleepers: Mutex<Sleepers>,
+    // number of workers asleep, lets submitting skip the `sleepers` lock
+    // while every worker is busy, which is most of the time under load
+    asleep: AtomicUsize,
+    // see ThreadPoolBuilder::max_queue_age()
+    max_queue_age: Option<Duration>,
+    // see ThreadPoolBuilder::on_queue_high() and on_queue_low()
+    watermarks: QueueWatermarks,
+    // set whenever a job is queued, workers spinning with
+    // IdleStrategy::SpinThenPark only look at the queue once it's set
+    // instead of taking turns at it in a tight loop
+    work_available: AtomicBool,
+    // set once the queue is closed
+    closed: AtomicBool,
+    // set by ThreadPool::close(), the pool only takes jobs from its own
+    // workers after that
+    closed_to_new_jobs: AtomicBool,
+    // set by ThreadPool::shutdown_with(ShutdownMode::Abandon), workers stop
+    // once they're done with the job they're running
+    abandoned: AtomicBool,
+    // jobs to be queued later, see Timer
+    timer: Timer,
+    // only with DispatchMode::RoundRobin, see dispatcher.rs
+    dispatcher: Option<dispatcher::Dispatcher>,
+    // see ThreadPool::latency_snapshot()
+    #[cfg(feature = "latency")]
+    latency: latency::LatencyHistogram,
+    #[cfg(feature = "metrics")]
+    metrics: telemetry::PoolMetrics,
+    created_at: Instant,
+}
+
+// per-worker counters which the worker updates itself, atomics rather than
+// part of Counters so that workers don't contend over them
+#[derive(Default)]
+struct WorkerState {
+    jobs_completed: AtomicUsize,
+    // set once by the worker itself after pinning itself to a core
+    pinned_core: OnceLock<usize>,
+    // the thread running as this worker, set by the thread itself when it
+    // starts and cleared when it exits, see ThreadPool::worker_thread_ids()
+    thread_id: Mutex<Option<thread::ThreadId>>,
+    // the job the worker is running, None while it's waiting for one
+    current_job: Mutex<Option<CurrentJob>>,
+    // jobs only this worker runs, see execute_keyed()
+    mailbox: Mutex<Mailbox>,
+    // notified with the `sleepers` lock when the worker has to wake up
+    wake: Condvar,
+    // set when the worker stops for having been idle for too long, see
+    // Shared::retire_idle()
+    retired: AtomicBool,
+    // tells the worker to exit once it's done with its current job, see
+    // ThreadPool::join_worker()
+    stop: AtomicBool,
+}
+
+// settings every worker starts with
+#[derive(Clone)]
+struct WorkerConfig {
+    pin_workers: bool,
+    batch_size: usize,
+    idle_strategy: IdleStrategy,
+    on_thread_start: Option<StartHook>,
+    before_each: Option<StartHook>,
+    after_each: Option<AfterHook>,
+    // see ThreadPoolBuilder::max_jobs_per_worker()
+    max_jobs_per_worker: Option<usize>,
+}
+
+#[derive(Default)]
+struct Mailbox {
+    jobs: VecDeque<Job>,
+    // set once the worker exits, jobs can't be left for it after that, the
+    // slot of a worker that's started on demand is closed until it starts
+    closed: bool,
+}
+
+struct Sleepers {
+    // indexed like Shared::workers
+    asleep: Vec<bool>,
+    // set when the pool is dropped, the workers exit once they run out of
+    // jobs after that instead of going to sleep
+    closed: bool,
+}
+
+// what a single worker has been up to, returned by ThreadPool::workers()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerStatus {
+    pub id: usize,
+    // jobs that ran to completion on this worker, with a single queue
+    // shared by every worker nothing guarantees an even spread, a worker
+    // that keeps winning the race for the lock on the queue can end up with
+    // most of the jobs when they are short
+    pub jobs_completed: usize,
+    // the cpu core the worker is pinned to, None unless pinning was enabled
+    // with ThreadPoolBuilder::pin_workers() and actually worked
+    pub pinned_core: Option<usize>,
+}
+
+#[derive(Default)]
+struct Counters {
+    queued: usize,
+    running: usize,
+    // worker threads that are up and running, running can never exceed it
+    // since both are only changed with the lock held
+    alive: usize,
+    // worker threads that were started and haven't stopped yet, unlike
+    // `alive` this includes threads that haven't started running yet, it's
+    // what decides whether another worker has to be started
+    started: usize,
+    // jobs waiting for the time they're due to be queued at, see Timer
+    delayed: usize,
+    completed: u64,
+    panicked: u64,
+    expired: u64,
+    // jobs submitted from one of the pool's own workers, i.e. by other jobs
+    submitted_by_workers: u64,
+    ran_inline: u64,
+    // whether `queued` went past the on_queue_high() threshold and hasn't
+    // gone back under the on_queue_low() one since
+    queue_high: bool,
+    // recorded here rather than behind a lock of their own since workers
+    // already lock the counters when picking up and finishing a job
+    queue_wait: DurationStats,
+    execution_time: DurationStats,
+}
+
+impl Counters {
+    fn has_pending(&self) -> bool {
+        self.queued > 0 || self.running > 0 || self.delayed > 0
+    }
+}
+
+// work left in the pool when ThreadPool::join_timeout() gave up waiting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PendingWork {
+    // jobs submitted but not picked up by a worker yet
+    pub queued: usize,
+    // jobs being run by a worker
+    pub running: usize,
+    // jobs waiting for a retry, see ThreadPool::execute_with_retry()
+    pub delayed: usize,
+}
+
+impl fmt::Display for PendingWork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "timed out with {} jobs queued, {} jobs running and {} jobs waiting to be retried",
+            self.queued, self.running, self.delayed
+        )
+    }
+}
+
+impl Error for PendingWork {}
+
+// returned by ThreadPool::run() when the closure panicked instead of
+// returning a value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobPanicked {
+    // the message the closure panicked with, panics with something other
+    // than a string, e.g. std::panic::panic_any(42), only get a placeholder
+    pub message: String,
+}
+
+impl JobPanicked {
+    fn from_payload(payload: Box<dyn Any + Send>) -> JobPanicked {
+        let message = if let Some(message) = payload.downcast_ref::<&str>() {
+            (*message).to_owned()
+        } else if let Some(message) = payload.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "Box<dyn Any>".to_owned()
+        };
+
+        JobPanicked { message }
+    }
+}
+
+impl fmt::Display for JobPanicked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "job panicked: {}", self.message)
+    }
+}
+
+impl Error for JobPanicked {}
+
+// returned by ThreadPool::try_execute(), ThreadPool::execute_on() and
+// ThreadPoolHandle::try_execute() when the pool doesn't take the job
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoolError {
+    // the pool has no worker with that id, ids count up from 1, or from
+    // ThreadPoolBuilder::first_worker_id(), one for every worker
+    NoSuchWorker(usize),
+    // the worker isn't running anymore, it panicked
+    WorkerStopped(usize),
+    // the pool was closed to new jobs(see ThreadPool::close()) or dropped
+    ShutDown,
+    // the queue is full and the job was submitted from one of the pool's
+    // own workers, see ReentrantFull::Error
+    QueueFull,
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::NoSuchWorker(id) => write!(f, "thread pool has no worker {id}"),
+            PoolError::WorkerStopped(id) => write!(f, "thread pool worker {id} has stopped"),
+            PoolError::ShutDown => f.write_str("thread pool is closed to new jobs"),
+            PoolError::QueueFull => f.write_str("thread pool queue is full"),
+        }
+    }
+}
+
+impl Error for PoolError {}
+
+// returned by ThreadPool::try_execute_returning() and
+// ThreadPoolHandle::try_execute_returning(), the job the pool didn't take,
+// handed back untouched so the caller can still run it some other way
+pub struct Rejected<F> {
+    pub error: PoolError,
+    pub job: F,
+}
+
+impl<F> fmt::Debug for Rejected<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rejected")
+            .field("error", &self.error)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F> fmt::Display for Rejected<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl<F> Error for Rejected<F> {}
+
+// how ThreadPool::shutdown_with() deals with the jobs still queued
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShutdownMode {
+    // runs every queued job before the workers exit, same as dropping the
+    // pool
+    #[default]
+    Drain,
+    // the workers exit as soon as they're done with the job they're
+    // running, the jobs still queued are thrown away without running
+    Abandon,
+}
+
+// how Shared::enqueue() deals with a bounded queue that is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum WhenFull {
+    // wait for a worker to make room
+    Block,
+    // put the job aside for the workers to take before the queue
+    Overflow,
+    // hand the job back
+    Fail,
+}
+
+impl Shared {
+    // see ThreadPool::execute(), the job is dropped without running if the
+    // pool doesn't take it, see ThreadPool::close() and ReentrantFull::Error
+    fn execute(&self, job: Task) -> Result<(), PoolError> {
+        let is_worker = context::is_worker_of(self);
+
+        if !self.takes_jobs(is_worker) {
+            return Err(PoolError::ShutDown);
+        }
+
+        let deadline = self.max_queue_age.map(|age| Instant::now() + age);
+
+        if is_worker && self.on_reentrant_full != ReentrantFull::Overflow {
+            // the queue of a pool that's shutting down takes jobs of its
+            // workers regardless, so the job coming back means it's full
+            return match self.enqueue(job, deadline, None, WhenFull::Fail) {
+                Ok(()) => Ok(()),
+                Err(job) if self.on_reentrant_full == ReentrantFull::Inline => {
+                    match context::worker_id_of(self) {
+                        // past MAX_INLINE_DEPTH the job goes past the
+                        // capacity instead, a chain of jobs each submitting
+                        // the next one while the queue stays full would
+                        // otherwise nest every step on the worker's stack
+                        // until it overflows
+                        Some(id) if context::inline_depth() < MAX_INLINE_DEPTH => {
+                            self.run_nested(id, job, deadline);
+                        }
+                        // can't fail, jobs that don't fit are put aside for
+                        // the workers
+                        _ => {
+                            let _ = self.enqueue(job, deadline, None, WhenFull::Overflow);
+                        }
+                    }
+                    Ok(())
+                }
+                Err(_) => Err(PoolError::QueueFull),
+            };
+        }
+
+        if self.caller_runs && !is_worker {
+            // the queue is only ever closed when the pool is dropped, which
+            // takes_jobs() already ruled out, so the job coming back means
+            // the queue is full
+            if let Err(job) = self.enqueue(job, deadline, None, WhenFull::Fail) {
+                self.run_inline(job);
+            }
+
+            return Ok(());
+        }
+
+        // the pool only ever stops taking jobs here once it's being dropped
+        self.submit(job, deadline).map_err(|_| PoolError::ShutDown)
+    }
+
+    // same as execute() but hands `f` back when the pool doesn't take it,
+    // see ThreadPool::try_execute_returning(), the job only ever takes `f`
+    // out of the slot to run it, so one that was dropped without running
+    // leaves it there to be handed back
+    fn execute_returning<F>(&self, f: F) -> Result<(), Rejected<F>>
     where
-        // any type F which implementation these traits can be passed in as the argument to this method
         F: FnOnce() + Send + 'static,
     {
-        // Box is needed to hold the trait object because, it has no
-        // definite known size at compile time, therefore rust compiler
-        // will fail to compile it unless it is stored on the heap using
-        // Box smart pointer
-        let job = Box::new(f);
-        // as_ref() just gives back an immutable reference to sender here
-        self.sender.as_ref().unwrap().send(job).unwrap();
+        let slot = Arc::new(Mutex::new(Some(f)));
+        let in_job = Arc::clone(&slot);
+        let job = Box::new(move || {
+            let f = in_job
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .take();
+
+            if let Some(f) = f {
+                f();
+            }
+        });
+
+        self.execute(job).map_err(|error| {
+            let job = slot
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .take()
+                .expect("a job the pool didn't take never runs");
+            Rejected { error, job }
+        })
     }
 
-    pub fn new(size: usize) -> ThreadPool {
-        // makes sure that there is at least 1 thread in the thread pool,
-        // panics if 0 is provided as the value for number of threads
-        assert!(size > 0);
+    // runs a job submitted by the worker with `id` right away inside the
+    // job submitting it, the same way the worker runs the jobs it takes off
+    // the queue, its panic is caught and counted as its own rather than
+    // unwinding into the job that submitted it, see PoolStats::jobs_ran_inline
+    fn run_nested(&self, id: usize, task: Task, deadline: Option<Instant>) {
+        let job = self.new_job(task, deadline, None);
+        // counted as queued in between like a job on its way to a worker
+        self.update_counters(|counters| {
+            counters.queued += 1;
+            counters.ran_inline += 1;
+        });
 
-        let (sender, receiver) = mpsc::channel::<Job>();
-        // since receiver itself cannot
-        // be cloned unlike sender.clone(), following the principle
-        // multiple producer single consumer(mpsc), we can have multiple
-        // instaces of sender by cloning it directly, but only a single
-        // instace of receiver, Arc lets us have multiple instances of
-        // receiver across threads in a thread-safe way, using Mutex to
-        // make sure at any time only a single thread can access the
-        // received messages queue stored in receiver
-        let receiver = Arc::new(Mutex::new(receiver));
-        let mut threads = Vec::with_capacity(size);
+        let _inlined = context::enter_inline();
+        run_job(self, id, job);
+    }
 
-        for id in 1..=size {
-            let receiver = Arc::clone(&receiver);
-            // here loop keyword is used to create a implicit loop closure
-            // that runs as long as it is not terminated by calling the
-            // break statement inside it, the looping is done basically to
-            // keep checking the receiver queue for new messages sent by
-            // the thread pool sender, loops internal to the closure scope
-            // are not used because they would make this thread own the
-            // and not release the receiver lock till the closure is terminated,
-            // basically making our multi-threaded implementation single-threaded,
-            // this has something to do with `temporary` value in rust which is
-            // dropped as soon as it is used, for example using values returned
-            // by a function in an expression
-            let thread = thread::spawn(move || loop {
-                let message = receiver.lock().unwrap().recv();
-
-                match message {
-                    Ok(job) => {
-                        println!("Thread {id} got a job; executing.");
-                        job();
-                    }
-                    Err(_) => {
-                        break;
-                    }
+    // runs a job on the thread submitting it rather than queuing it, see
+    // PoolStats::jobs_ran_inline
+    fn run_inline(&self, job: Task) {
+        self.update_counters(|counters| counters.ran_inline += 1);
+        job();
+    }
+
+    // runs a job on the calling thread right away as the worker with `id`,
+    // see ThreadPoolBuilder::inline(), counted as queued in between like a
+    // job on its way to a worker, hands the job back once the pool is
+    // shutting down, unless it's submitted by another job
+    fn run_in_place(
+        &self,
+        id: usize,
+        task: Task,
+        deadline: Option<Instant>,
+        name: Option<Arc<str>>,
+    ) -> Result<(), Task> {
+        if self.closed.load(Ordering::Acquire) && !context::is_worker_of(self) {
+            return Err(task);
+        }
+
+        let job = self.new_job(task, deadline, name);
+        self.update_counters(|counters| {
+            counters.queued += 1;
+            counters.ran_inline += 1;
+        });
+
+        // tells the job which worker it runs as(WorkerContext::current()),
+        // and the jobs it submits are taken like those of a worker thread
+        let _entered = self
+            .this
+            .upgrade()
+            .map(|this| context::enter_for_job(id, &this));
+        run_job(self, id, job);
+        Ok(())
+    }
+
+    // whether a job submitted from the calling thread is taken, jobs of the
+    // pool's own workers always are, the jobs already submitted could
+    // otherwise never finish, see ThreadPool::close()
+    fn takes_jobs(&self, is_worker: bool) -> bool {
+        is_worker
+            || !(self.closed_to_new_jobs.load(Ordering::Acquire)
+                || self.closed.load(Ordering::Acquire))
+    }
+
+    // sends a job to the workers, hands the job back if the pool is already
+    // shutting down and can't run it anymore, a job with a deadline is
+    // dropped instead of run if no worker got to it by then
+    //
+    // waits for room when the queue is bounded and full, except on one of
+    // the pool's own workers, where waiting could deadlock the pool, see
+    // Handle, jobs submitted there go past the capacity instead
+    fn submit(&self, job: Task, deadline: Option<Instant>) -> Result<(), Task> {
+        self.submit_named(job, deadline, None)
+    }
+
+    // same as submit() for a job with a name, see ThreadPool::execute_named()
+    fn submit_named(
+        &self,
+        job: Task,
+        deadline: Option<Instant>,
+        name: Option<Arc<str>>,
+    ) -> Result<(), Task> {
+        let when_full = if context::is_worker_of(self) {
+            WhenFull::Overflow
+        } else {
+            WhenFull::Block
+        };
+
+        self.enqueue(job, deadline, name, when_full)
+    }
+
+    // hands the job back if it couldn't be queued, which is only ever the
+    // case when the pool is shutting down with WhenFull::Block and when the
+    // queue is full with WhenFull::Fail, WhenFull::Overflow is only used on
+    // the pool's own workers, which drain the overflow before exiting
+    fn enqueue(
+        &self,
+        job: Task,
+        deadline: Option<Instant>,
+        name: Option<Arc<str>>,
+        when_full: WhenFull,
+    ) -> Result<(), Task> {
+        if self.inline {
+            return self.run_in_place(self.first_worker_id, job, deadline, name);
+        }
+
+        let job = self.new_job(job, deadline, name);
+        let seq = job.seq;
+        let by_worker = context::is_worker_of(self);
+
+        // counted before sending, otherwise a fast worker could pick the
+        // job up and try to move it out of queued before it was ever in it
+        let ((), crossing) = self.update_counters_deferred(|counters| {
+            counters.queued += 1;
+
+            if by_worker {
+                counters.submitted_by_workers += 1;
+            }
+        });
+
+        let result = match when_full {
+            WhenFull::Block => self.queue.push(job),
+            _ => match self.queue.try_push(job) {
+                Ok(()) => Ok(()),
+                Err(PushError::Full(job)) if when_full == WhenFull::Fail => Err(job),
+                // jobs of a pool that's shutting down submitted by one of its
+                // workers still run, the workers drain the overflow before
+                // exiting
+                Err(PushError::Full(job) | PushError::Closed(job)) => {
+                    self.push_overflow(job);
+                    Ok(())
+                }
+            },
+        };
+
+        // callbacks are fired only once the job is queued, a callback that
+        // submits a job to a full queue would otherwise wait for room while
+        // this job is still waiting to be queued itself
+        crossing.fire();
+
+        if result.is_ok() {
+            self.work_available.store(true, Ordering::Release);
+            self.wake_one();
+        }
+
+        result.map_err(|job| {
+            self.pending.remove(seq);
+            self.update_counters(|counters| {
+                counters.queued -= 1;
+
+                if by_worker {
+                    counters.submitted_by_workers -= 1;
                 }
             });
+            job.task
+        })
+    }
+
+    // next job put past the capacity of the queue, workers take these before
+    // going back to the queue
+    fn pop_overflow(&self) -> Option<Job> {
+        self.overflow
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .pop_front()
+    }
+
+    // whether there are jobs waiting for any worker to take them
+    fn has_queued_jobs(&self) -> bool {
+        !self.queue.is_empty()
+            || !self.classes.is_empty()
+            || !self
+                .overflow
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .is_empty()
+    }
+
+    // whether the workers take jobs only out of their mailboxes, which the
+    // dispatcher puts them in, see DispatchMode::RoundRobin, the workers
+    // drain the queue themselves once the pool is shutting down
+    fn dispatching(&self) -> bool {
+        self.dispatcher.is_some() && !self.closed.load(Ordering::Acquire)
+    }
+
+    // puts a job that was already counted as queued back in the queue, or
+    // aside for the workers when that's not possible
+    fn requeue(&self, job: Job) {
+        // this runs while unwinding from a panicking worker, so it can't wait
+        // for room, nor panic, a second panic would abort the process
+        match self.queue.try_push(job) {
+            Ok(()) => self.wake_one(),
+            Err(err) => self.push_overflow(err.into_job()),
+        }
+    }
 
-            threads.push(Some((id, thread)));
+    fn push_overflow(&self, job: Job) {
+        match self.overflow.lock() {
+            Ok(mut overflow) => overflow.push_back(job),
+            Err(poisoned) => poisoned.into_inner().push_back(job),
         }
 
-        // explicit drop of receiver not required here because it is
-        // dropped anyway afer this function's scope ends, but always
-        // remember to never have a valid instance of receiver, because
-        // as long as it exists in the memory, sender will assume that
-        // receiver is still accepting messages which might not be the
-        // desired behaviour in many cases
+        self.wake_one();
+    }
+
+    // queues a job of `class`, see ThreadPool::execute_classed()
+    fn submit_classed(&self, class: &str, job: Task, deadline: Option<Instant>) {
+        if self.inline {
+            let _ = self.run_in_place(self.first_worker_id, job, deadline, None);
+            return;
+        }
 
-        // drop(receiver);
+        let job = self.new_job(job, deadline, None);
 
-        ThreadPool {
-            sender: Some(sender),
-            threads,
+        // counted before it's in its class's queue for the same reason as
+        // in enqueue()
+        let ((), crossing) = self.update_counters_deferred(|counters| counters.queued += 1);
+        self.classes.push(class, job);
+        crossing.fire();
+
+        self.work_available.store(true, Ordering::Release);
+        self.wake_one();
+    }
+
+    // a job as it's put in the queue, listed in `pending` until a worker
+    // picks it up, so it has to be counted as queued along with it
+    fn new_job(&self, task: Task, deadline: Option<Instant>, name: Option<Arc<str>>) -> Job {
+        let queued_at = Instant::now();
+        let seq = self.pending.add(name.clone(), queued_at);
+
+        Job {
+            task,
+            queued_at,
+            deadline,
+            name,
+            seq,
         }
     }
-}
 
-impl Drop for ThreadPool {
-    // called whenever the associated thread pool object goes out of scope,
-    // here we need a custom implementation for it because we don't want
-    // to end the program abruptly, if there are messages remaining in the
-    // queue sent from the thread pool sender or if the threads are currently
-    // processing a request, they need to be handled before the server is
-    // shut down, so basically we're trying to gracefully shut down the
-    // server instead of shutting it down abruptly
-    fn drop(&mut self) {
-        // signals the receivers passed to threads in thread pool,
-        // that it has been dropped and for them to stop listening
-        // for new messages, so calling .recv() method on receivers
-        // results in an Err variant being returned, Err variant is
-        // a programmatic signal to the programmer to halt the execution
-        // of the thread closure
-        drop(self.sender.take());
+    // leaves a job for the worker at `index` alone to run, hands it back
+    // if that worker isn't running anymore, see execute_keyed() and
+    // execute_on()
+    fn send_to(&self, index: usize, task: Task, deadline: Option<Instant>) -> Result<(), Task> {
+        let mut mailbox = self.workers[index]
+            .mailbox
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
 
-        for thread in &mut self.threads {
-            // for each Some variant that holds a thread in thread pool
-            // we call thread.join().unwrap() for main() thread to wait
-            // for the spawned thread to finish it's processing successfully,
-            // ignores the None variant
-            if let Some((thread_id, thread)) = thread.take() {
-                if !thread.is_finished() {
-                    // this is synchronous and halts the thread it is
-                    // called in(main thread) here, until the thread it references
-                    // comes to a halt by completing its closure logic execution
-                    thread.join().unwrap();
+        if mailbox.closed {
+            return Err(task);
+        }
+
+        if self.inline {
+            drop(mailbox);
+            return self.run_in_place(self.worker_id(index), task, deadline, None);
+        }
+
+        let job = self.new_job(task, deadline, None);
+
+        // counted before it's in the mailbox for the same reason as in
+        // enqueue(), the mailbox lock is held meanwhile so that the worker
+        // can't close it in between
+        let ((), crossing) = self.update_counters_deferred(|counters| counters.queued += 1);
+        mailbox.jobs.push_back(job);
+        drop(mailbox);
+        crossing.fire();
+
+        self.work_available.store(true, Ordering::Release);
+        self.wake(index);
+        Ok(())
+    }
+
+    // index in `workers` of the worker with `id`
+    fn index_of(&self, id: usize) -> usize {
+        id - self.first_worker_id
+    }
+
+    // id of the worker at `index` in `workers`
+    fn worker_id(&self, index: usize) -> usize {
+        self.first_worker_id + index
+    }
+
+    fn worker(&self, id: usize) -> &WorkerState {
+        &self.workers[self.index_of(id)]
+    }
+
+    fn has_worker(&self, id: usize) -> bool {
+        id.checked_sub(self.first_worker_id)
+            .is_some_and(|index| index < self.workers.len())
+    }
+
+    // the next job for the worker with `id` to run, jobs left for that
+    // worker in particular come first, then the ones put aside while the
+    // queue was full, then the queue and the job classes by weight
+    fn next_job(&self, id: usize) -> Option<Job> {
+        if self.dispatching() {
+            let (job, emptied) = {
+                let mut mailbox = self
+                    .worker(id)
+                    .mailbox
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                let job = mailbox.jobs.pop_front();
+                (job, mailbox.jobs.is_empty())
+            };
+
+            // the dispatcher might be waiting for a free worker
+            if let (Some(_), true, Some(dispatcher)) = (&job, emptied, &self.dispatcher) {
+                dispatcher.notify();
+            }
+
+            return job;
+        }
+
+        self.worker(id)
+            .mailbox
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .jobs
+            .pop_front()
+            .or_else(|| self.pop_overflow())
+            .or_else(|| self.classes.pop(&*self.queue))
+    }
+
+    // puts the worker with `id` to sleep until there's a job for it
+    fn wait_for_job(&self, id: usize, idle_since: &mut Instant) -> Received {
+        // gives whoever is submitting jobs a chance to submit the next one
+        // first, waking a worker for every single job costs a lot more than
+        // the job itself when they're small
+        for _ in 0..SLEEP_BACKOFF {
+            thread::yield_now();
+
+            if let Some(job) = self.next_job(id) {
+                return Received::Job(job);
+            }
+        }
+
+        let index = self.index_of(id);
+        let mut sleepers = self
+            .sleepers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        // workers after the core ones stop once they've been idle for long
+        // enough, see ThreadPoolBuilder::keep_alive()
+        let retire_at = self
+            .keep_alive
+            .filter(|_| index >= self.core_workers)
+            .map(|keep_alive| Instant::now() + keep_alive);
+
+        loop {
+            // counted as asleep before checking for jobs one last time, a
+            // job submitted after the check sees the worker asleep and wakes
+            // it up, see wake_one()
+            if !sleepers.asleep[index] {
+                sleepers.asleep[index] = true;
+                self.asleep.fetch_add(1, Ordering::SeqCst);
+            }
+            atomic::fence(Ordering::SeqCst);
+
+            // a worker told to stop leaves the jobs to the others
+            let stopping = self.worker(id).stop.load(Ordering::SeqCst);
+            let job = if stopping { None } else { self.next_job(id) };
+            let timed_out = retire_at.is_some_and(|retire_at| Instant::now() >= retire_at);
+
+            if job.is_some() || sleepers.closed || stopping || timed_out {
+                // still marked as asleep unless someone woke it up already
+                if sleepers.asleep[index] {
+                    sleepers.asleep[index] = false;
+                    self.asleep.fetch_sub(1, Ordering::SeqCst);
                 }
-                println!("Thread {} disconnected; shutting down.", thread_id);
+
+                return match job {
+                    Some(job) => Received::Job(job),
+                    None if sleepers.closed || stopping => Received::Closed,
+                    None => Received::TimedOut,
+                };
             }
+
+            // wakes up every idle_sample_interval() regardless, only to
+            // count the time it has been idle so far, it goes right back
+            // to sleep after that
+            let timeout = match retire_at {
+                Some(retire_at) => retire_at
+                    .saturating_duration_since(Instant::now())
+                    .min(self.idle_sample_interval),
+                None => self.idle_sample_interval,
+            };
+            sleepers = self
+                .worker(id)
+                .wake
+                .wait_timeout(sleepers, timeout)
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .0;
+            self.record_idle(idle_since);
         }
     }
-}
 
-// type alias for a Job trait object stored on the heap using Box smart pointer
-type Job = Box<dyn FnOnce() + Send + 'static>;
+    // counts the time since `idle_since` as time a worker spent idle, and
+    // starts counting again from now
+    fn record_idle(&self, idle_since: &mut Instant) {
+        let now = Instant::now();
+        let idle = now.duration_since(*idle_since);
+
+        self.total_idle_nanos.fetch_add(
+            u64::try_from(idle.as_nanos()).unwrap_or(u64::MAX),
+            Ordering::Relaxed,
+        );
+        *idle_since = now;
+    }
+
+    // stops counting the idle worker with `id` as started unless there
+    // are jobs queued, e.g. behind others of the same key in
+    // execute_serial(), in which case it has to stay around for them, the
+    // mailbox lock is held meanwhile so that no job can be left for it
+    // in between, jobs left for it are counted as queued
+    fn retire_idle(&self, id: usize) -> bool {
+        let worker = &self.worker(id);
+        let mut mailbox = worker
+            .mailbox
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        self.update_counters(|counters| {
+            if counters.queued > 0 {
+                return false;
+            }
+
+            counters.started -= 1;
+            mailbox.closed = true;
+            worker.retired.store(true, Ordering::Release);
+            true
+        })
+    }
+
+    // starts a thread to take over from the worker with `id` once it has
+    // run max_jobs_per_worker() jobs, the worker exits right after, leaving
+    // its slot, its mailbox and its count in `started` to the new thread,
+    // false when the pool is shutting down or the thread couldn't be
+    // started, the worker keeps going itself then
+    fn replace_worker(&self, id: usize) -> bool {
+        // held while checking whether the pool is closed, shut_down() joins
+        // the threads in it only once it is, so none is added after that
+        let mut replacements = self
+            .replacement_threads
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        // a worker told to stop isn't replaced either, see
+        // ThreadPool::join_worker()
+        if self.closed.load(Ordering::Acquire) || self.worker(id).stop.load(Ordering::SeqCst) {
+            return false;
+        }
+
+        let started = self
+            .this
+            .upgrade()
+            .ok_or_else(|| io::Error::other("thread pool is gone"))
+            .and_then(|shared| spawn_worker(shared, id, None));
+
+        match started {
+            Ok(thread) => {
+                // threads that were replaced in turn are done, there's
+                // nothing left to wait for
+                replacements.retain(|(_, thread)| !thread.is_finished());
+                replacements.push((id, thread));
+                true
+            }
+            Err(err) => {
+                warn!(
+                    "Failed to start a thread to replace thread {}-{id}: {err}",
+                    self.name
+                );
+                false
+            }
+        }
+    }
+
+    // joins the threads that took over from the worker with `id`, see
+    // replace_worker(), returns the panic of the last one that panicked,
+    // the calling thread itself is left alone
+    fn join_replacements(&self, id: usize) -> thread::Result<()> {
+        let mut joined = Ok(());
+
+        // one of them can start the thread replacing it before it's joined
+        loop {
+            let threads: Vec<_> = {
+                let mut replacements = self
+                    .replacement_threads
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                let (threads, others) =
+                    mem::take(&mut *replacements)
+                        .into_iter()
+                        .partition(|(worker_id, thread)| {
+                            *worker_id == id && thread.thread().id() != thread::current().id()
+                        });
+                *replacements = others;
+                threads
+            };
+
+            if threads.is_empty() {
+                return joined;
+            }
+
+            for (_, thread) in threads {
+                if let Err(payload) = thread.join() {
+                    joined = Err(payload);
+                }
+            }
+        }
+    }
+
+    // starts another worker if every worker is busy with jobs waiting and
+    // the pool has room for another thread, see ThreadPoolBuilder::max_threads()
+    fn grow(&self) {
+        if self.workers.len() == self.core_workers {
+            return;
+        }
+
+        // also keeps two threads from taking the same slot, a thread that's
+        // already starting a worker takes this job into account as well,
+        // and waiting for it could deadlock, it might be waiting for a
+        // worker that stopped to finish handing back its jobs, which ends up
+        // here
+        let mut threads = match self.extra_threads.try_lock() {
+            Ok(threads) => threads,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return,
+        };
+
+        // the pool is being dropped, it only waits for the workers it knows
+        // about, the ones started before this
+        if self.closed.load(Ordering::Acquire) {
+            return;
+        }
+
+        let needed = self.update_counters(|counters| {
+            let free = counters.started.saturating_sub(counters.running);
+
+            if counters.queued > free && counters.started < self.workers.len() {
+                counters.started += 1;
+                true
+            } else {
+                false
+            }
+        });
+
+        if !needed {
+            return;
+        }
+
+        // slots of stopped workers are closed, e.g. a worker that panicked
+        // is still counted as started until it's done handing back its jobs
+        let slot = (self.core_workers..self.workers.len()).find(|index| {
+            self.workers[*index]
+                .mailbox
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .closed
+        });
+
+        let Some(index) = slot else {
+            self.update_counters(|counters| counters.started -= 1);
+            return;
+        };
+
+        // the worker that had the slot before has stopped taking jobs, but
+        // it might not be done with the slot yet
+        if let Some(thread) = threads[index - self.core_workers].take() {
+            let _ = thread.join();
+        }
+        let _ = self.join_replacements(self.worker_id(index));
+
+        self.workers[index]
+            .mailbox
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .closed = false;
+
+        let started = self
+            .this
+            .upgrade()
+            .ok_or_else(|| io::Error::other("thread pool is gone"))
+            .and_then(|shared| spawn_worker(shared, self.worker_id(index), None));
+
+        match started {
+            Ok(thread) => threads[index - self.core_workers] = Some(thread),
+            Err(err) => {
+                warn!(
+                    "Failed to start another thread of pool {}: {err}",
+                    self.name
+                );
+                self.workers[index]
+                    .mailbox
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .closed = true;
+                self.update_counters(|counters| counters.started -= 1);
+            }
+        }
+    }
+
+    // wakes up a sleeping worker, if any, for a job anyone can run, called
+    // once the job is where the workers look for jobs
+    fn wake_one(&self) {
+        // it's up to the dispatcher which worker gets the job
+        if let (true, Some(dispatcher)) = (self.dispatching(), &self.dispatcher) {
+            dispatcher.notify();
+            return;
+        }
+
+        // pairs with the fence in wait_for_job(), either the worker going
+        // to sleep finds the job or this finds the worker asleep
+        atomic::fence(Ordering::SeqCst);
+
+        if self.asleep.load(Ordering::Relaxed) == 0 {
+            self.grow();
+            return;
+        }
+
+        // doesn't unwrap the lock, this runs while unwinding from a
+        // panicking job when handing back the jobs it left behind
+        let mut sleepers = self
+            .sleepers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        match sleepers.asleep.iter().position(|asleep| *asleep) {
+            Some(index) => self.wake_locked(&mut sleepers, index),
+            None => {
+                drop(sleepers);
+                self.grow();
+            }
+        }
+    }
+
+    // wakes up the worker at `index` if it's sleeping
+    fn wake(&self, index: usize) {
+        atomic::fence(Ordering::SeqCst);
+
+        if self.asleep.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+
+        let mut sleepers = self
+            .sleepers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if sleepers.asleep[index] {
+            self.wake_locked(&mut sleepers, index);
+        }
+    }
+
+    fn wake_locked(&self, sleepers: &mut Sleepers, index: usize) {
+        sleepers.asleep[index] = false;
+        self.asleep.fetch_sub(1, Ordering::SeqCst);
+        self.workers[index].wake.notify_one();
+    }
+
+    // throws away every job that's still waiting, once the workers have
+    // exited, see ShutdownMode::Abandon
+    fn discard_queued(&self) -> usize {
+        let mut discarded = 0;
+
+        // jobs of exited workers' mailboxes and batches end up in the
+        // overflow
+        while self.queue.try_pop().is_some() {
+            discarded += 1;
+        }
+
+        discarded += mem::take(
+            &mut *self
+                .overflow
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        )
+        .len();
+        discarded += self.classes.clear();
+        discarded += mem::take(
+            &mut *self
+                .serial
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        )
+        .into_values()
+        .map(|waiting| waiting.len())
+        .sum::<usize>();
+        discarded += mem::take(
+            &mut *self
+                .limits
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        )
+        .into_values()
+        .map(|keyed| keyed.waiting.len())
+        .sum::<usize>();
+
+        self.update_counters(|counters| counters.queued -= discarded);
+        self.pending.clear();
+        discarded
+    }
+
+    // wakes up every worker for good, called when the pool is dropped
+    fn close(&self) {
+        let mut sleepers = self
+            .sleepers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        sleepers.closed = true;
+
+        for index in 0..self.workers.len() {
+            if sleepers.asleep[index] {
+                self.wake_locked(&mut sleepers, index);
+            }
+        }
+    }
+
+    // changes the counters through `f`, then fires the on_queue_high() or
+    // on_queue_low() callback if the number of queued jobs crossed one of
+    // them, every change to `queued` goes through here
+    fn update_counters<R>(&self, f: impl FnOnce(&mut Counters) -> R) -> R {
+        let (result, crossing) = self.update_counters_deferred(f);
+        crossing.fire();
+
+        result
+    }
+
+    // same as update_counters() but leaves firing the callback to the
+    // caller, for callers that hold another lock which has to be released
+    // before a callback can be fired
+    fn update_counters_deferred<R>(&self, f: impl FnOnce(&mut Counters) -> R) -> (R, Crossing<'_>) {
+        // doesn't unwrap the lock, this can run while unwinding from a
+        // panicking job and a second panic would abort the process
+        let mut counters = match self.counters.lock() {
+            Ok(counters) => counters,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let result = f(&mut counters);
+        let Counters {
+            queued, queue_high, ..
+        } = &mut *counters;
+        let crossing = self.watermarks.check(*queued, queue_high);
+
+        #[cfg(feature = "metrics")]
+        self.metrics.queue_depth(*queued);
+
+        (result, crossing)
+    }
+
+    // moves a job that a worker just received from queued to running, the
+    // returned guard moves it out of running once dropped, counting it as
+    // panicked if it's told the job panicked, see run_worker()
+    fn start_job(
+        &self,
+        id: usize,
+        seq: u64,
+        queued_at: Instant,
+        name: Option<Arc<str>>,
+    ) -> RunningJob<'_> {
+        let started_at = Instant::now();
+
+        // a job run nested inside another one, see run_nested(), puts the
+        // one it interrupted back once it's done
+        let interrupted = self
+            .worker(id)
+            .current_job
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .replace(CurrentJob { started_at, name });
+        self.pending.remove(seq);
+
+        self.update_counters(|counters| {
+            counters.queued -= 1;
+            counters.running += 1;
+            counters.queue_wait.record(started_at - queued_at);
+        });
+
+        RunningJob {
+            shared: self,
+            id,
+            started_at,
+            panicked: false,
+            interrupted,
+        }
+    }
+
+    // takes a job that a worker just received and won't run since it is
+    // past its deadline out of queued
+    fn expire_job(&self, seq: u64) {
+        self.pending.remove(seq);
+        self.update_counters(|counters| {
+            counters.queued -= 1;
+            counters.expired += 1;
+
+            if !counters.has_pending() {
+                self.idle.notify_all();
+            }
+        });
+    }
+
+    // counts the calling worker thread as alive until the returned guard
+    // is dropped, which happens both when it shuts down and when it panics
+    // outside of a job, e.g. in ThreadPoolBuilder::on_thread_start(), the
+    // panic of a job is caught, see run_worker()
+    fn worker_alive(&self, id: usize) -> WorkerAlive<'_> {
+        self.counters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .alive += 1;
+        *self
+            .worker(id)
+            .thread_id
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(thread::current().id());
+
+        WorkerAlive {
+            shared: self,
+            id,
+            replaced: false,
+        }
+    }
+}
+
+struct RunningJob<'a> {
+    shared: &'a Shared,
+    id: usize,
+    started_at: Instant,
+    // the job panicked, the panic was caught by the worker
+    panicked: bool,
+    // the job of the same worker this one runs nested inside of, if any
+    interrupted: Option<CurrentJob>,
+}
+
+impl Drop for RunningJob<'_> {
+    fn drop(&mut self) {
+        // doesn't unwrap the locks, this can run while unwinding from a
+        // panicking job and a second panic would abort the process
+        let interrupted = self.interrupted.take();
+        match self.shared.worker(self.id).current_job.lock() {
+            Ok(mut current_job) => *current_job = interrupted,
+            Err(poisoned) => *poisoned.into_inner() = interrupted,
+        }
+
+        let elapsed = self.started_at.elapsed();
+        // a panic the worker didn't catch, i.e. one taking the worker down
+        // while its job was running, counts as well
+        let panicked = self.panicked || thread::panicking();
+
+        #[cfg(feature = "latency")]
+        self.shared.latency.record(elapsed);
+
+        let mut counters = match self.shared.counters.lock() {
+            Ok(counters) => counters,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        counters.running -= 1;
+        counters.execution_time.record(elapsed);
+
+        if panicked {
+            counters.panicked += 1;
+        } else {
+            counters.completed += 1;
+        }
+
+        #[cfg(feature = "metrics")]
+        self.shared.metrics.job_finished(elapsed, panicked);
+
+        if !counters.has_pending() {
+            self.shared.idle.notify_all();
+        }
+    }
+}
+
+struct WorkerAlive<'a> {
+    shared: &'a Shared,
+    id: usize,
+    // the worker exits for another thread to take over from it, see
+    // Shared::replace_worker()
+    replaced: bool,
+}
+
+impl Drop for WorkerAlive<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            error!(
+                "Thread {}-{} panicked; shutting down.",
+                self.shared.name, self.id
+            );
+
+            self.shared
+                .panicked_workers
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .push(self.id);
+        }
+
+        // a retired worker already gave up its slot, which might have been
+        // taken by a new worker since, a replaced one handed it over to the
+        // thread replacing it, whose flag isn't this one's to reset
+        let retired = self.replaced
+            || self
+                .shared
+                .worker(self.id)
+                .retired
+                .swap(false, Ordering::AcqRel);
+
+        // unless the new worker has registered its own thread already
+        {
+            let mut thread_id = self
+                .shared
+                .worker(self.id)
+                .thread_id
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+            if *thread_id == Some(thread::current().id()) {
+                *thread_id = None;
+            }
+        }
+
+        if !retired {
+            // jobs left for this worker in particular are better run by
+            // another worker than not at all, join() would wait for them
+            // forever
+            let jobs = {
+                let mut mailbox = self
+                    .shared
+                    .worker(self.id)
+                    .mailbox
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                mailbox.closed = true;
+                mem::take(&mut mailbox.jobs)
+            };
+
+            for job in jobs {
+                self.shared.requeue(job);
+            }
+        }
+
+        self.shared.update_counters(|counters| {
+            counters.alive -= 1;
+
+            if !retired {
+                counters.started -= 1;
+            }
+        });
+    }
+}
+
+impl ThreadPool {
+    // called from inside a job of the same pool the job is queued like any
+    // other, but never waits for room in a bounded queue, every worker could
+    // be waiting for room only a worker can make otherwise, see Handle,
+    // ThreadPoolBuilder::on_reentrant_full() decides what happens once it's
+    // full
+    //
+    // with ThreadPoolBuilder::caller_runs() the job runs on the calling
+    // thread right away once the queue is full, see
+    // ThreadPoolBuilder::on_reentrant_full() for jobs submitted from inside
+    // jobs once it's full
+    //
+    // a job that panics is stopped by the worker running it, which logs the
+    // panic and goes on with the next job, see PoolStats::jobs_panicked, a
+    // Mutex the job held while panicking is poisoned all the same though,
+    // lock() returns an error for it from then on, PoisonError::into_inner()
+    // still gets to the value when it can't be left half-updated by the
+    // job, see run() for getting the panic back instead
+    //
+    // panics once the pool is closed, see close() and try_execute()
+    pub fn execute<F>(&self, f: F)
+    where
+        // any type F which implementation these traits can be passed in as the argument to this method
+        F: FnOnce() + Send + 'static,
+    {
+        // Box is needed to hold the trait object because, it has no
+        // definite known size at compile time, therefore rust compiler
+        // will fail to compile it unless it is stored on the heap using
+        // Box smart pointer
+        self.execute_boxed(Box::new(f));
+    }
+
+    // same as execute() for a job that's boxed already, e.g. by a scheduler
+    // of its own that keeps jobs as trait objects, the box is queued as it
+    // is, execute() would put it in a box of its own
+    pub fn execute_boxed(&self, job: Box<dyn FnOnce() + Send + 'static>) {
+        if let Err(err) = self.shared.execute(job) {
+            panic!("{err}");
+        }
+    }
+
+    // same as execute() but the job is dropped without running if no worker
+    // picked it up by `deadline`, regardless of the pool's max_queue_age(),
+    // e.g. to give a request the time its client is going to wait for it
+    pub fn execute_with_deadline<F>(&self, deadline: Instant, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.submit(Box::new(f), Some(deadline));
+    }
+
+    // runs `f` on the pool and runs it again, as laid out by `policy`, every
+    // time it returns an error, e.g. for a request to a server that might
+    // be restarting:-
+    //
+    // let handle = pool.execute_with_retry(policy, move || notify(&url));
+    // let attempts = handle.wait()?;
+    //
+    // the next attempt is queued once the backoff has passed, like any other
+    // job, so no worker sits waiting for it meanwhile, join() does wait for
+    // it, a panic isn't retried, the returned handle tells how it went, the
+    // job keeps going when the handle is dropped
+    //
+    // panics once the pool is closed, see close()
+    pub fn execute_with_retry<F, E>(&self, policy: RetryPolicy, f: F) -> RetryHandle<E>
+    where
+        F: Fn() -> Result<(), E> + Send + 'static,
+        E: Send + 'static,
+    {
+        let (retry, handle) = Retry::new(Arc::downgrade(&self.shared), policy, f);
+
+        self.submit(Box::new(move || retry.attempt()), None);
+        handle
+    }
+
+    // runs `f` on the pool again and again for as long as it returns
+    // ControlFlow::Continue, e.g. to poll for something in small steps
+    // without holding on to a worker in between:-
+    //
+    // pool.execute_recurring(move || match source.poll() {
+    //     Some(item) => { process(item); ControlFlow::Continue(()) }
+    //     None => ControlFlow::Break(()),
+    // });
+    //
+    // every run is queued behind the jobs submitted meanwhile, so the other
+    // jobs get their turn between runs, see execute_recurring_with_delay()
+    // for something that has to wait between runs, a panic stops it like a
+    // ControlFlow::Break, join() waits for it to break, it's dropped
+    // without running again once the pool shuts down
+    //
+    // panics once the pool is closed, see close()
+    pub fn execute_recurring<F>(&self, f: F)
+    where
+        F: FnMut() -> ControlFlow<()> + Send + 'static,
+    {
+        self.execute_recurring_with_delay(Duration::ZERO, f);
+    }
+
+    // same as execute_recurring() but every run after the first is queued
+    // only once `delay` has passed since the last one returned, so a job
+    // polling something that isn't ready yet doesn't keep a worker busy,
+    // like the retries of execute_with_retry() no worker sits waiting for it
+    // meanwhile
+    pub fn execute_recurring_with_delay<F>(&self, delay: Duration, f: F)
+    where
+        F: FnMut() -> ControlFlow<()> + Send + 'static,
+    {
+        let shared = Arc::downgrade(&self.shared);
+
+        self.submit(Box::new(move || recur(shared, delay, f)), None);
+    }
+
+    // same as execute() but never waits for room in a full queue(see
+    // ThreadPoolBuilder::queue_capacity()), `fallback` is called on the
+    // calling thread instead and `f` is dropped without running, e.g. to
+    // answer a request with 503 Service Unavailable right away:-
+    //
+    // pool.execute_or(move || handle(stream), || rejected.fetch_add(1, Ordering::Relaxed));
+    //
+    // `fallback` can do the work itself as well, whatever it needs for that
+    // has to be shared with `f` though, e.g. through an Arc, it applies to
+    // jobs submitted from the pool's own workers as well, which would go
+    // past the capacity otherwise, jobs go through the queue as usual
+    // regardless of ThreadPoolBuilder::caller_runs()
+    //
+    // panics once the pool is closed, see close()
+    pub fn execute_or<F, G>(&self, f: F, fallback: G)
+    where
+        F: FnOnce() + Send + 'static,
+        G: FnOnce(),
+    {
+        self.assert_takes_jobs();
+
+        let deadline = self.shared.max_queue_age.map(|age| Instant::now() + age);
+
+        // the queue is only ever closed when the pool is dropped, so the
+        // job coming back means the queue is full
+        if self
+            .shared
+            .enqueue(Box::new(f), deadline, None, WhenFull::Fail)
+            .is_err()
+        {
+            fallback();
+        }
+    }
+
+    // same as execute() but returns an error instead of panicking once the
+    // pool is closed(see close()), or for a job of the pool's own workers
+    // that doesn't fit into the queue with ReentrantFull::Error
+    pub fn try_execute<F>(&self, f: F) -> Result<(), PoolError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shared.execute(Box::new(f))
+    }
+
+    // same as try_execute() but hands the job back when the pool doesn't
+    // take it, e.g. for the Drop of a guard offloading its cleanup to the
+    // pool, which can run while the pool itself is shutting down, with jobs
+    // thrown away by ShutdownMode::Abandon or dropped along with the pool,
+    // where execute() would panic, and a panic inside a Drop that's running
+    // while unwinding aborts the process, the cleanup is best run right
+    // there instead:-
+    //
+    // impl Drop for TempDir {
+    //     fn drop(&mut self) {
+    //         let path = mem::take(&mut self.path);
+    //         let cleanup = move || drop(fs::remove_dir_all(path));
+    //
+    //         if let Err(rejected) = self.pool.try_execute_returning(cleanup) {
+    //             (rejected.job)();
+    //         }
+    //     }
+    // }
+    //
+    // a guard outliving the pool is better off holding a ThreadPoolHandle,
+    // which has the same method
+    pub fn try_execute_returning<F>(&self, f: F) -> Result<(), Rejected<F>>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shared.execute_returning(f)
+    }
+
+    // stops the pool from taking new jobs while the ones already submitted
+    // keep running, e.g. for a server that stops accepting connections but
+    // finishes answering the requests it's in the middle of:-
+    //
+    // pool.close();
+    // pool.join();
+    //
+    // the workers keep running until the pool is dropped, jobs submitted
+    // from inside its own jobs are still taken, the jobs already submitted
+    // could otherwise never finish, try_execute() and execute_on() return
+    // PoolError::ShutDown for any other job, every other way of submitting
+    // a job panics, a pool can't be opened again once it's closed
+    pub fn close(&self) {
+        self.shared
+            .closed_to_new_jobs
+            .store(true, Ordering::Release);
+    }
+
+    // a handle for submitting jobs to the pool that can be cloned and sent
+    // to other threads, see ThreadPoolHandle
+    pub fn handle(&self) -> ThreadPoolHandle {
+        ThreadPoolHandle::new(Arc::downgrade(&self.shared), Arc::clone(&self.shared.name))
+    }
+
+    // a group of jobs to wait for or cancel together, see JobGroup
+    pub fn group(&self) -> JobGroup {
+        JobGroup::new(self.handle())
+    }
+
+    // whether close() was called, see ThreadPoolHandle::is_shut_down() for
+    // telling whether the pool still takes jobs from wherever it's submitted
+    // to, shutting it down included
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed_to_new_jobs.load(Ordering::Acquire)
+    }
+
+    // whether a job submitted from the calling thread is taken, see close()
+    fn takes_jobs(&self) -> bool {
+        self.shared.takes_jobs(self.is_current_worker())
+    }
+
+    // panics when the pool doesn't take the job, see close()
+    fn assert_takes_jobs(&self) {
+        assert!(self.takes_jobs(), "thread pool is closed to new jobs");
+    }
+
+    // the jobs of execute_limited(), execute_serial() and run() go through
+    // here without a deadline, they carry bookkeeping of their own which
+    // would be left hanging if they were dropped without running
+    fn submit(&self, job: Task, deadline: Option<Instant>) {
+        self.submit_named(job, deadline, None);
+    }
+
+    fn submit_named(&self, job: Task, deadline: Option<Instant>, name: Option<Arc<str>>) {
+        self.assert_takes_jobs();
+
+        // the queue is only ever closed when the pool is dropped, so
+        // submitting can't fail while there's a pool to call execute() on
+        if self.shared.submit_named(job, deadline, name).is_err() {
+            unreachable!("thread pool is shut down");
+        }
+    }
+
+    // blocks the calling thread until every job submitted so far has
+    // finished running, including jobs submitted by those jobs in turn,
+    // unlike dropping the pool the threads are kept alive afterwards and
+    // the pool can be used again, calling it from inside a job of the same
+    // pool never returns since that job itself never finishes
+    pub fn join(&self) {
+        let counters = self
+            .shared
+            .counters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let _counters = self
+            .shared
+            .idle
+            .wait_while(counters, |counters| counters.has_pending())
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+
+    // stops the worker with `id` once it's done with the job it's running
+    // and waits for its thread to exit, e.g. to check that the pool keeps
+    // going without it, the rest of the workers keep running, the jobs left
+    // for that worker(execute_on(), execute_keyed()) or taken off the queue
+    // along with its last job are handed to them, it returns what joining
+    // the thread returned, i.e. the panic of a worker that was taken down by
+    // a panic outside of its jobs, and Ok for a worker that was already
+    // joined, or that the pool only starts on demand and isn't running
+    //
+    // the worker isn't replaced, the pool runs with one worker fewer from
+    // then on, a worker started on demand(ThreadPoolBuilder::max_threads())
+    // can still be started again in its place, joining every worker leaves
+    // the jobs still queued without a worker to run them, calling it from
+    // inside a job of the worker itself never returns, panics if the pool
+    // has no worker with `id`
+    pub fn join_worker(&mut self, id: usize) -> thread::Result<()> {
+        assert!(self.shared.has_worker(id), "thread pool has no worker {id}");
+
+        let index = self.shared.index_of(id);
+        let worker = &self.shared.workers[index];
+
+        if index < self.shared.core_workers {
+            let thread = self
+                .threads
+                .iter_mut()
+                .find(|thread| {
+                    thread
+                        .as_ref()
+                        .is_some_and(|(thread_id, _)| *thread_id == id)
+                })
+                .and_then(Option::take);
+
+            let Some((_, thread)) = thread else {
+                return Ok(());
+            };
+
+            worker.stop.store(true, Ordering::SeqCst);
+            self.shared.wake(index);
+            let joined = thread.join();
+            // the thread that took over from it, if it was replaced, see
+            // ThreadPoolBuilder::max_jobs_per_worker()
+            return joined.and(self.shared.join_replacements(id));
+        }
+
+        // held till the worker has exited so that no worker is started in
+        // its place meanwhile, see Shared::grow()
+        let mut threads = self
+            .shared
+            .extra_threads
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let Some(thread) = threads[index - self.shared.core_workers].take() else {
+            return Ok(());
+        };
+
+        worker.stop.store(true, Ordering::SeqCst);
+        self.shared.wake(index);
+        let joined = thread.join().and(self.shared.join_replacements(id));
+
+        // the slot is free to be started again
+        worker.stop.store(false, Ordering::SeqCst);
+        joined
+    }
+
+    // status of every worker, ordered by id, including the ones the pool
+    // only starts on demand(ThreadPoolBuilder::max_threads()) whether or
+    // not they're running right now
+    pub fn workers(&self) -> Vec<WorkerStatus> {
+        self.shared
+            .workers
+            .iter()
+            .enumerate()
+            .map(|(index, worker)| WorkerStatus {
+                id: self.shared.worker_id(index),
+                jobs_completed: worker.jobs_completed.load(Ordering::Relaxed),
+                pinned_core: worker.pinned_core.get().copied(),
+            })
+            .collect()
+    }
+
+    // id of every worker running right now paired with the id of its
+    // thread, ordered by worker id, e.g. to tell which worker a thread seen
+    // by a profiler or in a thread dump is, workers that exited(see
+    // join_worker() and ThreadPoolBuilder::keep_alive()) are left out, a
+    // worker started again in the place of one that exited shows up with
+    // the id of its own thread
+    pub fn worker_thread_ids(&self) -> Vec<(usize, thread::ThreadId)> {
+        self.shared
+            .workers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, worker)| {
+                let thread_id = *worker
+                    .thread_id
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                thread_id.map(|thread_id| (self.shared.worker_id(index), thread_id))
+            })
+            .collect()
+    }
+
+    // the name given with ThreadPoolBuilder::name(), or the one the pool
+    // got without it, e.g. "pool-2"
+    pub fn name(&self) -> &str {
+        &self.shared.name
+    }
+
+    // snapshot of the pool's statistics since it was created, every counter
+    // is read under the same lock the workers update them with, so the
+    // snapshot is consistent, e.g. workers_busy never exceeds workers_alive
+    pub fn stats(&self) -> PoolStats {
+        let counters = self
+            .shared
+            .counters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        PoolStats {
+            pool_name: self.shared.name.to_string(),
+            workers_alive: counters.alive,
+            workers_max: self.shared.workers.len(),
+            workers_busy: counters.running,
+            jobs_queued: counters.queued,
+            jobs_completed: counters.completed,
+            jobs_panicked: counters.panicked,
+            jobs_expired: counters.expired,
+            jobs_submitted_by_workers: counters.submitted_by_workers,
+            jobs_ran_inline: counters.ran_inline,
+            execution_time: counters.execution_time.clone(),
+            queue_wait: counters.queue_wait.clone(),
+            total_idle: Duration::from_nanos(self.shared.total_idle_nanos.load(Ordering::Relaxed)),
+            uptime: self.shared.created_at.elapsed(),
+        }
+    }
+
+    // percentiles of how long the jobs run so far took, panicked ones
+    // included, e.g. for capacity planning:-
+    //
+    // let latency = pool.latency_snapshot();
+    // info!("p50 {:?} p99 {:?} max {:?}", latency.p50, latency.p99, latency.max);
+    //
+    // unlike PoolStats::execution_time, which only tells durations apart by
+    // orders of magnitude, these are within about 3% of the actual durations,
+    // needs the latency feature
+    #[cfg(feature = "latency")]
+    pub fn latency_snapshot(&self) -> LatencySnapshot {
+        self.shared.latency.snapshot()
+    }
+
+    // the jobs waiting for a worker right now, oldest first, e.g. to log
+    // what's piling up once the server falls behind:-
+    //
+    // for job in pool.queue_snapshot() {
+    //     warn!("{} waiting for {:?}", job.name.as_deref().unwrap_or("-"), job.waited);
+    // }
+    //
+    // jobs are coming and going while it's taken, so it can list a job that
+    // has just been picked up or miss one that has just been submitted, it
+    // takes a single lock that submitting and picking up jobs take as well,
+    // so it's cheap but not something to call for every job, it includes
+    // jobs left for particular workers(execute_keyed() and execute_on()) but
+    // not the ones waiting behind jobs of the same key in execute_serial()
+    // and execute_limited(), those are only counted in PoolStats::jobs_queued
+    pub fn queue_snapshot(&self) -> Vec<QueuedJobInfo> {
+        self.shared.pending.snapshot()
+    }
+
+    // same as join() but gives up once `timeout` has passed, in which case
+    // the error describes the work that was still left at that moment, the
+    // jobs themselves keep going, e.g. to log what a shutdown is about to
+    // wait for before dropping the pool
+    pub fn join_timeout(&self, timeout: Duration) -> Result<(), PendingWork> {
+        let counters = self
+            .shared
+            .counters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let (counters, _) = self
+            .shared
+            .idle
+            .wait_timeout_while(counters, timeout, |counters| counters.has_pending())
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        // checks the counters rather than the timeout result, work that
+        // finished right as the timeout passed is still finished
+        if counters.has_pending() {
+            Err(PendingWork {
+                queued: counters.queued,
+                running: counters.running,
+                delayed: counters.delayed,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    // same as execute() but at most `limit` jobs submitted with the same
+    // `key` run at the same time, regardless of the size of the pool, e.g.
+    // to keep only 2 jobs tagged "disk-io" hammering the disk at once
+    //
+    // a worker that dequeues a job whose key is already at its limit doesn't
+    // spin or re-queue it, it parks the job on the key's wait list and goes
+    // back to the queue, whichever worker finishes a job of that key picks
+    // the parked job up next, so no worker ever sits around waiting for a
+    // key to be released and the parked jobs are still run before the pool
+    // finishes shutting down
+    pub fn execute_limited<F>(&self, key: &str, limit: usize, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // a limit of 0 would park every job of the key forever
+        assert!(limit > 0);
+
+        let shared = Arc::clone(&self.shared);
+        let key = key.to_owned();
+
+        self.submit(
+            Box::new(move || run_limited(shared, key, limit, Box::new(f))),
+            None,
+        );
+    }
+
+    // same as execute() but every job with the same `key` runs on the same
+    // worker, the one `key % num_threads()` workers after the first one to
+    // be exact, i.e. worker `key % num_threads()` with ids starting at 0(see
+    // ThreadPoolBuilder::first_worker_id()), workers the pool only starts on
+    // demand come and go, so they're left out, so that state a worker keeps
+    // for a key, e.g. in a thread_local cache, stays warm
+    // between jobs of that key, e.g. every request of a session:-
+    //
+    // pool.execute_keyed(session_id, move || handle(request));
+    //
+    // the job waits for that worker even while others are idle, and goes
+    // past the capacity of a bounded queue, the worker runs the jobs left
+    // for it before taking any from the pool's queue, in the order they were
+    // submitted, if the worker is gone(it panicked) the job goes through the
+    // pool's queue like any other job
+    pub fn execute_keyed<F>(&self, key: u64, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.assert_takes_jobs();
+
+        let index = (key % self.shared.core_workers as u64) as usize;
+        let deadline = self.shared.max_queue_age.map(|age| Instant::now() + age);
+
+        if let Err(job) = self.shared.send_to(index, Box::new(f), deadline) {
+            self.submit(job, deadline);
+        }
+    }
+
+    // same as execute() but the job belongs to `class`, while jobs of
+    // several classes are waiting the workers take jobs from each class in
+    // proportion to its weight(ThreadPoolBuilder::class_weight()), e.g. to
+    // keep a backlog of slow reports from holding up quick api calls:-
+    //
+    // let pool = ThreadPool::builder().class_weight("api", 4).build()?;
+    // pool.execute_classed("api", move || handle(request));
+    // pool.execute_classed("reports", move || build_report(query));
+    //
+    // where api calls get 4 out of every 5 workers freeing up as long as
+    // both have jobs waiting, jobs submitted with execute() are a class of
+    // their own with a weight of 1, so are classes without a weight, within
+    // a class jobs are taken in the order they were submitted, jobs of a
+    // class are never held back while there are no others waiting, and
+    // they go past the capacity of a bounded queue(queue_capacity())
+    pub fn execute_classed<F>(&self, class: &str, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.assert_takes_jobs();
+
+        let deadline = self.shared.max_queue_age.map(|age| Instant::now() + age);
+        self.shared.submit_classed(class, Box::new(f), deadline);
+    }
+
+    // runs a job on the worker with `worker_id` and no other, e.g. to look
+    // after state that worker keeps for itself(see
+    // ThreadPoolBuilder::on_thread_start()):-
+    //
+    // pool.execute_on(2, || CACHE.with(|cache| cache.borrow_mut().clear()))?;
+    //
+    // the job is run before any job from the pool's queue, after the jobs
+    // already left for that worker, also the ones of execute_keyed(), it's
+    // still run when the pool is dropped meanwhile, if the worker panics
+    // before getting to it, it's run by another worker instead, a worker
+    // the pool starts on demand(ThreadPoolBuilder::max_threads()) that isn't
+    // running right now is PoolError::WorkerStopped
+    pub fn execute_on<F>(&self, worker_id: usize, f: F) -> Result<(), PoolError>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        if !self.shared.has_worker(worker_id) {
+            return Err(PoolError::NoSuchWorker(worker_id));
+        }
+
+        if !self.takes_jobs() {
+            return Err(PoolError::ShutDown);
+        }
+
+        self.shared
+            .send_to(self.shared.index_of(worker_id), Box::new(f), None)
+            .map_err(|_| PoolError::WorkerStopped(worker_id))
+    }
+
+    // same as execute() but jobs submitted with the same `key` run one at a
+    // time and in the order they were submitted, e.g. every write to the
+    // same file, while jobs of other keys keep running in parallel
+    //
+    // unlike execute_limited() the order is decided here, at submission,
+    // rather than by whichever worker dequeues a job first, only the first
+    // job of a key goes through the pool's queue, the ones submitted while
+    // it's in flight wait in the key's own queue and the worker that
+    // finishes a job of the key runs the next one before going back to the
+    // pool's queue
+    pub fn execute_serial<F>(&self, key: u64, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.assert_takes_jobs();
+
+        let job: Task = Box::new(f);
+
+        {
+            let mut serial = self
+                .shared
+                .serial
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+            if let Some(waiting) = serial.get_mut(&key) {
+                waiting.push_back(job);
+                // counts as queued until it is picked up, so that join()
+                // doesn't return while it is waiting
+                let ((), crossing) = self
+                    .shared
+                    .update_counters_deferred(|counters| counters.queued += 1);
+
+                drop(serial);
+                crossing.fire();
+                return;
+            }
+
+            serial.insert(key, VecDeque::new());
+        }
+
+        let shared = Arc::clone(&self.shared);
+        self.submit(Box::new(move || run_serial(shared, key, job)), None);
+    }
+
+    // same as execute() but the job carries a name, which is what the
+    // watchdog(ThreadPoolBuilder::watchdog()) reports it by, e.g. the route
+    // a request was for
+    pub fn execute_named<F>(&self, name: &str, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let deadline = self.shared.max_queue_age.map(|age| Instant::now() + age);
+
+        // the name is carried through the queue along with the job, so that
+        // it's listed by queue_snapshot() while the job waits
+        self.submit_named(Box::new(f), deadline, Some(Arc::from(name)));
+    }
+
+    // runs `f` on the pool and blocks the calling thread until it's done,
+    // returning whatever it returned, a panic inside `f` is caught and
+    // returned as an error instead of only being logged by the worker, so
+    // it's also a way of isolating code that might panic
+    //
+    // called from inside a job of the same pool `f` is run right away on
+    // the calling worker instead, otherwise a pool whose every worker is
+    // waiting in run() would have no worker left to run the closures
+    pub fn run<F, R>(&self, f: F) -> Result<R, JobPanicked>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        // the closure only ever runs once and nothing is left observing
+        // its state after a panic except the payload, so unwind safety
+        // doesn't need to be proven by the caller
+        let run = move || panic::catch_unwind(AssertUnwindSafe(f));
+
+        if self.is_current_worker() {
+            return run().map_err(JobPanicked::from_payload);
+        }
+
+        let (sender, receiver) = mpsc::channel();
+
+        self.submit(
+            Box::new(move || {
+                // the caller might not be waiting anymore, nothing to do then
+                let _ = sender.send(run());
+            }),
+            None,
+        );
+
+        receiver
+            .recv()
+            .expect("thread pool dropped a job without running it")
+            .map_err(JobPanicked::from_payload)
+    }
+
+    // runs `f` on a thread pool of its own with a single worker, which is
+    // shut down again right after, and returns whatever `f` returned, for
+    // offloading a one-off piece of work without keeping a pool around:-
+    //
+    // let checksum = ThreadPool::run_once(move || checksum(&data));
+    //
+    // a panic inside `f` is resumed on the calling thread with the same
+    // message
+    pub fn run_once<F, T>(f: F) -> T
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = ThreadPool::new(1);
+        let result = pool.run(f);
+        pool.shutdown();
+
+        result.unwrap_or_else(|panicked| panic!("{}", panicked.message))
+    }
+
+    // applies `f` to every item on the pool, each item being a job of its
+    // own, and blocks the calling thread until all of them are done,
+    // returning the results in the order of the items, e.g.:-
+    //
+    // let squares = pool.map(1..=100, |n| n * n)?;
+    //
+    // every item is mapped even when `f` panics on one of them, the panic
+    // of the first such item is returned as an error after that
+    //
+    // called from inside a job of the same pool the items are mapped one
+    // after another on the calling worker instead, see run()
+    pub fn map<I, T, R, F>(&self, items: I, f: F) -> Result<Vec<R>, JobPanicked>
+    where
+        I: IntoIterator<Item = T>,
+        T: Send + 'static,
+        R: Send + 'static,
+        F: Fn(T) -> R + Send + Sync + Clone + 'static,
+    {
+        if self.is_current_worker() {
+            return items
+                .into_iter()
+                .map(|item| panic::catch_unwind(AssertUnwindSafe(|| f(item))))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|result| result.map_err(JobPanicked::from_payload))
+                .collect();
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let mut count = 0;
+
+        for (index, item) in items.into_iter().enumerate() {
+            let f = f.clone();
+            let sender = sender.clone();
+
+            self.submit(
+                Box::new(move || {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| f(item)));
+                    // the caller might not be waiting anymore, nothing to do then
+                    let _ = sender.send((index, result));
+                }),
+                None,
+            );
+            count += 1;
+        }
+
+        // the results come in the order the jobs finish, the index each one
+        // is sent with puts it back in its place
+        drop(sender);
+        let mut results: Vec<_> = (0..count).map(|_| None).collect();
+
+        for (index, result) in receiver {
+            results[index] = Some(result);
+        }
+
+        results
+            .into_iter()
+            .map(|result| {
+                result
+                    .expect("thread pool dropped a job without running it")
+                    .map_err(JobPanicked::from_payload)
+            })
+            .collect()
+    }
+
+    // applies `map` to every item on the pool and combines the results with
+    // `reduce` into one, blocking the calling thread until it's done, e.g.:-
+    //
+    // let total = pool.map_reduce(orders, |order| order.price, 0, |a, b| a + b)?;
+    //
+    // rather than a job per item the items are split into a few runs of
+    // consecutive items per worker, each run is folded into a partial
+    // result on a worker, starting from `identity`, and only the partial
+    // results make it back to the calling thread, which folds them in turn,
+    // so `reduce` has to be associative, i.e. reduce(reduce(a, b), c) has to
+    // equal reduce(a, reduce(b, c)), and `identity` has to change nothing,
+    // i.e. reduce(identity, a) has to equal a, e.g. 0 for a sum, the items
+    // are combined in their order though, so `reduce` doesn't have to be
+    // commutative, e.g. concatenating strings works
+    //
+    // a panic in `map` or `reduce` is returned as an error once every run
+    // is done, the panic of the first such run in the order of the items
+    //
+    // called from inside a job of the same pool the items are folded one
+    // after another on the calling worker instead, see run()
+    pub fn map_reduce<I, T, R, M, F>(
+        &self,
+        items: I,
+        map: M,
+        identity: R,
+        reduce: F,
+    ) -> Result<R, JobPanicked>
+    where
+        I: IntoIterator<Item = T>,
+        T: Send + 'static,
+        R: Clone + Send + 'static,
+        M: Fn(T) -> R + Send + Sync + 'static,
+        F: Fn(R, R) -> R + Send + Sync + 'static,
+    {
+        fn fold<T, R>(
+            items: impl IntoIterator<Item = T>,
+            identity: R,
+            map: &impl Fn(T) -> R,
+            reduce: &impl Fn(R, R) -> R,
+        ) -> R {
+            items
+                .into_iter()
+                .fold(identity, |folded, item| reduce(folded, map(item)))
+        }
+
+        if self.is_current_worker() {
+            return panic::catch_unwind(AssertUnwindSafe(|| fold(items, identity, &map, &reduce)))
+                .map_err(JobPanicked::from_payload);
+        }
+
+        let items: Vec<T> = items.into_iter().collect();
+        let runs = (self.shared.workers.len() * RUNS_PER_WORKER).min(items.len());
+
+        if runs == 0 {
+            return Ok(identity);
+        }
+
+        let run_length = items.len().div_ceil(runs);
+        let map = Arc::new(map);
+        let reduce = Arc::new(reduce);
+        let (sender, receiver) = mpsc::channel();
+        let mut items = items.into_iter();
+        let mut count = 0;
+
+        loop {
+            let run: Vec<T> = items.by_ref().take(run_length).collect();
+
+            if run.is_empty() {
+                break;
+            }
+
+            let identity = identity.clone();
+            let map = Arc::clone(&map);
+            let reduce = Arc::clone(&reduce);
+            let sender = sender.clone();
+            let index = count;
+
+            self.submit(
+                Box::new(move || {
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        fold(run, identity, &*map, &*reduce)
+                    }));
+                    // the caller might not be waiting anymore, nothing to do then
+                    let _ = sender.send((index, result));
+                }),
+                None,
+            );
+            count += 1;
+        }
+
+        // same as in map(), the index puts each partial result back in its
+        // place
+        drop(sender);
+        let mut partials: Vec<_> = (0..count).map(|_| None).collect();
+
+        for (index, result) in receiver {
+            partials[index] = Some(result);
+        }
+
+        let partials = partials
+            .into_iter()
+            .map(|result| {
+                result
+                    .expect("thread pool dropped a job without running it")
+                    .map_err(JobPanicked::from_payload)
+            })
+            .collect::<Result<Vec<R>, _>>()?;
+
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            partials
+                .into_iter()
+                .fold(identity, |folded, partial| reduce(folded, partial))
+        }))
+        .map_err(JobPanicked::from_payload)
+    }
+
+    // whether the calling thread is one of this pool's workers
+    fn is_current_worker(&self) -> bool {
+        context::is_worker_of(&self.shared)
+    }
+
+    pub fn new(size: usize) -> ThreadPool {
+        // makes sure that there is at least 1 thread in the thread pool,
+        // panics if 0 is provided as the value for number of threads
+        assert!(size > 0);
+
+        ThreadPool::spawn(&ThreadPoolBuilder::new().num_threads(size))
+            .expect("thread pool workers failed to start")
+    }
+
+    // same as ThreadPool::new() but configured through a builder, which
+    // returns an error instead of panicking for invalid configurations
+    pub fn builder() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+    }
+
+    // a pool for jobs that keep a cpu core busy, see
+    // ThreadPoolBuilder::for_cpu() for what it's set up with, e.g. to tweak
+    // it before building:-
+    //
+    // let pool = ThreadPoolBuilder::for_cpu().name("hashing").build()?;
+    pub fn for_cpu() -> ThreadPool {
+        ThreadPoolBuilder::for_cpu()
+            .build()
+            .expect("thread pool workers failed to start")
+    }
+
+    // a pool for jobs that mostly wait on io, running up to `max_threads`
+    // threads, see ThreadPoolBuilder::for_io(), panics if `max_threads` is 0
+    pub fn for_io(max_threads: usize) -> ThreadPool {
+        assert!(max_threads > 0);
+
+        ThreadPoolBuilder::for_io(max_threads)
+            .build()
+            .expect("thread pool workers failed to start")
+    }
+
+    // spawns the threads of a pool for an already validated configuration
+    fn spawn(builder: &ThreadPoolBuilder) -> Result<ThreadPool, PoolCreationError> {
+        let size = builder.num_threads;
+        let max_threads = builder.max_threads.unwrap_or(size);
+        let queue: Box<dyn JobQueue> = match (&builder.job_queue, builder.queue_capacity) {
+            (Some(factory), _) => (factory.0)(),
+            (None, Some(capacity)) => Box::new(MpscQueue::bounded(capacity)),
+            (None, None) => Box::new(MpscQueue::new()),
+        };
+        let name: Arc<str> = match &builder.name {
+            Some(name) => Arc::from(name.as_str()),
+            None => Arc::from(format!(
+                "pool-{}",
+                NEXT_POOL_NUMBER.fetch_add(1, Ordering::Relaxed)
+            )),
+        };
+        let shared = Arc::new_cyclic(|this| Shared {
+            name: Arc::clone(&name),
+            queue,
+            classes: JobClasses::new(&builder.class_weights),
+            pending: PendingJobs::new(),
+            overflow: Mutex::new(VecDeque::new()),
+            counters: Mutex::new(Counters::default()),
+            idle: Condvar::new(),
+            limits: Mutex::new(HashMap::new()),
+            serial: Mutex::new(HashMap::new()),
+            workers: (0..max_threads)
+                .map(|index| WorkerState {
+                    // started on demand later
+                    mailbox: Mutex::new(Mailbox {
+                        jobs: VecDeque::new(),
+                        closed: index >= size,
+                    }),
+                    ..WorkerState::default()
+                })
+                .collect(),
+            core_workers: size,
+            first_worker_id: builder.first_worker_id,
+            keep_alive: builder.keep_alive,
+            idle_sample_interval: builder.idle_sample_interval,
+            total_idle_nanos: AtomicU64::new(0),
+            worker_config: WorkerConfig {
+                pin_workers: builder.pin_workers,
+                batch_size: builder.batch_size,
+                idle_strategy: builder.idle_strategy,
+                on_thread_start: builder.on_thread_start.clone(),
+                before_each: builder.before_each.clone(),
+                after_each: builder.after_each.clone(),
+                max_jobs_per_worker: builder.max_jobs_per_worker,
+            },
+            extra_threads: Mutex::new((size..max_threads).map(|_| None).collect()),
+            replacement_threads: Mutex::new(Vec::new()),
+            this: this.clone(),
+            caller_runs: builder.caller_runs,
+            on_reentrant_full: builder.on_reentrant_full,
+            warn_on_drop: builder.warn_on_drop,
+            inline: builder.inline,
+            panicked_workers: Mutex::new(Vec::new()),
+            sleepers: Mutex::new(Sleepers {
+                asleep: vec![false; max_threads],
+                closed: false,
+            }),
+            asleep: AtomicUsize::new(0),
+            max_queue_age: builder.max_queue_age,
+            watermarks: QueueWatermarks::new(builder.watermarks.clone()),
+            work_available: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            closed_to_new_jobs: AtomicBool::new(false),
+            abandoned: AtomicBool::new(false),
+            timer: Timer::new(),
+            dispatcher: match builder.dispatch_mode {
+                _ if builder.inline => None,
+                DispatchMode::Shared => None,
+                DispatchMode::RoundRobin => Some(dispatcher::Dispatcher::new()),
+            },
+            #[cfg(feature = "latency")]
+            latency: latency::LatencyHistogram::new(),
+            #[cfg(feature = "metrics")]
+            metrics: telemetry::PoolMetrics::register(&name),
+            created_at: Instant::now(),
+        });
+        let mut threads = Vec::with_capacity(size);
+        // every worker sends a message on this once it's ready to take jobs
+        let (ready_sender, ready) = mpsc::channel::<()>();
+        let mut spawn_error = None;
+        // jobs run on the thread submitting them, see ThreadPoolBuilder::inline()
+        let size = if builder.inline { 0 } else { size };
+
+        for id in (0..size).map(|index| shared.worker_id(index)) {
+            shared.update_counters(|counters| counters.started += 1);
+
+            match spawn_worker(Arc::clone(&shared), id, Some(ready_sender.clone())) {
+                Ok(thread) => threads.push(Some((id, thread))),
+                Err(err) => {
+                    shared.update_counters(|counters| counters.started -= 1);
+                    spawn_error = Some(err);
+                    break;
+                }
+            }
+        }
+
+        let mut pool = ThreadPool {
+            threads,
+            shared,
+            watchdog: None,
+            dispatcher: None,
+        };
+
+        // e.g. the process ran into its limit on the number of threads,
+        // dropping the pool made of the workers spawned so far shuts them
+        // down again the same way it always does
+        if let Some(err) = spawn_error {
+            return Err(PoolCreationError::SpawnFailed(err.kind()));
+        }
+
+        if pool.shared.dispatcher.is_some() {
+            // the pool is dropped along with the workers on failure
+            let dispatcher = dispatcher::spawn(Arc::clone(&pool.shared))
+                .map_err(|err| PoolCreationError::SpawnFailed(err.kind()))?;
+            pool.dispatcher = Some(dispatcher);
+        }
+
+        if let Some(config) = builder.watchdog.clone().filter(|_| !builder.inline) {
+            // the pool is dropped along with the workers on failure
+            let watchdog = Watchdog::spawn(config, Arc::clone(&pool.shared))
+                .map_err(|err| PoolCreationError::SpawnFailed(err.kind()))?;
+            pool.watchdog = Some(watchdog);
+        }
+
+        if builder.wait_for_workers {
+            let deadline = Instant::now() + WORKER_STARTUP_TIMEOUT;
+
+            for _ in 0..size {
+                let timeout = deadline.saturating_duration_since(Instant::now());
+
+                // dropping the pool here makes the workers that did start
+                // shut down again, the ones that didn't will exit as soon
+                // as they do start since the queue is closed by then
+                if ready.recv_timeout(timeout).is_err() {
+                    return Err(PoolCreationError::WorkersNotReady);
+                }
+            }
+        }
+
+        Ok(pool)
+    }
+}
+
+// starts the thread of the worker with `id`, which has to be counted as
+// started already, `ready` is sent a message once the worker is ready to
+// take jobs
+fn spawn_worker(
+    shared: Arc<Shared>,
+    id: usize,
+    ready: Option<mpsc::Sender<()>>,
+) -> io::Result<thread::JoinHandle<()>> {
+    let WorkerConfig {
+        pin_workers,
+        batch_size,
+        idle_strategy,
+        on_thread_start,
+        ..
+    } = shared.worker_config.clone();
+
+    // the process running into its limit on threads can't be brought about
+    // in a test without taking the tests running alongside it down as well
+    #[cfg(test)]
+    if tests::FAIL_SPAWN_OF.with(|fail| fail.get() == Some(id)) {
+        return Err(io::ErrorKind::WouldBlock.into());
+    }
+
+    thread::Builder::new()
+        .name(format!("{}-{id}", shared.name))
+        .spawn(move || {
+            let mut alive = shared.worker_alive(id);
+            context::enter(id, &shared);
+
+            if pin_workers {
+                pin_worker(&shared, id);
+            }
+
+            if let Some(on_thread_start) = on_thread_start {
+                (on_thread_start.0)(id);
+            }
+
+            // nobody is waiting anymore when the pool gave up on the
+            // workers starting or was built without waiting for them
+            if let Some(ready) = ready {
+                let _ = ready.send(());
+            }
+
+            alive.replaced = run_worker(&shared, id, batch_size, idle_strategy);
+        })
+}
+
+// runs the jobs of the worker with `id` until the pool shuts down, or until
+// the worker retires, see ThreadPoolBuilder::keep_alive(), returns true
+// when it stops for another thread to take over from it instead, see
+// ThreadPoolBuilder::max_jobs_per_worker()
+fn run_worker(shared: &Shared, id: usize, batch_size: usize, idle_strategy: IdleStrategy) -> bool {
+    // jobs taken off the queue along with the last one received,
+    // still counted as queued until they're started
+    let mut batch = Batch {
+        shared,
+        jobs: VecDeque::with_capacity(batch_size),
+    };
+    // jobs run on this thread, dropped ones aside
+    let mut jobs_run = 0;
+
+    // here loop keyword is used to create a loop that runs as long as it
+    // is not terminated by calling the break statement inside it, the
+    // looping is done basically to keep checking the queue for new jobs
+    // sent by the thread pool, `while let` loops are not used because they
+    // would make this thread own and not release the lock on the queue
+    // till the job it received is done running, basically making our
+    // multi-threaded implementation single-threaded,
+    // this has something to do with `temporary` value in rust which is
+    // dropped as soon as it is used, for example using values returned
+    // by a function in an expression
+    loop {
+        // the jobs of its batch and mailbox are handed back to the other
+        // workers once it has exited, see Batch and WorkerAlive
+        if shared.worker(id).stop.load(Ordering::Acquire) {
+            break;
+        }
+
+        let job = match batch.jobs.pop_front() {
+            Some(job) => job,
+            None => {
+                // everything up to getting a job counts as idle, see
+                // PoolStats::total_idle
+                let mut idle_since = Instant::now();
+                let received = receive(
+                    shared,
+                    id,
+                    &mut batch.jobs,
+                    batch_size,
+                    idle_strategy,
+                    &mut idle_since,
+                );
+                shared.record_idle(&mut idle_since);
+
+                match received {
+                    Received::Job(job) => job,
+                    // a worker that's still needed for jobs waiting to be
+                    // run goes back to waiting for them
+                    Received::TimedOut if shared.retire_idle(id) => break,
+                    Received::TimedOut => continue,
+                    Received::Closed => break,
+                }
+            }
+        };
+
+        // the job is left along with the rest of the batch for
+        // shutdown_with() to throw away
+        if shared.abandoned.load(Ordering::Acquire) {
+            batch.jobs.push_front(job);
+            break;
+        }
+
+        if !run_job(shared, id, job) {
+            continue;
+        }
+
+        // the jobs left in its batch are handed back, see Batch, failing
+        // to start the new thread it counts its jobs anew
+        jobs_run += 1;
+        if shared
+            .worker_config
+            .max_jobs_per_worker
+            .is_some_and(|max_jobs| jobs_run >= max_jobs)
+        {
+            if shared.replace_worker(id) {
+                debug!(
+                    "Thread {}-{id} ran {jobs_run} jobs; replaced by a new thread.",
+                    shared.name
+                );
+                return true;
+            }
+            jobs_run = 0;
+        }
+    }
+
+    false
+}
+
+// runs a job the worker with `id` got unless it's past its deadline,
+// returns whether it ran
+fn run_job(shared: &Shared, id: usize, job: Job) -> bool {
+    match job {
+        Job {
+            deadline: Some(deadline),
+            seq,
+            ..
+        } if Instant::now() > deadline => {
+            debug!(
+                "Thread {}-{id} dropped a job past its deadline.",
+                shared.name
+            );
+            shared.expire_job(seq);
+            false
+        }
+        Job {
+            task,
+            queued_at,
+            name,
+            seq,
+            ..
+        } => {
+            debug!("Thread {}-{id} got a job; executing.", shared.name);
+            let mut running = shared.start_job(id, seq, queued_at, name);
+
+            if let Some(before_each) = &shared.worker_config.before_each {
+                (before_each.0)(id);
+            }
+
+            // a panicking job is stopped here instead of taking the
+            // worker down with it, the worker goes on with the next job,
+            // no lock of the pool is held while the job runs, so none of
+            // them is poisoned by it, nothing of the job is looked at
+            // after the panic, which is what makes AssertUnwindSafe fine
+            let started_at = Instant::now();
+            let result = panic::catch_unwind(AssertUnwindSafe(task));
+
+            if let Some(after_each) = &shared.worker_config.after_each {
+                (after_each.0)(id, started_at.elapsed());
+            }
+
+            match result {
+                Ok(()) => {
+                    shared
+                        .worker(id)
+                        .jobs_completed
+                        .fetch_add(1, Ordering::Relaxed);
+                }
+                Err(payload) => {
+                    running.panicked = true;
+                    error!(
+                        "Thread {}-{id} caught a panicking job: {}",
+                        shared.name,
+                        JobPanicked::from_payload(payload).message
+                    );
+                }
+            }
+
+            true
+        }
+    }
+}
+
+// a run of a job of ThreadPool::execute_recurring(), queues the next run
+// itself unless the job is done, the pool is only referred to weakly, a
+// run waiting on the pool's timer would otherwise keep the pool alive
+fn recur<F>(shared: Weak<Shared>, delay: Duration, mut f: F)
+where
+    F: FnMut() -> ControlFlow<()> + Send + 'static,
+{
+    if f().is_break() {
+        return;
+    }
+
+    let Some(this) = shared.upgrade() else {
+        return;
+    };
+
+    let next: Task = Box::new(move || recur(shared, delay, f));
+
+    // either fails only once the pool is shutting down, the job is dropped
+    // along with the rest of them then
+    if delay.is_zero() {
+        let _ = this.submit(next, None);
+    } else {
+        let _ = this.timer.schedule(&this, Instant::now() + delay, next);
+    }
+}
+
+impl fmt::Debug for ThreadPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let counters = self
+            .shared
+            .counters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        f.debug_struct("ThreadPool")
+            .field("name", &self.shared.name)
+            .field("workers_alive", &counters.alive)
+            .field("workers_busy", &counters.running)
+            .field("jobs_queued", &counters.queued)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ThreadPool {
+    // called whenever the associated thread pool object goes out of scope,
+    // here we need a custom implementation for it because we don't want
+    // to end the program abruptly, if there are messages remaining in the
+    // queue sent from the thread pool or if the threads are currently
+    // processing a request, they need to be handled before the server is
+    // shut down, so basically we're trying to gracefully shut down the
+    // server instead of shutting it down abruptly
+    fn drop(&mut self) {
+        // a pool that was shut down already, e.g. through shutdown(), got
+        // rid of its jobs deliberately and reported how that went
+        if self.shared.closed.load(Ordering::Acquire) {
+            self.shut_down();
+            return;
+        }
+
+        if self.shared.warn_on_drop {
+            let queued = self
+                .shared
+                .counters
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .queued;
+
+            if queued > 0 {
+                warn!(
+                    "Dropping thread pool {} with {queued} jobs still queued; they will still run.",
+                    self.shared.name
+                );
+            }
+        }
+
+        let report = self.shut_down_reporting(ShutdownMode::Drain);
+        info!("Thread pool shut down: {report}.");
+    }
+}
+
+impl ThreadPool {
+    // shuts the pool down the way `mode` says and returns the number of
+    // jobs thrown away without running, always 0 with ShutdownMode::Drain,
+    // e.g. for an emergency shutdown that shouldn't wait for a long queue:-
+    //
+    // let abandoned = pool.shutdown_with(ShutdownMode::Abandon);
+    // warn!("{abandoned} requests were never answered");
+    //
+    // jobs already running are finished either way, so are the jobs of a
+    // key(execute_serial() and execute_limited()) that a running job picks
+    // up after its own, but jobs of a key that haven't started are thrown
+    // away
+    pub fn shutdown_with(mut self, mode: ShutdownMode) -> usize {
+        self.shut_down_reporting(mode).jobs_discarded
+    }
+
+    // shuts the pool down once every job submitted has run, same as dropping
+    // it, and reports how that went, e.g. to log on the way out:-
+    //
+    // info!("{}", pool.shutdown());
+    pub fn shutdown(mut self) -> ShutdownReport {
+        self.shut_down_reporting(ShutdownMode::Drain)
+    }
+
+    // same as shutdown() but gives the queued jobs only up to `timeout` to
+    // run, the ones still queued by then are thrown away, see
+    // ShutdownMode::Abandon, it still waits for the jobs running at that
+    // point to finish, which can take longer than `timeout`
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> ShutdownReport {
+        let started_at = Instant::now();
+        let completed_before = self.completed_jobs();
+
+        let mode = match self.join_timeout(timeout) {
+            Ok(()) => ShutdownMode::Drain,
+            Err(_) => ShutdownMode::Abandon,
+        };
+
+        let mut report = self.shut_down_reporting(mode);
+        report.jobs_completed_during_drain = report.jobs_completed - completed_before;
+        report.drain_time = started_at.elapsed();
+        report
+    }
+
+    fn shut_down_reporting(&mut self, mode: ShutdownMode) -> ShutdownReport {
+        let started_at = Instant::now();
+        let completed_before = self.completed_jobs();
+
+        if mode == ShutdownMode::Abandon {
+            self.shared.abandoned.store(true, Ordering::Release);
+        }
+
+        self.shut_down();
+
+        let jobs_discarded = match mode {
+            ShutdownMode::Drain => 0,
+            ShutdownMode::Abandon => self.shared.discard_queued(),
+        };
+        // taken only once the last worker has exited, nothing changes after
+        let counters = self
+            .shared
+            .counters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        ShutdownReport {
+            pool_name: self.shared.name.to_string(),
+            jobs_completed: counters.completed,
+            jobs_completed_during_drain: counters.completed - completed_before,
+            jobs_panicked: counters.panicked,
+            jobs_discarded,
+            jobs_expired: counters.expired,
+            drain_time: started_at.elapsed(),
+            panicked_workers: self
+                .shared
+                .panicked_workers
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .clone(),
+        }
+    }
+
+    fn completed_jobs(&self) -> u64 {
+        self.shared
+            .counters
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .completed
+    }
+
+    // stops and joins the workers, it's fine to call more than once, the
+    // workers are only joined the first time
+    fn shut_down(&mut self) {
+        // jobs waiting to be retried are dropped, which fails them with the
+        // error they last returned, see RetryError::ShutDown, stopped first
+        // since the timer can't queue anything once the queue is closed
+        self.shared.timer.stop(&self.shared);
+
+        // signals the workers that the pool has been dropped and for them
+        // to stop waiting for new jobs, once there's no job left for a
+        // worker it gets None instead of going to sleep, which is a signal
+        // to the worker to halt the execution of the thread closure
+        self.shared.queue.close();
+        // workers spinning on the flag find out the queue is closed on their
+        // next check instead of once they're done spinning
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.close();
+
+        // the workers take over draining the queue from the dispatcher
+        if let Some(dispatcher) = &self.shared.dispatcher {
+            dispatcher.notify();
+        }
+
+        if let Some(thread) = self.dispatcher.take() {
+            let _ = thread.join();
+        }
+
+        for thread in &mut self.threads {
+            // for each Some variant that holds a thread in thread pool
+            // we call thread.join().unwrap() for main() thread to wait
+            // for the spawned thread to finish it's processing successfully,
+            // ignores the None variant
+            if let Some((thread_id, thread)) = thread.take() {
+                // this is synchronous and halts the thread it is
+                // called in(main thread) here, until the thread it references
+                // comes to a halt by completing its closure logic execution,
+                // a worker that panicked was already reported as such
+                let _ = thread.join();
+                info!(
+                    "Thread {}-{thread_id} disconnected; shutting down.",
+                    self.shared.name
+                );
+            }
+        }
+
+        // workers started on demand, no more are started once the pool is
+        // closed, see Shared::grow()
+        let extra_threads = mem::take(
+            &mut *self
+                .shared
+                .extra_threads
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+
+        for (index, thread) in extra_threads.into_iter().enumerate() {
+            if let Some(thread) = thread {
+                // a worker that panicked was already reported as such
+                let _ = thread.join();
+                info!(
+                    "Thread {}-{} disconnected; shutting down.",
+                    self.shared.name,
+                    self.shared.worker_id(self.shared.core_workers + index)
+                );
+            }
+        }
+
+        // threads started in place of workers that ran max_jobs_per_worker()
+        // jobs, none is started once the pool is closed, see
+        // Shared::replace_worker()
+        let replacement_threads = mem::take(
+            &mut *self
+                .shared
+                .replacement_threads
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+
+        for (id, thread) in replacement_threads {
+            let _ = thread.join();
+            info!(
+                "Thread {}-{id} disconnected; shutting down.",
+                self.shared.name
+            );
+        }
+
+        // stopped only now so that it keeps an eye on the jobs the workers
+        // were still finishing up above
+        if let Some(watchdog) = self.watchdog.take() {
+            watchdog.stop();
+        }
+    }
+}
+
+// pins the calling worker to a core, see ThreadPoolBuilder::pin_workers(),
+// failing to pin is not worth taking the worker down over
+fn pin_worker(shared: &Shared, id: usize) {
+    match affinity::pin_current_thread(shared.index_of(id)) {
+        Ok(Some(core)) => {
+            let _ = shared.worker(id).pinned_core.set(core);
+        }
+        Ok(None) => {}
+        Err(err) => warn!(
+            "Thread {}-{id} failed to pin itself to a core: {err}",
+            shared.name
+        ),
+    }
+}
+
+// what a worker waiting for a job got, see receive()
+enum Received {
+    Job(Job),
+    // the worker went without a job for as long as it's kept alive, only
+    // workers the pool starts on demand wait with a timeout, see
+    // ThreadPoolBuilder::keep_alive()
+    TimedOut,
+    // the pool is shutting down and there's nothing left for the worker to
+    // run, or the worker was told to stop, see ThreadPool::join_worker()
+    Closed,
+}
+
+// jobs a worker took off the queue but hasn't run yet, when the worker is
+// taken down by a panic they're handed back to the other workers instead of
+// being lost, join() would wait for them forever
+struct Batch<'a> {
+    shared: &'a Shared,
+    jobs: VecDeque<Job>,
+}
+
+impl Drop for Batch<'_> {
+    fn drop(&mut self) {
+        for job in self.jobs.drain(..) {
+            self.shared.requeue(job);
+        }
+    }
+}
+
+// waits for the next job in the queue, then takes up to `batch_size - 1`
+// more without waiting, taking the lock on the queue only once for those, with
+// jobs that only take a moment the workers would otherwise spend most of
+// their time taking turns at the lock
+//
+// a worker only takes its fair share of what's queued along with the job
+// it waited for, i.e. the queue split evenly between the workers, otherwise
+// a single worker could take a batch of slow jobs and run them one after
+// another while the other workers sit idle
+fn receive(
+    shared: &Shared,
+    id: usize,
+    batch: &mut VecDeque<Job>,
+    batch_size: usize,
+    idle_strategy: IdleStrategy,
+    idle_since: &mut Instant,
+) -> Received {
+    let received = match (shared.next_job(id), idle_strategy) {
+        (Some(job), _) => Received::Job(job),
+        (None, IdleStrategy::Park) => shared.wait_for_job(id, idle_since),
+        (None, IdleStrategy::SpinThenPark { spins }) => spin(shared, id, spins, idle_since),
+    };
+
+    let Received::Job(job) = received else {
+        return received;
+    };
+
+    // jobs of execute_classed() are taken one at a time, a batch of
+    // jobs without a class would skip their turn
+    if batch_size > 1 && shared.classes.is_empty() && !shared.dispatching() {
+        // shared with the workers the pool can still start as well, the
+        // ones it starts for the jobs queued would find them taken otherwise,
+        // going by the queue itself, `queued` also counts jobs parked behind
+        // others of their key(execute_serial() and execute_limited()),
+        // which would have a worker take every job that is actually queued
+        // while the other workers sleep
+        let fair_share = shared.queue.len() / shared.workers.len();
+
+        shared
+            .queue
+            .pop_batch(fair_share.min(batch_size - 1), batch);
+    }
+
+    Received::Job(job)
+}
+
+// checks for a job up to `spins` times before going to sleep, the flag can
+// be cleared right after a job is queued, in which case the job is only
+// picked up once the worker goes to sleep, which checks for jobs one last
+// time, it's never missed
+fn spin(shared: &Shared, id: usize, spins: u32, idle_since: &mut Instant) -> Received {
+    for _ in 0..spins {
+        // the queue is empty for good, no point in spinning any longer
+        if shared.closed.load(Ordering::Acquire) {
+            break;
+        }
+
+        if shared.work_available.load(Ordering::Acquire) {
+            match shared.next_job(id) {
+                Some(job) => return Received::Job(job),
+                None => shared.work_available.store(false, Ordering::Release),
+            }
+        }
+
+        hint::spin_loop();
+    }
+
+    shared.wait_for_job(id, idle_since)
+}
+
+// numbers the pools built without a name, see ThreadPoolBuilder::name()
+static NEXT_POOL_NUMBER: AtomicUsize = AtomicUsize::new(1);
+
+// number of runs of items ThreadPool::map_reduce() splits its items into
+// for every worker, more than 1 so that a worker that's done early can take
+// another run instead of waiting for a slow one
+const RUNS_PER_WORKER: usize = 4;
+
+// how many jobs submitted from inside jobs to a full queue are run inline on
+// top of one another before the next one goes past the capacity instead, see
+// ReentrantFull::Inline, low enough to leave room on the stack of a
+// worker for the jobs themselves
+const MAX_INLINE_DEPTH: usize = 32;
+
+// how many times a worker out of jobs yields its thread, checking for a job
+// after each time, before it goes to sleep
+const SLEEP_BACKOFF: u32 = 10;
+
+// how long ThreadPool::new() and ThreadPoolBuilder::build() wait for every
+// worker to be ready, spawning a thread takes well under a millisecond
+// normally, so running into it means the system is in serious trouble
+const WORKER_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+// state of a single key used with execute_limited(), the entry is removed
+// from the map as soon as nothing of that key is running so that the map
+// doesn't keep growing with every key ever used
+#[derive(Default)]
+struct KeyedJobs {
+    running: usize,
+    waiting: VecDeque<Task>,
+}
+
+// runs on a worker thread, either runs the job right away if its key is
+// under the limit or parks it for a worker finishing a job of the same key,
+// a parked job counts as queued again until it is picked up, so that join()
+// doesn't return while there are still parked jobs
+fn run_limited(shared: Arc<Shared>, key: String, limit: usize, job: Task) {
+    {
+        let mut limits = shared
+            .limits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let keyed = limits.entry(key.clone()).or_default();
+
+        if keyed.running >= limit {
+            keyed.waiting.push_back(job);
+            let ((), crossing) = shared.update_counters_deferred(|counters| counters.queued += 1);
+
+            drop(limits);
+            crossing.fire();
+            return;
+        }
+
+        keyed.running += 1;
+    }
+
+    // releases the slot taken above even if a job panics, otherwise a single
+    // panicking job would block its key forever
+    LimitSlot { shared, key }.run(job);
+}
+
+// runs on a worker thread, runs the job and then every job of the same key
+// submitted in the meantime, the key is removed once none are left
+fn run_serial(shared: Arc<Shared>, key: u64, job: Task) {
+    // keeps the key going even if a job panics, see SerialSlot::drop()
+    let slot = SerialSlot { shared, key };
+    let mut next = Some(job);
+
+    while let Some(job) = next.take() {
+        job();
+        next = slot.next_job();
+    }
+}
+
+struct SerialSlot {
+    shared: Arc<Shared>,
+    key: u64,
+}
+
+impl SerialSlot {
+    // takes the next job of the key out of its queue, removes the key when
+    // there is none, which ends its turn, lock is not held while jobs run
+    fn next_job(&self) -> Option<Task> {
+        // doesn't unwrap the lock, this can run while unwinding from a
+        // panicking job and a second panic would abort the process
+        let mut serial = match self.shared.serial.lock() {
+            Ok(serial) => serial,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let next = serial.get_mut(&self.key).and_then(VecDeque::pop_front);
+
+        if next.is_none() {
+            serial.remove(&self.key);
+            return None;
+        }
+
+        let ((), crossing) = self
+            .shared
+            .update_counters_deferred(|counters| counters.queued -= 1);
+
+        drop(serial);
+        crossing.fire();
+
+        next
+    }
+}
+
+impl Drop for SerialSlot {
+    fn drop(&mut self) {
+        // only left to do when a job panicked, the panic unwinds out of
+        // run_serial() before it gets to the rest of the key's jobs, so
+        // they're handed back to the pool as a new job for a worker to pick
+        // up
+        if !thread::panicking() {
+            return;
+        }
+
+        let Some(job) = self.next_job() else {
+            return;
+        };
+
+        let shared = Arc::clone(&self.shared);
+        let key = self.key;
+        let job = Box::new(move || run_serial(shared, key, job));
+
+        // the pool is shutting down, the remaining jobs of the key can't be
+        // run anymore and are dropped, dropping them can't panic again
+        if self.shared.submit(job, None).is_err() {
+            let mut serial = match self.shared.serial.lock() {
+                Ok(serial) => serial,
+                Err(poisoned) => poisoned.into_inner(),
+            };
+
+            if let Some(waiting) = serial.remove(&self.key) {
+                drop(serial);
+                self.shared
+                    .update_counters(|counters| counters.queued -= waiting.len());
+            }
+        }
+    }
+}
+
+// a running job's share of the limit of its key, see run_limited()
+struct LimitSlot {
+    shared: Arc<Shared>,
+    key: String,
+}
+
+impl LimitSlot {
+    // runs `job`, then the jobs parked on the key one after another, each
+    // taking over the slot from the one before instead of it being released
+    fn run(self, job: Task) {
+        let mut next = Some(job);
+
+        while let Some(job) = next.take() {
+            job();
+            next = self.next_job();
+        }
+    }
+
+    // takes the oldest job parked on the key, lock is not held while jobs
+    // run
+    fn next_job(&self) -> Option<Task> {
+        // doesn't unwrap the locks, this can run while unwinding from a
+        // panicking job and a second panic would abort the process
+        let mut limits = match self.shared.limits.lock() {
+            Ok(limits) => limits,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let next = limits
+            .get_mut(&self.key)
+            .and_then(|keyed| keyed.waiting.pop_front())?;
+
+        let ((), crossing) = self
+            .shared
+            .update_counters_deferred(|counters| counters.queued -= 1);
+
+        drop(limits);
+        crossing.fire();
+
+        Some(next)
+    }
+
+    // when a job panicked the panic unwinds out of the job that would have
+    // gone on with the parked jobs, the slot is handed over to the oldest of
+    // them as a new job instead, true if the pool took it
+    fn hand_over(&self) -> bool {
+        let Some(job) = self.next_job() else {
+            return false;
+        };
+
+        // the slot is only taken over once the job runs
+        let shared = Arc::clone(&self.shared);
+        let key = self.key.clone();
+
+        if self
+            .shared
+            .submit(Box::new(move || LimitSlot { shared, key }.run(job)), None)
+            .is_ok()
+        {
+            return true;
+        }
+
+        // the pool is shutting down, the rest of the key's parked jobs can't
+        // be run anymore and are dropped, dropping them can't panic again
+        let mut limits = match self.shared.limits.lock() {
+            Ok(limits) => limits,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if let Some(keyed) = limits.get_mut(&self.key) {
+            let waiting = mem::take(&mut keyed.waiting);
+            drop(limits);
+            self.shared
+                .update_counters(|counters| counters.queued -= waiting.len());
+        }
+
+        false
+    }
+}
+
+impl Drop for LimitSlot {
+    fn drop(&mut self) {
+        // the slot goes on with another job of the key
+        if thread::panicking() && self.hand_over() {
+            return;
+        }
+
+        // doesn't unwrap the lock, this can run while unwinding from a
+        // panicking job and a second panic would abort the process
+        let mut limits = match self.shared.limits.lock() {
+            Ok(limits) => limits,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if let Some(keyed) = limits.get_mut(&self.key) {
+            keyed.running -= 1;
+
+            if keyed.running == 0 && keyed.waiting.is_empty() {
+                limits.remove(&self.key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::{Level, LevelFilter, Log, Metadata, Record};
+    use std::{
+        cell::Cell,
+        collections::HashSet,
+        io,
+        num::NonZeroUsize,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Barrier, Once, RwLock,
+        },
+        time::Duration,
+    };
+
+    thread_local! {
+        // id of the worker whose thread fails to spawn when a pool is built
+        // on this thread, see spawn_worker()
+        pub(super) static FAIL_SPAWN_OF: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+
+    // keeps every line logged at info level or above, by every test, tests
+    // run in parallel so they look for the lines of their own pool by name
+    struct Capture(Mutex<Vec<(Level, String)>>);
+
+    impl Log for Capture {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= Level::Info
+        }
+
+        fn log(&self, record: &Record) {
+            if self.enabled(record.metadata()) {
+                self.0
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .push((record.level(), record.args().to_string()));
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+    // has the lines logged from now on captured, only the first call in
+    // the test binary installs the logger
+    fn capture_logs() {
+        static INSTALLED: Once = Once::new();
+
+        INSTALLED.call_once(|| {
+            log::set_logger(&CAPTURE).unwrap();
+            log::set_max_level(LevelFilter::Info);
+        });
+    }
+
+    // lines captured at `level` containing `text`
+    fn logged(level: Level, text: &str) -> Vec<String> {
+        CAPTURE
+            .0
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .filter(|(logged_level, line)| *logged_level == level && line.contains(text))
+            .map(|(_, line)| line.clone())
+            .collect()
+    }
+
+    #[test]
+    fn limited_jobs_of_a_key_run_one_at_a_time() {
+        let pool = ThreadPool::new(4);
+        let running = Arc::new(AtomicUsize::new(0));
+        let most_running = Arc::new(AtomicUsize::new(0));
+        let (done, finished) = mpsc::channel();
+
+        for _ in 0..20 {
+            let running = Arc::clone(&running);
+            let most_running = Arc::clone(&most_running);
+            let done = done.clone();
+
+            pool.execute_limited("k", 1, move || {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most_running.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(5));
+                running.fetch_sub(1, Ordering::SeqCst);
+                done.send(()).unwrap();
+            });
+        }
+
+        for _ in 0..20 {
+            finished.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        assert_eq!(most_running.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn limited_job_panicking_hands_over_to_parked_jobs() {
+        let pool = ThreadPool::new(4);
+        let (release, released) = mpsc::channel();
+        let (done, finished) = mpsc::channel();
+
+        pool.execute_limited("k", 1, move || {
+            // keeps the slot taken until the other jobs are parked
+            released.recv().unwrap();
+            panic!("limited job panicked");
+        });
+
+        for n in 0..3 {
+            let done = done.clone();
+            pool.execute_limited("k", 1, move || done.send(n).unwrap());
+        }
+
+        thread::sleep(Duration::from_millis(50));
+        release.send(()).unwrap();
+        let mut ran: Vec<_> = (0..3)
+            .map(|_| finished.recv_timeout(Duration::from_secs(2)).unwrap())
+            .collect();
+        ran.sort();
+        assert_eq!(ran, [0, 1, 2]);
+        pool.join();
+        assert_eq!(pool.stats().workers_alive, 4);
+    }
+
+    #[test]
+    fn join_timeout_describes_the_work_left() {
+        let pool = ThreadPool::new(1);
+        pool.execute(|| thread::sleep(Duration::from_millis(200)));
+        pool.execute(|| {});
+
+        // give the worker a moment to pick up the first job
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            pool.join_timeout(Duration::from_millis(50)),
+            Err(PendingWork {
+                queued: 1,
+                running: 1,
+                delayed: 0,
+            })
+        );
+        assert_eq!(pool.join_timeout(Duration::from_secs(5)), Ok(()));
+    }
+
+    #[test]
+    fn jobs_completed_per_worker_add_up() {
+        let pool = ThreadPool::new(2);
+
+        for _ in 0..1000 {
+            pool.execute(|| {});
+        }
+        pool.join();
+
+        let workers = pool.workers();
+        assert_eq!(
+            workers.iter().map(|worker| worker.id).collect::<Vec<_>>(),
+            [1, 2]
+        );
+        assert_eq!(
+            workers
+                .iter()
+                .map(|worker| worker.jobs_completed)
+                .sum::<usize>(),
+            1000
+        );
+    }
+
+    #[test]
+    fn panicking_job_leaves_the_worker_running() {
+        let pool = ThreadPool::new(1);
+        let shared = Arc::new(Mutex::new(0));
+        let (done, finished) = mpsc::channel();
+
+        let in_job = Arc::clone(&shared);
+        pool.execute(move || {
+            let mut value = in_job.lock().unwrap();
+            *value += 1;
+            panic!("job panicked holding the lock");
+        });
+
+        for n in 0..3 {
+            let done = done.clone();
+            pool.execute(move || done.send(n).unwrap());
+        }
+
+        // run by the same, single, worker
+        let ran: Vec<_> = (0..3)
+            .map(|_| finished.recv_timeout(Duration::from_secs(2)).unwrap())
+            .collect();
+        assert_eq!(ran, [0, 1, 2]);
+
+        pool.join();
+        let stats = pool.stats();
+        assert_eq!(stats.workers_alive, 1);
+        assert_eq!(stats.jobs_panicked, 1);
+        assert_eq!(stats.jobs_completed, 3);
+
+        // the lock of the job is poisoned all the same
+        assert!(shared.is_poisoned());
+        assert_eq!(*shared.lock().unwrap_or_else(|err| err.into_inner()), 1);
+        assert!(pool.shutdown().panicked_workers.is_empty());
+    }
+
+    #[test]
+    fn panicking_job_leaves_the_other_workers_dequeuing() {
+        let pool = ThreadPool::new(4);
+        let (started, panicking) = mpsc::channel();
+        let (done, finished) = mpsc::channel();
+
+        pool.execute(move || {
+            started.send(()).unwrap();
+            panic!("job panicked");
+        });
+        panicking.recv_timeout(Duration::from_secs(2)).unwrap();
+
+        for n in 0..100 {
+            let done = done.clone();
+            pool.execute(move || done.send(n).unwrap());
+        }
+
+        let mut ran: Vec<_> = (0..100)
+            .map(|_| finished.recv_timeout(Duration::from_secs(2)).unwrap())
+            .collect();
+        ran.sort_unstable();
+        assert_eq!(ran, (0..100).collect::<Vec<_>>());
+
+        pool.join();
+        let stats = pool.stats();
+        assert_eq!(stats.workers_alive, 4);
+        assert_eq!(stats.jobs_panicked, 1);
+        assert_eq!(stats.jobs_completed, 100);
+    }
+
+    #[test]
+    fn no_lock_of_the_pool_held_while_a_job_runs() {
+        // every call below takes some lock of the pool, a job calling them
+        // would deadlock if its worker held any while running it
+        let pool = Arc::new(ThreadPool::new(1));
+        let (sender, receiver) = mpsc::channel();
+
+        let inner = Arc::clone(&pool);
+        pool.execute(move || {
+            let busy = inner.stats().workers_busy;
+            let workers = inner.workers().len();
+            let queued = inner.queue_snapshot().len();
+            sender.send(Some((busy, workers, queued))).unwrap();
+
+            inner.execute(move || sender.send(None).unwrap());
+            inner.execute_serial(1, || {});
+            inner.execute_limited("key", 1, || {});
+        });
+
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Some((1, 1, 0))
+        );
+        // as does submitting from it
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)).unwrap(), None);
+        pool.join();
+    }
+
+    #[test]
+    fn worker_thread_ids() {
+        let mut pool = ThreadPool::new(4);
+        let thread_ids = pool.worker_thread_ids();
+
+        assert_eq!(
+            thread_ids.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+        let distinct: HashSet<_> = thread_ids.iter().map(|(_, thread_id)| *thread_id).collect();
+        assert_eq!(distinct.len(), 4);
+        assert!(!distinct.contains(&thread::current().id()));
+
+        // the thread the worker's jobs run on
+        for (id, thread_id) in &thread_ids {
+            let (sent, received) = mpsc::channel();
+            pool.execute_on(*id, move || sent.send(thread::current().id()).unwrap())
+                .unwrap();
+            assert_eq!(received.recv().unwrap(), *thread_id);
+        }
+
+        pool.join_worker(2).unwrap();
+        let remaining: Vec<_> = pool
+            .worker_thread_ids()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(remaining, [1, 3, 4]);
+    }
+
+    #[test]
+    fn inline_jobs_run_before_execute_returns() {
+        let pool = ThreadPool::builder()
+            .num_threads(4)
+            .first_worker_id(1)
+            .inline(true)
+            .build()
+            .unwrap();
+        let caller = thread::current().id();
+        let ran = Arc::new(Mutex::new(Vec::new()));
+
+        let in_job = Arc::clone(&ran);
+        pool.execute(move || {
+            let worker = WorkerContext::current().unwrap();
+            assert_eq!(thread::current().id(), caller);
+            in_job
+                .lock()
+                .unwrap()
+                .push(format!("outer on {}", worker.id()));
+
+            // a job submitted by a job runs before the one submitting it
+            // goes on
+            let nested = Arc::clone(&in_job);
+            worker
+                .handle()
+                .execute(move || nested.lock().unwrap().push("nested".to_owned()));
+            in_job.lock().unwrap().push("outer done".to_owned());
+        });
+        assert_eq!(*ran.lock().unwrap(), ["outer on 1", "nested", "outer done"]);
+
+        let in_job = Arc::clone(&ran);
+        pool.execute_on(3, move || {
+            let worker = WorkerContext::current().unwrap().id();
+            in_job.lock().unwrap().push(format!("on {worker}"));
+        })
+        .unwrap();
+        let in_job = Arc::clone(&ran);
+        pool.execute_serial(7, move || in_job.lock().unwrap().push("serial".to_owned()));
+        assert_eq!(ran.lock().unwrap()[3..], ["on 3", "serial"]);
+        // back to not being a worker once the job is done
+        assert!(WorkerContext::current().is_none());
+
+        // caught and counted the same as on a worker thread
+        pool.execute(|| panic!("inline job panicked"));
+
+        let stats = pool.stats();
+        assert_eq!(stats.workers_alive, 0);
+        assert_eq!(stats.jobs_queued, 0);
+        assert_eq!(stats.jobs_completed, 4);
+        assert_eq!(stats.jobs_panicked, 1);
+        assert_eq!(stats.jobs_ran_inline, 5);
+        assert!(pool.worker_thread_ids().is_empty());
+        assert_eq!(pool.workers()[2].jobs_completed, 1);
+
+        // nothing left to wait for
+        let report = pool.shutdown();
+        assert_eq!(report.jobs_completed, 4);
+        assert_eq!(report.jobs_discarded, 0);
+    }
+
+    #[test]
+    fn worker_replaced_after_max_jobs() {
+        let started = Arc::new(AtomicUsize::new(0));
+        let in_hook = Arc::clone(&started);
+        let mut pool = ThreadPool::builder()
+            .num_threads(1)
+            .max_jobs_per_worker(2)
+            .on_thread_start(move |_| {
+                in_hook.fetch_add(1, Ordering::SeqCst);
+            })
+            .build()
+            .unwrap();
+
+        let (sender, receiver) = mpsc::channel();
+        let mut ran_on = Vec::new();
+        for n in 0..5 {
+            let sender = sender.clone();
+            let job = move || {
+                let worker = WorkerContext::current().unwrap().id();
+                sender.send((worker, thread::current().id())).unwrap();
+            };
+            // the thread taking over also takes the jobs left for the worker
+            if n == 4 {
+                pool.execute_on(1, job).unwrap();
+            } else {
+                pool.execute(job);
+            }
+            ran_on.push(receiver.recv_timeout(Duration::from_secs(2)).unwrap());
+        }
+
+        // same worker, a new thread every 2 jobs
+        assert!(ran_on.iter().all(|(worker, _)| *worker == 1));
+        let threads: Vec<_> = ran_on.iter().map(|(_, thread)| *thread).collect();
+        assert_eq!(threads[0], threads[1]);
+        assert_ne!(threads[1], threads[2]);
+        assert_eq!(threads[2], threads[3]);
+        assert_ne!(threads[3], threads[4]);
+        assert_ne!(threads[0], threads[4]);
+        // every new thread is set up like the first one
+        assert_eq!(started.load(Ordering::SeqCst), 3);
+
+        pool.join();
+        assert_eq!(pool.worker_thread_ids(), [(1, threads[4])]);
+        assert_eq!(pool.workers()[0].jobs_completed, 5);
+        assert_eq!(pool.stats().jobs_completed, 5);
+
+        // waits for the thread running as the worker now, not the first one
+        pool.join_worker(1).unwrap();
+        assert!(pool.worker_thread_ids().is_empty());
+        assert_eq!(pool.stats().workers_alive, 0);
+
+        assert_eq!(
+            ThreadPool::builder()
+                .max_jobs_per_worker(0)
+                .build()
+                .unwrap_err(),
+            PoolCreationError::ZeroMaxJobsPerWorker
+        );
+    }
+
+    #[test]
+    fn worker_ids_from_a_given_base() {
+        let started = Arc::new(Mutex::new(Vec::new()));
+        let in_hook = Arc::clone(&started);
+        let mut pool = ThreadPool::builder()
+            .name("zero")
+            .num_threads(4)
+            .max_threads(6)
+            .first_worker_id(0)
+            .on_thread_start(move |id| {
+                let name = thread::current().name().unwrap().to_owned();
+                in_hook.lock().unwrap().push((id, name));
+            })
+            .build()
+            .unwrap();
+
+        let mut started = started.lock().unwrap().clone();
+        started.sort();
+        assert_eq!(
+            started,
+            (0..4)
+                .map(|id| (id, format!("zero-{id}")))
+                .collect::<Vec<_>>()
+        );
+
+        // contiguous, workers started on demand included
+        let ids: Vec<_> = pool.workers().iter().map(|worker| worker.id).collect();
+        assert_eq!(ids, [0, 1, 2, 3, 4, 5]);
+        let running: Vec<_> = pool.worker_thread_ids().iter().map(|(id, _)| *id).collect();
+        assert_eq!(running, [0, 1, 2, 3]);
+
+        // the worker a key goes to is the key modulo the workers
+        let ran_on = Arc::new(Mutex::new(Vec::new()));
+        for key in 0..8 {
+            let ran_on = Arc::clone(&ran_on);
+            pool.execute_keyed(key, move || {
+                let worker = WorkerContext::current().unwrap().id();
+                ran_on.lock().unwrap().push((key, worker));
+            });
+        }
+        pool.join();
+        for &(key, worker) in ran_on.lock().unwrap().iter() {
+            assert_eq!(worker, key as usize % 4);
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        pool.execute_on(0, move || {
+            sender.send(WorkerContext::current().unwrap().id()).unwrap();
+        })
+        .unwrap();
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(2)), Ok(0));
+        assert_eq!(pool.execute_on(6, || {}), Err(PoolError::NoSuchWorker(6)));
+
+        pool.join_worker(0).unwrap();
+        let running: Vec<_> = pool.worker_thread_ids().iter().map(|(id, _)| *id).collect();
+        assert_eq!(running, [1, 2, 3]);
+
+        let pool = ThreadPool::builder()
+            .num_threads(2)
+            .first_worker_id(10)
+            .build()
+            .unwrap();
+        let ids: Vec<_> = pool.workers().iter().map(|worker| worker.id).collect();
+        assert_eq!(ids, [10, 11]);
+        assert_eq!(pool.execute_on(1, || {}), Err(PoolError::NoSuchWorker(1)));
+        assert_eq!(pool.execute_on(12, || {}), Err(PoolError::NoSuchWorker(12)));
+
+        assert!(matches!(
+            ThreadPool::builder()
+                .num_threads(2)
+                .first_worker_id(usize::MAX - 1)
+                .build(),
+            Err(PoolCreationError::WorkerIdOverflow)
+        ));
+    }
+
+    #[test]
+    fn hooks_around_each_job() {
+        #[derive(Debug, PartialEq)]
+        enum Event {
+            Before,
+            Job,
+            After(Duration),
+        }
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let before = Arc::clone(&events);
+        let after = Arc::clone(&events);
+        let pool = ThreadPool::builder()
+            .num_threads(3)
+            .before_each(move |id| before.lock().unwrap().push((id, Event::Before)))
+            .after_each(move |id, elapsed| after.lock().unwrap().push((id, Event::After(elapsed))))
+            .build()
+            .unwrap();
+
+        for n in 0..9 {
+            let events = Arc::clone(&events);
+            pool.execute(move || {
+                let id = WorkerContext::current().unwrap().id();
+                events.lock().unwrap().push((id, Event::Job));
+
+                if n == 0 {
+                    thread::sleep(Duration::from_millis(20));
+                }
+            });
+        }
+
+        pool.join();
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 27);
+
+        // every worker runs its jobs one after the other, so the events of
+        // each of them come in threes
+        for id in 1..=3 {
+            let events: Vec<_> = events.iter().filter(|(worker, _)| *worker == id).collect();
+
+            for job in events.chunks(3) {
+                assert!(matches!(
+                    job,
+                    [(_, Event::Before), (_, Event::Job), (_, Event::After(_))]
+                ));
+            }
+        }
+
+        let longest = events
+            .iter()
+            .filter_map(|(_, event)| match event {
+                Event::After(elapsed) => Some(*elapsed),
+                _ => None,
+            })
+            .max()
+            .unwrap();
+        assert!(longest >= Duration::from_millis(20));
+    }
+
+    #[test]
+    fn recurring_job_runs_till_it_breaks() {
+        let pool = ThreadPool::new(1);
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let (release, released) = mpsc::channel::<()>();
+
+        let recorded = Arc::clone(&runs);
+        let mut count = 0;
+        pool.execute_recurring(move || {
+            count += 1;
+            recorded.lock().unwrap().push("recurring");
+
+            if count == 1 {
+                released.recv().unwrap();
+            }
+            if count > 3 {
+                return ControlFlow::Break(());
+            }
+            ControlFlow::Continue(())
+        });
+
+        // queued while the first run is going, it gets its turn before the
+        // next one
+        let recorded = Arc::clone(&runs);
+        pool.execute(move || recorded.lock().unwrap().push("other"));
+        release.send(()).unwrap();
+
+        pool.join();
+        assert_eq!(
+            *runs.lock().unwrap(),
+            ["recurring", "other", "recurring", "recurring", "recurring"]
+        );
+        assert_eq!(pool.stats().jobs_completed, 5);
+    }
+
+    #[test]
+    fn recurring_job_with_a_delay() {
+        let pool = ThreadPool::new(2);
+        let runs = Arc::new(AtomicUsize::new(0));
+
+        let counted = Arc::clone(&runs);
+        let started = Instant::now();
+        pool.execute_recurring_with_delay(Duration::from_millis(20), move || {
+            match counted.fetch_add(1, Ordering::SeqCst) {
+                0..=2 => ControlFlow::Continue(()),
+                _ => ControlFlow::Break(()),
+            }
+        });
+
+        // waits for the runs waiting on the timer as well
+        pool.join();
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+        assert!(started.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn run_returns_the_value() {
+        let pool = ThreadPool::new(2);
+        assert_eq!(pool.run(|| 6 * 7), Ok(42));
+    }
+
+    #[test]
+    fn run_returns_the_panic() {
+        let pool = ThreadPool::new(1);
+        assert_eq!(
+            pool.run(|| -> u32 { panic!("boom {}", 1) }),
+            Err(JobPanicked {
+                message: "boom 1".to_owned(),
+            })
+        );
+        // caught by run(), not by the worker
+        assert_eq!(pool.stats().jobs_panicked, 0);
+        assert_eq!(pool.run(|| 1), Ok(1));
+    }
+
+    #[test]
+    fn run_once_returns_the_value() {
+        let items = [3, 4, 5];
+        assert_eq!(
+            ThreadPool::run_once(move || items.iter().map(|n| n * n).sum::<i32>()),
+            50
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "boom")]
+    fn run_once_resumes_the_panic() {
+        ThreadPool::run_once(|| panic!("boom"));
+    }
+
+    #[test]
+    fn run_from_inside_a_job_of_the_same_pool() {
+        // a single worker would deadlock waiting on itself unless the
+        // closure runs inline
+        let pool = Arc::new(ThreadPool::new(1));
+        let (sender, receiver) = mpsc::channel();
+
+        let inner = Arc::clone(&pool);
+        pool.execute(move || {
+            let worker = thread::current().id();
+            let _ = sender.send(inner.run(move || thread::current().id() == worker));
+        });
+
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap(),
+            Ok(true)
+        );
+        // the job's handle on the pool is gone once it's done, so it isn't
+        // dropped on its own worker
+        pool.join();
+    }
+
+    #[test]
+    fn serial_jobs_of_a_key_run_in_order() {
+        let pool = ThreadPool::new(4);
+        // a read and a write with a pause in between, so jobs overlapping
+        // would lose updates
+        let counter = Arc::new(AtomicUsize::new(0));
+        let order = Arc::new(Mutex::new(Vec::new()));
+
+        for i in 0..50 {
+            let counter = Arc::clone(&counter);
+            let order = Arc::clone(&order);
+
+            pool.execute_serial(1, move || {
+                let value = counter.load(Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(1));
+                counter.store(value + 1, Ordering::SeqCst);
+                order.lock().unwrap().push(i);
+            });
+        }
+
+        // not held up by the jobs of the other key
+        let (sender, receiver) = mpsc::channel();
+        pool.execute_serial(2, move || sender.send(()).unwrap());
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(counter.load(Ordering::SeqCst) < 50);
+
+        pool.join();
+        assert_eq!(counter.load(Ordering::SeqCst), 50);
+        assert_eq!(*order.lock().unwrap(), (0..50).collect::<Vec<_>>());
+        // keys without jobs left are cleaned up
+        assert!(pool.shared.serial.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn shutdown_logged() {
+        capture_logs();
+        let pool = ThreadPool::new(2);
+        pool.execute(|| {});
+        drop(pool);
+
+        // other tests drop pools of their own, so there can be more lines
+        // than this pool's workers
+        assert!(logged(Level::Info, "disconnected; shutting down.").len() >= 2);
+        assert!(logged(Level::Error, "disconnected; shutting down.").is_empty());
+    }
+
+    #[test]
+    fn jobs_past_the_max_queue_age_expire() {
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .max_queue_age(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        pool.execute(|| thread::sleep(Duration::from_millis(300)));
+        for _ in 0..3 {
+            let ran = Arc::clone(&ran);
+            pool.execute(move || {
+                ran.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        pool.join();
+
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+        let stats = pool.stats();
+        assert_eq!((stats.jobs_completed, stats.jobs_expired), (1, 3));
+    }
+
+    #[test]
+    fn job_past_its_own_deadline_expires() {
+        let pool = ThreadPool::new(1);
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        pool.execute(|| thread::sleep(Duration::from_millis(100)));
+        let late = Arc::clone(&ran);
+        pool.execute_with_deadline(Instant::now() + Duration::from_millis(10), move || {
+            late.fetch_add(1, Ordering::SeqCst);
+        });
+        let on_time = Arc::clone(&ran);
+        pool.execute_with_deadline(Instant::now() + Duration::from_secs(60), move || {
+            on_time.fetch_add(1, Ordering::SeqCst);
+        });
+        pool.join();
+
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.stats().jobs_expired, 1);
+    }
+
+    #[test]
+    fn workers_ready_once_built() {
+        for size in [1, 4, 16] {
+            let pool = ThreadPool::new(size);
+            assert_eq!(pool.stats().workers_alive, size);
+        }
+
+        let pool = ThreadPool::builder()
+            .num_threads(4)
+            .wait_for_workers(false)
+            .build()
+            .unwrap();
+        assert!(pool.stats().workers_alive <= 4);
+        pool.execute(|| {});
+        pool.join();
+    }
+
+    #[test]
+    fn batched_jobs_all_run_and_drain_on_drop() {
+        for batch_size in [1, 16] {
+            let ran = Arc::new(AtomicUsize::new(0));
+            let pool = ThreadPool::builder()
+                .num_threads(4)
+                .batch_size(batch_size)
+                .build()
+                .unwrap();
+
+            for _ in 0..10_000 {
+                let ran = Arc::clone(&ran);
+                pool.execute(move || {
+                    ran.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            drop(pool);
+
+            assert_eq!(ran.load(Ordering::Relaxed), 10_000);
+        }
+    }
+
+    #[test]
+    fn batches_dont_starve_idle_workers() {
+        let pool = ThreadPool::builder()
+            .num_threads(4)
+            .batch_size(16)
+            .build()
+            .unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        // every job blocks until all 4 run at once, a worker taking more
+        // than its share would leave the others waiting forever
+        let barrier = Arc::new(Barrier::new(4));
+        for _ in 0..4 {
+            let barrier = Arc::clone(&barrier);
+            let sender = sender.clone();
+            pool.execute(move || {
+                barrier.wait();
+                sender.send(()).unwrap();
+            });
+        }
+
+        for _ in 0..4 {
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+    }
+
+    #[test]
+    fn parked_jobs_dont_count_towards_batches() {
+        let pool = ThreadPool::builder()
+            .num_threads(4)
+            .batch_size(16)
+            .build()
+            .unwrap();
+        let (release, released) = mpsc::channel::<()>();
+        let (sender, receiver) = mpsc::channel();
+
+        // keeps a worker busy with the first job of the key while the rest
+        // are parked behind it
+        let released = Mutex::new(released);
+        pool.execute_serial(1, move || {
+            let _ = released.lock().unwrap().recv();
+        });
+        for _ in 0..50 {
+            pool.execute_serial(1, || {});
+        }
+
+        // the 3 workers left have to each take one of these, a batch sized
+        // by the parked jobs as well would leave the others waiting forever
+        let barrier = Arc::new(Barrier::new(3));
+        for _ in 0..3 {
+            let barrier = Arc::clone(&barrier);
+            let sender = sender.clone();
+            pool.execute(move || {
+                barrier.wait();
+                sender.send(()).unwrap();
+            });
+        }
+
+        for _ in 0..3 {
+            receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        }
+        drop(release);
+    }
+
+    #[test]
+    fn spinning_workers_miss_no_job() {
+        let pool = ThreadPool::builder()
+            .num_threads(4)
+            .idle_strategy(IdleStrategy::SpinThenPark { spins: 1000 })
+            .build()
+            .unwrap();
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        // in bursts with pauses in between, so workers go from spinning to
+        // parked and back
+        for _ in 0..20 {
+            for _ in 0..100 {
+                let ran = Arc::clone(&ran);
+                pool.execute(move || {
+                    ran.fetch_add(1, Ordering::Relaxed);
+                });
+            }
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(pool.join_timeout(Duration::from_secs(5)), Ok(()));
+        assert_eq!(ran.load(Ordering::Relaxed), 2000);
+
+        let started = Instant::now();
+        drop(pool);
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn spawn_failure_rolls_back_the_started_workers() {
+        // the queue is only dropped along with the pool's shared state,
+        // which every worker thread holds on to until it exits
+        struct Watched {
+            queue: MpscQueue,
+            dropped: Arc<AtomicBool>,
+        }
+
+        impl JobQueue for Watched {
+            fn push(&self, job: Job) -> Result<(), Job> {
+                self.queue.push(job)
+            }
+
+            fn try_pop(&self) -> Option<Job> {
+                self.queue.try_pop()
+            }
+
+            fn close(&self) {
+                self.queue.close();
+            }
+
+            fn len(&self) -> usize {
+                self.queue.len()
+            }
+        }
+
+        impl Drop for Watched {
+            fn drop(&mut self) {
+                self.dropped.store(true, Ordering::SeqCst);
+            }
+        }
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let watched = Arc::clone(&dropped);
+
+        FAIL_SPAWN_OF.with(|fail| fail.set(Some(3)));
+        let result = ThreadPool::builder()
+            .num_threads(4)
+            .job_queue(move || Watched {
+                queue: MpscQueue::new(),
+                dropped: Arc::clone(&watched),
+            })
+            .build();
+        FAIL_SPAWN_OF.with(|fail| fail.set(None));
+
+        assert_eq!(
+            result.err(),
+            Some(PoolCreationError::SpawnFailed(io::ErrorKind::WouldBlock))
+        );
+        // the 2 workers started before are shut down and joined again
+        assert!(dropped.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn map_keeps_the_order_of_the_items() {
+        let pool = ThreadPool::new(4);
+
+        assert_eq!(
+            pool.map(0..1000u64, |n| n * n),
+            Ok((0..1000u64).map(|n| n * n).collect())
+        );
+        assert_eq!(pool.map(Vec::<u64>::new(), |n| n * n), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn map_returns_the_first_panic() {
+        let pool = ThreadPool::new(4);
+        let mapped = Arc::new(AtomicUsize::new(0));
+
+        let counted = Arc::clone(&mapped);
+        let result = pool.map(0..100, move |n| {
+            counted.fetch_add(1, Ordering::SeqCst);
+            assert!(n % 10 != 3, "bad item {n}");
+            n
+        });
+
+        assert_eq!(
+            result,
+            Err(JobPanicked {
+                message: "bad item 3".to_owned(),
+            })
+        );
+        // the rest of the items are still mapped
+        assert_eq!(mapped.load(Ordering::SeqCst), 100);
+    }
+
+    #[test]
+    fn jobs_of_a_key_run_on_the_same_worker() {
+        let pool = ThreadPool::new(4);
+        let ran_on = Arc::new(Mutex::new(Vec::new()));
+
+        for key in [7, 7, 3, 7, 11, 3] {
+            let ran_on = Arc::clone(&ran_on);
+            pool.execute_keyed(key, move || {
+                let worker = WorkerContext::current().unwrap().id();
+                ran_on.lock().unwrap().push((key, worker));
+            });
+        }
+        pool.join();
+
+        let ran_on = ran_on.lock().unwrap();
+        assert_eq!(ran_on.len(), 6);
+        // worker ids start at 1
+        for &(key, worker) in ran_on.iter() {
+            assert_eq!(worker, key as usize % 4 + 1);
+        }
+    }
+
+    #[test]
+    fn job_run_on_a_given_worker() {
+        thread_local! {
+            static MARKED: Cell<bool> = const { Cell::new(false) };
+        }
+
+        let pool = ThreadPool::builder()
+            .num_threads(3)
+            .on_thread_start(|id| MARKED.with(|marked| marked.set(id == 2)))
+            .build()
+            .unwrap();
+        let (sender, receiver) = mpsc::channel();
+
+        for id in [2, 1] {
+            let sender = sender.clone();
+            pool.execute_on(id, move || {
+                sender.send((id, MARKED.with(Cell::get))).unwrap();
+            })
+            .unwrap();
+        }
+
+        let mut seen: Vec<_> = receiver.iter().take(2).collect();
+        seen.sort();
+        assert_eq!(seen, [(1, false), (2, true)]);
+        // worker ids start at 1
+        assert_eq!(pool.execute_on(0, || {}), Err(PoolError::NoSuchWorker(0)));
+        assert_eq!(pool.execute_on(4, || {}), Err(PoolError::NoSuchWorker(4)));
+    }
+
+    // one worker held up by a job while 5 more are queued behind it
+    fn pool_with_5_queued(ran: &Arc<AtomicUsize>) -> ThreadPool {
+        let pool = ThreadPool::new(1);
+        let (started, running) = mpsc::channel();
+
+        pool.execute(move || {
+            started.send(()).unwrap();
+            thread::sleep(Duration::from_millis(100));
+        });
+        for _ in 0..5 {
+            let ran = Arc::clone(ran);
+            pool.execute(move || {
+                ran.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        running.recv().unwrap();
+        pool
+    }
+
+    #[test]
+    fn shutdown_draining_the_queue() {
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        let pool = pool_with_5_queued(&ran);
+        assert_eq!(pool.shutdown_with(ShutdownMode::Drain), 0);
+        assert_eq!(ran.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn shutdown_abandoning_the_queue() {
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        let pool = pool_with_5_queued(&ran);
+        assert_eq!(pool.shutdown_with(ShutdownMode::Abandon), 5);
+        assert_eq!(ran.load(Ordering::SeqCst), 0);
+    }
+
+    // hands its cleanup to the pool when it's dropped, or runs it itself
+    // when the pool doesn't take it anymore
+    struct Guard {
+        handle: ThreadPoolHandle,
+        cleaned_up: mpsc::Sender<&'static str>,
+    }
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            let on_pool = self.cleaned_up.clone();
+            let cleanup = move || on_pool.send("pool").unwrap();
+
+            if let Err(rejected) = self.handle.try_execute_returning(cleanup) {
+                assert_eq!(rejected.error, PoolError::ShutDown);
+                self.cleaned_up.send("inline").unwrap();
+                // handed back, not run
+                drop(rejected.job);
+            }
+        }
+    }
+
+    #[test]
+    fn submitting_from_a_drop_during_shutdown() {
+        let pool = ThreadPool::new(1);
+        let (started, running) = mpsc::channel();
+        let (cleaned_up, cleanups) = mpsc::channel();
+
+        pool.execute(move || {
+            started.send(()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+        });
+        for _ in 0..3 {
+            let guard = Guard {
+                handle: pool.handle(),
+                cleaned_up: cleaned_up.clone(),
+            };
+            pool.execute(move || drop(guard));
+        }
+        running.recv().unwrap();
+
+        // the guards are dropped along with the jobs thrown away, while the
+        // pool is shutting down
+        assert_eq!(pool.shutdown_with(ShutdownMode::Abandon), 3);
+        drop(cleaned_up);
+        assert_eq!(cleanups.iter().collect::<Vec<_>>(), ["inline"; 3]);
+    }
+
+    #[test]
+    fn rejected_job_handed_back() {
+        let pool = ThreadPool::new(1);
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        let counted = Arc::clone(&ran);
+        assert!(pool
+            .try_execute_returning(move || {
+                counted.fetch_add(1, Ordering::SeqCst);
+            })
+            .is_ok());
+        pool.join();
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+
+        pool.close();
+        let counted = Arc::clone(&ran);
+        let rejected = pool
+            .try_execute_returning(move || {
+                counted.fetch_add(10, Ordering::SeqCst);
+            })
+            .unwrap_err();
+        assert_eq!(rejected.error, PoolError::ShutDown);
+        assert_eq!(rejected.to_string(), "thread pool is closed to new jobs");
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+
+        // still the caller's to run
+        (rejected.job)();
+        assert_eq!(ran.load(Ordering::SeqCst), 11);
+    }
+
+    #[test]
+    fn queue_snapshot_lists_the_oldest_first() {
+        let pool = ThreadPool::new(1);
+        let (started, running) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+
+        pool.execute_named("sleeper", move || {
+            started.send(()).unwrap();
+            released.recv().unwrap();
+        });
+        running.recv().unwrap();
+        for name in ["first", "second", "third"] {
+            pool.execute_named(name, || {});
+        }
+
+        let snapshot = pool.queue_snapshot();
+        let names: Vec<_> = snapshot.iter().map(|job| job.name.as_deref()).collect();
+        assert_eq!(names, [Some("first"), Some("second"), Some("third")]);
+        assert!(snapshot
+            .windows(2)
+            .all(|jobs| jobs[0].queued_at <= jobs[1].queued_at));
+
+        release.send(()).unwrap();
+        pool.join();
+        assert!(pool.queue_snapshot().is_empty());
+    }
+
+    #[test]
+    fn nested_jobs_on_a_full_queue_dont_deadlock() {
+        let pool = Arc::new(
+            ThreadPool::builder()
+                .num_threads(1)
+                .queue_capacity(1)
+                .build()
+                .unwrap(),
+        );
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        let (nested, children) = (Arc::clone(&pool), Arc::clone(&ran));
+        pool.execute(move || {
+            // waiting for room would wait on the only worker, i.e. itself
+            for _ in 0..3 {
+                let ran = Arc::clone(&children);
+                nested.execute(move || {
+                    ran.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        assert_eq!(pool.join_timeout(Duration::from_secs(5)), Ok(()));
+        assert_eq!(ran.load(Ordering::SeqCst), 3);
+        assert_eq!(pool.stats().jobs_ran_inline, 0);
+    }
+
+    // submits the next step of a chain `left` steps long from inside the
+    // current one, the last step sends on `done`
+    fn chain(pool: &Arc<ThreadPool>, left: usize, done: mpsc::Sender<()>) {
+        if left == 0 {
+            done.send(()).unwrap();
+            return;
+        }
+
+        let next = Arc::clone(pool);
+        pool.execute(move || chain(&next, left - 1, done));
+    }
+
+    #[test]
+    fn long_chain_of_jobs_submitting_the_next_one() {
+        let pool = Arc::new(ThreadPool::new(1));
+        let (sender, receiver) = mpsc::channel();
+
+        chain(&pool, 300_000, sender);
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(60)), Ok(()));
+        pool.join();
+        // every step is queued, none of them nested in the one before it
+        assert_eq!(pool.stats().jobs_ran_inline, 0);
+    }
+
+    #[test]
+    fn child_panicking_doesnt_reach_its_parent() {
+        let pool = Arc::new(ThreadPool::new(1));
+        let (sender, receiver) = mpsc::channel();
+
+        let nested = Arc::clone(&pool);
+        pool.execute(move || {
+            nested.execute(|| panic!("child"));
+            sender.send(()).unwrap();
+        });
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(()));
+        pool.join();
+        let stats = pool.stats();
+        assert_eq!(stats.jobs_panicked, 1);
+        assert_eq!(stats.jobs_completed, 1);
+    }
+
+    #[test]
+    fn closed_pool_finishes_the_queued_jobs() {
+        let pool = ThreadPool::new(1);
+        let ran = Arc::new(AtomicUsize::new(0));
+        let (release, released) = mpsc::channel::<()>();
+
+        pool.execute(move || released.recv().unwrap());
+        for _ in 0..3 {
+            let ran = Arc::clone(&ran);
+            pool.execute(move || {
+                ran.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        assert!(!pool.is_closed());
+        pool.close();
+        assert!(pool.is_closed());
+        assert_eq!(pool.try_execute(|| {}), Err(PoolError::ShutDown));
+
+        release.send(()).unwrap();
+        pool.join();
+        assert_eq!(ran.load(Ordering::SeqCst), 3);
+        drop(pool);
+    }
+
+    #[test]
+    #[should_panic]
+    fn execute_on_a_closed_pool() {
+        let pool = ThreadPool::new(1);
+
+        pool.close();
+        pool.execute(|| {});
+    }
+
+    #[test]
+    fn classes_served_by_weight() {
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .class_weight("api", 4)
+            .build()
+            .unwrap();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let (release, released) = mpsc::channel::<()>();
+
+        pool.execute(move || released.recv().unwrap());
+        for _ in 0..100 {
+            for class in ["api", "reports"] {
+                let order = Arc::clone(&order);
+                pool.execute_classed(class, move || order.lock().unwrap().push(class));
+            }
+        }
+        release.send(()).unwrap();
+        pool.join();
+
+        let order = order.lock().unwrap();
+        // 4 api calls for every report while both have jobs waiting, the
+        // reports left over run once the api calls run out
+        let api_calls = order[..100].iter().filter(|&&class| class == "api").count();
+        assert!((78..=82).contains(&api_calls), "{api_calls} api calls");
+        assert_eq!(order.len(), 200);
+        assert!(order[125..].iter().all(|&class| class == "reports"));
+    }
+
+    #[test]
+    fn map_reduce_sum() {
+        let pool = ThreadPool::new(4);
+
+        let sum = pool.map_reduce(1..=1_000_000u64, |n| n, 0, |a, b| a + b);
+        assert_eq!(sum, Ok((1..=1_000_000u64).sum()));
+        assert_eq!(
+            pool.map_reduce(Vec::<u64>::new(), |n| n, 0, |a, b| a + b),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn map_reduce_hash() {
+        use std::hash::{DefaultHasher, Hash, Hasher};
+
+        fn hash(word: String) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            word.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let pool = ThreadPool::new(4);
+        let words = || (0..200_000).map(|n| format!("word{n}"));
+
+        let hashed = pool.map_reduce(words(), hash, 0, u64::wrapping_add);
+        assert_eq!(hashed, Ok(words().map(hash).fold(0, u64::wrapping_add)));
+        // the items are combined in their order
+        let joined = pool.map_reduce(
+            words().take(1000),
+            |word| word,
+            String::new(),
+            |a, b| a + &b,
+        );
+        assert_eq!(joined, Ok(words().take(1000).collect::<String>()));
+    }
+
+    #[test]
+    fn map_reduce_panic() {
+        let pool = ThreadPool::new(4);
+
+        let result = pool.map_reduce(
+            0..1000u64,
+            |n| {
+                assert!(n != 500, "bad item {n}");
+                n
+            },
+            0,
+            |a, b| a + b,
+        );
+        assert_eq!(
+            result,
+            Err(JobPanicked {
+                message: "bad item 500".to_owned(),
+            })
+        );
+    }
+
+    // polls the pool's stats until `done` says so, for up to 5 seconds
+    fn wait_for_stats(pool: &ThreadPool, done: impl Fn(&PoolStats) -> bool) -> PoolStats {
+        let started = Instant::now();
+
+        loop {
+            let stats = pool.stats();
+            if done(&stats) || started.elapsed() > Duration::from_secs(5) {
+                return stats;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    // a job holding up a worker for as long as `gate` is write locked
+    fn gated(gate: &Arc<RwLock<()>>) -> impl FnOnce() + Send + 'static {
+        let gate = Arc::clone(gate);
+        move || drop(gate.read().unwrap())
+    }
+
+    #[test]
+    fn cpu_preset_limits() {
+        let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let pool = ThreadPool::for_cpu();
+        let gate = Arc::new(RwLock::new(()));
+        let closed = gate.write().unwrap();
+
+        let stats = pool.stats();
+        assert_eq!((stats.workers_alive, stats.workers_max), (cores, cores));
+
+        for _ in 0..cores {
+            pool.execute(gated(&gate));
+        }
+        wait_for_stats(&pool, |stats| stats.workers_busy == cores);
+        // 4 jobs queued per thread, the one after that runs right here
+        let (sender, ran_on) = mpsc::channel();
+        for _ in 0..cores * 4 + 1 {
+            let sender = sender.clone();
+            // the queued ones only run once the receiver is gone
+            pool.execute(move || {
+                let _ = sender.send(thread::current().id());
+            });
+        }
+        assert_eq!(ran_on.try_recv(), Ok(thread::current().id()));
+        assert!(ran_on.try_recv().is_err());
+
+        drop(closed);
+    }
+
+    #[test]
+    fn io_preset_limits() {
+        let cores = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        let max_threads = cores + 2;
+        let pool = ThreadPool::for_io(max_threads);
+        let gate = Arc::new(RwLock::new(()));
+        let closed = gate.write().unwrap();
+
+        let stats = pool.stats();
+        assert_eq!(
+            (stats.workers_alive, stats.workers_max),
+            (cores, max_threads)
+        );
+
+        // more threads are started for jobs that would wait otherwise, up
+        // to `max_threads`
+        for _ in 0..max_threads + 3 {
+            pool.execute(gated(&gate));
+        }
+        let stats = wait_for_stats(&pool, |stats| stats.workers_busy == max_threads);
+        assert_eq!(stats.workers_alive, max_threads);
+        assert_eq!(stats.workers_busy, max_threads);
+        assert_eq!(stats.jobs_queued, 3);
+
+        drop(closed);
+    }
+
+    #[test]
+    fn boxed_job_runs() {
+        let pool = ThreadPool::new(2);
+        let (sender, receiver) = mpsc::channel();
+
+        let job: Box<dyn FnOnce() + Send> = Box::new(move || sender.send("ran").unwrap());
+        pool.execute_boxed(job);
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok("ran"));
+    }
+
+    #[test]
+    fn round_robin_spreads_the_jobs_evenly() {
+        let pool = ThreadPool::builder()
+            .num_threads(8)
+            .dispatch_mode(DispatchMode::RoundRobin)
+            .build()
+            .unwrap();
+
+        for _ in 0..800 {
+            pool.execute(|| thread::sleep(Duration::from_millis(1)));
+        }
+        pool.join();
+
+        for worker in pool.workers() {
+            assert!(
+                (90..=110).contains(&worker.jobs_completed),
+                "{:?}",
+                pool.workers()
+            );
+        }
+    }
+
+    #[test]
+    fn timing_out_doesnt_stop_the_core_workers() {
+        let pool = ThreadPool::builder()
+            .num_threads(2)
+            .max_threads(3)
+            .keep_alive(Duration::from_millis(20))
+            .build()
+            .unwrap();
+        let gate = Arc::new(RwLock::new(()));
+        let closed = gate.write().unwrap();
+
+        // a third thread is started for the job waiting behind the first two
+        for _ in 0..3 {
+            pool.execute(gated(&gate));
+        }
+        wait_for_stats(&pool, |stats| stats.workers_busy == 3);
+        assert_eq!(pool.worker_thread_ids().len(), 3);
+        drop(closed);
+
+        // the third thread times out and exits, the core ones go back to
+        // waiting
+        let stats = wait_for_stats(&pool, |stats| stats.workers_alive == 2);
+        assert_eq!(stats.workers_alive, 2);
+        let ids: Vec<_> = pool
+            .worker_thread_ids()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect();
+        assert_eq!(ids, [1, 2]);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(pool.stats().workers_alive, 2);
+
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..2 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(()).unwrap());
+        }
+        assert_eq!(receiver.iter().take(2).count(), 2);
+    }
+
+    #[test]
+    fn fallback_once_the_queue_is_full() {
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .queue_capacity(2)
+            .build()
+            .unwrap();
+        let gate = Arc::new(RwLock::new(()));
+        let closed = gate.write().unwrap();
+        let fell_back = Cell::new(0);
+
+        pool.execute(gated(&gate));
+        wait_for_stats(&pool, |stats| stats.workers_busy == 1);
+        for _ in 0..3 {
+            pool.execute_or(|| {}, || fell_back.set(fell_back.get() + 1));
+        }
+
+        // 2 jobs fit into the queue, the third one didn't
+        assert_eq!(fell_back.get(), 1);
+        assert_eq!(pool.stats().jobs_queued, 2);
+        drop(closed);
+    }
+
+    #[test]
+    fn pools_told_apart_by_name() {
+        capture_logs();
+        let (sender, receiver) = mpsc::channel();
+
+        for name in ["named-http", "named-maintenance"] {
+            let pool = ThreadPool::builder()
+                .name(name)
+                .num_threads(3)
+                .watchdog(Duration::from_millis(10), Duration::from_millis(20), {
+                    let sender = sender.clone();
+                    move |pool, _, _, _| drop(sender.send(("watchdog", pool.to_owned())))
+                })
+                .build()
+                .unwrap();
+            let thread_names = sender.clone();
+            pool.execute_on(2, move || {
+                let thread_name = thread::current().name().unwrap().to_owned();
+                thread_names.send(("thread", thread_name)).unwrap();
+                thread::sleep(Duration::from_millis(100));
+            })
+            .unwrap();
+            pool.join();
+
+            assert_eq!(pool.name(), name);
+            assert_eq!(pool.stats().pool_name, name);
+            assert!(format!("{pool:?}").contains(&format!("name: \"{name}\"")));
+        }
+        drop(sender);
+
+        let seen: HashSet<_> = receiver.iter().collect();
+        let expected = HashSet::from([
+            ("thread", "named-http-2".to_owned()),
+            ("watchdog", "named-http".to_owned()),
+            ("thread", "named-maintenance-2".to_owned()),
+            ("watchdog", "named-maintenance".to_owned()),
+        ]);
+        assert_eq!(seen, expected);
+
+        // pools without a name are numbered
+        let unnamed = ThreadPool::new(1);
+        let number = unnamed.name().strip_prefix("pool-").unwrap();
+        assert!(number.parse::<usize>().is_ok());
+    }
+
+    #[test]
+    fn other_workers_keep_going_after_one_is_joined() {
+        let mut pool = ThreadPool::new(3);
+
+        assert!(pool.join_worker(1).is_ok());
+        assert_eq!(pool.stats().workers_alive, 2);
+        assert_eq!(pool.execute_on(1, || {}), Err(PoolError::WorkerStopped(1)));
+
+        let ran_on = Arc::new(Mutex::new(Vec::new()));
+        for _ in 0..20 {
+            let ran_on = Arc::clone(&ran_on);
+            pool.execute(move || {
+                let worker = WorkerContext::current().unwrap().id();
+                ran_on.lock().unwrap().push(worker);
+            });
+        }
+        pool.join();
+
+        let ran_on = ran_on.lock().unwrap();
+        assert_eq!(ran_on.len(), 20);
+        assert!(!ran_on.contains(&1));
+        // joining it again changes nothing
+        assert!(pool.join_worker(1).is_ok());
+    }
+
+    #[test]
+    fn children_of_a_job_on_a_full_pool_dont_wedge_it() {
+        // the first child fills the queue, only the second finds it full
+        for (on_reentrant_full, ran_inline) in
+            [(ReentrantFull::Overflow, 0), (ReentrantFull::Inline, 1)]
+        {
+            let pool = Arc::new(
+                ThreadPool::builder()
+                    .num_threads(1)
+                    .queue_capacity(1)
+                    .on_reentrant_full(on_reentrant_full)
+                    .build()
+                    .unwrap(),
+            );
+            let (sender, receiver) = mpsc::channel();
+
+            let nested = Arc::clone(&pool);
+            pool.execute(move || {
+                for child in 0..2 {
+                    let sender = sender.clone();
+                    nested.execute(move || sender.send(child).unwrap());
+                }
+            });
+
+            let mut children: Vec<_> = receiver.iter().take(2).collect();
+            children.sort();
+            assert_eq!(children, [0, 1]);
+            pool.join();
+            assert_eq!(pool.stats().jobs_ran_inline, ran_inline);
+        }
+    }
+
+    // 1 worker with a queue of 1 running children of its jobs inline once
+    // it's full
+    fn inlining_single_slot_pool() -> Arc<ThreadPool> {
+        Arc::new(
+            ThreadPool::builder()
+                .num_threads(1)
+                .queue_capacity(1)
+                .on_reentrant_full(ReentrantFull::Inline)
+                .build()
+                .unwrap(),
+        )
+    }
+
+    #[test]
+    fn long_chain_of_jobs_on_a_full_queue() {
+        let pool = inlining_single_slot_pool();
+        let (sender, receiver) = mpsc::channel();
+
+        // the first job fills the queue for good, the worker takes the jobs
+        // put past the capacity before it
+        let nested = Arc::clone(&pool);
+        pool.execute(move || {
+            nested.execute(|| {});
+            chain(&nested, 300_000, sender);
+        });
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(60)), Ok(()));
+        pool.join();
+        let ran_inline = pool.stats().jobs_ran_inline;
+        assert!(ran_inline > 0 && ran_inline < 300_000);
+    }
+
+    #[test]
+    fn child_run_inline_panicking() {
+        let pool = inlining_single_slot_pool();
+        let (sender, receiver) = mpsc::channel();
+
+        let nested = Arc::clone(&pool);
+        pool.execute(move || {
+            nested.execute(|| {});
+            nested.execute(|| panic!("child"));
+            // the panic of the child doesn't get here
+            sender.send(()).unwrap();
+        });
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(()));
+        pool.join();
+        let stats = pool.stats();
+        assert_eq!(stats.jobs_ran_inline, 1);
+        assert_eq!(stats.jobs_panicked, 1);
+        assert_eq!(stats.jobs_completed, 2);
+    }
+
+    #[test]
+    fn child_of_a_job_on_a_full_pool_refused() {
+        let pool = Arc::new(
+            ThreadPool::builder()
+                .num_threads(1)
+                .queue_capacity(1)
+                .on_reentrant_full(ReentrantFull::Error)
+                .build()
+                .unwrap(),
+        );
+        let ran = Arc::new(AtomicUsize::new(0));
+        let (sender, receiver) = mpsc::channel();
+
+        let (nested, children) = (Arc::clone(&pool), Arc::clone(&ran));
+        pool.execute(move || {
+            for _ in 0..2 {
+                let ran = Arc::clone(&children);
+                let submitted = nested.try_execute(move || {
+                    ran.fetch_add(1, Ordering::SeqCst);
+                });
+                sender.send(submitted).unwrap();
+            }
+        });
+
+        let submitted: Vec<_> = receiver.iter().take(2).collect();
+        assert_eq!(submitted, [Ok(()), Err(PoolError::QueueFull)]);
+        pool.join();
+        assert_eq!(ran.load(Ordering::SeqCst), 1);
+        assert_eq!(pool.stats().jobs_ran_inline, 0);
+    }
+
+    // drops a 1-worker pool named `name` with 3 jobs still queued
+    fn drop_with_3_queued(name: &str, warn_on_drop: bool) {
+        let pool = ThreadPool::builder()
+            .name(name)
+            .num_threads(1)
+            .warn_on_drop(warn_on_drop)
+            .build()
+            .unwrap();
+        let (started, running) = mpsc::channel();
+
+        pool.execute(move || {
+            started.send(()).unwrap();
+            thread::sleep(Duration::from_millis(50));
+        });
+        for _ in 0..3 {
+            pool.execute(|| {});
+        }
+        running.recv().unwrap();
+        drop(pool);
+    }
+
+    #[test]
+    fn dropped_with_jobs_queued() {
+        capture_logs();
+
+        drop_with_3_queued("dropped-early", true);
+        assert_eq!(
+            logged(Level::Warn, "dropped-early"),
+            ["Dropping thread pool dropped-early with 3 jobs still queued; they will still run."]
+        );
+
+        drop_with_3_queued("dropped-deliberately", false);
+        assert!(logged(Level::Warn, "dropped-deliberately").is_empty());
+    }
+}
@@ -1,77 +1,269 @@
-use hello::ThreadPool;
+use hello::{
+    cors::{AllowedOrigins, Cors},
+    http::{ParseLimits, Request, Response},
+    router::Router,
+    server::{self, AccessLog, Config, ConnectionLimiter, ConnectionPolicy, RateLimiter},
+    static_files::StaticFiles,
+    ThreadPool,
+};
+use log::{error, info, LevelFilter, Log, Metadata, Record};
 use std::{
-    fs,
-    io::{prelude::*, BufReader},
-    net::{TcpListener, TcpStream},
+    io::{self, Read},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::Arc,
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+// the library logs through the `log` crate instead of printing, which
+// leaves it up to the binary where those messages end up, this one just
+// prints them all to stdout, a real server would plug in env_logger or a
+// similar crate here instead
+struct StdoutLogger;
+
+impl Log for StdoutLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        println!("{}", record.args());
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StdoutLogger = StdoutLogger;
+
+// everything the threads handling connections share, behind a single Arc
+struct App {
+    router: Router,
+    rate_limiter: RateLimiter,
+    access_log: Option<AccessLog>,
+    parse_limits: ParseLimits,
+}
+
 fn main() {
+    // debug level so that the pool's messages about which thread picked up
+    // a request show up as well
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+
+    let config = Config {
+        access_log: Some("access.log".into()),
+        ..Config::default()
+    };
+
     // creates a tcp listener that listens for incoming tcp streams
     // at the provided address
-    let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
-    // creates a thread pool with 4 threads
-    let pool = ThreadPool::new(4);
+    let listener = TcpListener::bind(&config.address).unwrap();
+    // handlers spend most of their time blocked on reading files and
+    // sleeping rather than on the cpu, so the pool grows up to 64 threads
+    // by default while requests keep every thread busy
+    let pool = ThreadPool::for_io(config.threads);
+    // caps the number of connections being handled or waiting for a thread
+    // at 16, any connection beyond that is answered with a 503 right away
+    // instead of piling up in the thread pool's queue
+    let limiter = Arc::new(ConnectionLimiter::new(16, ConnectionPolicy::Reject));
+    // lets pages served from any other origin make requests to the server
+    let cors = Cors::new(AllowedOrigins::Any);
+    let app = Arc::new(App {
+        router: router(cors),
+        // lets every client make 10 requests at once and 5 requests a second
+        // after that, any request beyond that is answered with a 429
+        rate_limiter: RateLimiter::new(5, 10),
+        // every request answered gets a line in the access log
+        access_log: config
+            .access_log
+            .as_ref()
+            .map(|path| AccessLog::open(path).unwrap()),
+        // requests with a request line past these are answered with a 414
+        // and ones with headers past them with a 431
+        parse_limits: config.parse_limits,
+    });
 
     // listener.incoming() returns an iterator over the sequence of
     // incoming tcp streams, by default listens for incoming tcp streams indefinitely,
     // .take(20) makes it so that it only handles 20 incoming tcp streams and shuts down
     // right after, done to illustrate the concept of graceful shutdown
     for stream in listener.incoming().take(20) {
-        let stream = stream.unwrap();
+        let mut stream = stream.unwrap();
 
-        pool.execute(|| {
-            handle_connection(stream);
+        let Some(permit) = limiter.acquire() else {
+            // the client going away before reading this is not a problem
+            let _ = reject(&mut stream);
+            continue;
+        };
+
+        let app = Arc::clone(&app);
+
+        pool.execute(move || {
+            if let Err(err) = handle_connection(stream, &app) {
+                log_write_error(&err);
+            }
+            // gives the slot back only once the connection is handled
+            drop(permit);
         });
     }
 
+    // the drain phase, no more connections are taken and the ones already
+    // taken are answered before the pool shuts down
+    pool.close();
+    let report = pool.shutdown();
+
     // this message can show up in random order in the console output
     // since other threads can print their own messages simultaneously
-    println!("Shutting down.");
+    info!("Shutting down: {report}.");
 }
 
 // this function handles an incoming tcp stream, in this project it is passed to
-// a thread inside a closure each time there's a new request made to the server
-fn handle_connection(mut stream: TcpStream) {
-    let buf_reader = BufReader::new(&mut stream);
-    // get first line of http request, is generally of the format:-
+// a thread inside a closure each time there's a new request made to the server,
+// the connection is kept open for more requests when the client wants it to be,
+// see server::keep_alive(), it only fails when writing a response fails, e.g.
+// when the client went away before the whole response could be sent
+fn handle_connection(stream: TcpStream, app: &App) -> io::Result<()> {
+    // a client keeping the connection open without sending anything more
+    // would otherwise hold on to the thread for good, reading the next
+    // request gives up after 5 seconds and the connection is closed
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    // a client whose address can't be told anymore has already gone away,
+    // every request gets it, see Request::peer_addr()
+    let peer = stream.peer_addr().ok();
+
+    // reads the request line and the headers of every http request, the
+    // request line is generally of the format:-
     //
     // <http_method> <route_segment> <http_version>
-    let request_line = buf_reader.lines().next().unwrap().unwrap();
+    //
+    // a request that can't be parsed has been answered with a 400 already,
+    // and a connection closed without sending anything is simply dropped,
+    // neither gets here, a body larger than the route it's for allows is
+    // answered with a 413 before being read, see Router::body_limit(), and
+    // a request line or headers past the limits in the Config with a 414
+    // or a 431
+    let max_body_size = |request: &Request| app.router.body_limit(request);
+
+    server::serve_connection_limited(stream, peer, &app.parse_limits, max_body_size, |request| {
+        let response = respond(request, app);
+
+        if let Some(access_log) = &app.access_log {
+            let host = request.peer_addr().map(|peer| peer.ip());
+
+            // a full disk or similar is no reason to fail the request
+            if let Err(err) = access_log.log(host, request, &response) {
+                error!("Failed to write to the access log: {err}");
+            }
+        }
+
+        response
+    })
+}
+
+// answers a connection over the limit with a 503 on the accepting thread,
+// the request is never parsed, but whatever of it arrives within 100
+// milliseconds is read and dropped before the connection is closed, closing
+// it with the request still unread can make the client see a reset
+// connection instead of the response, same as in respond()
+fn reject(stream: &mut TcpStream) -> io::Result<()> {
+    Response::new(503)
+        .with_header("Connection", "close")
+        .write_to(stream)?;
+    // tells the client nothing more is coming, so it can close its end
+    // right away instead of waiting out the 100 milliseconds
+    stream.shutdown(Shutdown::Write)?;
 
-    // matches a set of pre-defined routes
-    let (response_status_line, file_name) = match &request_line[..] {
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "hello.html"),
-        "GET /sleep HTTP/1.1" => {
+    // a deadline for all of it rather than a timeout per read, a client
+    // trickling bytes in would otherwise hold up accepting connections
+    let deadline = Instant::now() + Duration::from_millis(100);
+    let mut buf = [0; 4096];
+
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(());
+        }
+
+        stream.set_read_timeout(Some(left))?;
+        if stream.read(&mut buf)? == 0 {
+            return Ok(());
+        }
+    }
+}
+
+fn respond(request: &mut Request, app: &App) -> Response {
+    // checked once the request is read, answering before that and closing
+    // the connection with the request still unread can make the client see
+    // a reset connection instead of the response
+    if let Some(peer) = request.peer_addr() {
+        if let Err(retry_after) = app.rate_limiter.check(peer.ip()) {
+            return RateLimiter::too_many_requests(retry_after);
+        }
+    }
+
+    app.router.handle(request)
+}
+
+// matches a set of pre-defined routes, HEAD requests are routed the same as
+// GET requests, the body is only left out when writing the response
+fn router(cors: Cors) -> Router {
+    // every file inside the `static` directory of the project is served
+    // under `/static/`, e.g. `/static/hello.css`, nothing outside of it is
+    let files = StaticFiles::new("static");
+
+    Router::new()
+        .get("/", |_| html_page(200, "hello.html"))
+        .get("/sleep", |_| {
             // makes the current thread it exists in sleep for 10 seconds, intentionally
             // done here to explain the concept of multithreading i.e., to delegate
             // incoming requests to other threads if one thread is stuck on a computation
             thread::sleep(Duration::from_secs(10));
-            ("HTTP/1.1 200 OK", "hello.html")
-        }
-        _ => ("HTTP/1.1 404 NOT FOUND", "404.html"),
-    };
+            html_page(200, "hello.html")
+        })
+        .get("/static/*path", move |request| files.handle(request))
+        .not_found(not_found)
+        // none of the routes takes a body, anything sent along is read and
+        // dropped, which is no reason to let it be megabytes
+        .max_body_size(64 * 1024)
+        .middleware(move |request, next| {
+            // browsers ask for permission before making some cross-origin
+            // requests, those are answered right away without being routed
+            match cors.preflight(request) {
+                Some(response) => response,
+                None => cors.apply(request, next.run(request)),
+            }
+        })
+}
 
-    let response_body = fs::read_to_string(file_name).unwrap();
-    // \r\n is CRLF character(carriage return line feed), it seperates different
-    // lines within a http request and response object, while parsing a http
-    // request object an empty line with zero characters and just \r\n signifies
-    // the start of request/response body(which is optional to be provided),
-    // here \r\n\r\n means end the current line and next line is an empty line
-    // this is the format:-
-    //
-    // <http_version> <status_code> <status_code_keyword>
-    // Content-Length: <content_length>
-    //
-    // <response_body>
-    let response = format!(
-        "{}\r\nContent-Length: {}\r\n\r\n{}",
-        response_status_line,
-        response_body.len(),
-        response_body
-    );
-
-    stream.write_all(response.as_bytes()).unwrap();
-    stream.flush().unwrap();
+// the file is streamed to the client while writing the response rather
+// than read into memory upfront, failing to open it gets the client a 500,
+// see Router::on_error(), the Content-Type comes from the file's extension
+fn html_page(status: u16, file_name: &str) -> io::Result<Response> {
+    Response::new(status).with_file(file_name)
+}
+
+// browsers get the 404.html page, clients asking for json or plain text,
+// like api clients or curl with an Accept header, get a short 404 in that
+// format instead of a page they can't make sense of
+fn not_found(request: &Request) -> io::Result<Response> {
+    let media_types = ["text/html", "application/json", "text/plain"];
+
+    match request.preferred_media_type(&media_types) {
+        Some("text/html") => html_page(404, "404.html"),
+        _ => Ok(Response::error(request, 404)),
+    }
+}
+
+// a client disconnecting before reading the whole response is a normal part
+// of serving over a network and only worth a note, anything else gets
+// reported as an error, neither takes the worker down
+fn log_write_error(err: &io::Error) {
+    match err.kind() {
+        io::ErrorKind::BrokenPipe
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted => {
+            info!("Client disconnected before the response was written.");
+        }
+        _ => error!("Failed to write the response: {err}"),
+    }
 }
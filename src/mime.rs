@@ -0,0 +1,139 @@
+use std::{collections::HashMap, path::Path};
+
+// media type of anything whose extension isn't known, browsers offer files
+// of that type as a download instead of guessing what's in them
+pub const FALLBACK: &str = "application/octet-stream";
+
+// media types of the extensions most often served, text gets a charset
+// since browsers otherwise fall back to a guess or to windows-1252, which
+// garbles anything outside of ascii
+const DEFAULTS: &[(&str, &str)] = &[
+    ("html", "text/html; charset=utf-8"),
+    ("htm", "text/html; charset=utf-8"),
+    ("css", "text/css; charset=utf-8"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("xml", "application/xml"),
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/x-icon"),
+    ("pdf", "application/pdf"),
+    ("wasm", "application/wasm"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+];
+
+// media type for the extension of the file at `path` from the table above,
+// FALLBACK when it has none or one that isn't in it, e.g.:-
+//
+// mime::for_path("hello.html") => "text/html; charset=utf-8"
+// mime::for_path("archive.tar.gz") => "application/octet-stream"
+pub fn for_path(path: impl AsRef<Path>) -> &'static str {
+    extension(path.as_ref())
+        .and_then(|extension| default_for(&extension))
+        .unwrap_or(FALLBACK)
+}
+
+// the table above along with extensions added at runtime, which take
+// precedence over it, e.g.:-
+//
+// let mut mime_types = MimeTypes::new();
+// mime_types.add("md", "text/markdown; charset=utf-8");
+#[derive(Debug, Clone, Default)]
+pub struct MimeTypes {
+    added: HashMap<String, String>,
+}
+
+impl MimeTypes {
+    pub fn new() -> MimeTypes {
+        MimeTypes::default()
+    }
+
+    // extensions are matched regardless of case, `md` is `.MD` as well
+    pub fn add(&mut self, extension: &str, media_type: &str) {
+        self.added
+            .insert(extension.to_ascii_lowercase(), media_type.to_owned());
+    }
+
+    // same as for_path() but looking at the added extensions first
+    pub fn for_path(&self, path: impl AsRef<Path>) -> &str {
+        let Some(extension) = extension(path.as_ref()) else {
+            return FALLBACK;
+        };
+
+        match self.added.get(&extension) {
+            Some(media_type) => media_type,
+            None => default_for(&extension).unwrap_or(FALLBACK),
+        }
+    }
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension()?
+        .to_str()
+        .map(|extension| extension.to_ascii_lowercase())
+}
+
+fn default_for(extension: &str) -> Option<&'static str> {
+    DEFAULTS
+        .iter()
+        .find(|(known, _)| *known == extension)
+        .map(|(_, media_type)| *media_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_extensions() {
+        assert_eq!(for_path("hello.html"), "text/html; charset=utf-8");
+        assert_eq!(for_path("static/site.css"), "text/css; charset=utf-8");
+        assert_eq!(for_path("app.js"), "text/javascript; charset=utf-8");
+        assert_eq!(for_path("data.json"), "application/json");
+        assert_eq!(for_path("logo.PNG"), "image/png");
+        assert_eq!(for_path("photo.jpg"), "image/jpeg");
+        assert_eq!(for_path("icon.svg"), "image/svg+xml");
+        assert_eq!(for_path("favicon.ico"), "image/x-icon");
+        assert_eq!(for_path("notes.txt"), "text/plain; charset=utf-8");
+        assert_eq!(for_path("module.wasm"), "application/wasm");
+        assert_eq!(for_path("font.woff2"), "font/woff2");
+    }
+
+    #[test]
+    fn fallback() {
+        assert_eq!(for_path("archive.tar.gz"), FALLBACK);
+        assert_eq!(for_path("README"), FALLBACK);
+        assert_eq!(for_path(".html"), FALLBACK);
+        assert_eq!(for_path("notes.md"), FALLBACK);
+    }
+
+    #[test]
+    fn added_extensions() {
+        let mut mime_types = MimeTypes::new();
+        mime_types.add("MD", "text/markdown; charset=utf-8");
+        mime_types.add("ico", "image/vnd.microsoft.icon");
+
+        assert_eq!(
+            mime_types.for_path("notes.md"),
+            "text/markdown; charset=utf-8"
+        );
+        assert_eq!(
+            mime_types.for_path("favicon.ico"),
+            "image/vnd.microsoft.icon"
+        );
+        assert_eq!(
+            mime_types.for_path("hello.html"),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(mime_types.for_path("archive.gz"), FALLBACK);
+        // only for the table it's added to
+        assert_eq!(for_path("notes.md"), FALLBACK);
+    }
+}
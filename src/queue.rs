@@ -0,0 +1,568 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    sync::{
+        atomic::{self, AtomicUsize, Ordering},
+        mpsc, Arc, Condvar, Mutex, MutexGuard, RwLock,
+    },
+    time::Instant,
+};
+
+// type alias for a Task trait object stored on the heap using Box smart pointer
+pub(crate) type Task = Box<dyn FnOnce() + Send + 'static>;
+
+// a job as it is put in the queue, stamped with the time it was submitted
+// so the worker picking it up knows how long it had to wait, queues only
+// ever move jobs around, running them is up to the workers
+pub struct Job {
+    pub(crate) task: Task,
+    pub(crate) queued_at: Instant,
+    // dropped without running if it's still in the queue by then
+    pub(crate) deadline: Option<Instant>,
+    // see ThreadPool::execute_named()
+    pub(crate) name: Option<Arc<str>>,
+    // identifies the job among the ones listed by ThreadPool::queue_snapshot()
+    pub(crate) seq: u64,
+}
+
+impl Job {
+    pub fn queued_at(&self) -> Instant {
+        self.queued_at
+    }
+}
+
+impl fmt::Debug for Job {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Job")
+            .field("queued_at", &self.queued_at)
+            .field("deadline", &self.deadline)
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+// the queue jobs wait in between being submitted and a worker picking them
+// up, MpscQueue unless the pool was built with ThreadPoolBuilder::job_queue(),
+// e.g. to try out a different data structure under a given workload:-
+//
+// let pool = ThreadPool::builder()
+//     .job_queue(|| VecDequeQueue::bounded(64))
+//     .build()?;
+//
+// every method is called from several threads at once, the submitting ones
+// push and the workers pop, once closed a queue takes no new jobs but still
+// hands out the ones already in it, that's how the workers finish the queued
+// jobs before exiting when the pool is dropped
+//
+// popping never waits, workers that find the queue empty are put to sleep by
+// the pool itself and woken up by whoever pushes the next job, a worker
+// waiting inside the queue couldn't be woken up for a job that's meant for
+// it in particular, see ThreadPool::execute_keyed()
+pub trait JobQueue: Send + Sync {
+    // adds a job to the back of the queue, waiting for room first if the
+    // queue is bounded and full, the job is handed back once it's closed
+    fn push(&self, job: Job) -> Result<(), Job>;
+
+    // same as push() but never waits, used from the pool's own workers where
+    // waiting for room could deadlock the pool, queues that are never full
+    // don't need to implement it
+    fn try_push(&self, job: Job) -> Result<(), PushError> {
+        self.push(job).map_err(PushError::Closed)
+    }
+
+    // takes the job at the front of the queue if there is one
+    fn try_pop(&self) -> Option<Job>;
+
+    // takes up to `max` more jobs off the front of the queue, see
+    // ThreadPoolBuilder::batch_size(), worth implementing for queues behind
+    // a lock so that it's only taken once for the whole batch
+    fn pop_batch(&self, max: usize, batch: &mut VecDeque<Job>) {
+        batch.extend(std::iter::from_fn(|| self.try_pop()).take(max));
+    }
+
+    // stops the queue from taking new jobs, and wakes up submitters waiting
+    // for room in push(), called when the pool is dropped
+    fn close(&self);
+
+    // number of jobs in the queue
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+// returned by JobQueue::try_push() along with the job it couldn't queue
+#[derive(Debug)]
+pub enum PushError {
+    Full(Job),
+    Closed(Job),
+}
+
+impl PushError {
+    pub fn into_job(self) -> Job {
+        match self {
+            PushError::Full(job) | PushError::Closed(job) => job,
+        }
+    }
+}
+
+impl fmt::Display for PushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PushError::Full(_) => f.write_str("job queue is full"),
+            PushError::Closed(_) => f.write_str("job queue is closed"),
+        }
+    }
+}
+
+impl Error for PushError {}
+
+// creates the queue of every pool built from a ThreadPoolBuilder, a
+// factory rather than the queue itself so that the builder can be cloned
+#[derive(Clone)]
+pub(crate) struct QueueFactory(pub(crate) Arc<dyn Fn() -> Box<dyn JobQueue> + Send + Sync>);
+
+impl fmt::Debug for QueueFactory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QueueFactory").finish_non_exhaustive()
+    }
+}
+
+// the default queue, a channel from the standard library
+pub struct MpscQueue {
+    // wrapped in Option type to make it easily destroyable by swapping the
+    // Some variant with None variant, sender being destroyed signals the
+    // receivers that no more messages are to be received, .try_recv()
+    // returns the messages still in the channel and an Err variant after
+    // that
+    sender: RwLock<Option<JobSender>>,
+    // since receiver itself cannot be cloned unlike sender.clone(),
+    // following the principle multiple producer single consumer(mpsc), we
+    // can have multiple instaces of sender by cloning it directly, but only
+    // a single instace of receiver, using Mutex to make sure at any time
+    // only a single thread can access the received messages queue stored in
+    // receiver
+    receiver: Mutex<mpsc::Receiver<Job>>,
+    // the channel doesn't tell how many messages are in it
+    len: AtomicUsize,
+    // submitters waiting for room in a full bounded channel wait on these
+    // rather than in SyncSender::send(), which close() couldn't wake them up
+    // from, see wait_for_room()
+    waiting: AtomicUsize,
+    room: Mutex<()>,
+    has_room: Condvar,
+}
+
+// sending half of the channel, bounded when the queue was created with
+// MpscQueue::bounded()
+enum JobSender {
+    Unbounded(mpsc::Sender<Job>),
+    Bounded(mpsc::SyncSender<Job>),
+}
+
+impl MpscQueue {
+    pub fn new() -> MpscQueue {
+        let (sender, receiver) = mpsc::channel();
+
+        MpscQueue::with(JobSender::Unbounded(sender), receiver)
+    }
+
+    // a queue holding at most `capacity` jobs, a capacity of 0 makes every
+    // push() wait for a worker to take the job right away
+    pub fn bounded(capacity: usize) -> MpscQueue {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+
+        MpscQueue::with(JobSender::Bounded(sender), receiver)
+    }
+
+    fn with(sender: JobSender, receiver: mpsc::Receiver<Job>) -> MpscQueue {
+        MpscQueue {
+            sender: RwLock::new(Some(sender)),
+            receiver: Mutex::new(receiver),
+            len: AtomicUsize::new(0),
+            waiting: AtomicUsize::new(0),
+            room: Mutex::new(()),
+            has_room: Condvar::new(),
+        }
+    }
+
+    fn receiver(&self) -> MutexGuard<'_, mpsc::Receiver<Job>> {
+        // nothing runs with the lock held that could panic
+        self.receiver
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn received(&self, job: Option<Job>) -> Option<Job> {
+        if job.is_some() {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+            self.made_room();
+        }
+
+        job
+    }
+
+    // tries to queue `job` again once a job was taken out of the full
+    // channel or the queue was closed, the submitter counts as waiting
+    // before trying once more with the lock held, so a job taken out in
+    // between either leaves room for that try or finds the submitter
+    // waiting and wakes it up
+    fn wait_for_room(&self, job: Job) -> Result<(), PushError> {
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        let room = self
+            .room
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        let result = match self.try_push(job) {
+            Err(PushError::Full(job)) => {
+                drop(
+                    self.has_room
+                        .wait(room)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner()),
+                );
+                Err(PushError::Full(job))
+            }
+            result => result,
+        };
+
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        result
+    }
+
+    // wakes up the submitters waiting in wait_for_room(), if any
+    fn made_room(&self) {
+        // pairs with wait_for_room() counting itself as waiting, either
+        // this sees the submitter or the submitter sees the room
+        atomic::fence(Ordering::SeqCst);
+
+        if self.waiting.load(Ordering::SeqCst) > 0 {
+            // taken so the submitter is either waiting already or hasn't
+            // tried again yet
+            drop(
+                self.room
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            );
+            self.has_room.notify_all();
+        }
+    }
+}
+
+impl JobQueue for MpscQueue {
+    fn push(&self, job: Job) -> Result<(), Job> {
+        let mut result = self.try_push(job);
+
+        loop {
+            match result {
+                Ok(()) => return Ok(()),
+                Err(PushError::Closed(job)) => return Err(job),
+                Err(PushError::Full(job)) => result = self.wait_for_room(job),
+            }
+        }
+    }
+
+    fn try_push(&self, job: Job) -> Result<(), PushError> {
+        // read lock since Sender can be shared between threads, only
+        // closing needs exclusive access to take the sender out, doesn't
+        // unwrap it, jobs are handed back while unwinding from a panicking
+        // job and a second panic would abort the process
+        let sender = self
+            .sender
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let Some(sender) = sender.as_ref() else {
+            return Err(PushError::Closed(job));
+        };
+
+        // counted before sending, otherwise a fast worker could take it
+        // back out before it was ever counted
+        self.len.fetch_add(1, Ordering::Relaxed);
+
+        // an unbounded channel is never full
+        let result = match sender {
+            JobSender::Unbounded(sender) => sender
+                .send(job)
+                .map_err(|err| mpsc::TrySendError::Disconnected(err.0)),
+            JobSender::Bounded(sender) => sender.try_send(job),
+        };
+
+        result.map_err(|err| {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+
+            match err {
+                mpsc::TrySendError::Full(job) => PushError::Full(job),
+                mpsc::TrySendError::Disconnected(job) => PushError::Closed(job),
+            }
+        })
+    }
+
+    fn try_pop(&self) -> Option<Job> {
+        let job = self.receiver().try_recv().ok();
+
+        self.received(job)
+    }
+
+    fn pop_batch(&self, max: usize, batch: &mut VecDeque<Job>) {
+        let receiver = self.receiver();
+        let len = batch.len();
+
+        batch.extend(std::iter::from_fn(|| receiver.try_recv().ok()).take(max));
+        drop(receiver);
+
+        if batch.len() > len {
+            self.len.fetch_sub(batch.len() - len, Ordering::Relaxed);
+            self.made_room();
+        }
+    }
+
+    fn close(&self) {
+        drop(
+            self.sender
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .take(),
+        );
+
+        // submitters waiting for room find the queue closed once woken up
+        drop(
+            self.room
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        );
+        self.has_room.notify_all();
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+}
+
+impl Default for MpscQueue {
+    fn default() -> MpscQueue {
+        MpscQueue::new()
+    }
+}
+
+impl fmt::Debug for MpscQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MpscQueue")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+// a VecDeque behind a single Mutex, with a Condvar for the submitters
+// waiting for room, simpler than a channel and every operation is a single
+// lock, which makes it a baseline to compare other queues against
+pub struct VecDequeQueue {
+    state: Mutex<DequeState>,
+    capacity: Option<usize>,
+    not_full: Condvar,
+}
+
+struct DequeState {
+    jobs: VecDeque<Job>,
+    closed: bool,
+}
+
+impl VecDequeQueue {
+    pub fn new() -> VecDequeQueue {
+        VecDequeQueue::with(None)
+    }
+
+    // a queue holding at most `capacity` jobs, at least 1 so that a job can
+    // be handed over at all
+    pub fn bounded(capacity: usize) -> VecDequeQueue {
+        VecDequeQueue::with(Some(capacity.max(1)))
+    }
+
+    fn with(capacity: Option<usize>) -> VecDequeQueue {
+        VecDequeQueue {
+            state: Mutex::new(DequeState {
+                jobs: VecDeque::new(),
+                closed: false,
+            }),
+            capacity,
+            not_full: Condvar::new(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, DequeState> {
+        // every change to the state is a single step that can't panic
+        // halfway through
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn is_full(&self, state: &DequeState) -> bool {
+        self.capacity
+            .is_some_and(|capacity| state.jobs.len() >= capacity)
+    }
+
+    fn take(&self, state: &mut DequeState) -> Option<Job> {
+        let job = state.jobs.pop_front();
+
+        if job.is_some() {
+            self.not_full.notify_one();
+        }
+
+        job
+    }
+}
+
+impl JobQueue for VecDequeQueue {
+    fn push(&self, job: Job) -> Result<(), Job> {
+        let mut state = self
+            .not_full
+            .wait_while(self.state(), |state| !state.closed && self.is_full(state))
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if state.closed {
+            return Err(job);
+        }
+
+        state.jobs.push_back(job);
+        Ok(())
+    }
+
+    fn try_push(&self, job: Job) -> Result<(), PushError> {
+        let mut state = self.state();
+
+        if state.closed {
+            return Err(PushError::Closed(job));
+        }
+
+        if self.is_full(&state) {
+            return Err(PushError::Full(job));
+        }
+
+        state.jobs.push_back(job);
+        Ok(())
+    }
+
+    fn try_pop(&self) -> Option<Job> {
+        self.take(&mut self.state())
+    }
+
+    fn pop_batch(&self, max: usize, batch: &mut VecDeque<Job>) {
+        let mut state = self.state();
+        let taken = max.min(state.jobs.len());
+
+        batch.extend(state.jobs.drain(..taken));
+
+        if taken > 0 {
+            self.not_full.notify_all();
+        }
+    }
+
+    fn close(&self) {
+        self.state().closed = true;
+
+        self.not_full.notify_all();
+    }
+
+    fn len(&self) -> usize {
+        self.state().jobs.len()
+    }
+}
+
+impl Default for VecDequeQueue {
+    fn default() -> VecDequeQueue {
+        VecDequeQueue::new()
+    }
+}
+
+impl fmt::Debug for VecDequeQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VecDequeQueue")
+            .field("len", &self.len())
+            .field("capacity", &self.capacity)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    fn job(seq: u64) -> Job {
+        Job {
+            task: Box::new(|| {}),
+            queued_at: Instant::now(),
+            deadline: None,
+            name: None,
+            seq,
+        }
+    }
+
+    fn seqs(jobs: impl IntoIterator<Item = Job>) -> Vec<u64> {
+        jobs.into_iter().map(|job| job.seq).collect()
+    }
+
+    // every queue implementation has to pass these
+    fn unbounded_conformance(queue: &dyn JobQueue) {
+        assert!(queue.is_empty());
+        assert!(queue.try_pop().is_none());
+
+        for seq in 0..5 {
+            queue.push(job(seq)).unwrap();
+        }
+        assert_eq!(queue.len(), 5);
+
+        assert_eq!(queue.try_pop().map(|job| job.seq), Some(0));
+        let mut batch = VecDeque::new();
+        queue.pop_batch(2, &mut batch);
+        assert_eq!(seqs(batch), [1, 2]);
+        assert_eq!(queue.len(), 2);
+
+        // jobs already queued are still handed out once closed
+        queue.close();
+        assert!(queue.push(job(5)).is_err());
+        assert!(matches!(queue.try_push(job(6)), Err(PushError::Closed(_))));
+        assert_eq!(seqs(std::iter::from_fn(|| queue.try_pop())), [3, 4]);
+        assert!(queue.is_empty());
+    }
+
+    fn bounded_conformance(queue: &dyn JobQueue) {
+        queue.push(job(0)).unwrap();
+        queue.try_push(job(1)).unwrap();
+        assert!(matches!(queue.try_push(job(2)), Err(PushError::Full(_))));
+        assert_eq!(queue.len(), 2);
+
+        // push() waits for room
+        thread::scope(|scope| {
+            let pushed = scope.spawn(|| queue.push(job(2)).is_ok());
+            thread::sleep(Duration::from_millis(50));
+            assert!(!pushed.is_finished());
+            assert_eq!(queue.try_pop().map(|job| job.seq), Some(0));
+            assert!(pushed.join().unwrap());
+        });
+
+        // and gives up once the queue is closed
+        thread::scope(|scope| {
+            let pushed = scope.spawn(|| queue.push(job(3)).is_ok());
+            thread::sleep(Duration::from_millis(50));
+            queue.close();
+            assert!(!pushed.join().unwrap());
+        });
+
+        assert_eq!(seqs(std::iter::from_fn(|| queue.try_pop())), [1, 2]);
+    }
+
+    #[test]
+    fn mpsc_queue() {
+        unbounded_conformance(&MpscQueue::new());
+        bounded_conformance(&MpscQueue::bounded(2));
+    }
+
+    #[test]
+    fn vec_deque_queue() {
+        unbounded_conformance(&VecDequeQueue::new());
+        bounded_conformance(&VecDequeQueue::bounded(2));
+    }
+}
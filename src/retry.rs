@@ -0,0 +1,262 @@
+use crate::{JobPanicked, Shared};
+use std::{
+    error::Error,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{mpsc, Weak},
+    time::{Duration, Instant},
+};
+
+// how ThreadPool::execute_with_retry() retries a job that returned an
+// error, e.g. up to 5 attempts, waiting 100ms, 200ms, 400ms and 800ms in
+// between:-
+//
+// let policy = RetryPolicy {
+//     max_attempts: 5,
+//     backoff: Backoff::Exponential {
+//         base: Duration::from_millis(100),
+//         cap: Duration::from_secs(1),
+//     },
+// };
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    // attempts in total, the first one included, the job is always run at
+    // least once, 0 counts as 1
+    pub max_attempts: u32,
+    pub backoff: Backoff,
+}
+
+// how long to wait before the next attempt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backoff {
+    Fixed(Duration),
+    // `base` after the first attempt, doubling after every attempt after
+    // that, but never more than `cap`
+    Exponential { base: Duration, cap: Duration },
+}
+
+impl Backoff {
+    // wait after `attempts` attempts
+    fn delay(&self, attempts: u32) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { base, cap } => 1u32
+                .checked_shl(attempts.saturating_sub(1))
+                .and_then(|factor| base.checked_mul(factor))
+                .map_or(cap, |delay| delay.min(cap)),
+        }
+    }
+}
+
+// why a job of ThreadPool::execute_with_retry() didn't succeed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RetryError<E> {
+    // every attempt returned an error, `error` is the last one's
+    Exhausted { attempts: u32, error: E },
+    // the job panicked, it's not tried again after that, the panic is
+    // unlikely to go away by itself
+    Panicked { attempts: u32, panic: JobPanicked },
+    // the pool shut down before the job got its next attempt, `error` is
+    // the last attempt's, None if the job never got to run
+    ShutDown { attempts: u32, error: Option<E> },
+}
+
+impl<E: fmt::Display> fmt::Display for RetryError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RetryError::Exhausted { attempts, error } => {
+                write!(f, "job failed after {attempts} attempts: {error}")
+            }
+            RetryError::Panicked { attempts, panic } => {
+                write!(f, "job failed on attempt {attempts}: {panic}")
+            }
+            RetryError::ShutDown {
+                attempts,
+                error: Some(error),
+            } => write!(
+                f,
+                "thread pool shut down after {attempts} failed attempts of the job: {error}"
+            ),
+            RetryError::ShutDown { error: None, .. } => {
+                f.write_str("thread pool shut down before the job ran")
+            }
+        }
+    }
+}
+
+impl<E: fmt::Debug + fmt::Display> Error for RetryError<E> {}
+
+// the outcome of a job of ThreadPool::execute_with_retry(), the job keeps
+// going whether or not anyone waits for it
+#[derive(Debug)]
+pub struct RetryHandle<E> {
+    receiver: mpsc::Receiver<Result<u32, RetryError<E>>>,
+}
+
+impl<E> RetryHandle<E> {
+    // blocks till the job succeeded, returning the number of attempts it
+    // took, or till it failed for good
+    pub fn wait(self) -> Result<u32, RetryError<E>> {
+        self.receiver.recv().unwrap_or(Err(RetryError::ShutDown {
+            attempts: 0,
+            error: None,
+        }))
+    }
+}
+
+// a job of ThreadPool::execute_with_retry() along with how far it got, it
+// reports the outcome once it's done, or once it's dropped without being
+// done, e.g. when the pool shuts down while it waits for its next attempt
+pub(crate) struct Retry<F, E> {
+    shared: Weak<Shared>,
+    f: F,
+    policy: RetryPolicy,
+    attempts: u32,
+    last_error: Option<E>,
+    // taken once the outcome is sent
+    outcome: Option<mpsc::SyncSender<Result<u32, RetryError<E>>>>,
+}
+
+impl<F, E> Retry<F, E>
+where
+    F: Fn() -> Result<(), E> + Send + 'static,
+    E: Send + 'static,
+{
+    pub(crate) fn new(
+        shared: Weak<Shared>,
+        policy: RetryPolicy,
+        f: F,
+    ) -> (Retry<F, E>, RetryHandle<E>) {
+        let (sender, receiver) = mpsc::sync_channel(1);
+        let retry = Retry {
+            shared,
+            f,
+            policy,
+            attempts: 0,
+            last_error: None,
+            outcome: Some(sender),
+        };
+
+        (retry, RetryHandle { receiver })
+    }
+
+    pub(crate) fn attempt(mut self) {
+        self.attempts += 1;
+        self.last_error = None;
+
+        // nothing is left observing the closure's state after a panic since
+        // it's not run again
+        let error = match panic::catch_unwind(AssertUnwindSafe(|| (self.f)())) {
+            Ok(Ok(())) => return self.finish(Ok(self.attempts)),
+            Ok(Err(error)) => error,
+            Err(payload) => {
+                let panic = JobPanicked::from_payload(payload);
+                return self.finish(Err(RetryError::Panicked {
+                    attempts: self.attempts,
+                    panic,
+                }));
+            }
+        };
+
+        if self.attempts >= self.policy.max_attempts {
+            let attempts = self.attempts;
+            return self.finish(Err(RetryError::Exhausted { attempts, error }));
+        }
+
+        self.last_error = Some(error);
+
+        // dropping the job reports the error it ended with, see Drop below
+        if let Some(shared) = self.shared.upgrade() {
+            let at = Instant::now() + self.policy.backoff.delay(self.attempts);
+            let _ = shared
+                .timer
+                .schedule(&shared, at, Box::new(move || self.attempt()));
+        }
+    }
+
+    fn finish(&mut self, outcome: Result<u32, RetryError<E>>) {
+        if let Some(sender) = self.outcome.take() {
+            // nobody might be waiting for it
+            let _ = sender.send(outcome);
+        }
+    }
+}
+
+impl<F, E> Drop for Retry<F, E> {
+    fn drop(&mut self) {
+        if let Some(sender) = self.outcome.take() {
+            let _ = sender.send(Err(RetryError::ShutDown {
+                attempts: self.attempts,
+                error: self.last_error.take(),
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreadPool;
+    use std::sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    };
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        max_attempts: 5,
+        backoff: Backoff::Fixed(Duration::from_millis(10)),
+    };
+
+    #[test]
+    fn exponential_backoff() {
+        let backoff = Backoff::Exponential {
+            base: Duration::from_millis(100),
+            cap: Duration::from_secs(1),
+        };
+
+        let delays: Vec<_> = (1..=6).map(|attempts| backoff.delay(attempts)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+        );
+        assert_eq!(backoff.delay(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn succeeds_on_the_third_attempt() {
+        let pool = ThreadPool::new(2);
+        let attempts = Arc::new(AtomicU32::new(0));
+
+        let counted = Arc::clone(&attempts);
+        let handle = pool.execute_with_retry(POLICY, move || {
+            match counted.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err("not yet"),
+                _ => Ok(()),
+            }
+        });
+
+        assert_eq!(handle.wait(), Ok(3));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn stops_at_max_attempts() {
+        let pool = ThreadPool::new(2);
+        let attempts = Arc::new(AtomicU32::new(0));
+
+        let counted = Arc::clone(&attempts);
+        let handle = pool.execute_with_retry(POLICY, move || {
+            Err(counted.fetch_add(1, Ordering::SeqCst) + 1)
+        });
+
+        assert_eq!(
+            handle.wait(),
+            Err(RetryError::Exhausted {
+                attempts: 5,
+                error: 5,
+            })
+        );
+        pool.join();
+        assert_eq!(attempts.load(Ordering::SeqCst), 5);
+    }
+}
@@ -0,0 +1,1059 @@
+use crate::http::{percent_decode, Method, Param, Request, Response, MAX_BODY_SIZE};
+use log::error;
+use std::{collections::HashMap, error::Error, fmt, io};
+
+// answers a request, an error, e.g. failing to read a file the response was
+// supposed to be made of, is turned into a response by the router's error
+// handler, see Router::on_error()
+pub type Handler = Box<dyn Fn(&Request) -> io::Result<Response> + Send + Sync>;
+
+// turns the error a handler returned for a request into its response
+pub type ErrorHandler = Box<dyn Fn(&Request, io::Error) -> Response + Send + Sync>;
+
+// runs around every request the router handles, gets to look at the request
+// before it's handled and at the response after, e.g. a middleware timing
+// requests:-
+//
+// router.middleware(|request, next| {
+//     let started_at = Instant::now();
+//     let response = next.run(request);
+//     println!("{} took {:?}", request.path, started_at.elapsed());
+//     response
+// })
+//
+// a middleware that doesn't call `next` answers the request itself, without
+// the middlewares after it or the handler ever running
+pub type Middleware = Box<dyn Fn(&Request, Next<'_>) -> Response + Send + Sync>;
+
+// the rest of the chain after the middleware it's passed to, i.e. the
+// middlewares added after it and then the handler of the matched route(or
+// the not-found handler when no route matched)
+//
+// an error returned by the handler has already been turned into a response
+// by the time it gets back to the middlewares
+pub struct Next<'a> {
+    middlewares: &'a [Middleware],
+    handler: &'a Handler,
+    on_error: &'a ErrorHandler,
+}
+
+impl Next<'_> {
+    pub fn run(self, request: &Request) -> Response {
+        match self.middlewares.split_first() {
+            Some((middleware, middlewares)) => middleware(
+                request,
+                Next {
+                    middlewares,
+                    ..self
+                },
+            ),
+            None => (self.handler)(request).unwrap_or_else(|err| (self.on_error)(request, err)),
+        }
+    }
+}
+
+impl fmt::Debug for Next<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Next")
+            .field("middlewares", &self.middlewares.len())
+            .finish_non_exhaustive()
+    }
+}
+
+// matches requests to handlers by their method and path, e.g.:-
+//
+// let router = Router::new()
+//     .get("/", |_| Response::new(200))
+//     .post("/submit", submit)
+//     .middleware(|request, next| cors.apply(request, next.run(request)));
+//
+// middlewares run in the order they're added, the first one added being the
+// outermost, around whichever handler the request is matched to
+pub struct Router {
+    routes: Vec<Route>,
+    // see max_body_size()
+    max_body_size: u64,
+    not_found: Handler,
+    on_error: ErrorHandler,
+    middlewares: Vec<Middleware>,
+}
+
+impl Router {
+    // a router without any routes, answering every request with a 404
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            max_body_size: MAX_BODY_SIZE,
+            not_found: Box::new(|request| Ok(Response::error(request, 404))),
+            on_error: Box::new(|request, err| {
+                error!(
+                    "Failed to handle {} {}: {err}",
+                    request.method, request.path
+                );
+                Response::error(request, 500)
+            }),
+            middlewares: Vec::new(),
+        }
+    }
+
+    // handles requests with `method` for `path`, a segment of the path
+    // starting with `:` matches any single segment and captures it under
+    // the name after the `:`, see Request::param(), e.g.:-
+    //
+    // router.get("/users/:id/posts/:post_id", |request| {
+    //     let id = request.param("id").unwrap();
+    //     ...
+    // })
+    //
+    // a last segment starting with `*` captures the rest of the path, slashes
+    // included, e.g. `css/site.css` for `/static/css/site.css` matched to
+    // `/static/*path`, which lets a single handler serve a whole directory
+    // tree, the rest can be empty, i.e. `/static/` matches as well
+    //
+    // the path is matched segment by segment, fixed segments against the
+    // decoded segments of the request path, so `/hello world` matches a
+    // request for `/hello%20world`, dot segments and duplicate slashes are
+    // resolved before matching, see Request::path
+    //
+    // when several routes match, the one with a fixed segment where the
+    // others capture one wins, and one capturing a single segment beats one
+    // capturing the rest of the path, going from the first segment to the
+    // last, i.e. `/users/new` beats `/users/:id` which beats `/users/*rest`,
+    // otherwise the one added first wins, a GET route handles HEAD requests
+    // as well unless a HEAD route for the same path was added before it, the
+    // body is left out when writing the response to a HEAD request anyway
+    //
+    // panics when `path` has a `*` segment anywhere but at the end, see
+    // try_route() for getting an error instead
+    pub fn route<H>(self, method: Method, path: &str, handler: H) -> Router
+    where
+        H: Fn(&Request) -> io::Result<Response> + Send + Sync + 'static,
+    {
+        self.try_route(method, path, handler)
+            .unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    // same as route(), but an invalid `path` is returned as an error
+    pub fn try_route<H>(
+        mut self,
+        method: Method,
+        path: &str,
+        handler: H,
+    ) -> Result<Router, RouteError>
+    where
+        H: Fn(&Request) -> io::Result<Response> + Send + Sync + 'static,
+    {
+        let segments: Vec<Segment> = path.split('/').map(Segment::parse).collect();
+
+        // the rest of the path can only be captured once, by the last segment
+        let wildcard_not_last = segments
+            .iter()
+            .rev()
+            .skip(1)
+            .any(|segment| matches!(segment, Segment::Wildcard(_)));
+
+        if wildcard_not_last {
+            return Err(RouteError::WildcardNotLast(path.to_owned()));
+        }
+
+        self.routes.push(Route {
+            method,
+            pattern: path.to_owned(),
+            segments,
+            handler: Box::new(handler),
+            max_body_size: None,
+        });
+        Ok(self)
+    }
+
+    // shorthands for route() with the method in the name
+    pub fn get<H>(self, path: &str, handler: H) -> Router
+    where
+        H: Fn(&Request) -> io::Result<Response> + Send + Sync + 'static,
+    {
+        self.route(Method::Get, path, handler)
+    }
+
+    pub fn post<H>(self, path: &str, handler: H) -> Router
+    where
+        H: Fn(&Request) -> io::Result<Response> + Send + Sync + 'static,
+    {
+        self.route(Method::Post, path, handler)
+    }
+
+    pub fn put<H>(self, path: &str, handler: H) -> Router
+    where
+        H: Fn(&Request) -> io::Result<Response> + Send + Sync + 'static,
+    {
+        self.route(Method::Put, path, handler)
+    }
+
+    pub fn delete<H>(self, path: &str, handler: H) -> Router
+    where
+        H: Fn(&Request) -> io::Result<Response> + Send + Sync + 'static,
+    {
+        self.route(Method::Delete, path, handler)
+    }
+
+    pub fn patch<H>(self, path: &str, handler: H) -> Router
+    where
+        H: Fn(&Request) -> io::Result<Response> + Send + Sync + 'static,
+    {
+        self.route(Method::Patch, path, handler)
+    }
+
+    // overrides the 204 OPTIONS requests for `path` get by default, see
+    // handle(), e.g. to answer CORS preflight requests, `*` as the path
+    // handles the OPTIONS requests for every path, `OPTIONS *` included
+    pub fn options<H>(self, path: &str, handler: H) -> Router
+    where
+        H: Fn(&Request) -> io::Result<Response> + Send + Sync + 'static,
+    {
+        self.route(Method::Options, path, handler)
+    }
+
+    // most bytes the body of a request can have, MAX_BODY_SIZE unless set,
+    // for every route but the ones given a limit of their own, see
+    // route_max_body_size() and body_limit()
+    pub fn max_body_size(mut self, max_body_size: u64) -> Router {
+        self.max_body_size = max_body_size;
+        self
+    }
+
+    // gives the route added for `method` and `path` a body size limit of its
+    // own, e.g. a larger one for an upload:-
+    //
+    // router
+    //     .post("/upload", upload)
+    //     .route_max_body_size(Method::Post, "/upload", 512 * 1024 * 1024)
+    //
+    // `path` is the one the route was added with, not a request path it
+    // matches, panics when there's no such route since the limit would
+    // never apply
+    pub fn route_max_body_size(mut self, method: Method, path: &str, max_body_size: u64) -> Router {
+        let mut found = false;
+
+        for route in &mut self.routes {
+            if route.method == method && route.pattern == path {
+                route.max_body_size = Some(max_body_size);
+                found = true;
+            }
+        }
+
+        assert!(found, "no route for {method} {path} to limit the body of");
+        self
+    }
+
+    // the most bytes the body of `request` can have, the limit of the route
+    // it's matched to or max_body_size(), looked at once its headers are
+    // read and before its body is, see server::serve_connection_limited()
+    pub fn body_limit(&self, request: &Request) -> u64 {
+        self.find(request)
+            .and_then(|(route, _)| route.max_body_size)
+            .unwrap_or(self.max_body_size)
+    }
+
+    // handles requests no route matches, instead of the default 404, a
+    // request for a path routed for other methods only gets a 405 instead,
+    // see handle()
+    pub fn not_found<H>(mut self, handler: H) -> Router
+    where
+        H: Fn(&Request) -> io::Result<Response> + Send + Sync + 'static,
+    {
+        self.not_found = Box::new(handler);
+        self
+    }
+
+    // turns the error a handler returned into the response sent instead, by
+    // default the error is logged and the client gets a 500, see
+    // Response::error()
+    pub fn on_error<E>(mut self, on_error: E) -> Router
+    where
+        E: Fn(&Request, io::Error) -> Response + Send + Sync + 'static,
+    {
+        self.on_error = Box::new(on_error);
+        self
+    }
+
+    // adds a middleware around every request, including those no route
+    // matches, middlewares run in the order they're added, the first one
+    // sees the request first and the response last
+    pub fn middleware<M>(mut self, middleware: M) -> Router
+    where
+        M: Fn(&Request, Next<'_>) -> Response + Send + Sync + 'static,
+    {
+        self.middlewares.push(Box::new(middleware));
+        self
+    }
+
+    // runs the request through the middlewares and the handler it matches,
+    // the segments the route captured are set on the request first, so the
+    // middlewares see them as well
+    //
+    // a request for a path that has routes, just none for the request's
+    // method, is answered with a 405 listing the methods there are routes
+    // for in an Allow header, e.g. `Allow: GET, HEAD, POST, OPTIONS`,
+    // instead of going to the not-found handler, a method the server doesn't
+    // know at all never gets this far, it's answered with a 501 while
+    // parsing the request, see server::bad_request()
+    //
+    // an OPTIONS request for such a path gets a 204 with the same Allow
+    // header instead, unless there's a route for OPTIONS itself, see
+    // options(), and `OPTIONS *` gets every method there's any route for
+    pub fn handle(&self, request: &mut Request) -> Response {
+        let allowed_handler: Handler;
+        let handler = match self.find(request) {
+            Some((route, params)) => {
+                request.set_params(params);
+                &route.handler
+            }
+            None => {
+                let allowed = self.allowed_methods(request);
+
+                if allowed.is_empty() {
+                    &self.not_found
+                } else {
+                    let allow = allowed.join(", ");
+                    allowed_handler = if request.method == Method::Options {
+                        Box::new(move |_| Ok(Response::no_content().with_header("Allow", &allow)))
+                    } else {
+                        Box::new(move |request| {
+                            Ok(Response::error(request, 405).with_header("Allow", &allow))
+                        })
+                    };
+                    &allowed_handler
+                }
+            }
+        };
+        let next = Next {
+            middlewares: &self.middlewares,
+            handler,
+            on_error: &self.on_error,
+        };
+
+        next.run(request)
+    }
+
+    // every method there's a route for the request's path for, in the order
+    // the routes were added, HEAD right after GET since GET routes handle it
+    // and OPTIONS last since every path with a route answers it, for
+    // `OPTIONS *` it's every method of any route
+    fn allowed_methods(&self, request: &Request) -> Vec<&'static str> {
+        let asterisk = request.target() == "*";
+        let segments: Vec<&str> = request.raw_path().split('/').collect();
+        let mut allowed = Vec::new();
+
+        for route in &self.routes {
+            // OPTIONS is added below for every routed path, a route for it
+            // alone doesn't make one, a catch-all one(`*`) would turn every
+            // 404 into a 405 otherwise
+            if route.method == Method::Options {
+                continue;
+            }
+
+            if !asterisk && route.captures(&segments).is_none() {
+                continue;
+            }
+
+            let methods: &[Method] = match route.method {
+                Method::Get => &[Method::Get, Method::Head],
+                _ => &[route.method],
+            };
+
+            for method in methods {
+                if !allowed.contains(&method.as_str()) {
+                    allowed.push(method.as_str());
+                }
+            }
+        }
+
+        if !allowed.is_empty() {
+            allowed.push(Method::Options.as_str());
+        }
+
+        allowed
+    }
+
+    // the most specific route matching the request, along with what it
+    // captured
+    fn find(&self, request: &Request) -> Option<(&Route, HashMap<String, Param>)> {
+        // matched against the path as sent, rather than the decoded one, so
+        // that an encoded slash(`%2F`) is part of a segment instead of
+        // separating two, the query string plays no part in routing
+        let segments: Vec<&str> = request.raw_path().split('/').collect();
+        let mut best: Option<(&Route, HashMap<String, Param>)> = None;
+
+        for route in &self.routes {
+            let method_matches = route.method == request.method
+                || (route.method == Method::Get && request.method == Method::Head);
+
+            if !method_matches {
+                continue;
+            }
+
+            let Some(params) = route.captures(&segments) else {
+                continue;
+            };
+
+            // only replaced by a more specific one, among equally specific
+            // routes the first one added wins
+            let more_specific = best
+                .as_ref()
+                .is_none_or(|(best, _)| route.specificity().gt(best.specificity()));
+
+            if more_specific {
+                best = Some((route, params));
+            }
+        }
+
+        best
+    }
+}
+
+// a route that can't be added, see Router::try_route()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RouteError {
+    // the path has a `*` segment other than its last one, i.e. something
+    // would come after the rest of the path
+    WildcardNotLast(String),
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouteError::WildcardNotLast(path) => {
+                write!(f, "route {path:?} has a `*` segment before its last one")
+            }
+        }
+    }
+}
+
+impl Error for RouteError {}
+
+impl Default for Router {
+    fn default() -> Router {
+        Router::new()
+    }
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let routes: Vec<_> = self
+            .routes
+            .iter()
+            .map(|route| format!("{} {}", route.method, route.pattern))
+            .collect();
+
+        f.debug_struct("Router")
+            .field("routes", &routes)
+            .field("middlewares", &self.middlewares.len())
+            .finish_non_exhaustive()
+    }
+}
+
+struct Route {
+    method: Method,
+    // the path as given to Router::route(), for Debug
+    pattern: String,
+    // the pattern split at every `/`, so the first one is always an empty
+    // fixed segment
+    segments: Vec<Segment>,
+    handler: Handler,
+    // see Router::route_max_body_size()
+    max_body_size: Option<u64>,
+}
+
+impl Route {
+    // the segments captured when `segments` of a request path match the
+    // route, None when they don't match, including when a captured segment
+    // isn't correctly encoded
+    fn captures(&self, segments: &[&str]) -> Option<HashMap<String, Param>> {
+        let wildcard = matches!(self.segments.last(), Some(Segment::Wildcard(_)));
+        // the wildcard can capture any number of segments, the empty one
+        // after the slash in front of it included, the rest need one each
+        let fits = if wildcard {
+            segments.len() >= self.segments.len()
+        } else {
+            segments.len() == self.segments.len()
+        };
+
+        if !fits {
+            return None;
+        }
+
+        let mut params = HashMap::new();
+
+        for (i, pattern) in self.segments.iter().enumerate() {
+            match pattern {
+                Segment::Fixed(fixed)
+                    if percent_decode(segments[i]).is_some_and(|segment| segment == *fixed) => {}
+                Segment::Param(name) if !segments[i].is_empty() => {
+                    params.insert(name.clone(), Param::new(segments[i])?);
+                }
+                Segment::Wildcard(name) => {
+                    let rest = segments[i..].join("/");
+                    params.insert(name.clone(), Param::new(&rest)?);
+                }
+                _ => return None,
+            }
+        }
+
+        Some(params)
+    }
+
+    // how specific each segment is, from the first to the last, compared
+    // lexicographically a route with a fixed segment where another one
+    // captures is more specific, and so is one capturing a single segment
+    // where another one captures the rest
+    fn specificity(&self) -> impl Iterator<Item = u8> + '_ {
+        self.segments.iter().map(|segment| match segment {
+            Segment::Fixed(_) => 2,
+            Segment::Param(_) => 1,
+            Segment::Wildcard(_) => 0,
+        })
+    }
+}
+
+enum Segment {
+    Fixed(String),
+    // `:name`
+    Param(String),
+    // `*name`
+    Wildcard(String),
+}
+
+impl Segment {
+    fn parse(segment: &str) -> Segment {
+        if let Some(name) = segment.strip_prefix(':') {
+            Segment::Param(name.to_owned())
+        } else if let Some(name) = segment.strip_prefix('*') {
+            Segment::Wildcard(name.to_owned())
+        } else {
+            Segment::Fixed(segment.to_owned())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Body;
+    use std::sync::{Arc, Mutex};
+
+    // what `router` writes back for a request, as sent
+    fn respond(router: &Router, request: &str) -> String {
+        let mut request = Request::parse(&mut request.as_bytes()).unwrap();
+        let response = router.handle(&mut request);
+        let mut written = Vec::new();
+        response.write_for(request.method, &mut written).unwrap();
+        String::from_utf8(written).unwrap()
+    }
+
+    #[test]
+    fn head_request_answered_like_get_without_the_body() {
+        let router = Router::new().route(Method::Get, "/", |_| {
+            Ok(Response::new(200)
+                .with_header("Content-Type", "text/plain")
+                .with_body("hello"))
+        });
+
+        let get = respond(&router, "GET / HTTP/1.1\r\n\r\n");
+        let head = respond(&router, "HEAD / HTTP/1.1\r\n\r\n");
+
+        assert_eq!(get, format!("{head}hello"));
+        assert!(head.contains("\r\nContent-Length: 5\r\n"));
+        assert!(head.ends_with("\r\n\r\n"));
+    }
+
+    #[test]
+    fn unmatched_requests_not_found() {
+        let router = Router::new().route(Method::Post, "/", |_| Ok(Response::new(200)));
+
+        let missing = respond(&router, "GET /missing HTTP/1.1\r\n\r\n");
+        assert!(missing.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        // the path matches, the method doesn't, see method_not_allowed()
+        let wrong_method = respond(&router, "GET / HTTP/1.1\r\n\r\n");
+        assert!(wrong_method.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+
+        let router = router.not_found(|_| Ok(Response::new(404).with_body("nothing here")));
+        let custom = respond(&router, "GET /missing HTTP/1.1\r\n\r\n");
+        assert!(custom.ends_with("\r\n\r\nnothing here"));
+    }
+
+    #[test]
+    fn routes_by_method() {
+        let router = Router::new()
+            .get("/items", |_| Ok(Response::new(200).with_body("list")))
+            .post("/items", |_| Ok(Response::new(201)))
+            .put("/items", |_| Ok(Response::new(200).with_body("replaced")))
+            .delete("/items", |_| Ok(Response::new(204)))
+            .patch("/items", |_| Ok(Response::new(200).with_body("patched")))
+            // never reached, the first route added for a method and path wins
+            .get("/items", |_| Ok(Response::new(500)));
+
+        let handle = |request: &str| {
+            let mut request = Request::parse(&mut request.as_bytes()).unwrap();
+            router.handle(&mut request)
+        };
+
+        assert!(matches!(
+            handle("GET /items HTTP/1.1\r\n\r\n").body,
+            Body::Text(body) if body == "list"
+        ));
+        assert_eq!(
+            handle("POST /items HTTP/1.1\r\nContent-Length: 0\r\n\r\n").status,
+            201
+        );
+        assert!(matches!(
+            handle("PUT /items HTTP/1.1\r\nContent-Length: 0\r\n\r\n").body,
+            Body::Text(body) if body == "replaced"
+        ));
+        assert_eq!(handle("DELETE /items HTTP/1.1\r\n\r\n").status, 204);
+        assert!(matches!(
+            handle("PATCH /items HTTP/1.1\r\nContent-Length: 0\r\n\r\n").body,
+            Body::Text(body) if body == "patched"
+        ));
+        // answered by the router itself, see options()
+        assert_eq!(handle("OPTIONS /items HTTP/1.1\r\n\r\n").status, 204);
+    }
+
+    #[test]
+    fn method_not_allowed() {
+        let router = Router::new()
+            .get("/", |_| Ok(Response::new(200)))
+            .post("/items/:id", |_| Ok(Response::new(200)))
+            .get("/items/*rest", |_| Ok(Response::new(200)))
+            .delete("/items/:id", |_| Ok(Response::new(204)))
+            .post("/items/*rest", |_| Ok(Response::new(200)));
+
+        let handle = |request: &str| {
+            let mut request = Request::parse(&mut request.as_bytes()).unwrap();
+            router.handle(&mut request)
+        };
+
+        let response = handle("POST / HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(response.status, 405);
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, OPTIONS"));
+
+        // every route matching the path counts, not only the most specific
+        let response = handle("PUT /items/7 HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(response.status, 405);
+        assert_eq!(
+            response.headers.get("Allow"),
+            Some("POST, GET, HEAD, DELETE, OPTIONS")
+        );
+
+        let response = handle("PUT /items/7/notes HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(
+            response.headers.get("Allow"),
+            Some("GET, HEAD, POST, OPTIONS")
+        );
+
+        // a path without any routes is still not found
+        let response = handle("GET /missing HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, 404);
+        assert_eq!(response.headers.get("Allow"), None);
+        assert_eq!(
+            handle("POST /missing HTTP/1.1\r\nContent-Length: 0\r\n\r\n").status,
+            404
+        );
+    }
+
+    #[test]
+    fn options_for_a_path() {
+        let router = Router::new()
+            .get("/items", |_| Ok(Response::new(200)))
+            .post("/items", |_| Ok(Response::new(201)))
+            .delete("/items/:id", |_| Ok(Response::new(204)));
+
+        let handle = |request: &str| {
+            let mut request = Request::parse(&mut request.as_bytes()).unwrap();
+            router.handle(&mut request)
+        };
+
+        let response = handle("OPTIONS /items HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, 204);
+        assert_eq!(
+            response.headers.get("Allow"),
+            Some("GET, HEAD, POST, OPTIONS")
+        );
+        assert!(!response.allows_body());
+
+        let response = handle("OPTIONS /items/7 HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, 204);
+        assert_eq!(response.headers.get("Allow"), Some("DELETE, OPTIONS"));
+
+        // nothing routed there
+        let response = handle("OPTIONS /missing HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, 404);
+        assert_eq!(response.headers.get("Allow"), None);
+    }
+
+    #[test]
+    fn options_for_the_whole_server() {
+        let router = Router::new()
+            .get("/", |_| Ok(Response::new(200)))
+            .post("/items", |_| Ok(Response::new(201)))
+            .delete("/items/:id", |_| Ok(Response::new(204)))
+            .post("/upload", |_| Ok(Response::new(201)));
+
+        let response = respond(&router, "OPTIONS * HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(response.contains("\r\nAllow: GET, HEAD, POST, DELETE, OPTIONS\r\n"));
+
+        // without any routes there's nothing to allow
+        let response = respond(&Router::new(), "OPTIONS * HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+
+    #[test]
+    fn options_routed_explicitly() {
+        let router = Router::new()
+            .get("/items", |_| Ok(Response::new(200)))
+            .options("/items", |_| {
+                Ok(Response::new(200).with_header("Allow", "GET, OPTIONS"))
+            });
+
+        let handle = |router: &Router, request: &str| {
+            let mut request = Request::parse(&mut request.as_bytes()).unwrap();
+            router.handle(&mut request)
+        };
+
+        let response = handle(&router, "OPTIONS /items HTTP/1.1\r\n\r\n");
+        assert_eq!(response.status, 200);
+        assert_eq!(response.headers.get("Allow"), Some("GET, OPTIONS"));
+        // doesn't make other methods allowed
+        let response = handle(&router, "POST /items HTTP/1.1\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(response.headers.get("Allow"), Some("GET, HEAD, OPTIONS"));
+
+        // a catch-all handles OPTIONS for every path and the server, but
+        // paths without other routes are still not found
+        let router = router.options("*", |request| {
+            Ok(Response::new(200).with_body(request.target().to_owned()))
+        });
+
+        assert!(matches!(
+            handle(&router, "OPTIONS * HTTP/1.1\r\n\r\n").body,
+            Body::Text(body) if body == "*"
+        ));
+        assert!(matches!(
+            handle(&router, "OPTIONS /missing HTTP/1.1\r\n\r\n").body,
+            Body::Text(body) if body == "/missing"
+        ));
+        assert_eq!(
+            handle(&router, "OPTIONS /items HTTP/1.1\r\n\r\n").status,
+            200
+        );
+        assert_eq!(handle(&router, "GET /missing HTTP/1.1\r\n\r\n").status, 404);
+    }
+
+    // a handler answering with the params it was given, in the format
+    // `name=value,name=value`, in the order of `names`
+    fn echo_params(names: &'static [&'static str]) -> impl Fn(&Request) -> io::Result<Response> {
+        move |request| {
+            let params: Vec<String> = names
+                .iter()
+                .map(|name| format!("{name}={}", request.param(name).unwrap_or("-")))
+                .collect();
+            Ok(Response::new(200).with_body(params.join(",")))
+        }
+    }
+
+    #[test]
+    fn path_params() {
+        let router = Router::new()
+            .get("/files/:name", echo_params(&["name"]))
+            .get(
+                "/users/:id/posts/:post_id",
+                echo_params(&["id", "post_id", "name"]),
+            );
+
+        let body = |request: &str| {
+            let response = respond(&router, request);
+            response.split_once("\r\n\r\n").unwrap().1.to_owned()
+        };
+
+        assert_eq!(body("GET /files/a.txt HTTP/1.1\r\n\r\n"), "name=a.txt");
+        assert_eq!(
+            body("GET /users/7/posts/42 HTTP/1.1\r\n\r\n"),
+            "id=7,post_id=42,name=-"
+        );
+        // captures are decoded, the query string is left out
+        assert_eq!(
+            body("GET /files/my%20notes.txt?download=1 HTTP/1.1\r\n\r\n"),
+            "name=my notes.txt"
+        );
+        assert_eq!(body("GET /files/a%2Fb HTTP/1.1\r\n\r\n"), "name=a/b");
+
+        for missing in [
+            // fewer segments than the pattern
+            "GET /files HTTP/1.1\r\n\r\n",
+            "GET /users/7/posts HTTP/1.1\r\n\r\n",
+            // more segments
+            "GET /files/a/b HTTP/1.1\r\n\r\n",
+            "GET /users/7/posts/42/comments HTTP/1.1\r\n\r\n",
+            // an empty segment doesn't count as captured
+            "GET /files/ HTTP/1.1\r\n\r\n",
+            "GET /users//posts/42 HTTP/1.1\r\n\r\n",
+        ] {
+            assert!(
+                respond(&router, missing).starts_with("HTTP/1.1 404 Not Found\r\n"),
+                "{missing:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn routed_without_the_query_string() {
+        let router = Router::new().get("/search", |request| {
+            let q = request.query_param("q").unwrap_or_default();
+            let limit = request.query_param("limit").unwrap_or_default();
+            Ok(Response::new(200).with_body(format!("{q}:{limit}")))
+        });
+
+        let response = respond(&router, "GET /search?q=rust&limit=10 HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nrust:10"));
+
+        let response = respond(&router, "GET /search? HTTP/1.1\r\n\r\n");
+        assert!(response.ends_with("\r\n\r\n:"));
+    }
+
+    #[test]
+    fn routed_on_the_normalized_path() {
+        let router = Router::new()
+            .get(
+                "/hello world",
+                |_| Ok(Response::new(200).with_body("hello")),
+            )
+            .get("/docs/:page", echo_params(&["page"]));
+
+        let body = |request: &str| {
+            let response = respond(&router, request);
+            response.split_once("\r\n\r\n").unwrap().1.to_owned()
+        };
+
+        assert_eq!(body("GET /hello%20world HTTP/1.1\r\n\r\n"), "hello");
+        assert_eq!(body("GET /docs/../hello%20world HTTP/1.1\r\n\r\n"), "hello");
+        assert_eq!(body("GET //docs/./b//../a HTTP/1.1\r\n\r\n"), "page=a");
+        assert_eq!(
+            body("GET /docs/%2e%2e%2fsecret HTTP/1.1\r\n\r\n"),
+            "page=../secret"
+        );
+    }
+
+    #[test]
+    fn fixed_segments_beat_params() {
+        let router = Router::new()
+            .get("/users/:id", echo_params(&["id"]))
+            .get("/users/new", |_| Ok(Response::new(200).with_body("new")))
+            .get("/users/:id/:tab", echo_params(&["id", "tab"]))
+            .get("/users/:id/posts", |_| {
+                Ok(Response::new(200).with_body("posts"))
+            })
+            .get("/:section/new/posts", |_| {
+                Ok(Response::new(200).with_body("section"))
+            });
+
+        let body = |request: &str| {
+            let response = respond(&router, request);
+            response.split_once("\r\n\r\n").unwrap().1.to_owned()
+        };
+
+        // added after the route capturing the same segment, still wins
+        assert_eq!(body("GET /users/new HTTP/1.1\r\n\r\n"), "new");
+        assert_eq!(body("GET /users/7 HTTP/1.1\r\n\r\n"), "id=7");
+        assert_eq!(body("GET /users/7/posts HTTP/1.1\r\n\r\n"), "posts");
+        assert_eq!(
+            body("GET /users/7/likes HTTP/1.1\r\n\r\n"),
+            "id=7,tab=likes"
+        );
+        // the first segment decides before the later ones
+        assert_eq!(body("GET /users/new/posts HTTP/1.1\r\n\r\n"), "posts");
+        assert_eq!(body("GET /blog/new/posts HTTP/1.1\r\n\r\n"), "section");
+    }
+
+    #[test]
+    fn wildcard_captures_the_rest_of_the_path() {
+        let router = Router::new()
+            .get("/static/*path", |request| {
+                Ok(Response::new(200).with_body(format!(
+                    "{}|{}",
+                    request.param("path").unwrap(),
+                    request.raw_param("path").unwrap()
+                )))
+            })
+            .get("/static/favicon.ico", |_| {
+                Ok(Response::new(200).with_body("favicon"))
+            })
+            .get("/static/:file/raw", echo_params(&["file"]));
+
+        let body = |request: &str| {
+            let response = respond(&router, request);
+            response.split_once("\r\n\r\n").unwrap().1.to_owned()
+        };
+
+        assert_eq!(
+            body("GET /static/css/site.css HTTP/1.1\r\n\r\n"),
+            "css/site.css|css/site.css"
+        );
+        // a more specific route wins, even one added after the wildcard
+        assert_eq!(body("GET /static/favicon.ico HTTP/1.1\r\n\r\n"), "favicon");
+        assert_eq!(body("GET /static/a.txt/raw HTTP/1.1\r\n\r\n"), "file=a.txt");
+        assert_eq!(
+            body("GET /static/img/favicon.ico HTTP/1.1\r\n\r\n"),
+            "img/favicon.ico|img/favicon.ico"
+        );
+        // the raw capture tells an encoded slash apart from a real one
+        assert_eq!(
+            body("GET /static/a%2Fb/my%20notes.txt?v=2 HTTP/1.1\r\n\r\n"),
+            "a/b/my notes.txt|a%2Fb/my%20notes.txt"
+        );
+        assert_eq!(body("GET /static/ HTTP/1.1\r\n\r\n"), "|");
+
+        for missing in [
+            "GET /static HTTP/1.1\r\n\r\n",
+            "GET /assets/site.css HTTP/1.1\r\n\r\n",
+        ] {
+            assert!(
+                respond(&router, missing).starts_with("HTTP/1.1 404 Not Found\r\n"),
+                "{missing:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn wildcard_only_at_the_end() {
+        for path in ["/static/*path/edit", "/*a/*b", "/*rest/"] {
+            let err = Router::new()
+                .try_route(Method::Get, path, |_| Ok(Response::new(200)))
+                .unwrap_err();
+            assert_eq!(err, RouteError::WildcardNotLast(path.to_owned()));
+        }
+
+        assert!(Router::new()
+            .try_route(Method::Get, "/files/:dir/*rest", |_| Ok(Response::new(200)))
+            .is_ok());
+    }
+
+    #[test]
+    #[should_panic(expected = "has a `*` segment before its last one")]
+    fn wildcard_not_at_the_end_panics() {
+        let _ = Router::new().get("/*rest/more", |_| Ok(Response::new(200)));
+    }
+
+    #[test]
+    fn body_limit_per_route() {
+        let ok = |_: &Request| Ok(Response::new(200));
+        let limit = |router: &Router, request: &str| {
+            router.body_limit(&Request::parse(&mut request.as_bytes()).unwrap())
+        };
+
+        let router = Router::new().post("/", ok).post("/upload/:name", ok);
+        assert_eq!(
+            limit(&router, "POST / HTTP/1.1\r\nContent-Length: 0\r\n\r\n"),
+            MAX_BODY_SIZE
+        );
+
+        let router = Router::new()
+            .post("/", ok)
+            .post("/upload/:name", ok)
+            .put("/upload/:name", ok)
+            .max_body_size(1024)
+            .route_max_body_size(Method::Post, "/upload/:name", 1024 * 1024);
+
+        assert_eq!(
+            limit(&router, "POST / HTTP/1.1\r\nContent-Length: 0\r\n\r\n"),
+            1024
+        );
+        assert_eq!(
+            limit(
+                &router,
+                "POST /upload/a.png HTTP/1.1\r\nContent-Length: 0\r\n\r\n"
+            ),
+            1024 * 1024
+        );
+        // only for the method it's given for
+        assert_eq!(
+            limit(
+                &router,
+                "PUT /upload/a.png HTTP/1.1\r\nContent-Length: 0\r\n\r\n"
+            ),
+            1024
+        );
+        // nothing matched, the router's own
+        assert_eq!(
+            limit(
+                &router,
+                "POST /missing HTTP/1.1\r\nContent-Length: 0\r\n\r\n"
+            ),
+            1024
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "no route for POST /upload/a.png to limit the body of")]
+    fn body_limit_for_a_missing_route_panics() {
+        let _ = Router::new()
+            .post("/upload/:name", |_| Ok(Response::new(200)))
+            .route_max_body_size(Method::Post, "/upload/a.png", 1024);
+    }
+
+    #[test]
+    fn middlewares_run_in_order() {
+        let ran = Arc::new(Mutex::new(Vec::new()));
+        let (first, second, handler) = (Arc::clone(&ran), Arc::clone(&ran), Arc::clone(&ran));
+
+        let router = Router::new()
+            .route(Method::Get, "/", move |_| {
+                handler.lock().unwrap().push("handler");
+                Ok(Response::new(200))
+            })
+            .middleware(move |request, next| {
+                first.lock().unwrap().push("first");
+                next.run(request).with_header("X-Trace", "first")
+            })
+            .middleware(move |request, next| {
+                second.lock().unwrap().push("second");
+                next.run(request).with_header("X-Trace", "second")
+            });
+
+        let mut request = Request::parse(&mut &b"GET / HTTP/1.1\r\n\r\n"[..]).unwrap();
+        let response = router.handle(&mut request);
+
+        assert_eq!(*ran.lock().unwrap(), ["first", "second", "handler"]);
+        // the first one added wraps the rest of the chain, so it gets the
+        // response last
+        assert_eq!(
+            response.headers.get_all("X-Trace").collect::<Vec<_>>(),
+            ["second", "first"]
+        );
+    }
+
+    #[test]
+    fn handler_error_answered_with_500() {
+        let router = Router::new()
+            .get("/", |_| Err(io::Error::other("disk on fire")))
+            .middleware(|request, next| next.run(request).with_header("X-Seen", "yes"));
+
+        let response = respond(&router, "GET / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 500 Internal Server Error\r\n"));
+        // the middleware gets the response the error was turned into
+        assert!(response.contains("\r\nX-Seen: yes\r\n"));
+
+        let router = router.on_error(|_, err| Response::new(503).with_body(err.to_string()));
+        let response = respond(&router, "GET / HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(response.ends_with("\r\n\r\ndisk on fire"));
+    }
+
+    #[test]
+    fn middleware_answering_by_itself() {
+        let router = Router::new()
+            .route(Method::Get, "/", |_| panic!("handler ran"))
+            .middleware(|_, _| Response::new(403));
+
+        let mut request = Request::parse(&mut &b"GET / HTTP/1.1\r\n\r\n"[..]).unwrap();
+        assert_eq!(router.handle(&mut request).status, 403);
+    }
+}
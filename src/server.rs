@@ -0,0 +1,1195 @@
+use crate::http::{
+    HeaderMap, HttpVersion, Method, ParseError, ParseLimits, Request, Response, MAX_BODY_SIZE,
+};
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Write},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+// settings of the server binary, the defaults are what it has always used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    // address the server listens on
+    pub address: String,
+    // most threads handling connections at once, the pool starts with
+    // fewer and only starts more while every thread is busy, see
+    // ThreadPool::for_io()
+    pub threads: usize,
+    // file every request is logged to, see AccessLog, None logs nothing
+    pub access_log: Option<PathBuf>,
+    // how long the request line and how large the headers of a request can
+    // get, see ParseLimits
+    pub parse_limits: ParseLimits,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            address: "127.0.0.1:7878".to_owned(),
+            threads: 64,
+            access_log: None,
+            parse_limits: ParseLimits::default(),
+        }
+    }
+}
+
+// what to do with a new connection while the connection limit is reached
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionPolicy {
+    // wait for a connection to finish before accepting the next one, new
+    // connections pile up in the operating system's backlog meanwhile
+    Block,
+    // accept it anyway but answer it with `503 Service Unavailable` right
+    // away instead of handing it to the pool
+    Reject,
+}
+
+// caps the number of connections being handled at once, without it the
+// accept loop keeps accepting and queuing jobs for as long as connections
+// keep coming, no matter how far behind the pool is, a semaphore in the
+// form of a counter behind a Mutex and a Condvar notified on every release
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    max_connections: usize,
+    policy: ConnectionPolicy,
+    active: Mutex<usize>,
+    released: Condvar,
+}
+
+impl ConnectionLimiter {
+    pub fn new(max_connections: usize, policy: ConnectionPolicy) -> ConnectionLimiter {
+        // a limit of 0 would never let a connection through
+        assert!(max_connections > 0);
+
+        ConnectionLimiter {
+            max_connections,
+            policy,
+            active: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    // takes a slot for a new connection, which is given back once the
+    // returned permit is dropped, the permit is meant to be moved into the
+    // job handling the connection, so the slot is held until that job is
+    // done(or panicked), with ConnectionPolicy::Block this waits until a
+    // slot is free, with ConnectionPolicy::Reject it returns None instead
+    pub fn acquire(self: &Arc<Self>) -> Option<ConnectionPermit> {
+        let mut active = self.active.lock().unwrap();
+
+        if *active >= self.max_connections {
+            match self.policy {
+                ConnectionPolicy::Block => {
+                    active = self
+                        .released
+                        .wait_while(active, |active| *active >= self.max_connections)
+                        .unwrap();
+                }
+                ConnectionPolicy::Reject => return None,
+            }
+        }
+
+        *active += 1;
+
+        Some(ConnectionPermit {
+            limiter: Arc::clone(self),
+        })
+    }
+
+    // number of connections currently holding a permit
+    pub fn active(&self) -> usize {
+        *self.active.lock().unwrap()
+    }
+
+    pub fn policy(&self) -> ConnectionPolicy {
+        self.policy
+    }
+}
+
+// a slot taken from a ConnectionLimiter, given back when dropped
+#[derive(Debug)]
+pub struct ConnectionPermit {
+    limiter: Arc<ConnectionLimiter>,
+}
+
+impl Drop for ConnectionPermit {
+    fn drop(&mut self) {
+        // doesn't unwrap the lock, the permit is dropped while unwinding
+        // when the job handling the connection panics
+        let mut active = match self.limiter.active.lock() {
+            Ok(active) => active,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        *active -= 1;
+
+        self.limiter.released.notify_one();
+    }
+}
+
+// limits how many requests each client(going by ip address) can make, with
+// a token bucket per client, a bucket holds up to `burst` tokens and refills
+// at `requests_per_second` tokens a second, every request takes a token and
+// requests finding their client's bucket empty are turned away, so clients
+// can make `burst` requests at once but only keep up `requests_per_second`
+// in the long run
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<IpAddr, TokenBucket>,
+    last_cleanup: Instant,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    // when `tokens` was last brought up to date, tokens are added lazily
+    // whenever the bucket is looked at rather than by a timer
+    updated_at: Instant,
+}
+
+// how often buckets of clients that stopped making requests are removed,
+// without it the map would keep an entry for every client ever seen
+const BUCKET_CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+impl RateLimiter {
+    pub fn new(requests_per_second: u32, burst: u32) -> RateLimiter {
+        // a rate or burst of 0 would never let a request through
+        assert!(requests_per_second > 0 && burst > 0);
+
+        RateLimiter {
+            requests_per_second: f64::from(requests_per_second),
+            burst: f64::from(burst),
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_cleanup: Instant::now(),
+            }),
+        }
+    }
+
+    // takes a token from the bucket of `ip`, the error is how long the
+    // client has to wait for the next token when the bucket is empty
+    pub fn check(&self, ip: IpAddr) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if now.duration_since(buckets.last_cleanup) >= BUCKET_CLEANUP_INTERVAL {
+            buckets.last_cleanup = now;
+            // a bucket that would be full by now is no different from a
+            // new one, so forgetting about it changes nothing
+            buckets
+                .buckets
+                .retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+        }
+
+        let bucket = buckets.buckets.entry(ip).or_insert(TokenBucket {
+            tokens: self.burst,
+            updated_at: now,
+        });
+
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let missing = 1.0 - bucket.tokens;
+            Err(Duration::from_secs_f64(missing / self.requests_per_second))
+        }
+    }
+
+    // tokens in `bucket` at `now`, capped at the burst size
+    fn refilled(&self, bucket: &TokenBucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+
+        (bucket.tokens + elapsed * self.requests_per_second).min(self.burst)
+    }
+
+    // `429 Too Many Requests` response for a request turned away by
+    // check(), Retry-After is in whole seconds so the wait is rounded up
+    pub fn too_many_requests(retry_after: Duration) -> Response {
+        let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+
+        Response::new(429).with_header("Retry-After", &seconds.max(1).to_string())
+    }
+}
+
+// log with a line per request in the Common Log Format that web servers
+// like apache write their access logs in, which plenty of log analyzers
+// understand:-
+//
+// 127.0.0.1 - - [16/Oct/2026:13:55:36 +0000] "GET /sleep HTTP/1.1" 200 196
+//
+// i.e. the client's address, 2 fields for the user that are never known
+// here, the time the request was answered, the request line, the status
+// code and the size of the body sent(`-` for none), every line is flushed
+// right away so the log is complete even if the server is killed
+#[derive(Debug)]
+pub struct AccessLog {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl AccessLog {
+    // appends to the file at `path`, creating it if it doesn't exist yet
+    pub fn open(path: impl AsRef<Path>) -> io::Result<AccessLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(AccessLog {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    // logs a request answered with `response` to the client at `host`, None
+    // when the client's address couldn't be told
+    pub fn log(
+        &self,
+        host: Option<IpAddr>,
+        request: &Request,
+        response: &Response,
+    ) -> io::Result<()> {
+        let host = host.map_or_else(|| "-".to_owned(), |host| host.to_string());
+        // the body of a response to a HEAD request is never sent, neither is
+        // one of a status without a body, and the length of a streamed body
+        // isn't known
+        let bytes = match request.method {
+            Method::Head => None,
+            _ if !response.allows_body() => None,
+            _ => response.body.len(),
+        };
+        let bytes = match bytes {
+            Some(bytes) if bytes > 0 => bytes.to_string(),
+            _ => "-".to_owned(),
+        };
+
+        let line = format!(
+            "{host} - - [{}] \"{} {} {}\" {} {bytes}\n",
+            clf_time(SystemTime::now()),
+            request.method,
+            request.target(),
+            request.version.as_str(),
+            response.status,
+        );
+
+        // formatted before taking the lock so that workers only wait on
+        // each other for the write itself
+        let mut writer = self.writer.lock().unwrap();
+        writer.write_all(line.as_bytes())?;
+        writer.flush()
+    }
+}
+
+// answers the requests sent over `stream` one after the other with what
+// `respond` makes of them, for as long as the connection is kept open, see
+// keep_alive(), every response gets a Connection header telling the client
+// whether it is, a handler can close the connection by setting
+// `Connection: close` itself, the error is only for failing to write a
+// response
+//
+// `peer` is the address of the client at the other end, which every request
+// read gets, see Request::peer_addr(), e.g.:-
+//
+// server::serve_connection(&stream, stream.peer_addr().ok(), respond)
+//
+// the request line and the headers of a request are limited the
+// ParseLimits::default() way and its body to MAX_BODY_SIZE bytes, see
+// serve_connection_limited() for other limits
+pub fn serve_connection<S, F>(stream: S, peer: Option<SocketAddr>, respond: F) -> io::Result<()>
+where
+    S: Read + Write,
+    F: FnMut(&mut Request) -> Response,
+{
+    serve_connection_limited(
+        stream,
+        peer,
+        &ParseLimits::default(),
+        |_| MAX_BODY_SIZE,
+        respond,
+    )
+}
+
+// same as serve_connection() but the request line and the headers of every
+// request are limited by `parse_limits` and its body can be up to what
+// `max_body_size` says for it once its headers are read, e.g. the limit of
+// the route it's for:-
+//
+// server::serve_connection_limited(&stream, peer, &config.parse_limits, |request| router.body_limit(request), respond)
+//
+// a request with a longer request line is answered with a 414, one with
+// larger headers with a 431 and one with a larger body with a 413, the
+// connection is closed either way, see read_request_limited()
+pub fn serve_connection_limited<S, L, F>(
+    stream: S,
+    peer: Option<SocketAddr>,
+    parse_limits: &ParseLimits,
+    max_body_size: L,
+    mut respond: F,
+) -> io::Result<()>
+where
+    S: Read + Write,
+    L: Fn(&Request) -> u64,
+    F: FnMut(&mut Request) -> Response,
+{
+    // kept for the whole connection rather than made per request, a client
+    // may send its next request before the last one is answered, which can
+    // be sitting in the buffer already
+    let mut reader = BufReader::new(stream);
+
+    loop {
+        let Some(mut request) = read_request_limited(&mut reader, parse_limits, &max_body_size)?
+        else {
+            return Ok(());
+        };
+        request.set_peer_addr(peer);
+
+        let mut response = respond(&mut request);
+        let keep_alive = keep_alive(&request) && !connection_option(&response.headers, "close");
+        let connection = if keep_alive { "keep-alive" } else { "close" };
+        response.headers.insert("Connection", connection);
+
+        response.write_for(request.method, reader.get_mut())?;
+
+        if !keep_alive {
+            return Ok(());
+        }
+    }
+}
+
+// whether the client wants the connection kept open after `request` is
+// answered, HTTP/1.0 clients close it unless they ask otherwise with
+// `Connection: keep-alive`, HTTP/1.1 clients keep it open unless they send
+// `Connection: close`
+pub fn keep_alive(request: &Request) -> bool {
+    match request.version {
+        HttpVersion::Http10 => connection_option(&request.headers, "keep-alive"),
+        HttpVersion::Http11 => !connection_option(&request.headers, "close"),
+    }
+}
+
+// whether `option` is listed in the Connection header(s), which hold a
+// comma separated list of case-insensitive options, e.g. `keep-alive, Upgrade`
+fn connection_option(headers: &HeaderMap, option: &str) -> bool {
+    headers
+        .get_all("Connection")
+        .flat_map(|value| value.split(','))
+        .any(|token| token.trim().eq_ignore_ascii_case(option))
+}
+
+// reads the next request sent over the connection `reader` reads from, one
+// that can't be parsed is answered right away and None returned, see
+// bad_request(), the error is only for failing to write that answer, a
+// client waiting for approval to send the body(Request::expects_continue())
+// gets a `100 Continue` once the headers turn out fine, and the error
+// response instead of it otherwise
+pub fn read_request<S: Read + Write>(reader: &mut BufReader<S>) -> io::Result<Option<Request>> {
+    read_request_limited(reader, &ParseLimits::default(), |_| MAX_BODY_SIZE)
+}
+
+// same as read_request() but with a request line and headers within
+// `parse_limits` and a body of up to what `max_body_size` says for the
+// request, a request line going past its limit is answered with a 414 and
+// headers going past theirs with a 431 as soon as they do, a larger
+// body with a 413, before reading any of it when its length is sent
+// upfront and as soon as a chunked one goes past the limit otherwise, the
+// rest of the request is left unread so the connection has to be closed
+pub fn read_request_limited<S, L>(
+    reader: &mut BufReader<S>,
+    parse_limits: &ParseLimits,
+    max_body_size: L,
+) -> io::Result<Option<Request>>
+where
+    S: Read + Write,
+    L: Fn(&Request) -> u64,
+{
+    let request = Request::parse_head_limited(reader, parse_limits).and_then(|mut request| {
+        let max_body_size = max_body_size(&request);
+        request.check_body_size(max_body_size)?;
+
+        if request.expects_continue() {
+            // failing to write it leaves nobody to answer, like failing to
+            // read the request does
+            Response::new(100)
+                .write_to(reader.get_mut())
+                .map_err(ParseError::Io)?;
+        }
+
+        request.read_body_limited(reader, parse_limits, max_body_size)?;
+        Ok(request)
+    });
+
+    match request {
+        Ok(request) => Ok(Some(request)),
+        Err(err) => {
+            if let Some(response) = bad_request(&err) {
+                response.write_to(reader.get_mut())?;
+            }
+            Ok(None)
+        }
+    }
+}
+
+// the answer to a request that failed to parse with `err`, a 501 for a
+// method or transfer encoding the server doesn't implement, a 505 for an
+// http version it doesn't speak, a 411 for a body of unknown length, a 413
+// for a body that's too large, a 414 for a request line that is, a 431 for
+// headers that are, a 417 for an expectation it can't meet and a 400 for
+// anything else the client got wrong, None when the connection was
+// closed without a request(port scanners and health checks do this all the
+// time) or reading from it failed, there's no one left to answer then, the
+// connection is always closed after it, what's left of the request can't be
+// told apart from the next one, the response says so with
+// `Connection: close`
+pub fn bad_request(err: &ParseError) -> Option<Response> {
+    let status = match err {
+        ParseError::Empty => return None,
+        // not valid UTF-8, the client sent something other than http
+        ParseError::Io(io_err) if io_err.kind() == io::ErrorKind::InvalidData => 400,
+        ParseError::Io(_) => return None,
+        ParseError::UnsupportedMethod(_) | ParseError::UnsupportedTransferEncoding(_) => 501,
+        ParseError::UnsupportedVersion(_) => 505,
+        ParseError::LengthRequired(_) => 411,
+        ParseError::BodyTooLarge(_) => 413,
+        ParseError::UriTooLong(_) => 414,
+        ParseError::HeaderLineTooLong(_)
+        | ParseError::TooManyHeaders(_)
+        | ParseError::HeadersTooLarge(_) => 431,
+        ParseError::UnsupportedExpectation(_) => 417,
+        ParseError::MalformedRequestLine
+        | ParseError::MalformedHeader(_)
+        | ParseError::InvalidContentLength(_)
+        | ParseError::MalformedChunk(_)
+        | ParseError::ChunkLineTooLong(_)
+        | ParseError::InvalidPath(_) => 400,
+    };
+
+    Some(
+        Response::new(status)
+            .with_header("Content-Type", "text/plain")
+            .with_header("Connection", "close")
+            .with_body(format!("{err}\n")),
+    )
+}
+
+// formats `time` the way the Common Log Format wants it, always in UTC,
+// e.g. 16/Oct/2026:13:55:36 +0000
+fn clf_time(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+
+    // turns days since 1970-01-01 into a calendar date, works in 400 year
+    // eras starting on march 1st, so that the leap day is the last day of
+    // the year, see http://howardhinnant.github.io/date_algorithms.html
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    // month_index counts from march
+    let month = (month_index + 2) % 12;
+    let year = year_of_era + era * 400 + u64::from(month < 2);
+
+    format!(
+        "{day:02}/{}/{year}:{:02}:{:02}:{:02} +0000",
+        MONTHS[month as usize],
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreadPool;
+    use std::{
+        env, fs,
+        net::{TcpListener, TcpStream},
+        process, thread,
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn connection_limiter_rejects_past_the_limit() {
+        let limiter = Arc::new(ConnectionLimiter::new(2, ConnectionPolicy::Reject));
+        let first = limiter.acquire().unwrap();
+        let _second = limiter.acquire().unwrap();
+        assert_eq!(limiter.active(), 2);
+        assert!(limiter.acquire().is_none());
+
+        drop(first);
+        assert_eq!(limiter.active(), 1);
+        assert!(limiter.acquire().is_some());
+    }
+
+    #[test]
+    fn connection_limiter_blocks_past_the_limit() {
+        let pool = ThreadPool::new(2);
+        let limiter = Arc::new(ConnectionLimiter::new(1, ConnectionPolicy::Block));
+
+        let permit = limiter.acquire().unwrap();
+        pool.execute(move || {
+            thread::sleep(Duration::from_millis(100));
+            drop(permit);
+        });
+
+        let started = Instant::now();
+        let _permit = limiter.acquire().unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(90));
+        assert_eq!(limiter.active(), 1);
+    }
+
+    #[test]
+    fn connection_permit_released_by_a_panicking_job() {
+        let pool = ThreadPool::new(1);
+        let limiter = Arc::new(ConnectionLimiter::new(1, ConnectionPolicy::Reject));
+
+        let permit = limiter.acquire().unwrap();
+        pool.execute(move || {
+            let _permit = permit;
+            panic!("connection handler panicked");
+        });
+        pool.join();
+
+        assert_eq!(limiter.active(), 0);
+        assert!(limiter.acquire().is_some());
+    }
+
+    #[test]
+    fn rate_limiter_turns_away_past_the_burst() {
+        // slow enough that no token comes back while the test runs
+        let limiter = RateLimiter::new(1, 5);
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+
+        for _ in 0..5 {
+            assert_eq!(limiter.check(client), Ok(()));
+        }
+
+        let retry_after = limiter.check(client).unwrap_err();
+        assert!(retry_after > Duration::from_millis(900) && retry_after <= Duration::from_secs(1));
+
+        // every client has a bucket of its own
+        assert_eq!(limiter.check("10.0.0.2".parse().unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn rate_limiter_refills() {
+        let limiter = RateLimiter::new(100, 1);
+        let client: IpAddr = "::1".parse().unwrap();
+
+        assert_eq!(limiter.check(client), Ok(()));
+        assert!(limiter.check(client).is_err());
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(limiter.check(client), Ok(()));
+    }
+
+    #[test]
+    fn too_many_requests() {
+        let response = RateLimiter::too_many_requests(Duration::from_millis(1500));
+        assert_eq!(response.status, 429);
+        assert_eq!(response.headers.get("Retry-After"), Some("2"));
+
+        let response = RateLimiter::too_many_requests(Duration::from_millis(10));
+        assert_eq!(response.headers.get("Retry-After"), Some("1"));
+    }
+
+    // a connection whose client sends `input`, keeps what the server writes
+    struct Connection {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Connection {
+        fn new(input: &[u8]) -> Connection {
+            Connection {
+                input: io::Cursor::new(input.to_vec()),
+                output: Vec::new(),
+            }
+        }
+
+        fn written(&self) -> String {
+            String::from_utf8_lossy(&self.output).into_owned()
+        }
+    }
+
+    impl Read for Connection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Connection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn request_read() {
+        let mut reader = BufReader::new(Connection::new(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"));
+        let request = read_request(&mut reader).unwrap().unwrap();
+
+        assert_eq!(request.path, "/");
+        assert!(reader.get_ref().output.is_empty());
+    }
+
+    #[test]
+    fn empty_request_left_unanswered() {
+        let mut reader = BufReader::new(Connection::new(b""));
+
+        assert!(read_request(&mut reader).unwrap().is_none());
+        assert!(reader.get_ref().output.is_empty());
+    }
+
+    #[test]
+    fn malformed_requests_answered_with_400() {
+        for input in [
+            &b"\x16\x03\x01\x02\x00\x01\x00\x01\xfc\x03\x03\r\n\r\n"[..],
+            b"hello there\r\n\r\n",
+            b"GET /\r\n\r\n",
+            b"GET / HTTP/1.1\r\nno colon\r\n\r\n",
+        ] {
+            let mut reader = BufReader::new(Connection::new(input));
+
+            assert!(read_request(&mut reader).unwrap().is_none());
+            let written = reader.get_ref().written();
+            assert!(
+                written.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+                "{written:?}"
+            );
+            assert!(written.contains("\r\nContent-Type: text/plain\r\n"));
+            // the connection is closed after it, which the response says
+            assert!(written.contains("\r\nConnection: close\r\n"));
+        }
+    }
+
+    #[test]
+    fn unsupported_method_and_version() {
+        let mut reader = BufReader::new(Connection::new(b"BREW /pot HTTP/1.1\r\n\r\n"));
+        assert!(read_request(&mut reader).unwrap().is_none());
+        assert!(reader
+            .get_ref()
+            .written()
+            .starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+
+        let mut reader = BufReader::new(Connection::new(b"GET / HTTP/3\r\n\r\n"));
+        assert!(read_request(&mut reader).unwrap().is_none());
+        assert!(reader
+            .get_ref()
+            .written()
+            .starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+
+        let mut reader = BufReader::new(Connection::new(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n",
+        ));
+        assert!(read_request(&mut reader).unwrap().is_none());
+        assert!(reader
+            .get_ref()
+            .written()
+            .starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+    }
+
+    #[test]
+    fn malformed_and_too_large_chunked_bodies() {
+        let mut reader = BufReader::new(Connection::new(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n",
+        ));
+        assert!(read_request(&mut reader).unwrap().is_none());
+        assert!(reader
+            .get_ref()
+            .written()
+            .starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+        let mut reader = BufReader::new(Connection::new(
+            b"POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nfffffffff\r\n",
+        ));
+        assert!(read_request(&mut reader).unwrap().is_none());
+        assert!(reader
+            .get_ref()
+            .written()
+            .starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    }
+
+    #[test]
+    fn conflicting_content_lengths() {
+        let (answered, written) = serve(
+            "POST /a HTTP/1.1\r\nContent-Length: 0\r\nContent-Length: 29\r\n\r\n\
+             GET /smuggled HTTP/1.1\r\n\r\n",
+            ok,
+        );
+
+        assert!(answered.is_empty());
+        assert!(written.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert_eq!(written.matches("HTTP/1.1").count(), 1);
+    }
+
+    #[test]
+    fn content_length_along_with_chunked() {
+        let (answered, written) = serve(
+            "POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 40\r\n\r\n\
+             0\r\n\r\nGET /smuggled HTTP/1.1\r\n\r\n",
+            ok,
+        );
+
+        assert!(answered.is_empty());
+        assert!(written.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert_eq!(written.matches("HTTP/1.1").count(), 1);
+
+        let (answered, written) = serve(
+            "POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\nTransfer-Encoding: identity\r\n\r\n\
+             0\r\n\r\nGET /smuggled HTTP/1.1\r\n\r\n",
+            ok,
+        );
+
+        assert!(answered.is_empty());
+        assert!(written.starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+        assert_eq!(written.matches("HTTP/1.1").count(), 1);
+    }
+
+    // a client that sends `head` right away and `body` only once the server
+    // answered with `100 Continue`, reading before then fails the way a
+    // read timing out would
+    struct Expecting {
+        head: io::Cursor<Vec<u8>>,
+        body: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Expecting {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.head.position() < self.head.get_ref().len() as u64 {
+                return self.head.read(buf);
+            }
+            if !self.output.starts_with(b"HTTP/1.1 100 Continue\r\n\r\n") {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            self.body.read(buf)
+        }
+    }
+
+    impl Write for Expecting {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn continue_sent_before_reading_the_body() {
+        let mut reader = BufReader::new(Expecting {
+            head: io::Cursor::new(
+                b"PUT /upload HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 5\r\n\r\n"
+                    .to_vec(),
+            ),
+            body: io::Cursor::new(b"hello".to_vec()),
+            output: Vec::new(),
+        });
+
+        let request = read_request(&mut reader).unwrap().unwrap();
+        assert_eq!(request.body(), b"hello");
+        assert_eq!(reader.get_ref().output, b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    #[test]
+    fn expectation_not_met() {
+        // refused before the client sends the body
+        let mut reader = BufReader::new(Connection::new(
+            format!(
+                "PUT /upload HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: {}\r\n\r\n",
+                MAX_BODY_SIZE + 1
+            )
+            .as_bytes(),
+        ));
+        assert!(read_request(&mut reader).unwrap().is_none());
+        assert!(reader
+            .get_ref()
+            .written()
+            .starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+
+        let mut reader = BufReader::new(Connection::new(
+            b"PUT /upload HTTP/1.1\r\nExpect: 200-ok\r\nContent-Length: 5\r\n\r\nhello",
+        ));
+        assert!(read_request(&mut reader).unwrap().is_none());
+        assert!(reader
+            .get_ref()
+            .written()
+            .starts_with("HTTP/1.1 417 Expectation Failed\r\n"));
+    }
+
+    #[test]
+    fn continue_only_when_expected() {
+        for input in [
+            // no body to wait for
+            &b"GET / HTTP/1.1\r\nExpect: 100-continue\r\n\r\n"[..],
+            // ignored for an HTTP/1.0 client, whatever it asks for
+            b"PUT / HTTP/1.0\r\nExpect: 100-continue\r\nContent-Length: 2\r\n\r\nhi",
+            b"PUT / HTTP/1.0\r\nExpect: 200-ok\r\nContent-Length: 2\r\n\r\nhi",
+        ] {
+            let mut reader = BufReader::new(Connection::new(input));
+            assert!(read_request(&mut reader).unwrap().is_some());
+            assert!(reader.get_ref().output.is_empty());
+        }
+    }
+
+    // serves `input` as a connection, answering every request with
+    // `respond`, returns the paths of the requests answered and what was
+    // written back
+    fn serve(input: &str, respond: fn(&Request) -> Response) -> (Vec<String>, String) {
+        let mut connection = Connection::new(input.as_bytes());
+        let mut answered = Vec::new();
+
+        serve_connection(&mut connection, None, |request| {
+            answered.push(request.path.clone());
+            respond(request)
+        })
+        .unwrap();
+
+        (answered, connection.written())
+    }
+
+    #[test]
+    fn handler_sees_the_peer_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            (stream.local_addr().unwrap(), response)
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        let mut seen = None;
+        serve_connection(&stream, stream.peer_addr().ok(), |request| {
+            seen = request.peer_addr();
+            Response::new(200)
+        })
+        .unwrap();
+        drop(stream);
+
+        let (client_address, response) = client.join().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let seen = seen.unwrap();
+        assert!(seen.ip().is_loopback());
+        assert_eq!(seen, client_address);
+
+        // nothing to tell for a request that didn't come over a connection
+        let request = Request::parse(&mut &b"GET / HTTP/1.1\r\n\r\n"[..]).unwrap();
+        assert_eq!(request.peer_addr(), None);
+    }
+
+    fn ok(_: &Request) -> Response {
+        Response::new(200)
+    }
+
+    fn echo_body(request: &Request) -> Response {
+        Response::new(200).with_body(format!("[{}]", request.body_string().unwrap()))
+    }
+
+    #[test]
+    fn bodies_read_by_their_content_length() {
+        let (answered, written) = serve(
+            "POST /a HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello\
+             PUT /b HTTP/1.1\r\nContent-Length: 0\r\n\r\n\
+             GET /c HTTP/1.1\r\nConnection: close\r\n\r\n",
+            echo_body,
+        );
+
+        // neither body is taken for the start of the next request
+        assert_eq!(answered, ["/a", "/b", "/c"]);
+        assert_eq!(
+            written
+                .split("\r\n\r\n")
+                .skip(1)
+                .map(|rest| rest.split("HTTP/1.1").next().unwrap())
+                .collect::<Vec<_>>(),
+            ["[hello]", "[]", "[]"]
+        );
+    }
+
+    #[test]
+    fn body_of_unknown_length() {
+        let (answered, written) = serve("POST /a HTTP/1.1\r\n\r\nhello", ok);
+
+        assert!(answered.is_empty());
+        // the body isn't taken for the next request either, the connection
+        // is closed
+        assert!(written.starts_with("HTTP/1.1 411 Length Required\r\n"));
+        assert_eq!(written.matches("HTTP/1.1").count(), 1);
+
+        let (answered, written) = serve("POST /a HTTP/1.1\r\nContent-Length: five\r\n\r\n", ok);
+        assert!(answered.is_empty());
+        assert!(written.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    #[test]
+    fn body_shorter_than_its_content_length() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            stream
+                .write_all(b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nhello")
+                .unwrap();
+            // the rest of the body never comes, the connection stays open
+            // till the server gives up on it
+            let mut response = Vec::new();
+            stream.read_to_end(&mut response).unwrap();
+            response
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let started_at = Instant::now();
+        let mut answered = false;
+        serve_connection(&stream, None, |_| {
+            answered = true;
+            Response::new(200)
+        })
+        .unwrap();
+        drop(stream);
+
+        assert!(!answered);
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+        // there's no telling what the client meant to send, it's only hung
+        // up on
+        assert!(client.join().unwrap().is_empty());
+    }
+
+    #[test]
+    fn request_line_too_long() {
+        let input = format!(
+            "GET /{} HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n",
+            "a".repeat(1024 * 1024)
+        );
+        let mut connection = Connection::new(input.as_bytes());
+        let mut answered = false;
+        serve_connection(&mut connection, None, |_| {
+            answered = true;
+            Response::new(200)
+        })
+        .unwrap();
+
+        assert!(!answered);
+        let written = connection.written();
+        assert!(written.starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+        // the connection is closed without reading the rest of it
+        assert_eq!(written.matches("HTTP/1.1").count(), 1);
+        assert!(connection.input.position() < 64 * 1024);
+    }
+
+    #[test]
+    fn headers_too_large() {
+        let input = format!(
+            "GET /a HTTP/1.1\r\nX-Huge: {}\r\n\r\nGET /b HTTP/1.1\r\n\r\n",
+            "a".repeat(1024 * 1024)
+        );
+        let (answered, written) = serve(&input, ok);
+
+        assert!(answered.is_empty());
+        assert!(written.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        // the rest of the request is never read, the connection is closed
+        assert_eq!(written.matches("HTTP/1.1").count(), 1);
+        assert_eq!(written.matches("\r\nConnection: close\r\n").count(), 1);
+
+        let headers: String = (0..500).map(|n| format!("X-Header-{n}: {n}\r\n")).collect();
+        let (answered, written) = serve(
+            &format!("GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n{headers}\r\n"),
+            ok,
+        );
+        assert_eq!(answered, ["/a"]);
+        let (_, rejected) = written.split_at(written.rfind("HTTP/1.1").unwrap());
+        assert!(rejected.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        assert_eq!(written.matches("HTTP/1.1").count(), 2);
+    }
+
+    #[test]
+    fn chunked_body_framing_too_large() {
+        let chunked = "POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n";
+
+        let input = format!("{chunked}3;{}\r\nabc\r\n0\r\n\r\n", "x".repeat(1024 * 1024));
+        let mut connection = Connection::new(input.as_bytes());
+        serve_connection(&mut connection, None, |_| Response::new(200)).unwrap();
+        assert!(connection
+            .written()
+            .starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(connection.input.position() < 64 * 1024);
+
+        let trailers: String = (0..500)
+            .map(|n| format!("X-Trailer-{n}: {n}\r\n"))
+            .collect();
+        let (answered, written) = serve(&format!("{chunked}3\r\nabc\r\n0\r\n{trailers}\r\n"), ok);
+        assert!(answered.is_empty());
+        assert!(written.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+        assert_eq!(written.matches("HTTP/1.1").count(), 1);
+    }
+
+    #[test]
+    fn body_larger_than_its_limit() {
+        let serve_limited = |input: &str| {
+            let mut connection = Connection::new(input.as_bytes());
+            let mut answered = Vec::new();
+            // bodies of up to 5 bytes, 10 for `/upload`
+            let max_body_size = |request: &Request| if request.path == "/upload" { 10 } else { 5 };
+
+            serve_connection_limited(
+                &mut connection,
+                None,
+                &ParseLimits::default(),
+                max_body_size,
+                |request| {
+                    answered.push(request.path.clone());
+                    echo_body(request)
+                },
+            )
+            .unwrap();
+
+            (answered, connection.written())
+        };
+
+        let (answered, written) = serve_limited(
+            "POST /upload HTTP/1.1\r\nContent-Length: 10\r\n\r\nhelloworld\
+             POST /a HTTP/1.1\r\nContent-Length: 10\r\n\r\nhelloworld\
+             GET /b HTTP/1.1\r\n\r\n",
+        );
+        assert_eq!(answered, ["/upload"]);
+        assert!(written.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(written.contains("[helloworld]"));
+        // the body is left unread, the connection is closed
+        let (_, rejected) = written.split_at(written.rfind("HTTP/1.1").unwrap());
+        assert!(rejected.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+        assert_eq!(written.matches("HTTP/1.1").count(), 2);
+
+        // a client waiting to be told to send the body is told off instead
+        let (answered, written) =
+            serve_limited("PUT /a HTTP/1.1\r\nExpect: 100-continue\r\nContent-Length: 10\r\n\r\n");
+        assert!(answered.is_empty());
+        assert!(written.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+        assert!(!written.contains("100 Continue"));
+
+        // a chunked body past the limit
+        let (answered, written) = serve_limited(
+            "POST /a HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+             3\r\nabc\r\n3\r\ndef\r\n0\r\n\r\n\
+             GET /b HTTP/1.1\r\n\r\n",
+        );
+        assert!(answered.is_empty());
+        assert!(written.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+        assert_eq!(written.matches("HTTP/1.1").count(), 1);
+    }
+
+    #[test]
+    fn http10_connection_closed_by_default() {
+        let (answered, written) = serve("GET /a HTTP/1.0\r\n\r\nGET /b HTTP/1.0\r\n\r\n", ok);
+
+        // the second request is never read
+        assert_eq!(answered, ["/a"]);
+        assert_eq!(written.matches("HTTP/1.1 200 OK\r\n").count(), 1);
+        assert!(written.contains("\r\nConnection: close\r\n"));
+
+        let (answered, written) = serve(
+            "GET /a HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\nGET /b HTTP/1.0\r\n\r\n",
+            ok,
+        );
+        assert_eq!(answered, ["/a", "/b"]);
+        let (first, second) = written.split_at(written.rfind("HTTP/1.1").unwrap());
+        assert!(first.contains("\r\nConnection: keep-alive\r\n"));
+        assert!(second.contains("\r\nConnection: close\r\n"));
+    }
+
+    #[test]
+    fn http11_connection_kept_open_by_default() {
+        let (answered, written) = serve(
+            "GET /a HTTP/1.1\r\n\r\n\
+             POST /b HTTP/1.1\r\nContent-Length: 5\r\n\r\nhello\
+             GET /c HTTP/1.1\r\nConnection: upgrade, close\r\n\r\n\
+             GET /d HTTP/1.1\r\n\r\n",
+            ok,
+        );
+
+        assert_eq!(answered, ["/a", "/b", "/c"]);
+        assert_eq!(written.matches("\r\nConnection: keep-alive\r\n").count(), 2);
+        assert!(written.ends_with("\r\nConnection: close\r\nContent-Length: 0\r\n\r\n"));
+
+        // the client closing the connection between requests ends it as well
+        let (answered, _) = serve("GET /a HTTP/1.1\r\n\r\n", ok);
+        assert_eq!(answered, ["/a"]);
+    }
+
+    #[test]
+    fn connection_closed_by_the_response() {
+        let (answered, written) = serve("GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\n", |_| {
+            Response::new(200).with_header("Connection", "close")
+        });
+
+        assert_eq!(answered, ["/a"]);
+        assert_eq!(written.matches("\r\nConnection: ").count(), 1);
+    }
+
+    #[test]
+    fn access_log_line() {
+        let path = env::temp_dir().join(format!("hello-access-{}.log", process::id()));
+        let _ = fs::remove_file(&path);
+        let log = AccessLog::open(&path).unwrap();
+
+        let request = Request::parse(&mut &b"GET /sleep?s=1 HTTP/1.1\r\n\r\n"[..]).unwrap();
+        let host = "127.0.0.1".parse().ok();
+        log.log(host, &request, &Response::new(200).with_body("hello"))
+            .unwrap();
+
+        let request = Request::parse(&mut &b"HEAD / HTTP/1.0\r\n\r\n"[..]).unwrap();
+        let host = "::1".parse().ok();
+        log.log(host, &request, &Response::new(200).with_body("hello"))
+            .unwrap();
+
+        let logged = fs::read_to_string(&path).unwrap();
+        let lines = logged.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("127.0.0.1 - - ["));
+        assert!(lines[0].ends_with(" +0000] \"GET /sleep?s=1 HTTP/1.1\" 200 5"));
+        assert!(lines[1].starts_with("::1 - - ["));
+        assert!(lines[1].ends_with("] \"HEAD / HTTP/1.0\" 200 -"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn common_log_format_time() {
+        let at = |seconds| clf_time(UNIX_EPOCH + Duration::from_secs(seconds));
+
+        assert_eq!(at(0), "01/Jan/1970:00:00:00 +0000");
+        assert_eq!(at(1_709_251_199), "29/Feb/2024:23:59:59 +0000");
+        assert_eq!(at(1_792_158_936), "16/Oct/2026:13:55:36 +0000");
+    }
+}
@@ -0,0 +1,85 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// a job waiting for a worker, as listed by ThreadPool::queue_snapshot()
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueuedJobInfo {
+    // see ThreadPool::execute_named(), None for jobs submitted without one
+    pub name: Option<String>,
+    pub queued_at: Instant,
+    // how long it had been waiting when the snapshot was taken
+    pub waited: Duration,
+}
+
+// what's known about every job counted as queued, kept next to the queue
+// rather than in it since the default queue(a channel) can't be looked
+// into, every job gets a sequence number when it's queued, which is what
+// it's taken out by once a worker picks it up
+//
+// numbers are handed out with the lock held, so the entries are always in
+// the order of their numbers, i.e. oldest first, and since workers mostly
+// pick up the oldest job the one to take out is almost always the first
+pub(crate) struct PendingJobs {
+    state: Mutex<PendingState>,
+}
+
+#[derive(Default)]
+struct PendingState {
+    next_seq: u64,
+    jobs: VecDeque<(u64, Option<Arc<str>>, Instant)>,
+}
+
+impl PendingJobs {
+    pub(crate) fn new() -> PendingJobs {
+        PendingJobs {
+            state: Mutex::new(PendingState::default()),
+        }
+    }
+
+    // returns the sequence number of the job, to be passed to remove()
+    pub(crate) fn add(&self, name: Option<Arc<str>>, queued_at: Instant) -> u64 {
+        let mut state = self.lock();
+        let seq = state.next_seq;
+
+        state.next_seq += 1;
+        state.jobs.push_back((seq, name, queued_at));
+        seq
+    }
+
+    pub(crate) fn remove(&self, seq: u64) {
+        let mut state = self.lock();
+
+        if let Ok(index) = state.jobs.binary_search_by_key(&seq, |(seq, ..)| *seq) {
+            state.jobs.remove(index);
+        }
+    }
+
+    pub(crate) fn clear(&self) {
+        self.lock().jobs.clear();
+    }
+
+    pub(crate) fn snapshot(&self) -> Vec<QueuedJobInfo> {
+        let now = Instant::now();
+
+        self.lock()
+            .jobs
+            .iter()
+            .map(|(_, name, queued_at)| QueuedJobInfo {
+                name: name.as_deref().map(str::to_owned),
+                queued_at: *queued_at,
+                waited: now.saturating_duration_since(*queued_at),
+            })
+            .collect()
+    }
+
+    // doesn't unwrap the lock, jobs are taken out while unwinding from a
+    // panicking job when the jobs it left behind are handed back
+    fn lock(&self) -> std::sync::MutexGuard<'_, PendingState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
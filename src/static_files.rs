@@ -0,0 +1,548 @@
+use crate::{
+    http::{percent_decode, percent_encode, Request, Response},
+    mime::MimeTypes,
+};
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+// serves the files inside a root directory, e.g.:-
+//
+// let files = StaticFiles::new("public").directory_listing(true);
+// let response = files.serve("/docs/")?;
+//
+// or mounted on a route capturing the rest of the path as `*path`, see
+// handle():-
+//
+// let router = Router::new().get("/static/*path", move |request| files.handle(request));
+//
+// a request for a directory gets the directory's index.html, or when it has
+// none, a generated page listing its entries if directory_listing() is on,
+// it's off by default since a listing can reveal files that were never
+// meant to be linked to, e.g. backups left next to the real files, a
+// request for a directory without the trailing slash, e.g. `/docs`, is
+// redirected to `/docs/` first, links relative to the page, like the ones
+// of a listing or of most index.html pages, would point into the parent
+// directory otherwise
+//
+// files are served with a Content-Type going by their extension, see
+// mime_type()
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+    directory_listing: bool,
+    show_hidden: bool,
+    mime_types: MimeTypes,
+}
+
+impl StaticFiles {
+    pub fn new(root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles {
+            root: root.into(),
+            directory_listing: false,
+            show_hidden: false,
+            mime_types: MimeTypes::new(),
+        }
+    }
+
+    pub fn directory_listing(mut self, directory_listing: bool) -> StaticFiles {
+        self.directory_listing = directory_listing;
+        self
+    }
+
+    // lists the entries whose name starts with a dot as well, e.g.
+    // `.htaccess` or `.git/`, they're left out of listings by default, it
+    // doesn't change what's served, a hidden file can still be requested by
+    // its name
+    pub fn show_hidden(mut self, show_hidden: bool) -> StaticFiles {
+        self.show_hidden = show_hidden;
+        self
+    }
+
+    // serves files with `extension` as `media_type`, on top of the ones
+    // known already(see mime::for_path()) or instead of one of them, e.g.:-
+    //
+    // StaticFiles::new("public").mime_type("md", "text/markdown; charset=utf-8")
+    pub fn mime_type(mut self, extension: &str, media_type: &str) -> StaticFiles {
+        self.mime_types.add(extension, media_type);
+        self
+    }
+
+    // response for the file or directory `request_path` maps to inside the
+    // root, see safe_join(), 404 when there's nothing there and 403 for a
+    // directory without an index.html when listings are off or one the
+    // server isn't allowed to read, any other error reading from the disk
+    // is returned for the router to answer, see Router::on_error()
+    pub fn serve(&self, request_path: &str) -> io::Result<Response> {
+        let Some(path) = safe_join(&self.root, request_path) else {
+            return Ok(Response::new(404));
+        };
+
+        if !path.is_dir() {
+            return self.file_response(&path);
+        }
+
+        // a relative location is resolved against the url the client asked
+        // for, which keeps this working wherever the files are mounted, the
+        // `./` keeps a name like `a:b` from being taken for a scheme
+        if !request_path.ends_with('/') {
+            let name = request_path.rsplit('/').next().unwrap_or_default();
+            return Ok(Response::redirect(301, &format!("./{name}/")));
+        }
+
+        let index = path.join("index.html");
+
+        if index.is_file() {
+            return self.file_response(&index);
+        }
+
+        if !self.directory_listing {
+            return Ok(Response::new(403));
+        }
+
+        match directory_listing(&path, request_path, self.show_hidden) {
+            Ok(listing) => Ok(Response::new(200)
+                .with_header("Content-Type", "text/html; charset=utf-8")
+                .with_body(listing)),
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => Ok(Response::new(403)),
+            Err(err) => Err(err),
+        }
+    }
+
+    // the file can still go away or turn out to be unreadable after being
+    // found
+    fn file_response(&self, path: &Path) -> io::Result<Response> {
+        let response = Response::new(200)
+            .with_header("Content-Type", self.mime_types.for_path(path))
+            .with_file(path);
+
+        match response {
+            Ok(response) => Ok(response),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Response::new(404)),
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => Ok(Response::new(403)),
+            Err(err) => Err(err),
+        }
+    }
+
+    // serves the part of the path a route captured as `*path`, e.g.
+    // `css/site.css` for `/static/css/site.css` matched to `/static/*path`,
+    // or the whole path when the route captured nothing, e.g. when used for
+    // Router::not_found(), the capture is taken as it was sent since serve()
+    // does the percent-decoding, decoding it twice would turn a `%2525` into
+    // a `%` instead of `%25`
+    pub fn handle(&self, request: &Request) -> io::Result<Response> {
+        match request.raw_param("path") {
+            Some(path) => self.serve(&format!("/{path}")),
+            None => self.serve(request.raw_path()),
+        }
+    }
+}
+
+// html page linking to every entry of the directory at `path`, sorted by
+// name, with subdirectories marked by a trailing slash, the links are
+// relative to the directory, which the request path always ends in a slash
+// for, see StaticFiles
+fn directory_listing(path: &Path, request_path: &str, show_hidden: bool) -> io::Result<String> {
+    let mut entries = Vec::new();
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        // names which aren't valid UTF-8 can't be part of a link that
+        // safe_join() would map back onto the same file
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+
+        if name.starts_with('.') && !show_hidden {
+            continue;
+        }
+
+        entries.push((name, entry.file_type()?.is_dir()));
+    }
+
+    entries.sort();
+
+    let title = html_escape(request_path);
+    let mut listing = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n  <head>\n    <meta charset=\"utf-8\">\n    \
+         <title>Index of {title}</title>\n  </head>\n  <body>\n    <h1>Index of {title}</h1>\n    \
+         <ul>\n"
+    );
+
+    for (name, is_dir) in entries {
+        let slash = if is_dir { "/" } else { "" };
+
+        listing.push_str(&format!(
+            "      <li><a href=\"{}{slash}\">{}{slash}</a></li>\n",
+            percent_encode(&name),
+            html_escape(&name),
+        ));
+    }
+
+    listing.push_str("    </ul>\n  </body>\n</html>\n");
+    Ok(listing)
+}
+
+// file names can contain characters that mean something in html, a file
+// named `<script>...` must show up as text rather than run
+fn html_escape(input: &str) -> String {
+    let mut escaped = String::with_capacity(input.len());
+
+    for c in input.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+// maps the path segment of a request url onto a file inside `root`, returns
+// None whenever the result could end up outside of `root` or doesn't exist,
+// the caller is expected to respond with a 404 in that case without telling
+// the client why, e.g.:-
+//
+// safe_join(root, "/docs/index.html") => Some(<root>/docs/index.html)
+// safe_join(root, "/../../etc/passwd") => None
+// safe_join(root, "/%2e%2e/etc/passwd") => None
+//
+// the request path is percent-decoded first, so encoded `..` segments are
+// caught as well, then every segment must be a plain file or directory name,
+// `..`, `.` and anything that is absolute on its own(a drive prefix like
+// `C:` on windows) are rejected instead of being resolved, because a
+// segment like that passed to Path::join() would replace or climb out of
+// the root instead of being appended to it
+pub fn safe_join(root: &Path, request_path: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(request_path)?;
+
+    // NUL can't be part of a file name and backslashes are separators on
+    // windows, there's no legitimate url that needs either of them
+    if decoded.contains(['\0', '\\']) {
+        return None;
+    }
+
+    let mut path = root.to_path_buf();
+
+    // empty segments come from leading, trailing or duplicate slashes and
+    // are skipped, `/docs//index.html` is the same file as `/docs/index.html`
+    for segment in decoded.split('/').filter(|segment| !segment.is_empty()) {
+        let mut components = Path::new(segment).components();
+
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) => path.push(name),
+            _ => return None,
+        }
+    }
+
+    // the checks above only look at the text of the path, a symlink inside
+    // the root can still point anywhere on the file system, so the real
+    // location of the file is resolved and has to stay inside the real
+    // location of the root, canonicalize() also fails for missing files
+    let root = root.canonicalize().ok()?;
+    let path = path.canonicalize().ok()?;
+
+    if path.starts_with(&root) {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::router::Router;
+    use std::{env, fs, process};
+
+    // a directory of its own for every test, tests run in parallel
+    fn fixture(name: &str) -> PathBuf {
+        let root = env::temp_dir().join(format!("hello-static-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+
+    // the body of the response as it's sent, files are only read then
+    fn body(response: &Response) -> Vec<u8> {
+        let mut written = Vec::new();
+        response.write_to(&mut written).unwrap();
+
+        let head_end = written.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+        written.split_off(head_end + 4)
+    }
+
+    #[test]
+    fn safe_join_paths() {
+        let root = fixture("safe-join");
+        fs::create_dir(root.join("docs")).unwrap();
+        fs::write(root.join("docs/a b.txt"), "").unwrap();
+        let root = root.canonicalize().unwrap();
+
+        assert_eq!(
+            safe_join(&root, "/docs/a%20b.txt"),
+            Some(root.join("docs/a b.txt"))
+        );
+        assert_eq!(
+            safe_join(&root, "//docs//a%20b.txt"),
+            Some(root.join("docs/a b.txt"))
+        );
+        assert_eq!(safe_join(&root, "/docs/"), Some(root.join("docs")));
+        assert_eq!(safe_join(&root, "/docs/missing.txt"), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn safe_join_traversal() {
+        let root = fixture("safe-join-traversal");
+        fs::create_dir(root.join("public")).unwrap();
+        fs::write(root.join("secret.txt"), "").unwrap();
+        let public = root.join("public");
+
+        assert_eq!(safe_join(&public, "/../secret.txt"), None);
+        assert_eq!(safe_join(&public, "/../../etc/passwd"), None);
+        assert_eq!(safe_join(&public, "/%2e%2e/secret.txt"), None);
+        assert_eq!(safe_join(&public, "/%2E%2E%2fsecret.txt"), None);
+        assert_eq!(safe_join(&public, "/..%5csecret.txt"), None);
+        assert_eq!(safe_join(&public, "/./secret.txt"), None);
+        // invalid encoding
+        assert_eq!(safe_join(&public, "/%zz"), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn safe_join_absolute_path() {
+        let root = fixture("safe-join-absolute");
+        let outside = env::temp_dir();
+
+        // a request path is always made relative to the root, `/tmp` is
+        // looked for inside it rather than taken as the real `/tmp`
+        let absolute = format!("/{}", outside.display());
+        assert_eq!(safe_join(&root, &absolute), None);
+        assert_eq!(safe_join(&root, "/etc/passwd"), None);
+        assert_eq!(safe_join(&root, "/%2fetc/passwd"), None);
+        assert_eq!(safe_join(&root, "/a%00b"), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn safe_join_symlink_escape() {
+        let root = fixture("safe-join-symlink");
+        fs::create_dir(root.join("public")).unwrap();
+        fs::write(root.join("secret.txt"), "").unwrap();
+        std::os::unix::fs::symlink(root.join("secret.txt"), root.join("public/link")).unwrap();
+
+        assert_eq!(safe_join(&root.join("public"), "/link"), None);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn mounted_on_a_route() {
+        let root = fixture("mounted");
+        fs::create_dir_all(root.join("public/css")).unwrap();
+        fs::create_dir(root.join("public/empty")).unwrap();
+        fs::write(root.join("public/css/site.css"), "h1 {}").unwrap();
+        fs::write(root.join("secret.txt"), "secret").unwrap();
+
+        let files = StaticFiles::new(root.join("public"));
+        let router = Router::new().get("/static/*path", move |request| files.handle(request));
+        let get = |target: &str| {
+            let request = format!("GET {target} HTTP/1.1\r\n\r\n");
+            let mut request = Request::parse(&mut request.as_bytes()).unwrap();
+            router.handle(&mut request)
+        };
+
+        let response = get("/static/css/site.css");
+        let mut written = Vec::new();
+        response.write_to(&mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(written.contains("\r\nContent-Length: 5\r\n"));
+        assert!(written.ends_with("\r\n\r\nh1 {}"));
+
+        assert_eq!(get("/static/css/missing.css").status, 404);
+        assert_eq!(get("/static/empty/").status, 403);
+
+        // `..` is resolved while parsing the request, this one never gets
+        // to the route, the encoded ones are caught by safe_join()
+        assert_eq!(get("/static/../../etc/passwd").status, 404);
+        assert_eq!(get("/static/../secret.txt").status, 404);
+        assert_eq!(get("/static/%2e%2e%2fsecret.txt").status, 404);
+        assert_eq!(get("/static/%2e%2e%2f%2e%2e%2fetc/passwd").status, 404);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn binary_file() {
+        let root = fixture("binary");
+        // every byte value, most of them not valid UTF-8 in this order
+        let content: Vec<u8> = (0..=255).rev().collect();
+        fs::write(root.join("pixel.png"), &content).unwrap();
+
+        let response = StaticFiles::new(&root).serve("/pixel.png").unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.headers.get("Content-Type"), Some("image/png"));
+        assert_eq!(response.body.len(), Some(256));
+        assert_eq!(body(&response), content);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn head_request_for_a_file() {
+        let root = fixture("head");
+        fs::write(root.join("site.css"), "h1 { color: red; }").unwrap();
+
+        let files = StaticFiles::new(&root);
+        let router = Router::new().get("/static/*path", move |request| files.handle(request));
+        let respond = |request: &[u8]| {
+            let mut request = Request::parse(&mut &request[..]).unwrap();
+            let mut written = Vec::new();
+            router
+                .handle(&mut request)
+                .write_for(request.method, &mut written)
+                .unwrap();
+            String::from_utf8(written).unwrap()
+        };
+
+        let get = respond(b"GET /static/site.css HTTP/1.1\r\n\r\n");
+        let head = respond(b"HEAD /static/site.css HTTP/1.1\r\n\r\n");
+
+        // the same headers, nothing after them
+        assert_eq!(get, format!("{head}h1 {{ color: red; }}"));
+        assert!(head.contains("\r\nContent-Length: 18\r\n"));
+        assert!(head.contains("\r\nContent-Type: text/css; charset=utf-8\r\n"));
+        assert!(head.ends_with("\r\n\r\n"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn content_type_by_extension() {
+        let root = fixture("content-type");
+        for name in ["site.css", "app.js", "logo.png", "notes.md", "data.bin"] {
+            fs::write(root.join(name), "").unwrap();
+        }
+
+        let files = StaticFiles::new(&root).mime_type("md", "text/markdown; charset=utf-8");
+        let content_type = |path: &str| {
+            let response = files.serve(path).unwrap();
+            assert_eq!(response.headers.get_all("Content-Type").count(), 1);
+            response.headers.get("Content-Type").unwrap().to_owned()
+        };
+
+        assert_eq!(content_type("/site.css"), "text/css; charset=utf-8");
+        assert_eq!(content_type("/app.js"), "text/javascript; charset=utf-8");
+        assert_eq!(content_type("/logo.png"), "image/png");
+        assert_eq!(content_type("/notes.md"), "text/markdown; charset=utf-8");
+        assert_eq!(content_type("/data.bin"), "application/octet-stream");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn directory_index() {
+        let root = fixture("index");
+        fs::create_dir(root.join("docs")).unwrap();
+        fs::write(root.join("docs/index.html"), "<h1>docs</h1>").unwrap();
+        fs::write(root.join("docs/other.html"), "").unwrap();
+
+        // served even with listings on
+        let response = StaticFiles::new(&root)
+            .directory_listing(true)
+            .serve("/docs/")
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(body(&response), b"<h1>docs</h1>");
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn directory_listing() {
+        let root = fixture("listing");
+        fs::create_dir_all(root.join("docs/b")).unwrap();
+        fs::write(root.join("docs/c.txt"), "").unwrap();
+        fs::write(root.join("docs/a.txt"), "").unwrap();
+
+        let response = StaticFiles::new(&root)
+            .directory_listing(true)
+            .serve("/docs/")
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(
+            response.headers.get("Content-Type"),
+            Some("text/html; charset=utf-8")
+        );
+
+        let listing = String::from_utf8(body(&response)).unwrap();
+        assert!(listing.contains("<title>Index of /docs/</title>"));
+        assert!(listing.contains(
+            "      <li><a href=\"a.txt\">a.txt</a></li>\n      \
+             <li><a href=\"b/\">b/</a></li>\n      \
+             <li><a href=\"c.txt\">c.txt</a></li>\n"
+        ));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn directory_listing_escapes_names() {
+        let root = fixture("listing-escaped");
+        fs::write(root.join("<b>&\"x y.txt"), "").unwrap();
+        fs::write(root.join(".env"), "").unwrap();
+        fs::create_dir(root.join(".git")).unwrap();
+
+        let files = StaticFiles::new(&root).directory_listing(true);
+        let listing = String::from_utf8(body(&files.serve("/").unwrap())).unwrap();
+        assert!(listing.contains(
+            "      <li><a href=\"%3Cb%3E%26%22x%20y.txt\">&lt;b&gt;&amp;&quot;x y.txt</a></li>\n"
+        ));
+        // hidden by default
+        assert!(!listing.contains(".env"));
+        assert!(!listing.contains(".git"));
+        assert_eq!(files.serve("/.env").unwrap().status, 200);
+
+        let files = files.show_hidden(true);
+        let listing = String::from_utf8(body(&files.serve("/").unwrap())).unwrap();
+        assert!(listing.contains("      <li><a href=\".env\">.env</a></li>\n"));
+        assert!(listing.contains("      <li><a href=\".git/\">.git/</a></li>\n"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn directory_without_trailing_slash_redirected() {
+        let root = fixture("redirect");
+        fs::create_dir_all(root.join("docs/a:b")).unwrap();
+        fs::write(root.join("docs/index.html"), "").unwrap();
+
+        let files = StaticFiles::new(&root);
+        let response = files.serve("/docs").unwrap();
+        assert_eq!(response.status, 301);
+        assert_eq!(response.headers.get("Location"), Some("./docs/"));
+
+        let response = files.serve("/docs/a:b").unwrap();
+        assert_eq!(response.status, 301);
+        assert_eq!(response.headers.get("Location"), Some("./a:b/"));
+
+        // resolved against the url of the request, wherever it's mounted
+        let router = Router::new().get("/static/*path", move |request| files.handle(request));
+        let mut request = Request::parse(&mut &b"GET /static/docs HTTP/1.1\r\n\r\n"[..]).unwrap();
+        let response = router.handle(&mut request);
+        assert_eq!(response.status, 301);
+        assert_eq!(response.headers.get("Location"), Some("./docs/"));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn directory_without_index() {
+        let root = fixture("no-index");
+        fs::create_dir(root.join("docs")).unwrap();
+
+        let files = StaticFiles::new(&root);
+        assert_eq!(files.serve("/docs/").unwrap().status, 403);
+        assert_eq!(files.serve("/missing/").unwrap().status, 404);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
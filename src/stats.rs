@@ -0,0 +1,381 @@
+use std::{fmt, time::Duration};
+
+// upper bounds of the histogram buckets of DurationStats, each one 10 times
+// the previous, anything of 10s or longer ends up in the 8th bucket which
+// has no upper bound
+pub const BUCKET_BOUNDS: [Duration; 7] = [
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+];
+
+// snapshot of what the pool has been up to, returned by ThreadPool::stats(),
+// the Display implementation sums it up on a single line for logging:-
+//
+// pool-1: 4 workers (1 busy), 0 queued, 120 completed, 0 panicked, 0 expired, execution avg 2.1ms max 10.0s, queue wait avg 15µs max 3.2ms, up 61.0s
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    // see ThreadPool::name()
+    pub pool_name: String,
+    // worker threads that are up and running
+    pub workers_alive: usize,
+    // most worker threads the pool runs at once, see
+    // ThreadPoolBuilder::max_threads()
+    pub workers_max: usize,
+    // workers running a job right now
+    pub workers_busy: usize,
+    // jobs submitted but not picked up by a worker yet
+    pub jobs_queued: usize,
+    pub jobs_completed: u64,
+    pub jobs_panicked: u64,
+    // jobs dropped without running since they waited in the queue past
+    // their deadline, see ThreadPoolBuilder::max_queue_age()
+    pub jobs_expired: u64,
+    // jobs submitted by other jobs of the pool, from one of its workers
+    pub jobs_submitted_by_workers: u64,
+    // jobs run right away on the thread that submitted them instead of
+    // being queued, see ThreadPoolBuilder::caller_runs() and
+    // on_reentrant_full(), every job of a pool built with
+    // ThreadPoolBuilder::inline() is
+    pub jobs_ran_inline: u64,
+    // how long jobs took to run, panicked ones included
+    pub execution_time: DurationStats,
+    // how long jobs sat in the queue before a worker picked them up, the
+    // best signal for a pool being saturated, since jobs only start
+    // waiting once every worker is busy
+    pub queue_wait: DurationStats,
+    // time the workers spent waiting for a job, added up over every worker,
+    // so it can be more than `uptime`, the time a worker has been waiting
+    // for its current job is only added every
+    // ThreadPoolBuilder::idle_sample_interval()
+    pub total_idle: Duration,
+    // time since the pool was created
+    pub uptime: Duration,
+}
+
+impl PoolStats {
+    // rough estimate of the 99th percentile of queue wait, see
+    // DurationStats::percentile_estimate()
+    pub fn queue_wait_p99_estimate(&self) -> Option<Duration> {
+        self.queue_wait.percentile_estimate(0.99)
+    }
+
+    // share of the workers' time spent running jobs rather than waiting for
+    // one, from 0.0 to 1.0, going by `execution_time` and `total_idle`, a
+    // pool that stays close to 0.0 has more workers than it needs, None
+    // before the workers have done either
+    pub fn utilization(&self) -> Option<f64> {
+        let busy = self.execution_time.total.as_secs_f64();
+        let total = busy + self.total_idle.as_secs_f64();
+
+        (total > 0.0).then(|| busy / total)
+    }
+}
+
+impl fmt::Display for PoolStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} workers ({} busy), {} queued, {} completed, {} panicked, {} expired, \
+             execution avg {:.1?} max {:.1?}, queue wait avg {:.1?} max {:.1?}, up {:.1?}",
+            self.pool_name,
+            self.workers_alive,
+            self.workers_busy,
+            self.jobs_queued,
+            self.jobs_completed,
+            self.jobs_panicked,
+            self.jobs_expired,
+            self.execution_time.avg(),
+            self.execution_time.max,
+            self.queue_wait.avg(),
+            self.queue_wait.max,
+            self.uptime,
+        )
+    }
+}
+
+// aggregate of a series of durations, cheap enough to record one sample per
+// job, the histogram buckets are bounded by BUCKET_BOUNDS, i.e. `buckets[0]`
+// counts samples under 10µs, `buckets[1]` samples from 10µs up to 100µs etc.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DurationStats {
+    pub count: u64,
+    pub total: Duration,
+    // both are zero until the first sample is recorded
+    pub min: Duration,
+    pub max: Duration,
+    pub buckets: [u64; 8],
+}
+
+impl DurationStats {
+    pub(crate) fn record(&mut self, sample: Duration) {
+        if self.count == 0 || sample < self.min {
+            self.min = sample;
+        }
+        self.max = self.max.max(sample);
+        self.count += 1;
+        self.total += sample;
+
+        let bucket = BUCKET_BOUNDS
+            .iter()
+            .position(|bound| sample < *bound)
+            .unwrap_or(BUCKET_BOUNDS.len());
+        self.buckets[bucket] += 1;
+    }
+
+    pub fn avg(&self) -> Duration {
+        match u32::try_from(self.count) {
+            Ok(0) => Duration::ZERO,
+            Ok(count) => self.total / count,
+            // more samples than fit into a u32, which Duration can divide by
+            Err(_) => Duration::from_secs_f64(self.total.as_secs_f64() / self.count as f64),
+        }
+    }
+
+    // upper bound of the histogram bucket that the `quantile`(0.0 to 1.0)
+    // falls into, capped at the largest sample, so it's only accurate to
+    // within a factor of 10, enough to tell "microseconds" from "seconds",
+    // None when nothing was recorded yet
+    pub fn percentile_estimate(&self, quantile: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+
+        // number of samples at or below the percentile, at least 1
+        let rank = ((quantile.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+
+            if seen >= rank {
+                let bound = BUCKET_BOUNDS.get(bucket).copied().unwrap_or(self.max);
+                return Some(bound.min(self.max));
+            }
+        }
+
+        Some(self.max)
+    }
+}
+
+// what happened while the pool shut down, returned by ThreadPool::shutdown()
+// and shutdown_timeout(), and logged when the pool is dropped, the Display
+// implementation sums it up on a single line:-
+//
+// pool-1: 120 jobs completed, 8 of them during a drain of 1.2s, 0 discarded, 0 expired, no worker panicked
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShutdownReport {
+    // see ThreadPool::name()
+    pub pool_name: String,
+    // every job the pool ran to completion, the ones finished during the
+    // drain included
+    pub jobs_completed: u64,
+    // jobs that finished running after the shutdown started, the ones that
+    // were running at that moment included
+    pub jobs_completed_during_drain: u64,
+    pub jobs_panicked: u64,
+    // jobs thrown away without running since they were still queued, see
+    // ShutdownMode::Abandon
+    pub jobs_discarded: usize,
+    // every job dropped for waiting past its deadline, see
+    // ThreadPoolBuilder::max_queue_age()
+    pub jobs_expired: u64,
+    // from the start of the shutdown till the last worker exited
+    pub drain_time: Duration,
+    // ids of the workers taken down by a panic over the life of the pool,
+    // in the order they panicked, a worker the pool starts on demand can
+    // show up more than once, the panic of a job is caught by the worker
+    // running it, so that's only a panic outside of a job, e.g. in
+    // ThreadPoolBuilder::on_thread_start()
+    pub panicked_workers: Vec<usize>,
+}
+
+impl fmt::Display for ShutdownReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} jobs completed, {} of them during a drain of {:.1?}, {} discarded, {} expired, ",
+            self.pool_name,
+            self.jobs_completed,
+            self.jobs_completed_during_drain,
+            self.drain_time,
+            self.jobs_discarded,
+            self.jobs_expired,
+        )?;
+
+        match self.panicked_workers.as_slice() {
+            [] => f.write_str("no worker panicked"),
+            ids => write!(f, "workers {ids:?} panicked"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreadPool;
+    use std::{sync::mpsc, thread};
+
+    #[test]
+    fn duration_stats() {
+        let mut stats = DurationStats::default();
+        assert_eq!(stats.percentile_estimate(0.99), None);
+
+        for millis in [5, 50, 500] {
+            stats.record(Duration::from_millis(millis));
+        }
+
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.min, Duration::from_millis(5));
+        assert_eq!(stats.max, Duration::from_millis(500));
+        assert_eq!(stats.avg(), Duration::from_millis(185));
+        assert_eq!(stats.buckets, [0, 0, 0, 1, 1, 1, 0, 0]);
+        assert_eq!(
+            stats.percentile_estimate(0.5),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(
+            stats.percentile_estimate(0.99),
+            Some(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn queue_wait_of_a_job_behind_a_slow_one() {
+        let pool = ThreadPool::new(1);
+        pool.execute(|| thread::sleep(Duration::from_millis(100)));
+        pool.execute(|| {});
+        pool.join();
+
+        let stats = pool.stats();
+        assert_eq!(stats.queue_wait.count, 2);
+        assert!(stats.queue_wait.max >= Duration::from_millis(90));
+        assert!(stats.queue_wait_p99_estimate().unwrap() >= Duration::from_millis(90));
+    }
+
+    #[test]
+    fn pool_stats_summary() {
+        let stats = PoolStats {
+            pool_name: "pool-1".to_owned(),
+            workers_alive: 4,
+            workers_busy: 1,
+            jobs_completed: 120,
+            uptime: Duration::from_secs(61),
+            ..PoolStats::default()
+        };
+
+        assert_eq!(
+            stats.to_string(),
+            "pool-1: 4 workers (1 busy), 0 queued, 120 completed, 0 panicked, 0 expired, \
+             execution avg 0.0ns max 0.0ns, queue wait avg 0.0ns max 0.0ns, up 61.0s"
+        );
+    }
+
+    #[test]
+    fn busy_workers_never_outnumber_alive_ones() {
+        let pool = ThreadPool::new(2);
+
+        for _ in 0..100 {
+            pool.execute(|| thread::sleep(Duration::from_millis(1)));
+        }
+
+        for _ in 0..100 {
+            let stats = pool.stats();
+            assert!(stats.workers_busy <= stats.workers_alive);
+        }
+
+        pool.join();
+        let stats = pool.stats();
+        assert_eq!((stats.workers_alive, stats.workers_busy), (2, 0));
+        assert_eq!((stats.jobs_queued, stats.jobs_completed), (0, 100));
+        assert_eq!(stats.execution_time.count, 100);
+    }
+
+    #[test]
+    fn idle_time_of_a_pool_left_idle() {
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .idle_sample_interval(Duration::from_millis(10))
+            .build()
+            .unwrap();
+
+        thread::sleep(Duration::from_millis(200));
+
+        // added up while the worker is still waiting, not only once a job
+        // comes in
+        let idle = pool.stats().total_idle;
+        assert!(idle >= Duration::from_millis(150), "{idle:?}");
+        assert!(idle <= Duration::from_millis(300), "{idle:?}");
+
+        pool.execute(|| thread::sleep(Duration::from_millis(50)));
+        pool.join();
+
+        let utilization = pool.stats().utilization().unwrap();
+        assert!(utilization > 0.0 && utilization < 0.5, "{utilization}");
+    }
+
+    // a pool left with 1 worker after the other one panicked on starting,
+    // which ran a panicking job and is now running a job that takes
+    // `running` with 10 more queued behind it
+    fn pool_with_10_queued(running: Duration) -> ThreadPool {
+        let mut pool = ThreadPool::builder()
+            .num_threads(2)
+            .wait_for_workers(false)
+            .on_thread_start(|id| {
+                if id == 2 {
+                    panic!("scripted panic");
+                }
+            })
+            .build()
+            .unwrap();
+        let (started, running_now) = mpsc::channel();
+
+        // a worker that panics isn't replaced
+        assert!(pool.join_worker(2).is_err());
+
+        // caught by the worker, which keeps going
+        pool.execute(|| panic!("scripted panic"));
+        pool.join();
+
+        pool.execute(move || {
+            started.send(()).unwrap();
+            thread::sleep(running);
+        });
+        for _ in 0..10 {
+            pool.execute(|| {});
+        }
+        running_now.recv().unwrap();
+        pool
+    }
+
+    #[test]
+    fn shutdown_report_of_a_drain() {
+        let report = pool_with_10_queued(Duration::from_millis(20)).shutdown();
+
+        assert_eq!(report.jobs_completed, 11);
+        assert_eq!(report.jobs_completed_during_drain, 11);
+        assert_eq!(report.jobs_panicked, 1);
+        assert_eq!(report.jobs_discarded, 0);
+        assert_eq!(report.jobs_expired, 0);
+        assert_eq!(report.panicked_workers.len(), 1);
+    }
+
+    #[test]
+    fn shutdown_report_of_a_timed_out_drain() {
+        let pool = pool_with_10_queued(Duration::from_millis(200));
+        let report = pool.shutdown_timeout(Duration::from_millis(50));
+
+        // 12 submitted, the one that panicked and the ones discarded aside
+        // every job completed, the one running at the timeout included
+        assert_eq!(report.jobs_discarded, 10);
+        assert_eq!(report.jobs_panicked, 1);
+        assert_eq!(report.jobs_completed, 12 - 10 - 1);
+        assert_eq!(report.jobs_completed_during_drain, 1);
+        assert!(report.drain_time >= Duration::from_millis(50));
+        assert_eq!(report.panicked_workers.len(), 1);
+    }
+}
@@ -0,0 +1,99 @@
+use metrics::{Counter, Gauge, Histogram, Label};
+use std::time::Duration;
+
+// the metrics a pool reports through the `metrics` crate with the metrics
+// feature enabled, for whichever recorder the application installed, e.g.
+// a prometheus exporter:-
+//
+// threadpool.jobs.completed   counter, jobs that ran to completion
+// threadpool.jobs.panicked    counter, jobs that panicked
+// threadpool.queue.depth      gauge, jobs waiting for a worker
+// threadpool.job.duration     histogram, seconds each job ran for
+//
+// labeled with `pool` set to the pool's name, see ThreadPoolBuilder::name(),
+// the handles are registered once when the pool is built, so the recorder
+// has to be installed before that, pools built before it report to nothing
+pub(crate) struct PoolMetrics {
+    jobs_completed: Counter,
+    jobs_panicked: Counter,
+    queue_depth: Gauge,
+    job_duration: Histogram,
+}
+
+impl PoolMetrics {
+    pub(crate) fn register(name: &str) -> PoolMetrics {
+        let labels = vec![Label::new("pool", name.to_owned())];
+
+        PoolMetrics {
+            jobs_completed: metrics::counter!("threadpool.jobs.completed", labels.clone()),
+            jobs_panicked: metrics::counter!("threadpool.jobs.panicked", labels.clone()),
+            queue_depth: metrics::gauge!("threadpool.queue.depth", labels.clone()),
+            job_duration: metrics::histogram!("threadpool.job.duration", labels),
+        }
+    }
+
+    pub(crate) fn job_finished(&self, duration: Duration, panicked: bool) {
+        if panicked {
+            self.jobs_panicked.increment(1);
+        } else {
+            self.jobs_completed.increment(1);
+        }
+
+        self.job_duration.record(duration);
+    }
+
+    pub(crate) fn queue_depth(&self, queued: usize) {
+        self.queue_depth.set(queued as f64);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ThreadPool;
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use std::collections::HashMap;
+
+    #[test]
+    fn pool_metrics_recorded() {
+        // local to this thread so the pools of other tests don't report to it
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let pool = metrics::with_local_recorder(&recorder, || {
+            ThreadPool::builder()
+                .name("measured")
+                .num_threads(2)
+                .build()
+                .unwrap()
+        });
+
+        for _ in 0..3 {
+            pool.execute(|| {});
+        }
+        pool.execute(|| panic!("counted"));
+        pool.join();
+
+        let metrics = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| {
+                let (_, key) = key.into_parts();
+                assert!(key
+                    .labels()
+                    .any(|label| label.key() == "pool" && label.value() == "measured"));
+                (key.name().to_owned(), value)
+            })
+            .collect::<HashMap<_, _>>();
+
+        assert_eq!(metrics["threadpool.jobs.completed"], DebugValue::Counter(3));
+        assert_eq!(metrics["threadpool.jobs.panicked"], DebugValue::Counter(1));
+        assert_eq!(
+            metrics["threadpool.queue.depth"],
+            DebugValue::Gauge(0.0.into())
+        );
+        assert!(matches!(
+            &metrics["threadpool.job.duration"],
+            DebugValue::Histogram(durations) if durations.len() == 4
+        ));
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{queue::Task, Shared};
+use log::warn;
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    mem,
+    sync::{Condvar, Mutex, MutexGuard},
+    thread,
+    time::Instant,
+};
+
+// jobs to be queued at a later time, e.g. the next attempt of a job of
+// ThreadPool::execute_with_retry(), kept in order of when they're due by a
+// thread that only starts once the first job is scheduled, and sleeps till
+// the next one is due, the jobs count as delayed(see Counters) till then,
+// so join() waits for them too
+pub(crate) struct Timer {
+    state: Mutex<TimerState>,
+    wake: Condvar,
+}
+
+#[derive(Default)]
+struct TimerState {
+    jobs: BinaryHeap<Delayed>,
+    // keeps jobs due at the same time in the order they were scheduled
+    next_seq: u64,
+    thread: Option<thread::JoinHandle<()>>,
+    stopped: bool,
+}
+
+struct Delayed {
+    at: Instant,
+    seq: u64,
+    task: Task,
+}
+
+impl Timer {
+    pub(crate) fn new() -> Timer {
+        Timer {
+            state: Mutex::new(TimerState::default()),
+            wake: Condvar::new(),
+        }
+    }
+
+    // queues `task` on the pool of `shared` once `at` has passed, hands it
+    // back once the pool is shutting down, or if the thread couldn't start
+    pub(crate) fn schedule(&self, shared: &Shared, at: Instant, task: Task) -> Result<(), Task> {
+        let mut state = self.lock();
+
+        if state.stopped {
+            return Err(task);
+        }
+
+        if state.thread.is_none() {
+            let Some(this) = shared.this.upgrade() else {
+                return Err(task);
+            };
+
+            let thread = thread::Builder::new()
+                .name(format!("{}-timer", shared.name))
+                .spawn(move || run(&this));
+
+            match thread {
+                Ok(thread) => state.thread = Some(thread),
+                Err(err) => {
+                    warn!(
+                        "Failed to start the timer thread of pool {}: {err}",
+                        shared.name
+                    );
+                    return Err(task);
+                }
+            }
+        }
+
+        let seq = state.next_seq;
+        state.next_seq += 1;
+        state.jobs.push(Delayed { at, seq, task });
+        shared.update_counters(|counters| counters.delayed += 1);
+
+        // the new job might be due before the one the thread waits for
+        self.wake.notify_one();
+        Ok(())
+    }
+
+    // stops the thread, the jobs that aren't due yet are dropped without
+    // running, called when the pool shuts down
+    pub(crate) fn stop(&self, shared: &Shared) {
+        let (jobs, thread) = {
+            let mut state = self.lock();
+            state.stopped = true;
+            (mem::take(&mut state.jobs), state.thread.take())
+        };
+
+        self.wake.notify_one();
+
+        if let Some(thread) = thread {
+            let _ = thread.join();
+        }
+
+        let dropped = jobs.len();
+        // dropped with no lock held, dropping a job can run code of its own
+        drop(jobs);
+
+        shared.update_counters(|counters| {
+            counters.delayed -= dropped;
+
+            if !counters.has_pending() {
+                shared.idle.notify_all();
+            }
+        });
+    }
+
+    // doesn't unwrap the lock, jobs are scheduled and dropped while
+    // unwinding from a panicking job
+    fn lock(&self) -> MutexGuard<'_, TimerState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn run(shared: &Shared) {
+    let timer = &shared.timer;
+    let mut state = timer.lock();
+
+    while !state.stopped {
+        let now = Instant::now();
+
+        match state.jobs.peek() {
+            Some(next) if next.at <= now => {
+                let Delayed { task, .. } = state.jobs.pop().unwrap();
+                drop(state);
+
+                // queued before it stops counting as delayed, so that join()
+                // never sees it as neither, the queue is only closed after
+                // the timer is stopped
+                let _ = shared.submit(task, None);
+                shared.update_counters(|counters| counters.delayed -= 1);
+
+                state = timer.lock();
+            }
+            Some(next) => {
+                let timeout = next.at - now;
+                state = timer
+                    .wake
+                    .wait_timeout(state, timeout)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .0;
+            }
+            None => {
+                state = timer
+                    .wake
+                    .wait(state)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+            }
+        }
+    }
+}
+
+// BinaryHeap is a max-heap, the job due first has to come out on top
+impl Ord for Delayed {
+    fn cmp(&self, other: &Delayed) -> Ordering {
+        (other.at, other.seq).cmp(&(self.at, self.seq))
+    }
+}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Delayed) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Delayed) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Delayed {}
@@ -0,0 +1,144 @@
+use crate::Shared;
+use std::{
+    fmt, io,
+    sync::{mpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+pub(crate) type WatchdogCallback = Arc<dyn Fn(&str, usize, Option<&str>, Duration) + Send + Sync>;
+
+// see ThreadPoolBuilder::watchdog()
+#[derive(Clone)]
+pub(crate) struct WatchdogConfig {
+    pub(crate) interval: Duration,
+    pub(crate) threshold: Duration,
+    pub(crate) callback: WatchdogCallback,
+}
+
+impl fmt::Debug for WatchdogConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchdogConfig")
+            .field("interval", &self.interval)
+            .field("threshold", &self.threshold)
+            .finish_non_exhaustive()
+    }
+}
+
+// the job a worker is running right now, published by the worker itself for
+// the watchdog to look at
+#[derive(Debug, Clone)]
+pub(crate) struct CurrentJob {
+    pub(crate) started_at: Instant,
+    // set by ThreadPool::execute_named() jobs once they start running
+    pub(crate) name: Option<Arc<str>>,
+}
+
+// thread that wakes up every `interval` to look for jobs that have been
+// running for longer than `threshold`, it's stopped by dropping `stop`,
+// which wakes it up right away instead of at the end of its interval
+pub(crate) struct Watchdog {
+    stop: mpsc::Sender<()>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Watchdog {
+    pub(crate) fn spawn(config: WatchdogConfig, shared: Arc<Shared>) -> io::Result<Watchdog> {
+        let (stop, stopped) = mpsc::channel::<()>();
+
+        let thread = thread::Builder::new()
+            .name(format!("{}-watchdog", shared.name))
+            .spawn(move || {
+                // nothing is ever sent, the channel only disconnects
+                while let Err(mpsc::RecvTimeoutError::Timeout) =
+                    stopped.recv_timeout(config.interval)
+                {
+                    inspect(&config, &shared);
+                }
+            })?;
+
+        Ok(Watchdog { stop, thread })
+    }
+
+    pub(crate) fn stop(self) {
+        drop(self.stop);
+
+        // a panicking callback already took the watchdog down with it
+        let _ = self.thread.join();
+    }
+}
+
+fn inspect(config: &WatchdogConfig, shared: &Shared) {
+    let now = Instant::now();
+
+    for (index, worker) in shared.workers.iter().enumerate() {
+        // copied out so that the callback doesn't run with the lock held,
+        // which would keep the worker from starting its next job
+        let current = worker
+            .current_job
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone();
+
+        if let Some(CurrentJob { started_at, name }) = current {
+            let elapsed = now.duration_since(started_at);
+
+            if elapsed > config.threshold {
+                (config.callback)(
+                    &shared.name,
+                    shared.worker_id(index),
+                    name.as_deref(),
+                    elapsed,
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ThreadPool;
+    use std::{
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn reports_jobs_past_the_threshold() {
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let reports = Arc::clone(&reported);
+        let pool = ThreadPool::builder()
+            .name("watched")
+            .num_threads(2)
+            .watchdog(
+                Duration::from_millis(20),
+                Duration::from_millis(100),
+                move |pool, worker, job, elapsed| {
+                    reports.lock().unwrap().push((
+                        pool.to_owned(),
+                        worker,
+                        job.map(str::to_owned),
+                        elapsed,
+                    ));
+                },
+            )
+            .build()
+            .unwrap();
+
+        for _ in 0..100 {
+            pool.execute_named("fast", || {});
+        }
+        pool.execute_named("stuck", || thread::sleep(Duration::from_millis(400)));
+        pool.join();
+
+        let reported = reported.lock().unwrap();
+        assert!(!reported.is_empty());
+        assert!(reported.iter().all(|(pool, worker, job, elapsed)| {
+            pool == "watched"
+                && (1..=2).contains(worker)
+                && job.as_deref() == Some("stuck")
+                && *elapsed >= Duration::from_millis(100)
+        }));
+    }
+}
@@ -0,0 +1,228 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex, TryLockError},
+};
+
+pub(crate) type Callback = Arc<dyn Fn() + Send + Sync>;
+
+// thresholds on the number of queued jobs set with
+// ThreadPoolBuilder::on_queue_high() and on_queue_low(), together they work
+// like a thermostat, the queue goes "high" once it grows past the high
+// threshold and only goes back to "low" once it shrinks below the low one,
+// so a queue hovering around a single threshold doesn't fire a callback for
+// every job
+#[derive(Clone, Default)]
+pub(crate) struct Watermarks {
+    pub(crate) high: Option<(usize, Callback)>,
+    pub(crate) low: Option<(usize, Callback)>,
+}
+
+impl Watermarks {
+    // whether the number of queued jobs changing to `queued` makes the queue
+    // go from high to low or the other way around, `high` being whether the
+    // queue is high right now
+    fn crosses(&self, queued: usize, high: bool) -> bool {
+        if high {
+            // without a low threshold the queue is low again as soon as it
+            // is back at or under the high one
+            match (&self.low, &self.high) {
+                (Some((threshold, _)), _) => queued < *threshold,
+                (None, Some((threshold, _))) => queued <= *threshold,
+                (None, None) => false,
+            }
+        } else {
+            // without a high threshold the queue is high as soon as it's at
+            // or over the low one, so that the low callback has something
+            // to fire on
+            match (&self.high, &self.low) {
+                (Some((threshold, _)), _) => queued > *threshold,
+                (None, Some((threshold, _))) => queued >= *threshold,
+                (None, None) => false,
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Watermarks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watermarks")
+            .field("high", &self.high.as_ref().map(|(threshold, _)| threshold))
+            .field("low", &self.low.as_ref().map(|(threshold, _)| threshold))
+            .finish()
+    }
+}
+
+// the watermarks of a running pool along with the callbacks of crossings
+// that happened but weren't fired yet
+//
+// crossings are detected with the counters lock held but callbacks can't be
+// fired with it held, a callback submitting a job would deadlock on it, so
+// they are queued up in the order the crossings happened and fired once the
+// lock is released, by a single thread at a time, otherwise a high callback
+// and the low callback right after it could run on two threads at once and
+// finish in the wrong order, leaving e.g. an `overloaded` flag set while the
+// queue is empty
+pub(crate) struct QueueWatermarks {
+    watermarks: Watermarks,
+    pending: Mutex<VecDeque<Callback>>,
+    firing: Mutex<()>,
+}
+
+impl QueueWatermarks {
+    pub(crate) fn new(watermarks: Watermarks) -> QueueWatermarks {
+        QueueWatermarks {
+            watermarks,
+            pending: Mutex::new(VecDeque::new()),
+            firing: Mutex::new(()),
+        }
+    }
+
+    // called with the counters lock held every time the number of queued
+    // jobs changes, `high` is whether the queue is currently high and is
+    // flipped on a crossing, the returned Crossing has to be fired once
+    // every lock is released
+    pub(crate) fn check(&self, queued: usize, high: &mut bool) -> Crossing<'_> {
+        if !self.watermarks.crosses(queued, *high) {
+            return Crossing(None);
+        }
+
+        *high = !*high;
+
+        let callback = if *high {
+            &self.watermarks.high
+        } else {
+            &self.watermarks.low
+        };
+
+        // crossing the high threshold of a pool with only a low callback
+        // has nothing to fire, it only arms the low callback
+        let Some((_, callback)) = callback else {
+            return Crossing(None);
+        };
+
+        self.pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push_back(Arc::clone(callback));
+        Crossing(Some(self))
+    }
+
+    fn fire_pending(&self) {
+        loop {
+            // whichever thread is firing already fires this crossing's
+            // callback as well, that includes this very thread when a
+            // callback itself makes the queue cross a threshold, which would
+            // otherwise deadlock on the firing lock
+            let firing = match self.firing.try_lock() {
+                Ok(firing) => firing,
+                // a callback panicked while firing, nothing is left in a
+                // bad state by that
+                Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+                Err(TryLockError::WouldBlock) => return,
+            };
+
+            loop {
+                let callback = self
+                    .pending
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .pop_front();
+                let Some(callback) = callback else {
+                    break;
+                };
+
+                callback();
+            }
+
+            drop(firing);
+
+            // a crossing that failed to take the firing lock after the loop
+            // above found nothing left but before the lock was released
+            // would otherwise not be fired until the next crossing
+            if self
+                .pending
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .is_empty()
+            {
+                return;
+            }
+        }
+    }
+}
+
+// a crossing whose callback is waiting to be fired
+#[must_use]
+pub(crate) struct Crossing<'a>(Option<&'a QueueWatermarks>);
+
+impl Crossing<'_> {
+    pub(crate) fn fire(self) {
+        if let Some(watermarks) = self.0 {
+            watermarks.fire_pending();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreadPool;
+    use std::{sync::mpsc, time::Duration};
+
+    // watermarks recording which callback fired, in order
+    fn recording(high: usize, low: usize) -> (QueueWatermarks, Arc<Mutex<Vec<&'static str>>>) {
+        let fired = Arc::new(Mutex::new(Vec::new()));
+        let on_high = Arc::clone(&fired);
+        let on_low = Arc::clone(&fired);
+
+        let watermarks = QueueWatermarks::new(Watermarks {
+            high: Some((high, Arc::new(move || on_high.lock().unwrap().push("high")))),
+            low: Some((low, Arc::new(move || on_low.lock().unwrap().push("low")))),
+        });
+
+        (watermarks, fired)
+    }
+
+    #[test]
+    fn fired_once_per_crossing() {
+        let (watermarks, fired) = recording(3, 1);
+        let mut high = false;
+
+        for queued in [0, 1, 2, 3, 4, 5, 4, 3, 4, 3, 2, 1, 2, 1, 0, 1, 0, 4, 5] {
+            watermarks.check(queued, &mut high).fire();
+        }
+
+        assert_eq!(*fired.lock().unwrap(), ["high", "low", "high"]);
+        assert!(high);
+    }
+
+    #[test]
+    fn fired_by_the_pool() {
+        let (sender, receiver) = mpsc::channel();
+        let (high_sender, low_sender) = (sender.clone(), sender);
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .on_queue_high(4, move || high_sender.send("high").unwrap())
+            .on_queue_low(2, move || low_sender.send("low").unwrap())
+            .build()
+            .unwrap();
+
+        // keeps the only worker busy until the jobs are all queued
+        let (release, blocked) = mpsc::channel::<()>();
+        pool.execute(move || {
+            let _ = blocked.recv();
+        });
+
+        for _ in 0..10 {
+            pool.execute(|| {});
+        }
+
+        assert_eq!(receiver.try_recv(), Ok("high"));
+        drop(release);
+        pool.join();
+
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok("low"));
+        assert!(receiver.try_recv().is_err());
+    }
+}